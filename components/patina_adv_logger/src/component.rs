@@ -13,7 +13,10 @@ use alloc::boxed::Box;
 use core::{ffi::c_void, ptr};
 use patina::{
     boot_services::{BootServices, StandardBootServices},
-    component::IntoComponent,
+    component::{
+        IntoComponent,
+        service::{IntoService, crash_record::LogTail},
+    },
     error::{EfiError, Result},
     pi::hob::{Hob, PhaseHandoffInformationTable},
     serial::SerialIO,
@@ -35,6 +38,38 @@ where
     adv_logger: &'static AdvancedLogger<'static, S>,
 }
 
+/// The [LogTail] service backed by the advanced logger memory log.
+///
+/// The DXE core consumes [LogTail] when capturing crash records, so this service should be registered directly with
+/// the core (e.g. `Core::with_service`) rather than produced by a component.
+#[derive(IntoService)]
+#[service(dyn LogTail)]
+pub struct AdvancedLogTail<S>
+where
+    S: SerialIO + Send + 'static,
+{
+    adv_logger: &'static AdvancedLogger<'static, S>,
+}
+
+impl<S> AdvancedLogTail<S>
+where
+    S: SerialIO + Send + 'static,
+{
+    /// Creates a new AdvancedLogTail reading from the memory log of `adv_logger`.
+    pub const fn new(adv_logger: &'static AdvancedLogger<S>) -> Self {
+        Self { adv_logger }
+    }
+}
+
+impl<S> LogTail for AdvancedLogTail<S>
+where
+    S: SerialIO + Send + 'static,
+{
+    fn tail(&self, buffer: &mut [u8]) -> usize {
+        self.adv_logger.log_tail(buffer)
+    }
+}
+
 /// The component that will install the Advanced Logger protocol.
 #[derive(IntoComponent)]
 pub struct AdvancedLoggerComponent<S>
@@ -201,4 +236,33 @@ mod tests {
 
         // TODO: Need to mock the protocol interface but requires final component interface.
     }
+
+    #[test]
+    fn log_tail_test() {
+        static TAIL_LOGGER: AdvancedLogger<UartNull> =
+            AdvancedLogger::new(patina::log::Format::Standard, &[], log::LevelFilter::Trace, UartNull {});
+        let log_tail = AdvancedLogTail::new(&TAIL_LOGGER);
+
+        let tail = |len: usize| {
+            let mut buffer = alloc::vec![0u8; len];
+            let copied = log_tail.tail(&mut buffer);
+            buffer.truncate(copied);
+            buffer
+        };
+
+        // Nothing is available until the memory log is initialized.
+        assert!(tail(16).is_empty());
+
+        let component = AdvancedLoggerComponent::new(&TAIL_LOGGER);
+        // SAFETY: The hob list created is valid for this test.
+        unsafe { component.init_advanced_logger(create_adv_logger_hob_list()) }.unwrap();
+
+        TAIL_LOGGER.log_write(memory_log::DEBUG_LEVEL_INFO, b"first line\n");
+        TAIL_LOGGER.log_write(memory_log::DEBUG_LEVEL_INFO, b"second line\n");
+
+        assert_eq!(tail(8), b"nd line\n");
+        assert_eq!(tail(20), b"st line\nsecond line\n");
+        assert!(tail(4096).ends_with(b"first line\nsecond line\n"));
+        assert!(tail(0).is_empty());
+    }
 }
@@ -45,6 +45,10 @@
 //! For the protocol to be created for use of by external components, the platform
 //! should invoke patina_dxe_core.start with the advanced logger component.
 //!
+//! To include the tail of the memory log in the crash records captured by the DXE
+//! core, the platform should also register `component::AdvancedLogTail::new(&LOGGER)`
+//! as a service with the core.
+//!
 //! ## License
 //!
 //! Copyright (c) Microsoft Corporation.
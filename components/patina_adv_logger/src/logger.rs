@@ -100,6 +100,28 @@ where
     pub(crate) fn get_log_address(&self) -> Option<efi::PhysicalAddress> {
         self.memory_log.get().map(|log| log.get_address())
     }
+
+    /// Copies the most recent message data in the memory log into `buffer`, up to its length, and returns the number
+    /// of bytes copied. Does not allocate.
+    pub(crate) fn log_tail(&self, buffer: &mut [u8]) -> usize {
+        let Some(memory_log) = self.memory_log.get() else {
+            return 0;
+        };
+
+        let total: usize = memory_log.iter().map(|entry| entry.get_message().len()).sum();
+        let mut skip = total.saturating_sub(buffer.len());
+        let mut len = 0;
+        for entry in memory_log.iter() {
+            let message = entry.get_message();
+            let start = skip.min(message.len());
+            skip -= start;
+            // Entries may have been added since the size was calculated, those that no longer fit are left out.
+            let count = (message.len() - start).min(buffer.len() - len);
+            buffer[len..len + count].copy_from_slice(&message[start..start + count]);
+            len += count;
+        }
+        len
+    }
 }
 
 impl<S> log::Log for AdvancedLogger<'_, S>
@@ -20,6 +20,7 @@
 //!        | patina::performance::Measurement::DriverBindingSupport     // Adds driver binding support measurements.
 //!        | patina::performance::Measurement::LoadImage                // Adds load image measurements.
 //!        | patina::performance::Measurement::StartImage               // Adds start image measurements.
+//!        | patina::performance::Measurement::ComponentRun             // Adds component run measurements.
 //!     }
 //! })
 //! .with_component(patina_performance::component::Performance)
@@ -19,6 +19,8 @@ use patina::{error::EfiError, pi::protocols::cpu_arch::EfiSystemContext};
 
 mod exception_handling;
 
+pub use exception_handling::{UnhandledExceptionHook, set_unhandled_exception_hook};
+
 cfg_if::cfg_if! {
     if #[cfg(all(target_os = "uefi", target_arch = "x86_64"))] {
         mod x64;
@@ -127,6 +129,18 @@ pub(crate) trait EfiExceptionStackTrace {
     fn dump_system_context_registers(&self);
 }
 
+/// Trait for reading the register state saved in an architecture specific context.
+pub trait ExceptionContextRegisters {
+    /// Returns the program counter at the time the context was saved.
+    fn program_counter(&self) -> u64;
+
+    /// Returns the stack pointer at the time the context was saved.
+    fn stack_pointer(&self) -> u64;
+
+    /// Calls `f` with the name and value of each register that is dumped for an unhandled exception.
+    fn for_each_register(&self, f: &mut dyn FnMut(&'static str, u64));
+}
+
 /// Trait for structs that implement and manage interrupts.
 ///
 /// Generic trait that can be used to abstract the architecture and platform
@@ -58,6 +58,60 @@ impl super::EfiExceptionStackTrace for ExceptionContextAArch64 {
     }
 }
 
+impl super::ExceptionContextRegisters for ExceptionContextAArch64 {
+    fn program_counter(&self) -> u64 {
+        self.elr
+    }
+
+    fn stack_pointer(&self) -> u64 {
+        self.sp
+    }
+
+    fn for_each_register(&self, f: &mut dyn FnMut(&'static str, u64)) {
+        let registers = [
+            ("ESR", self.esr),
+            ("ELR", self.elr),
+            ("SPSR", self.spsr),
+            ("FAR", self.far),
+            ("x0", self.x0),
+            ("x1", self.x1),
+            ("x2", self.x2),
+            ("x3", self.x3),
+            ("x4", self.x4),
+            ("x5", self.x5),
+            ("x6", self.x6),
+            ("x7", self.x7),
+            ("x8", self.x8),
+            ("x9", self.x9),
+            ("x10", self.x10),
+            ("x11", self.x11),
+            ("x12", self.x12),
+            ("x13", self.x13),
+            ("x14", self.x14),
+            ("x15", self.x15),
+            ("x16", self.x16),
+            ("x17", self.x17),
+            ("x18", self.x18),
+            ("x19", self.x19),
+            ("x20", self.x20),
+            ("x21", self.x21),
+            ("x22", self.x22),
+            ("x23", self.x23),
+            ("x24", self.x24),
+            ("x25", self.x25),
+            ("x26", self.x26),
+            ("x27", self.x27),
+            ("x28", self.x28),
+            ("fp", self.fp),
+            ("lr", self.lr),
+            ("sp", self.sp),
+        ];
+        for (name, value) in registers {
+            f(name, value);
+        }
+    }
+}
+
 #[allow(unused)]
 pub fn enable_interrupts() {
     #[cfg(all(not(test), target_arch = "aarch64"))]
@@ -35,6 +35,27 @@ static EXCEPTION_HANDLERS: [RwLock<HandlerType>; NUM_EXCEPTION_TYPES] = {
     [INIT; NUM_EXCEPTION_TYPES]
 };
 
+/// A callback invoked for exceptions that have no registered handler, after the context has been dumped and before the
+/// system panics.
+pub type UnhandledExceptionHook = fn(ExceptionType, &ExceptionContext);
+
+static UNHANDLED_EXCEPTION_HOOK: RwLock<Option<UnhandledExceptionHook>> = RwLock::new(None);
+
+/// Sets the hook invoked for exceptions that have no registered handler, replacing any previous hook.
+///
+/// The hook runs in the exception context, so it must not rely on any state that the faulting code may have left
+/// locked.
+pub fn set_unhandled_exception_hook(hook: UnhandledExceptionHook) {
+    *UNHANDLED_EXCEPTION_HOOK.write() = Some(hook);
+}
+
+/// Invokes the unhandled exception hook, if one is set.
+fn call_unhandled_exception_hook(exception_type: ExceptionType, context: &ExceptionContext) {
+    if let Some(hook) = UNHANDLED_EXCEPTION_HOOK.try_read().and_then(|hook| *hook) {
+        hook(exception_type, context);
+    }
+}
+
 /// Registers a handler callback for the provided exception type.
 ///
 /// # Errors
@@ -110,6 +131,7 @@ extern "efiapi" fn exception_handler(exception_type: usize, context: &mut Except
             context.dump_system_context_registers();
             log::error!("");
             context.dump_stack_trace();
+            call_unhandled_exception_hook(exception_type, context);
             panic!("Unhandled Exception! {exception_type:#X}");
         }
     }
@@ -123,10 +145,11 @@ mod tests {
     use patina::pi::protocols::cpu_arch::EfiSystemContext;
 
     use super::*;
-    use core::sync::atomic::AtomicBool;
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
     const CALLBACK_EXCEPTION: usize = 0;
     const HANDLER_EXCEPTION: usize = 1;
+    const UNHANDLED_EXCEPTION: usize = 3;
     static mut CALLBACK_INVOKED: bool = false;
 
     struct TestHandler {
@@ -183,6 +206,24 @@ mod tests {
         unregister_exception_handler(HANDLER_EXCEPTION).expect_err("Allowed double unregister!");
     }
 
+    #[test]
+    fn test_unhandled_exception_hook() {
+        static HOOK_EXCEPTION: AtomicUsize = AtomicUsize::new(0);
+
+        fn test_hook(exception_type: ExceptionType, _context: &ExceptionContext) {
+            HOOK_EXCEPTION.store(exception_type, Ordering::SeqCst);
+        }
+
+        let context = crate::interrupts::null::ExceptionContextNull {};
+        // No hook is set yet, so this does nothing.
+        call_unhandled_exception_hook(UNHANDLED_EXCEPTION, &context);
+        assert_eq!(HOOK_EXCEPTION.load(Ordering::SeqCst), 0);
+
+        set_unhandled_exception_hook(test_hook);
+        call_unhandled_exception_hook(UNHANDLED_EXCEPTION, &context);
+        assert_eq!(HOOK_EXCEPTION.load(Ordering::SeqCst), UNHANDLED_EXCEPTION);
+    }
+
     #[test]
     fn test_invalid_input() {
         register_exception_handler(NUM_EXCEPTION_TYPES, HandlerType::UefiRoutine(test_callback))
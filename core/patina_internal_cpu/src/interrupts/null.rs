@@ -28,6 +28,18 @@ impl super::EfiExceptionStackTrace for ExceptionContextNull {
     fn dump_system_context_registers(&self) {}
 }
 
+impl super::ExceptionContextRegisters for ExceptionContextNull {
+    fn program_counter(&self) -> u64 {
+        0
+    }
+
+    fn stack_pointer(&self) -> u64 {
+        0
+    }
+
+    fn for_each_register(&self, _f: &mut dyn FnMut(&'static str, u64)) {}
+}
+
 /// A function that does nothing as this is a null implementation.
 #[allow(unused)]
 pub fn enable_interrupts() {}
@@ -77,6 +77,49 @@ impl super::EfiExceptionStackTrace for ExceptionContextX64 {
     }
 }
 
+impl super::ExceptionContextRegisters for ExceptionContextX64 {
+    fn program_counter(&self) -> u64 {
+        self.rip
+    }
+
+    fn stack_pointer(&self) -> u64 {
+        self.rsp
+    }
+
+    fn for_each_register(&self, f: &mut dyn FnMut(&'static str, u64)) {
+        let registers = [
+            ("CR0", self.cr0),
+            ("CR2", self.cr2),
+            ("CR3", self.cr3),
+            ("CR4", self.cr4),
+            ("RIP", self.rip),
+            ("CS", self.cs),
+            ("SS", self.ss),
+            ("DS", self.ds),
+            ("RSP", self.rsp),
+            ("RFLAGS", self.rflags),
+            ("RAX", self.rax),
+            ("RBX", self.rbx),
+            ("RCX", self.rcx),
+            ("RDX", self.rdx),
+            ("RSI", self.rsi),
+            ("RDI", self.rdi),
+            ("RBP", self.rbp),
+            ("R8", self.r8),
+            ("R9", self.r9),
+            ("R10", self.r10),
+            ("R11", self.r11),
+            ("R12", self.r12),
+            ("R13", self.r13),
+            ("R14", self.r14),
+            ("R15", self.r15),
+        ];
+        for (name, value) in registers {
+            f(name, value);
+        }
+    }
+}
+
 #[allow(unused)]
 pub fn enable_interrupts() {
     // SAFETY: The caller must ensure the system is ready to handle interrupts at this point
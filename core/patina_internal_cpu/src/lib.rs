@@ -16,3 +16,4 @@ extern crate alloc;
 pub mod cpu;
 pub mod interrupts;
 pub mod paging;
+pub mod timer;
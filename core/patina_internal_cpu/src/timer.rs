@@ -0,0 +1,106 @@
+//! UEFI Timer Module
+//!
+//! This module provides implementation for [Timer]. The [EfiTimer] struct is the only accessible struct when using
+//! this module. The other structs are architecture specific implementations and replace the [EfiTimer] struct at
+//! compile time based on the target architecture.
+//!
+//! A [Timer] provides a free running counter along with a periodic interrupt source, which together are sufficient to
+//! produce the Timer, Watchdog Timer and Metronome Architectural Protocols.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+cfg_if::cfg_if! {
+    if #[cfg(all(target_os = "uefi", target_arch = "x86_64"))] {
+        mod x64;
+        pub type EfiTimer = x64::EfiTimerX64;
+    } else if #[cfg(all(target_os = "uefi", target_arch = "aarch64"))] {
+        mod aarch64;
+        pub type EfiTimer = aarch64::EfiTimerAarch64;
+    } else if #[cfg(feature = "doc")] {
+        mod x64;
+        mod aarch64;
+        mod null;
+        pub use x64::EfiTimerX64;
+        pub use aarch64::EfiTimerAarch64;
+        pub use null::EfiTimerNull;
+
+        /// Type alias whose implementation is [EfiTimerX64], [EfiTimerAarch64], or [EfiTimerNull] depending on the
+        /// compilation target.
+        ///
+        /// This struct is for documentation purposes only. Please refer to the individual implementations for specific
+        /// details.
+        pub type EfiTimer = EfiTimerNull;
+    } else {
+        mod x64;
+        mod aarch64;
+        mod null;
+        pub type EfiTimer = null::EfiTimerNull;
+        pub use x64::EfiTimerX64;
+        pub use aarch64::EfiTimerAarch64;
+        pub use null::EfiTimerNull;
+    }
+}
+
+use patina::error::EfiError;
+
+use crate::interrupts::ExceptionType;
+
+/// The number of 100ns units in one second, which is the unit UEFI timer periods are expressed in.
+pub const PERIODS_PER_SECOND: u64 = 10_000_000;
+
+/// Describes how the periodic timer interrupt is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerInterrupt {
+    /// The interrupt is delivered directly on the given exception type and should be registered with the
+    /// [InterruptManager](crate::interrupts::InterruptManager).
+    Exception(ExceptionType),
+    /// The interrupt is delivered through the platform interrupt controller on the given interrupt source and should
+    /// be registered with the Hardware Interrupt Protocol.
+    HardwareInterrupt(u64),
+}
+
+/// A trait to facilitate architecture-specific timer implementations.
+///
+/// Timers are accessed from interrupt context, so implementations are expected to leverage internal locking or
+/// atomics for any mutable state.
+pub trait Timer: Sync {
+    /// Returns the current value of the free running counter.
+    fn counter(&self) -> u64;
+
+    /// Returns the frequency of the free running counter in Hz.
+    fn frequency(&self) -> u64;
+
+    /// Programs the periodic timer interrupt to fire every `period` 100ns units. A period of zero disables the
+    /// interrupt.
+    ///
+    /// Returns the period actually programmed, which may have been rounded to the granularity of the hardware.
+    ///
+    /// ## Errors
+    ///
+    /// DeviceError   If the timer hardware could not be programmed.
+    fn set_period(&self, period: u64) -> Result<u64, EfiError>;
+
+    /// Acknowledges the timer interrupt, arming the timer for the next period if required by the hardware.
+    fn acknowledge(&self);
+
+    /// Returns how the periodic timer interrupt is delivered.
+    fn interrupt(&self) -> TimerInterrupt;
+
+    /// Converts a number of counter ticks into 100ns units.
+    fn ticks_to_period(&self, ticks: u64) -> u64 {
+        match self.frequency() {
+            0 => 0,
+            frequency => (ticks as u128 * PERIODS_PER_SECOND as u128 / frequency as u128) as u64,
+        }
+    }
+
+    /// Converts a number of 100ns units into counter ticks.
+    fn period_to_ticks(&self, period: u64) -> u64 {
+        (period as u128 * self.frequency() as u128 / PERIODS_PER_SECOND as u128) as u64
+    }
+}
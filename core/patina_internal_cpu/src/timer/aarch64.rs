@@ -0,0 +1,132 @@
+//! AArch64 Timer implementation
+//!
+//! The free running counter and the periodic interrupt are both produced by the EL1 physical timer of the Arm generic
+//! timer. The generic timer has no periodic mode, so the timer is re-armed every time the interrupt is acknowledged.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#[cfg(all(not(test), target_arch = "aarch64"))]
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use patina::{component::service::IntoService, error::EfiError};
+
+use crate::timer::{Timer, TimerInterrupt};
+
+const CNTP_CTL_ENABLE: u64 = 1 << 0;
+const CNTP_CTL_IMASK: u64 = 1 << 1;
+
+/// Struct to implement the AArch64 Timer.
+///
+/// This struct cannot be used directly. It replaces the `EfiTimer` struct when compiling for the AArch64 architecture.
+#[derive(Default, IntoService)]
+#[service(dyn Timer)]
+pub struct EfiTimerAarch64 {
+    reload: AtomicU64,
+}
+
+impl EfiTimerAarch64 {
+    /// The interrupt ID (PPI) of the non-secure EL1 physical timer.
+    pub const INTERRUPT: u64 = 30;
+
+    /// Creates a new instance of the AArch64 implementation of the timer.
+    pub const fn new() -> Self {
+        Self { reload: AtomicU64::new(0) }
+    }
+
+    /// Initializes the timer, making sure the physical timer is stopped until a period is programmed.
+    pub fn initialize(&mut self) -> Result<(), EfiError> {
+        self.write_control(CNTP_CTL_IMASK);
+        Ok(())
+    }
+
+    #[cfg(all(not(test), target_arch = "aarch64"))]
+    fn write_control(&self, value: u64) {
+        // SAFETY: CNTP_CTL_EL0 only controls the physical timer, which is owned by this implementation.
+        unsafe {
+            asm!("msr cntp_ctl_el0, {}", "isb", in(reg) value, options(nostack, preserves_flags));
+        }
+    }
+
+    #[cfg(any(test, not(target_arch = "aarch64")))]
+    fn write_control(&self, _value: u64) {}
+
+    #[cfg(all(not(test), target_arch = "aarch64"))]
+    fn write_timer_value(&self, value: u64) {
+        // SAFETY: CNTP_TVAL_EL0 only controls the physical timer, which is owned by this implementation.
+        unsafe {
+            asm!("msr cntp_tval_el0, {}", "isb", in(reg) value, options(nostack, preserves_flags));
+        }
+    }
+
+    #[cfg(any(test, not(target_arch = "aarch64")))]
+    fn write_timer_value(&self, _value: u64) {}
+}
+
+impl Timer for EfiTimerAarch64 {
+    #[cfg(all(not(test), target_arch = "aarch64"))]
+    fn counter(&self) -> u64 {
+        let value: u64;
+        // SAFETY: Reading CNTPCT_EL0 has no side effects.
+        unsafe {
+            asm!("isb", "mrs {}, cntpct_el0", out(reg) value, options(nostack, preserves_flags));
+        }
+        value
+    }
+
+    #[cfg(any(test, not(target_arch = "aarch64")))]
+    fn counter(&self) -> u64 {
+        0
+    }
+
+    #[cfg(all(not(test), target_arch = "aarch64"))]
+    fn frequency(&self) -> u64 {
+        let value: u64;
+        // SAFETY: Reading CNTFRQ_EL0 has no side effects.
+        unsafe {
+            asm!("mrs {}, cntfrq_el0", out(reg) value, options(nostack, preserves_flags));
+        }
+        value
+    }
+
+    #[cfg(any(test, not(target_arch = "aarch64")))]
+    fn frequency(&self) -> u64 {
+        0
+    }
+
+    fn set_period(&self, period: u64) -> Result<u64, EfiError> {
+        if period == 0 {
+            self.reload.store(0, Ordering::SeqCst);
+            self.write_control(CNTP_CTL_IMASK);
+            return Ok(0);
+        }
+
+        if self.frequency() == 0 {
+            log::error!("Generic timer frequency (CNTFRQ_EL0) is not programmed.");
+            return Err(EfiError::DeviceError);
+        }
+
+        // CNTP_TVAL_EL0 is a signed 32-bit down counter.
+        let reload = self.period_to_ticks(period).clamp(1, i32::MAX as u64);
+        self.reload.store(reload, Ordering::SeqCst);
+        self.write_timer_value(reload);
+        self.write_control(CNTP_CTL_ENABLE);
+
+        Ok(self.ticks_to_period(reload))
+    }
+
+    fn acknowledge(&self) {
+        match self.reload.load(Ordering::SeqCst) {
+            0 => self.write_control(CNTP_CTL_IMASK),
+            reload => self.write_timer_value(reload),
+        }
+    }
+
+    fn interrupt(&self) -> TimerInterrupt {
+        TimerInterrupt::HardwareInterrupt(Self::INTERRUPT)
+    }
+}
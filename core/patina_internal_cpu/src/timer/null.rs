@@ -0,0 +1,104 @@
+//! Null Timer implementation - For doc tests and host based unit tests
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use patina::{component::service::IntoService, error::EfiError};
+
+use crate::timer::{PERIODS_PER_SECOND, Timer, TimerInterrupt};
+
+/// Struct to implement a Null Timer driven by a fake clock.
+///
+/// This struct cannot be used directly. It replaces the `EfiTimer` struct when not compiling for x86_64 or AArch64
+/// UEFI architectures. The counter only moves when [advance](EfiTimerNull::advance) is called, which allows host
+/// based tests to drive anything built on top of a [Timer] deterministically.
+#[derive(Default, IntoService)]
+#[service(dyn Timer)]
+pub struct EfiTimerNull {
+    counter: AtomicU64,
+    period: AtomicU64,
+    acknowledged: AtomicUsize,
+}
+
+impl EfiTimerNull {
+    /// The frequency of the fake clock. One tick is one 100ns unit.
+    pub const FREQUENCY: u64 = PERIODS_PER_SECOND;
+
+    /// The exception type reported for the (never delivered) periodic interrupt.
+    pub const VECTOR: usize = 0x40;
+
+    /// Creates a new instance of the null implementation of the timer, with the fake clock at zero.
+    pub const fn new() -> Self {
+        Self { counter: AtomicU64::new(0), period: AtomicU64::new(0), acknowledged: AtomicUsize::new(0) }
+    }
+
+    /// Initializes the timer. This does nothing for the null implementation.
+    pub fn initialize(&mut self) -> Result<(), EfiError> {
+        Ok(())
+    }
+
+    /// Advances the fake clock by the given number of ticks.
+    pub fn advance(&self, ticks: u64) {
+        self.counter.fetch_add(ticks, Ordering::SeqCst);
+    }
+
+    /// Returns the currently programmed period in 100ns units.
+    pub fn period(&self) -> u64 {
+        self.period.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of times the timer interrupt has been acknowledged.
+    pub fn acknowledged(&self) -> usize {
+        self.acknowledged.load(Ordering::SeqCst)
+    }
+}
+
+impl Timer for EfiTimerNull {
+    fn counter(&self) -> u64 {
+        self.counter.load(Ordering::SeqCst)
+    }
+
+    fn frequency(&self) -> u64 {
+        Self::FREQUENCY
+    }
+
+    fn set_period(&self, period: u64) -> Result<u64, EfiError> {
+        self.period.store(period, Ordering::SeqCst);
+        Ok(period)
+    }
+
+    fn acknowledge(&self) {
+        self.acknowledged.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn interrupt(&self) -> TimerInterrupt {
+        TimerInterrupt::Exception(Self::VECTOR)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_clock() {
+        let timer = EfiTimerNull::new();
+        assert_eq!(timer.counter(), 0);
+        timer.advance(PERIODS_PER_SECOND);
+        assert_eq!(timer.counter(), PERIODS_PER_SECOND);
+        assert_eq!(timer.ticks_to_period(timer.counter()), PERIODS_PER_SECOND);
+        assert_eq!(timer.period_to_ticks(100), 100);
+
+        assert_eq!(timer.set_period(100_000), Ok(100_000));
+        assert_eq!(timer.period(), 100_000);
+
+        timer.acknowledge();
+        assert_eq!(timer.acknowledged(), 1);
+    }
+}
@@ -0,0 +1,178 @@
+//! X64 Timer implementation
+//!
+//! The free running counter is the TSC and the periodic interrupt is produced by the local APIC timer, which is
+//! calibrated against the TSC the first time it is programmed. Both xAPIC (MMIO) and x2APIC (MSR) modes are supported.
+//! In xAPIC mode the local APIC register page must be mapped as MMIO by the platform.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use mu_rust_helpers::perf_timer::{Arch, ArchFunctionality};
+use patina::{component::service::IntoService, error::EfiError};
+
+use crate::{
+    interrupts::ExceptionType,
+    timer::{PERIODS_PER_SECOND, Timer, TimerInterrupt},
+};
+
+#[cfg(all(not(test), target_arch = "x86_64"))]
+use x86_64::registers::model_specific::Msr;
+
+#[cfg(all(not(test), target_arch = "x86_64"))]
+const IA32_APIC_BASE_MSR: u32 = 0x1B;
+#[cfg(all(not(test), target_arch = "x86_64"))]
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+#[cfg(all(not(test), target_arch = "x86_64"))]
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+#[cfg(all(not(test), target_arch = "x86_64"))]
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+const APIC_EOI: u32 = 0xB0;
+const APIC_LVT_TIMER: u32 = 0x320;
+const APIC_INITIAL_COUNT: u32 = 0x380;
+const APIC_CURRENT_COUNT: u32 = 0x390;
+const APIC_DIVIDE_CONFIG: u32 = 0x3E0;
+
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_PERIODIC: u32 = 1 << 17;
+const DIVIDE_BY_1: u32 = 0b1011;
+
+/// The period, in 100ns units, the local APIC timer is measured against the TSC for during calibration (1ms).
+const CALIBRATION_PERIOD: u64 = 10_000;
+
+/// Struct to implement the X64 Timer.
+///
+/// This struct cannot be used directly. It replaces the `EfiTimer` struct when compiling for the x86_64 architecture.
+#[derive(Default, IntoService)]
+#[service(dyn Timer)]
+pub struct EfiTimerX64 {
+    apic_frequency: AtomicU64,
+}
+
+impl EfiTimerX64 {
+    /// The interrupt vector the local APIC timer is delivered on.
+    pub const VECTOR: ExceptionType = 0x40;
+
+    /// Creates a new instance of the x64 implementation of the timer.
+    pub const fn new() -> Self {
+        Self { apic_frequency: AtomicU64::new(0) }
+    }
+
+    /// Initializes the timer, making sure the local APIC timer is stopped until a period is programmed.
+    pub fn initialize(&mut self) -> Result<(), EfiError> {
+        self.write_register(APIC_LVT_TIMER, LVT_MASKED | Self::VECTOR as u32);
+        self.write_register(APIC_INITIAL_COUNT, 0);
+        Ok(())
+    }
+
+    /// Returns the frequency of the local APIC timer in Hz, calibrating it against the TSC on first use.
+    fn apic_frequency(&self) -> u64 {
+        let cached = self.apic_frequency.load(Ordering::Relaxed);
+        if cached != 0 {
+            return cached;
+        }
+
+        // Run the APIC timer one-shot, masked, from its maximum count while the TSC measures the calibration period.
+        self.write_register(APIC_DIVIDE_CONFIG, DIVIDE_BY_1);
+        self.write_register(APIC_LVT_TIMER, LVT_MASKED | Self::VECTOR as u32);
+        self.write_register(APIC_INITIAL_COUNT, u32::MAX);
+
+        let start = self.counter();
+        let wait = self.period_to_ticks(CALIBRATION_PERIOD);
+        while self.counter().wrapping_sub(start) < wait {
+            core::hint::spin_loop();
+        }
+        let apic_ticks = u32::MAX - self.read_register(APIC_CURRENT_COUNT);
+        let tsc_ticks = self.counter().wrapping_sub(start);
+        self.write_register(APIC_INITIAL_COUNT, 0);
+
+        if tsc_ticks == 0 {
+            return 0;
+        }
+        let frequency = (apic_ticks as u128 * self.frequency() as u128 / tsc_ticks as u128) as u64;
+        log::info!("Local APIC timer calibrated to {frequency} Hz.");
+        self.apic_frequency.store(frequency, Ordering::Relaxed);
+        frequency
+    }
+
+    #[cfg(all(not(test), target_arch = "x86_64"))]
+    fn read_register(&self, offset: u32) -> u32 {
+        // SAFETY: Reading IA32_APIC_BASE has no side effects and is architecturally defined on all x64 processors.
+        let apic_base = unsafe { Msr::new(IA32_APIC_BASE_MSR).read() };
+        if apic_base & APIC_BASE_X2APIC_ENABLE != 0 {
+            // SAFETY: In x2APIC mode every local APIC register is accessible at a fixed MSR offset.
+            unsafe { Msr::new(X2APIC_MSR_BASE + (offset >> 4)).read() as u32 }
+        } else {
+            let register = ((apic_base & APIC_BASE_ADDRESS_MASK) + offset as u64) as *const u32;
+            // SAFETY: In xAPIC mode the local APIC registers are memory mapped at the APIC base address.
+            unsafe { register.read_volatile() }
+        }
+    }
+
+    #[cfg(any(test, not(target_arch = "x86_64")))]
+    fn read_register(&self, _offset: u32) -> u32 {
+        0
+    }
+
+    #[cfg(all(not(test), target_arch = "x86_64"))]
+    fn write_register(&self, offset: u32, value: u32) {
+        // SAFETY: Reading IA32_APIC_BASE has no side effects and is architecturally defined on all x64 processors.
+        let apic_base = unsafe { Msr::new(IA32_APIC_BASE_MSR).read() };
+        if apic_base & APIC_BASE_X2APIC_ENABLE != 0 {
+            // SAFETY: In x2APIC mode every local APIC register is accessible at a fixed MSR offset.
+            unsafe { Msr::new(X2APIC_MSR_BASE + (offset >> 4)).write(value as u64) }
+        } else {
+            let register = ((apic_base & APIC_BASE_ADDRESS_MASK) + offset as u64) as *mut u32;
+            // SAFETY: In xAPIC mode the local APIC registers are memory mapped at the APIC base address.
+            unsafe { register.write_volatile(value) }
+        }
+    }
+
+    #[cfg(any(test, not(target_arch = "x86_64")))]
+    fn write_register(&self, _offset: u32, _value: u32) {}
+}
+
+impl Timer for EfiTimerX64 {
+    fn counter(&self) -> u64 {
+        Arch::cpu_count()
+    }
+
+    fn frequency(&self) -> u64 {
+        Arch::perf_frequency()
+    }
+
+    fn set_period(&self, period: u64) -> Result<u64, EfiError> {
+        if period == 0 {
+            self.write_register(APIC_LVT_TIMER, LVT_MASKED | Self::VECTOR as u32);
+            self.write_register(APIC_INITIAL_COUNT, 0);
+            return Ok(0);
+        }
+
+        let apic_frequency = self.apic_frequency();
+        if apic_frequency == 0 {
+            log::error!("Local APIC timer could not be calibrated.");
+            return Err(EfiError::DeviceError);
+        }
+
+        let count = (period as u128 * apic_frequency as u128 / PERIODS_PER_SECOND as u128).clamp(1, u32::MAX as u128);
+
+        self.write_register(APIC_DIVIDE_CONFIG, DIVIDE_BY_1);
+        self.write_register(APIC_LVT_TIMER, LVT_PERIODIC | Self::VECTOR as u32);
+        self.write_register(APIC_INITIAL_COUNT, count as u32);
+
+        Ok((count * PERIODS_PER_SECOND as u128 / apic_frequency as u128) as u64)
+    }
+
+    fn acknowledge(&self) {
+        self.write_register(APIC_EOI, 0);
+    }
+
+    fn interrupt(&self) -> TimerInterrupt {
+        TimerInterrupt::Exception(Self::VECTOR)
+    }
+}
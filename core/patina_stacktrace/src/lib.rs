@@ -106,6 +106,9 @@
 //!
 //!     // Inside rust panic handler and drivers
 //!     StackTrace::dump();
+//!
+//!     // To record the frames instead of logging them
+//!     StackTrace::walk_with(rip, rsp, |frame| frames.push(*frame));
 //! ```
 //!
 //! ## Reference
@@ -130,4 +133,4 @@ cfg_if::cfg_if! {
     }
 }
 
-pub use stacktrace::StackTrace;
+pub use stacktrace::{StackFrame, StackTrace};
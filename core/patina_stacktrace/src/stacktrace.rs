@@ -15,6 +15,23 @@ cfg_if::cfg_if! {
 /// A structure representing a stack trace.
 pub struct StackTrace;
 
+/// A single frame visited by [StackTrace::walk_with].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackFrame {
+    /// The program counter of the frame.
+    pub pc: u64,
+    /// The stack pointer of the frame (Child-SP).
+    pub sp: u64,
+    /// The address the frame returns to.
+    pub return_address: u64,
+    /// The name of the image containing `pc`, if it could be determined.
+    pub image_name: Option<&'static str>,
+    /// The base address of the image containing `pc`.
+    pub image_base: u64,
+    /// The offset of `pc` from `image_base`.
+    pub pc_rva: u64,
+}
+
 impl StackTrace {
     /// Dumps the stack trace for the given PC and SP values.
     ///
@@ -36,20 +53,43 @@ impl StackTrace {
     /// 7 0000005E2AEFFD50      0000000000000000       ntdll+75AEC
     /// ```
     pub unsafe fn dump_with(pc: u64, sp: u64) -> StResult<()> {
-        let mut pc = pc;
-        let mut sp = sp;
-        let mut i = 0;
-
         log::info!("Dumping stack trace with PC: {pc:#x}, SP: {sp:#x}");
 
         log::info!("      # Child-SP              Return Address         Call Site");
 
-        loop {
-            let no_name = "<no module>";
+        let mut i = 0;
+        unsafe {
+            StackTrace::walk_with(pc, sp, |frame| {
+                let image_name = frame.image_name.unwrap_or("<no module>");
+                log::info!(
+                    "      {i} {:016X}      {:016X}       {image_name}+{:X}",
+                    frame.sp,
+                    frame.return_address,
+                    frame.pc_rva
+                );
+                i += 1;
+            })
+        }
+    }
 
-            let image = unsafe { PE::locate_image(pc) }?;
+    /// Walks the stack for the given PC and SP values, calling `f` for each
+    /// frame, innermost first.
+    ///
+    /// Frames visited before an error is returned have already been passed to
+    /// `f`, so callers can keep a partial stack trace.
+    ///
+    /// # Safety
+    ///
+    /// This function is marked `unsafe` to indicate that the caller is
+    /// responsible for validating the provided PC and SP values. Invalid values
+    /// can result in undefined behavior, including potential page faults.
+    pub unsafe fn walk_with<F: FnMut(&StackFrame)>(pc: u64, sp: u64, mut f: F) -> StResult<()> {
+        let mut pc = pc;
+        let mut sp = sp;
+        let mut i = 0;
 
-            let image_name = image.image_name.unwrap_or(no_name);
+        loop {
+            let image = unsafe { PE::locate_image(pc) }?;
 
             let pc_rva = pc - image.base_address;
 
@@ -57,7 +97,14 @@ impl StackTrace {
             let unwind_info = runtime_function.get_unwind_info()?;
             let (curr_sp, _curr_pc, prev_sp, prev_pc) = unwind_info.get_current_stack_frame(sp, pc)?;
 
-            log::info!("      {i} {curr_sp:016X}      {prev_pc:016X}       {image_name}+{pc_rva:X}");
+            f(&StackFrame {
+                pc,
+                sp: curr_sp,
+                return_address: prev_pc,
+                image_name: image.image_name,
+                image_base: image.base_address,
+                pc_rva,
+            });
 
             sp = prev_sp;
             pc = prev_pc;
@@ -20,6 +20,7 @@ use core::{
     ops::Range,
     ptr::NonNull,
     slice::{self, from_raw_parts_mut},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 extern crate alloc;
@@ -28,8 +29,11 @@ use mu_rust_helpers::function;
 
 use crate::{
     GCD, config_tables,
+    ebs_diagnostics::EBS_DIAGNOSTICS,
+    ensure, error,
     gcd::{self, AllocateType as AllocationStrategy},
     memory_attributes_table::MemoryAttributesTable,
+    protocol_audit::PROTOCOL_AUDIT,
     protocol_db::{self, INVALID_HANDLE},
     protocols::PROTOCOL_DB,
     systemtables::EfiSystemTable,
@@ -43,10 +47,11 @@ use r_efi::{efi, system::TPL_HIGH_LEVEL};
 pub use uefi_allocator::UefiAllocator;
 
 use patina::{
-    base::{SIZE_4KB, UEFI_PAGE_MASK, UEFI_PAGE_SIZE},
+    base::{SIZE_4KB, UEFI_PAGE_MASK, UEFI_PAGE_SIZE, align_up},
     error::EfiError,
     guids::{self, HOB_MEMORY_ALLOC_STACK},
-    uefi_size_to_pages,
+    runtime_services::RuntimeServices,
+    uefi_pages_to_size, uefi_size_to_pages,
 };
 
 // Allocation Strategy when not specified by caller.
@@ -57,6 +62,23 @@ pub const DEFAULT_ALLOCATION_STRATEGY: AllocationStrategy = AllocationStrategy::
 const PRIVATE_ALLOCATOR_TRACKING_GUID: efi::Guid =
     efi::Guid::from_fields(0x9d1fa6e9, 0x0c86, 0x4f7f, 0xa9, 0x9b, &[0xdd, 0x22, 0x9c, 0x9b, 0x38, 0x93]);
 
+// Memory types that are confined to address-stable bins when the stable runtime memory map is enabled, in the order
+// the bins are laid out in memory.
+const STABLE_RUNTIME_MEMORY_TYPES: [efi::MemoryType; 3] =
+    [efi::RUNTIME_SERVICES_CODE, efi::RUNTIME_SERVICES_DATA, efi::ACPI_MEMORY_NVS];
+
+// Variable holding the runtime memory layout of the previous boot, used to detect layout changes across boots.
+// {B1A9E4D2-6C3F-4F0B-9E57-2A8D4C61F3B7}
+const RUNTIME_MEMORY_LAYOUT_VARIABLE_GUID: efi::Guid =
+    efi::Guid::from_fields(0xb1a9e4d2, 0x6c3f, 0x4f0b, 0x9e, 0x57, &[0x2a, 0x8d, 0x4c, 0x61, 0xf3, 0xb7]);
+// L"RuntimeMemoryLayout"
+const RUNTIME_MEMORY_LAYOUT_VARIABLE_NAME: &[u16] = &[
+    0x52, 0x75, 0x6e, 0x74, 0x69, 0x6d, 0x65, 0x4d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x4c, 0x61, 0x79, 0x6f, 0x75, 0x74,
+    0x00,
+];
+
+static STABLE_RUNTIME_MEMORY_MAP: AtomicBool = AtomicBool::new(false);
+
 pub(crate) const DEFAULT_PAGE_ALLOCATION_GRANULARITY: usize = SIZE_4KB;
 
 // Per the UEFI spec, AARCH64 runtime pages need to be allocated on 64KB boundaries in units of 64KB to accommodate
@@ -260,6 +282,15 @@ pub(crate) fn get_memory_ranges_for_memory_type(memory_type: efi::MemoryType) ->
     Vec::new()
 }
 
+/// Returns the number of pool allocations across all allocators that have not been freed.
+pub(crate) fn outstanding_pool_allocations() -> usize {
+    ALLOCATORS
+        .lock()
+        .iter()
+        .map(|allocator| allocator.stats())
+        .fold(0, |count, stats| count.wrapping_add(stats.pool_allocation_calls).wrapping_sub(stats.pool_free_calls))
+}
+
 // The following structure is used to track additional allocators that are created in response to allocation requests
 // that are not satisfied by the static allocators.
 static ALLOCATORS: tpl_lock::TplMutex<AllocatorMap> = AllocatorMap::new();
@@ -441,6 +472,10 @@ pub fn core_free_pool(buffer: *mut c_void) -> Result<(), EfiError> {
     if buffer.is_null() {
         return Err(EfiError::InvalidParameter);
     }
+    // Any page of the buffer may hold a poisoned protocol interface, so restore all of them before the pool reuses it.
+    // SAFETY: The caller guarantees that `buffer` was allocated by `core_allocate_pool`, as `free_pool` below does.
+    let size = unsafe { UefiAllocator::pool_size(buffer) }.unwrap_or(1);
+    PROTOCOL_AUDIT.unpoison_range(buffer as u64, size as u64);
     let allocators = ALLOCATORS.lock();
     unsafe {
         if allocators.iter().any(|allocator| allocator.free_pool(buffer).is_ok()) {
@@ -550,6 +585,7 @@ pub fn core_free_pages(memory: efi::PhysicalAddress, pages: usize) -> Result<(),
         return Err(EfiError::InvalidParameter);
     }
 
+    PROTOCOL_AUDIT.unpoison_range(memory, size as u64);
     let allocators = ALLOCATORS.lock();
 
     let mut memory_type = efi::CONVENTIONAL_MEMORY;
@@ -640,85 +676,92 @@ pub(crate) fn get_memory_map_descriptors(active_attributes: bool) -> Result<Vec<
 
     Ok(descriptors
         .iter()
-        .filter_map(|descriptor| {
-            let memory_type = ALLOCATORS.lock().memory_type_for_handle(descriptor.image_handle).or({
-                match descriptor.memory_type {
-                    // free memory not tracked by any allocator.
-                    GcdMemoryType::SystemMemory => Some(efi::CONVENTIONAL_MEMORY),
-
-                    // MMIO. Note: there could also be MMIO tracked by the allocators which would not hit this case.
-                    GcdMemoryType::MemoryMappedIo => {
-                        // we should only be returning runtime MMIO here
-                        if descriptor.attributes & efi::MEMORY_RUNTIME == 0 {
-                            return None;
-                        }
-
-                        Some(efi::MEMORY_MAPPED_IO)
-                    }
+        .filter_map(|descriptor| memory_map_descriptor(descriptor, active_attributes))
+        .fold(merged_descriptors, merge_blocks))
+}
 
-                    // Persistent. Note: this type is not allocatable, but might be created by agents other than the core directly
-                    // in the GCD.
-                    GcdMemoryType::Persistent => Some(efi::PERSISTENT_MEMORY),
+/// Converts a GCD descriptor to the memory map descriptor reporting it, or `None` if it is not reported in the memory
+/// map. See [get_memory_map_descriptors] for `active_attributes`. Does not allocate.
+fn memory_map_descriptor(descriptor: &MemorySpaceDescriptor, active_attributes: bool) -> Option<efi::MemoryDescriptor> {
+    let memory_type = ALLOCATORS.lock().memory_type_for_handle(descriptor.image_handle).or({
+        match descriptor.memory_type {
+            // free memory not tracked by any allocator.
+            GcdMemoryType::SystemMemory => Some(efi::CONVENTIONAL_MEMORY),
+
+            // MMIO. Note: there could also be MMIO tracked by the allocators which would not hit this case.
+            GcdMemoryType::MemoryMappedIo => {
+                // we should only be returning runtime MMIO here
+                if descriptor.attributes & efi::MEMORY_RUNTIME == 0 {
+                    return None;
+                }
 
-                    // Unaccepted. Note: this type is not allocatable, but might be created by agents other than the core directly
-                    // in the GCD.
-                    GcdMemoryType::Unaccepted => Some(efi::UNACCEPTED_MEMORY_TYPE),
+                Some(efi::MEMORY_MAPPED_IO)
+            }
 
-                    // Reserved.
-                    GcdMemoryType::Reserved => Some(efi::RESERVED_MEMORY_TYPE),
+            // Persistent. Note: this type is not allocatable, but might be created by agents other than the core directly
+            // in the GCD.
+            GcdMemoryType::Persistent => Some(efi::PERSISTENT_MEMORY),
 
-                    // Other memory types are ignored for purposes of the memory map
-                    _ => None,
-                }
-            })?;
+            // Unaccepted. Note: this type is not allocatable, but might be created by agents other than the core directly
+            // in the GCD.
+            GcdMemoryType::Unaccepted => Some(efi::UNACCEPTED_MEMORY_TYPE),
 
-            let number_of_pages = uefi_size_to_pages!(descriptor.length as usize) as u64;
-            if number_of_pages == 0 {
-                debug_assert!(false, "GCD returned a memory descriptor smaller than a page.");
-                return None; //skip entries for things smaller than a page
-            }
-            if (descriptor.base_address % UEFI_PAGE_SIZE as u64) != 0 {
-                debug_assert!(false, "GCD returned a non-page-aligned memory descriptor.");
-                return None; //skip entries not page aligned.
-            }
+            // Reserved.
+            GcdMemoryType::Reserved => Some(efi::RESERVED_MEMORY_TYPE),
 
-            let mut attributes = match active_attributes {
-                true => descriptor.attributes,
-                false => {
-                    // when we are building the EFI memory map, follow edk2 conventions as OSes will expect that.
-                    // When using the capabilities, drop the runtime attribute and
-                    // pick it up from the active attributes. We also drop the access attributes because
-                    // some OSes think the EFI_MEMORY_MAP attribute field is actually set attributes, not
-                    // capabilities.
-                    match descriptor.memory_type {
-                        GcdMemoryType::Persistent => {
-                            (descriptor.capabilities & !(efi::MEMORY_ACCESS_MASK | efi::MEMORY_RUNTIME))
-                                | (descriptor.attributes & efi::MEMORY_RUNTIME)
-                                | efi::MEMORY_NV
-                        }
-                        _ => {
-                            (descriptor.capabilities & !(efi::MEMORY_ACCESS_MASK | efi::MEMORY_RUNTIME))
-                                | (descriptor.attributes & efi::MEMORY_RUNTIME)
-                        }
-                    }
+            // Other memory types are ignored for purposes of the memory map
+            _ => None,
+        }
+    })?;
+
+    let number_of_pages = uefi_size_to_pages!(descriptor.length as usize) as u64;
+    if number_of_pages == 0 {
+        debug_assert!(false, "GCD returned a memory descriptor smaller than a page.");
+        return None; //skip entries for things smaller than a page
+    }
+    if !descriptor.base_address.is_multiple_of(UEFI_PAGE_SIZE as u64) {
+        debug_assert!(false, "GCD returned a non-page-aligned memory descriptor.");
+        return None; //skip entries not page aligned.
+    }
+
+    let mut attributes = match active_attributes {
+        true => descriptor.attributes,
+        false => {
+            // when we are building the EFI memory map, follow edk2 conventions as OSes will expect that.
+            // When using the capabilities, drop the runtime attribute and
+            // pick it up from the active attributes. We also drop the access attributes because
+            // some OSes think the EFI_MEMORY_MAP attribute field is actually set attributes, not
+            // capabilities. The GCD presence capabilities of non-system memory are not UEFI memory
+            // attributes, so they are dropped as well. Specific-purpose (EFI_MEMORY_SP) and non-volatile
+            // (EFI_MEMORY_NV) capabilities are preserved.
+            let capabilities = descriptor.capabilities
+                & !(efi::MEMORY_ACCESS_MASK
+                    | efi::MEMORY_RUNTIME
+                    | hob::EFI_MEMORY_PRESENT
+                    | hob::EFI_MEMORY_INITIALIZED
+                    | hob::EFI_MEMORY_TESTED);
+            match descriptor.memory_type {
+                GcdMemoryType::Persistent => {
+                    capabilities | (descriptor.attributes & efi::MEMORY_RUNTIME) | efi::MEMORY_NV
                 }
-            };
-
-            if matches!(memory_type, efi::RUNTIME_SERVICES_CODE | efi::RUNTIME_SERVICES_DATA) {
-                // Add the runtime attribute for runtime services code and data as
-                // higher level code will expect this but it is not explicitly tracked.
-                attributes |= efi::MEMORY_RUNTIME;
+                _ => capabilities | (descriptor.attributes & efi::MEMORY_RUNTIME),
             }
+        }
+    };
 
-            Some(efi::MemoryDescriptor {
-                r#type: memory_type,
-                physical_start: descriptor.base_address,
-                virtual_start: 0,
-                number_of_pages,
-                attribute: attributes,
-            })
-        })
-        .fold(merged_descriptors, merge_blocks))
+    if matches!(memory_type, efi::RUNTIME_SERVICES_CODE | efi::RUNTIME_SERVICES_DATA) {
+        // Add the runtime attribute for runtime services code and data as
+        // higher level code will expect this but it is not explicitly tracked.
+        attributes |= efi::MEMORY_RUNTIME;
+    }
+
+    Some(efi::MemoryDescriptor {
+        r#type: memory_type,
+        physical_start: descriptor.base_address,
+        virtual_start: 0,
+        number_of_pages,
+        attribute: attributes,
+    })
 }
 
 extern "efiapi" fn get_memory_map(
@@ -776,7 +819,9 @@ extern "efiapi" fn get_memory_map(
 
         if !map_key.is_null() {
             let memory_map_as_bytes = slice::from_raw_parts(memory_map as *mut u8, required_map_size);
-            map_key.write_unaligned(crc32fast::hash(memory_map_as_bytes) as usize);
+            let key = crc32fast::hash(memory_map_as_bytes) as usize;
+            map_key.write_unaligned(key);
+            EBS_DIAGNOSTICS.record_memory_map_key(key);
         }
     }
 
@@ -1015,22 +1060,22 @@ fn process_hob_allocations(hob_list: &HobList) {
                             debug_assert!(false);
                         }
                     }
-                    // Set Guard page to read protect.
-                    match GCD.set_memory_space_attributes(
+                    // Set Guard page to read protect, unless the platform has opted out of stack guards.
+                    if !GCD.memory_protection_policy().stack_guard() {
+                        log::info!("Stack guard page disabled by the memory protection policy.");
+                    } else if let Err(e) = GCD.set_memory_space_attributes(
                         stack_address as usize,
                         UEFI_PAGE_SIZE,
                         attributes | efi::MEMORY_RP,
-                    ) {
-                        Ok(_) | Err(EfiError::NotReady) => (),
-                        Err(e) => {
-                            log::error!(
-                                "Could not set RP for memory address {:#X} for len {:#X} with error {:?}",
-                                stack_address,
-                                UEFI_PAGE_SIZE,
-                                e
-                            );
-                            debug_assert!(false);
-                        }
+                    ) && e != EfiError::NotReady
+                    {
+                        log::error!(
+                            "Could not set RP for memory address {:#X} for len {:#X} with error {:?}",
+                            stack_address,
+                            UEFI_PAGE_SIZE,
+                            e
+                        );
+                        debug_assert!(false);
                     }
                 }
                 Err(_) => {
@@ -1047,6 +1092,11 @@ fn process_hob_allocations(hob_list: &HobList) {
     // EFI_MEMORY_MAP reports as EfiConventionalMemory), which will cause a failure that is unnecessary. We do this
     // after HOB processing because we want to ensure that the GCD is fully populated with the memory map
     // before we allocate page 0, as it may not live in system memory, in which case we cannot allocate it.
+    if !GCD.memory_protection_policy().null_pointer_detection() {
+        log::info!("Null pointer detection disabled by the memory protection policy, page 0 will not be allocated.");
+        return;
+    }
+
     match GCD.get_memory_descriptor_for_address(0) {
         Ok(desc) if desc.memory_type == GcdMemoryType::SystemMemory => {
             let mut address: efi::PhysicalAddress = 0;
@@ -1077,6 +1127,78 @@ fn process_hob_allocations(hob_list: &HobList) {
     }
 }
 
+/// Enables or disables the stable runtime memory map.
+///
+/// When enabled, [`init_memory_support`] places the Runtime Services Code, Runtime Services Data and ACPI NVS buckets
+/// from the MEMORY_TYPE_INFO HOB in a single contiguous region and confines those allocators to their buckets, and
+/// [`record_runtime_memory_layout`] and [`check_runtime_memory_layout`] compare the resulting layout against the
+/// previous boot at ExitBootServices.
+///
+/// Must be set before [`init_memory_support`] is called.
+pub(crate) fn set_stable_runtime_memory_map(enabled: bool) {
+    STABLE_RUNTIME_MEMORY_MAP.store(enabled, Ordering::SeqCst);
+}
+
+/// Indicates whether the stable runtime memory map is enabled.
+pub(crate) fn stable_runtime_memory_map() -> bool {
+    STABLE_RUNTIME_MEMORY_MAP.load(Ordering::SeqCst)
+}
+
+// Reserves the buckets for the stable runtime memory types back to back in one region of memory and confines the
+// corresponding allocators to them. The region is allocated before any other bucket, so given the same memory
+// resources and MEMORY_TYPE_INFO HOB, it lands at the same address every boot and the runtime memory is reported to the
+// OS as a single span rather than interleaved with boot services memory.
+fn reserve_stable_runtime_buckets(memory_type_info: &[EFiMemoryTypeInformation]) -> Result<(), EfiError> {
+    let granularity_pages = uefi_size_to_pages!(RUNTIME_PAGE_ALLOCATION_GRANULARITY);
+
+    let mut buckets = Vec::with_capacity(STABLE_RUNTIME_MEMORY_TYPES.len());
+    for memory_type in STABLE_RUNTIME_MEMORY_TYPES {
+        let pages = memory_type_info
+            .iter()
+            .find(|bucket| bucket.memory_type == memory_type)
+            .map_or(0, |bucket| bucket.number_of_pages as usize);
+        if pages == 0 {
+            log::warn!(
+                "No memory bucket for memory type {memory_type:#x?}, its allocations will not be address-stable across boots."
+            );
+            continue;
+        }
+        let handle = AllocatorMap::handle_for_memory_type(memory_type)?;
+        let allocator = ALLOCATORS.lock().get_or_create_allocator(memory_type, handle)?;
+        buckets.push((allocator, align_up(pages, granularity_pages)?));
+    }
+
+    if buckets.is_empty() {
+        return Ok(());
+    }
+
+    let total_len = buckets.iter().map(|(_, pages)| uefi_pages_to_size!(*pages)).sum();
+    let base_address = GCD.allocate_memory_space(
+        DEFAULT_ALLOCATION_STRATEGY,
+        GcdMemoryType::SystemMemory,
+        RUNTIME_PAGE_ALLOCATION_GRANULARITY.trailing_zeros() as usize,
+        total_len,
+        buckets[0].0.handle(),
+        None,
+    )?;
+    GCD.free_memory_space(base_address, total_len)?;
+
+    let mut address = base_address;
+    for (allocator, pages) in buckets {
+        log::info!(
+            "Reserving stable memory bucket for memory type: {:#x?} at {:#x?}, {:#x?} pages.",
+            allocator.memory_type(),
+            address,
+            pages
+        );
+        allocator.reserve_memory_pages_at(address, pages)?;
+        allocator.confine_to_reserved_range()?;
+        address += uefi_pages_to_size!(pages);
+    }
+
+    Ok(())
+}
+
 /// Initializes memory support
 ///
 /// This routine sets the boot services routines for memory allocation and does initial configuration of the allocators.
@@ -1116,8 +1238,16 @@ pub fn init_memory_support(hob_list: &HobList) {
             _ => None,
         }
     }) {
+        let stable_runtime_memory_map = stable_runtime_memory_map();
+        if stable_runtime_memory_map && let Err(err) = reserve_stable_runtime_buckets(memory_type_info) {
+            log::error!("failed to reserve stable runtime memory buckets: {err:#x?}");
+            debug_assert!(false);
+        }
+
         for bucket in memory_type_info {
-            if bucket.number_of_pages == 0 {
+            if bucket.number_of_pages == 0
+                || (stable_runtime_memory_map && STABLE_RUNTIME_MEMORY_TYPES.contains(&bucket.memory_type))
+            {
                 continue;
             }
             log::info!(
@@ -1146,6 +1276,254 @@ pub fn init_memory_support(hob_list: &HobList) {
                 }
             }
         }
+    } else if stable_runtime_memory_map() {
+        log::warn!(
+            "Stable runtime memory map enabled, but no memory type info HOB was found. Runtime memory is unbinned."
+        );
+    }
+}
+
+/// A runtime memory map entry as recorded in the runtime memory layout variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RuntimeMemoryRegion {
+    pub memory_type: efi::MemoryType,
+    pub physical_start: efi::PhysicalAddress,
+    pub number_of_pages: u64,
+}
+
+impl RuntimeMemoryRegion {
+    // Serialized size: memory type (u32), reserved (u32), physical start (u64), number of pages (u64).
+    const SERIALIZED_SIZE: usize = 24;
+
+    fn encode(regions: &[Self]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(regions.len() * Self::SERIALIZED_SIZE);
+        for region in regions {
+            data.extend_from_slice(&region.memory_type.to_le_bytes());
+            data.extend_from_slice(&0u32.to_le_bytes());
+            data.extend_from_slice(&region.physical_start.to_le_bytes());
+            data.extend_from_slice(&region.number_of_pages.to_le_bytes());
+        }
+        data
+    }
+
+    fn decode(data: &[u8]) -> Option<Vec<Self>> {
+        if !data.len().is_multiple_of(Self::SERIALIZED_SIZE) {
+            return None;
+        }
+        data.chunks_exact(Self::SERIALIZED_SIZE)
+            .map(|chunk| {
+                Some(Self {
+                    memory_type: u32::from_le_bytes(chunk[0..4].try_into().ok()?),
+                    physical_start: u64::from_le_bytes(chunk[8..16].try_into().ok()?),
+                    number_of_pages: u64::from_le_bytes(chunk[16..24].try_into().ok()?),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Result of comparing the runtime memory layout against the one recorded by the previous boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RuntimeMemoryLayoutCheck {
+    /// The layout matches the previous boot.
+    Unchanged,
+    /// No previous layout was recorded.
+    Recorded,
+    /// The layout differs from the previous boot, or changed after it was recorded for the next boot.
+    Changed,
+    /// The layout could not be read, recorded or compared, e.g. because variable services are not available.
+    Unavailable,
+}
+
+// The number of GCD descriptors and runtime regions the layout may grow by between ReadyToBoot and ExitBootServices
+// before it can no longer be compared without allocating.
+const RUNTIME_MEMORY_LAYOUT_SLACK: usize = 256;
+
+/// The state needed to compare the runtime memory layout at ExitBootServices without allocating, captured at
+/// ReadyToBoot by [record_runtime_memory_layout].
+struct RuntimeMemoryLayoutSnapshot {
+    /// The layout recorded by the previous boot, if any.
+    previous: Option<Vec<RuntimeMemoryRegion>>,
+    /// The layout recorded for the next boot.
+    recorded: Vec<RuntimeMemoryRegion>,
+    /// Preallocated buffer for the GCD descriptors.
+    descriptors: Vec<MemorySpaceDescriptor>,
+    /// Preallocated buffer for the layout at ExitBootServices.
+    current: Vec<RuntimeMemoryRegion>,
+}
+
+// Safety: the descriptors are only buffers for GCD output; their handles are never dereferenced.
+unsafe impl Send for RuntimeMemoryLayoutSnapshot {}
+
+static RUNTIME_MEMORY_LAYOUT_SNAPSHOT: tpl_lock::TplMutex<Option<RuntimeMemoryLayoutSnapshot>> =
+    tpl_lock::TplMutex::new(TPL_HIGH_LEVEL, None, "RuntimeMemoryLayoutLock");
+
+/// Returns the memory map entries of the memory types kept stable by the stable runtime memory map.
+pub(crate) fn runtime_memory_layout() -> Result<Vec<RuntimeMemoryRegion>, EfiError> {
+    let mut descriptors = Vec::with_capacity(GCD.memory_descriptor_count() + 10);
+    let mut layout = Vec::with_capacity(GCD.memory_descriptor_count() + 10);
+    runtime_memory_layout_into(&mut descriptors, &mut layout)?;
+    Ok(layout)
+}
+
+/// Builds the runtime memory layout into `layout`, using `descriptors` as scratch space for the GCD descriptors.
+///
+/// Does not allocate: fails with [EfiError::BufferTooSmall] if either buffer lacks the capacity.
+fn runtime_memory_layout_into(
+    descriptors: &mut Vec<MemorySpaceDescriptor>,
+    layout: &mut Vec<RuntimeMemoryRegion>,
+) -> Result<(), EfiError> {
+    descriptors.clear();
+    layout.clear();
+    ensure!(descriptors.capacity() >= GCD.memory_descriptor_count(), EfiError::BufferTooSmall);
+    GCD.get_memory_descriptors(descriptors)?;
+
+    // Merged the same way as get_memory_map_descriptors, so that the layout matches the memory map.
+    let mut last: Option<efi::MemoryDescriptor> = None;
+    for descriptor in descriptors.iter().filter_map(|descriptor| memory_map_descriptor(descriptor, false)) {
+        let merged = match last.as_mut() {
+            Some(last)
+                if last.r#type == descriptor.r#type
+                    && last.attribute == descriptor.attribute
+                    && last.physical_start + last.number_of_pages * UEFI_PAGE_SIZE as u64
+                        == descriptor.physical_start =>
+            {
+                last.number_of_pages += descriptor.number_of_pages;
+                true
+            }
+            _ => {
+                last = Some(descriptor);
+                false
+            }
+        };
+
+        if !STABLE_RUNTIME_MEMORY_TYPES.contains(&descriptor.r#type) {
+            continue;
+        }
+        match layout.last_mut() {
+            // the descriptor was merged into the last memory map entry, which is the last region of the layout.
+            Some(region) if merged => region.number_of_pages += descriptor.number_of_pages,
+            _ => {
+                ensure!(layout.len() < layout.capacity(), EfiError::BufferTooSmall);
+                layout.push(RuntimeMemoryRegion {
+                    memory_type: descriptor.r#type,
+                    physical_start: descriptor.physical_start,
+                    number_of_pages: descriptor.number_of_pages,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the runtime memory layout recorded by the previous boot, records the current layout for the next boot, and
+/// prepares the comparison made by [check_runtime_memory_layout] at ExitBootServices.
+///
+/// Reading and recording the layout goes through GetVariable and SetVariable, which may change the memory map, so this
+/// is called at ReadyToBoot rather than from ExitBootServices. Runtime memory allocated or freed afterwards is not part
+/// of the recorded layout, and is reported by [check_runtime_memory_layout].
+pub(crate) fn record_runtime_memory_layout(runtime_services: &impl RuntimeServices) -> RuntimeMemoryLayoutCheck {
+    let current = match runtime_memory_layout() {
+        Ok(layout) => layout,
+        Err(err) => {
+            log::error!("Failed to build the runtime memory layout: {err:?}");
+            return RuntimeMemoryLayoutCheck::Unavailable;
+        }
+    };
+
+    let previous = match runtime_services.get_variable::<Vec<u8>>(
+        RUNTIME_MEMORY_LAYOUT_VARIABLE_NAME,
+        &RUNTIME_MEMORY_LAYOUT_VARIABLE_GUID,
+        Some(current.len() * RuntimeMemoryRegion::SERIALIZED_SIZE),
+    ) {
+        Ok((data, _)) => match RuntimeMemoryRegion::decode(&data) {
+            Some(previous) => Some(previous),
+            None => {
+                log::error!("Recorded runtime memory layout is malformed ({:#x} bytes), replacing it.", data.len());
+                Some(Vec::new())
+            }
+        },
+        Err(efi::Status::NOT_FOUND) => {
+            log::info!("No runtime memory layout recorded by a previous boot.");
+            None
+        }
+        Err(status) => {
+            log::warn!("Unable to read the recorded runtime memory layout: {status:?}");
+            return RuntimeMemoryLayoutCheck::Unavailable;
+        }
+    };
+
+    if previous.as_ref() != Some(&current)
+        && let Err(status) = runtime_services.set_variable(
+            RUNTIME_MEMORY_LAYOUT_VARIABLE_NAME,
+            &RUNTIME_MEMORY_LAYOUT_VARIABLE_GUID,
+            efi::VARIABLE_NON_VOLATILE | efi::VARIABLE_BOOTSERVICE_ACCESS,
+            &RuntimeMemoryRegion::encode(&current),
+        )
+    {
+        log::error!("Failed to record the runtime memory layout: {status:?}");
+        return RuntimeMemoryLayoutCheck::Unavailable;
+    }
+
+    let result = match &previous {
+        Some(previous) if *previous == current => RuntimeMemoryLayoutCheck::Unchanged,
+        Some(_) => RuntimeMemoryLayoutCheck::Changed,
+        None => RuntimeMemoryLayoutCheck::Recorded,
+    };
+    let capacity = GCD.memory_descriptor_count() + RUNTIME_MEMORY_LAYOUT_SLACK;
+    *RUNTIME_MEMORY_LAYOUT_SNAPSHOT.lock() = Some(RuntimeMemoryLayoutSnapshot {
+        previous,
+        descriptors: Vec::with_capacity(capacity),
+        current: Vec::with_capacity(current.len() + RUNTIME_MEMORY_LAYOUT_SLACK),
+        recorded: current,
+    });
+    result
+}
+
+/// Compares the final runtime memory layout against the layout recorded by the previous boot, using the state prepared
+/// by [record_runtime_memory_layout].
+///
+/// Called from ExitBootServices once the before exit boot services handlers have run, so this neither allocates nor
+/// calls variable services. A changed layout breaks S4 (hibernate) resume, so every difference is logged as an error,
+/// as is any runtime memory allocated or freed since the layout was recorded for the next boot.
+pub(crate) fn check_runtime_memory_layout() -> RuntimeMemoryLayoutCheck {
+    let mut snapshot = RUNTIME_MEMORY_LAYOUT_SNAPSHOT.lock();
+    let Some(RuntimeMemoryLayoutSnapshot { previous, recorded, descriptors, current }) = snapshot.as_mut() else {
+        log::warn!("The runtime memory layout was not recorded at ReadyToBoot, unable to check it.");
+        return RuntimeMemoryLayoutCheck::Unavailable;
+    };
+    if let Err(err) = runtime_memory_layout_into(descriptors, current) {
+        log::error!("Failed to build the runtime memory layout: {err:?}");
+        return RuntimeMemoryLayoutCheck::Unavailable;
+    }
+
+    let result = match previous {
+        Some(previous) if previous == current => {
+            log::info!("Runtime memory layout matches the previous boot.");
+            RuntimeMemoryLayoutCheck::Unchanged
+        }
+        Some(previous) => {
+            log::error!("Runtime memory layout changed since the previous boot, S4 resume will fail.");
+            log_layout_differences(previous, current);
+            RuntimeMemoryLayoutCheck::Changed
+        }
+        None => RuntimeMemoryLayoutCheck::Recorded,
+    };
+
+    if recorded != current {
+        log::error!("Runtime memory changed after ReadyToBoot, the layout recorded for the next boot is stale.");
+        log_layout_differences(recorded, current);
+        return RuntimeMemoryLayoutCheck::Changed;
+    }
+    result
+}
+
+fn log_layout_differences(previous: &[RuntimeMemoryRegion], current: &[RuntimeMemoryRegion]) {
+    for region in previous.iter().filter(|region| !current.contains(region)) {
+        log::error!("  Previous only: {region:x?}");
+    }
+    for region in current.iter().filter(|region| !previous.contains(region)) {
+        log::error!("  Current only:  {region:x?}");
     }
 }
 
@@ -1265,6 +1643,237 @@ mod tests {
         .unwrap();
     }
 
+    // Initializes memory support with the stable runtime memory map enabled and runtime memory buckets in the memory type
+    // info HOB. The stable runtime memory map is disabled again before returning.
+    fn init_stable_runtime_memory_support() {
+        let physical_hob_list = build_test_hob_list(0x1000000);
+        unsafe {
+            GCD.reset();
+            gcd::init_gcd(physical_hob_list);
+            test_support::init_test_protocol_db();
+            ALLOCATORS.lock().reset();
+        }
+
+        let mut hob_list = HobList::default();
+        hob_list.discover_hobs(physical_hob_list);
+
+        hob_list.push(Hob::GuidHob(
+            &GuidHob {
+                header: header::Hob { r#type: GUID_EXTENSION, length: 56, reserved: 0 },
+                name: MEMORY_TYPE_INFO_HOB_GUID,
+            },
+            &[
+                0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, //0x0100 pages of LOADER_DATA
+                0x0a, 0x00, 0x00, 0x00, 0x30, 0x00, 0x00, 0x00, //0x0030 pages of ACPI_MEMORY_NVS
+                0x06, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, //0x0020 pages of RUNTIME_SERVICES_DATA
+                0x05, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, //0x0010 pages of RUNTIME_SERVICES_CODE
+            ],
+        ));
+
+        let stack_base_address = (physical_hob_list as u64).wrapping_add(0xEB000);
+        let stack_hob = patina::pi::hob::MemoryAllocation {
+            header: patina::pi::hob::header::Hob {
+                r#type: hob::MEMORY_ALLOCATION,
+                length: core::mem::size_of::<hob::MemoryAllocation>() as u16,
+                reserved: 0x00000000,
+            },
+            alloc_descriptor: patina::pi::hob::header::MemoryAllocation {
+                name: HOB_MEMORY_ALLOC_STACK,
+                memory_base_address: stack_base_address,
+                memory_length: 0x2000,
+                memory_type: efi::BOOT_SERVICES_DATA,
+                reserved: Default::default(),
+            },
+        };
+        hob_list.push(Hob::MemoryAllocation(&stack_hob));
+
+        set_stable_runtime_memory_map(true);
+        init_memory_support(&hob_list);
+        set_stable_runtime_memory_map(false);
+    }
+
+    #[test]
+    fn init_memory_support_should_place_stable_runtime_buckets_contiguously() {
+        test_support::with_global_lock(|| {
+            init_stable_runtime_memory_support();
+
+            let range = |memory_type| ALLOCATORS.lock().get_allocator(memory_type).unwrap().reserved_range().unwrap();
+            let code_range = range(efi::RUNTIME_SERVICES_CODE);
+            let data_range = range(efi::RUNTIME_SERVICES_DATA);
+            let nvs_range = range(efi::ACPI_MEMORY_NVS);
+
+            // buckets are laid out back to back in a fixed order, regardless of the order in the HOB.
+            assert_eq!(code_range.end - code_range.start, 0x10 * 0x1000);
+            assert_eq!(data_range.end - data_range.start, 0x20 * 0x1000);
+            assert_eq!(nvs_range.end - nvs_range.start, 0x30 * 0x1000);
+            assert_eq!(code_range.end, data_range.start);
+            assert_eq!(data_range.end, nvs_range.start);
+
+            // other buckets are unaffected.
+            let loader_range = range(efi::LOADER_DATA);
+            assert_eq!(loader_range.end - loader_range.start, 0x100 * 0x1000);
+
+            // runtime allocations come from the buckets and cannot outgrow them.
+            let mut address: efi::PhysicalAddress = 0;
+            assert_eq!(
+                allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::RUNTIME_SERVICES_DATA, 0x10, &mut address),
+                efi::Status::SUCCESS
+            );
+            assert!(data_range.contains(&address));
+            assert_eq!(
+                allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::RUNTIME_SERVICES_DATA, 0x20, &mut address),
+                efi::Status::OUT_OF_RESOURCES
+            );
+
+            // the buckets are reported as the runtime memory map entries above the pre-DXE allocations from the HOB
+            // list.
+            let layout = runtime_memory_layout().unwrap();
+            assert!(layout.ends_with(&[
+                RuntimeMemoryRegion {
+                    memory_type: efi::RUNTIME_SERVICES_CODE,
+                    physical_start: code_range.start,
+                    number_of_pages: 0x10
+                },
+                RuntimeMemoryRegion {
+                    memory_type: efi::RUNTIME_SERVICES_DATA,
+                    physical_start: data_range.start,
+                    number_of_pages: 0x20
+                },
+                RuntimeMemoryRegion {
+                    memory_type: efi::ACPI_MEMORY_NVS,
+                    physical_start: nvs_range.start,
+                    number_of_pages: 0x30
+                },
+            ]));
+        })
+        .unwrap();
+    }
+
+    // Minimal single-variable store for runtime memory layout tests.
+    struct TestVariableStore {
+        data: std::sync::Mutex<Option<Vec<u8>>>,
+        status: efi::Status,
+    }
+
+    impl RuntimeServices for TestVariableStore {
+        fn query_variable_info(
+            &self,
+            _attributes: u32,
+        ) -> Result<patina::runtime_services::variable_services::VariableInfo, efi::Status> {
+            Err(efi::Status::UNSUPPORTED)
+        }
+
+        unsafe fn set_variable_unchecked(
+            &self,
+            _name: &mut [u16],
+            namespace: &efi::Guid,
+            _attributes: u32,
+            data: &[u8],
+        ) -> Result<(), efi::Status> {
+            assert_eq!(namespace, &RUNTIME_MEMORY_LAYOUT_VARIABLE_GUID);
+            if self.status.is_error() {
+                return Err(self.status);
+            }
+            *self.data.lock().unwrap() = Some(data.to_vec());
+            Ok(())
+        }
+
+        unsafe fn get_variable_unchecked(
+            &self,
+            _name: &mut [u16],
+            _namespace: &efi::Guid,
+            data: Option<&mut [u8]>,
+        ) -> patina::runtime_services::variable_services::GetVariableStatus {
+            use patina::runtime_services::variable_services::GetVariableStatus;
+            if self.status.is_error() {
+                return GetVariableStatus::Error(self.status);
+            }
+            match (self.data.lock().unwrap().as_ref(), data) {
+                (None, _) => GetVariableStatus::Error(efi::Status::NOT_FOUND),
+                (Some(stored), Some(buffer)) if buffer.len() >= stored.len() => {
+                    buffer[..stored.len()].copy_from_slice(stored);
+                    GetVariableStatus::Success { data_size: stored.len(), attributes: 0 }
+                }
+                (Some(stored), _) => GetVariableStatus::BufferTooSmall { data_size: stored.len(), attributes: 0 },
+            }
+        }
+
+        unsafe fn get_next_variable_name_unchecked(
+            &self,
+            _prev_name: &[u16],
+            _prev_namespace: &efi::Guid,
+            _next_name: &mut Vec<u16>,
+            _next_namespace: &mut efi::Guid,
+        ) -> Result<(), efi::Status> {
+            Err(efi::Status::UNSUPPORTED)
+        }
+    }
+
+    #[test]
+    fn runtime_memory_region_should_round_trip_through_variable_data() {
+        let regions = [
+            RuntimeMemoryRegion { memory_type: efi::RUNTIME_SERVICES_CODE, physical_start: 0x1000, number_of_pages: 1 },
+            RuntimeMemoryRegion {
+                memory_type: efi::ACPI_MEMORY_NVS,
+                physical_start: 0x1_0000_0000,
+                number_of_pages: 0x30,
+            },
+        ];
+        let data = RuntimeMemoryRegion::encode(&regions);
+        assert_eq!(data.len(), 2 * RuntimeMemoryRegion::SERIALIZED_SIZE);
+        assert_eq!(RuntimeMemoryRegion::decode(&data).unwrap(), regions);
+        assert_eq!(RuntimeMemoryRegion::decode(&data[1..]), None);
+        assert_eq!(RuntimeMemoryRegion::decode(&[]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn runtime_memory_layout_should_be_recorded_at_ready_to_boot_and_checked_at_exit_boot_services() {
+        test_support::with_global_lock(|| {
+            init_stable_runtime_memory_support();
+            *RUNTIME_MEMORY_LAYOUT_SNAPSHOT.lock() = None;
+            assert_eq!(check_runtime_memory_layout(), RuntimeMemoryLayoutCheck::Unavailable);
+
+            let store = TestVariableStore { data: std::sync::Mutex::new(None), status: efi::Status::SUCCESS };
+            assert_eq!(record_runtime_memory_layout(&store), RuntimeMemoryLayoutCheck::Recorded);
+            let recorded = store.data.lock().unwrap().clone().unwrap();
+            assert_eq!(RuntimeMemoryRegion::decode(&recorded).unwrap(), runtime_memory_layout().unwrap());
+            assert_eq!(check_runtime_memory_layout(), RuntimeMemoryLayoutCheck::Recorded);
+
+            assert_eq!(record_runtime_memory_layout(&store), RuntimeMemoryLayoutCheck::Unchanged);
+            assert_eq!(check_runtime_memory_layout(), RuntimeMemoryLayoutCheck::Unchanged);
+
+            // allocations within the runtime buckets do not change the layout.
+            let mut address: efi::PhysicalAddress = 0;
+            core_allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::RUNTIME_SERVICES_DATA, 1, &mut address, None).unwrap();
+            assert_eq!(check_runtime_memory_layout(), RuntimeMemoryLayoutCheck::Unchanged);
+            core_free_pages(address, 1).unwrap();
+
+            // a layout that changed after it was recorded for the next boot is reported at ExitBootServices.
+            RUNTIME_MEMORY_LAYOUT_SNAPSHOT.lock().as_mut().unwrap().recorded.pop();
+            assert_eq!(check_runtime_memory_layout(), RuntimeMemoryLayoutCheck::Changed);
+
+            // a layout from a different boot is reported and replaced.
+            let previous = [RuntimeMemoryRegion {
+                memory_type: efi::RUNTIME_SERVICES_DATA,
+                physical_start: 0x1000,
+                number_of_pages: 1,
+            }];
+            *store.data.lock().unwrap() = Some(RuntimeMemoryRegion::encode(&previous));
+            assert_eq!(record_runtime_memory_layout(&store), RuntimeMemoryLayoutCheck::Changed);
+            assert_eq!(store.data.lock().unwrap().as_ref(), Some(&recorded));
+            assert_eq!(check_runtime_memory_layout(), RuntimeMemoryLayoutCheck::Changed);
+
+            // malformed data is replaced.
+            *store.data.lock().unwrap() = Some(vec![0; 5]);
+            assert_eq!(record_runtime_memory_layout(&store), RuntimeMemoryLayoutCheck::Changed);
+            assert_eq!(store.data.lock().unwrap().as_ref(), Some(&recorded));
+
+            let store = TestVariableStore { data: std::sync::Mutex::new(None), status: efi::Status::UNSUPPORTED };
+            assert_eq!(record_runtime_memory_layout(&store), RuntimeMemoryLayoutCheck::Unavailable);
+        })
+        .unwrap();
+    }
+
     #[test]
     fn init_memory_support_should_process_resource_allocations() {
         test_support::with_global_lock(|| {
@@ -171,6 +171,10 @@ pub struct FixedSizeBlockAllocator {
     /// S4 resume.
     pub(crate) reserved_range: Option<Range<efi::PhysicalAddress>>,
 
+    /// When set, all page and pool-expansion allocations must be satisfied from `reserved_range`. This is used to keep
+    /// runtime memory in address-stable bins.
+    confined_to_reserved_range: bool,
+
     /// Statistics about the allocator's usage.
     stats: AllocationStatistics,
 
@@ -189,6 +193,7 @@ impl FixedSizeBlockAllocator {
             list_heads: [EMPTY; BLOCK_SIZES.len()],
             allocators: None,
             reserved_range: None,
+            confined_to_reserved_range: false,
             stats: AllocationStatistics::new(),
             page_allocation_granularity,
         }
@@ -202,6 +207,7 @@ impl FixedSizeBlockAllocator {
         self.list_heads = [EMPTY; BLOCK_SIZES.len()];
         self.allocators = None;
         self.reserved_range = None;
+        self.confined_to_reserved_range = false;
         self.memory_type_info_mut().number_of_pages = 0;
         self.stats = AllocationStatistics::new();
     }
@@ -598,20 +604,14 @@ impl SpinLockedFixedSizeBlockAllocator {
         // Page allocations and pool allocations are disjoint; page allocations are allocated directly from the GCD and are
         // freed straight back to GCD. As such, a tracking allocator structure is not required.
         let start_address = self
-            .gcd
-            .allocate_memory_space(
-                allocation_strategy,
-                GcdMemoryType::SystemMemory,
-                align_shift,
-                uefi_pages_to_size!(required_pages),
-                self.handle,
-                None,
-            )
+            .allocate_backing_memory(allocation_strategy, align_shift, uefi_pages_to_size!(required_pages))
             .map_err(|err| match err {
                 EfiError::InvalidParameter | EfiError::NotFound => err,
                 _ => EfiError::OutOfResources,
             })?;
 
+        self.apply_memory_protection_policy(start_address, uefi_pages_to_size!(required_pages));
+
         let allocation = slice_from_raw_parts_mut(start_address as *mut u8, uefi_pages_to_size!(required_pages));
         let allocation = NonNull::new(allocation).ok_or(EfiError::OutOfResources)?;
 
@@ -621,6 +621,96 @@ impl SpinLockedFixedSizeBlockAllocator {
         Ok(allocation)
     }
 
+    /// Allocates `len` bytes of system memory from the GCD for this allocator.
+    ///
+    /// If the allocator is [confined](Self::confine_to_reserved_range) to its reserved range, the allocation is
+    /// satisfied from that range only; an exhausted range results in [`EfiError::OutOfResources`] rather than a
+    /// fallback to memory outside of it, and `Address` requests outside the range fail with [`EfiError::NotFound`].
+    fn allocate_backing_memory(
+        &self,
+        allocation_strategy: AllocationStrategy,
+        align_shift: usize,
+        len: usize,
+    ) -> Result<usize, EfiError> {
+        let confined_range = {
+            let inner = self.lock();
+            if inner.confined_to_reserved_range { inner.reserved_range.clone() } else { None }
+        };
+
+        let Some(range) = confined_range else {
+            return self.gcd.allocate_memory_space(
+                allocation_strategy,
+                GcdMemoryType::SystemMemory,
+                align_shift,
+                len,
+                self.handle,
+                None,
+            );
+        };
+
+        let in_range = |address: usize| {
+            range.start <= address as efi::PhysicalAddress
+                && (address as efi::PhysicalAddress).saturating_add(len as efi::PhysicalAddress) <= range.end
+        };
+
+        // Searching top down from the end of the reserved range visits the free blocks owned by this allocator first.
+        let strategy = match allocation_strategy {
+            AllocationStrategy::Address(address) if !in_range(address) => return Err(EfiError::NotFound),
+            AllocationStrategy::Address(_) => allocation_strategy,
+            _ => AllocationStrategy::TopDown(Some(range.end as usize - 1)),
+        };
+
+        let address = self
+            .gcd
+            .allocate_memory_space(strategy, GcdMemoryType::SystemMemory, align_shift, len, self.handle, None)
+            .map_err(|err| match (strategy, err) {
+                (AllocationStrategy::Address(_), err) => err,
+                (_, EfiError::NotFound) => EfiError::OutOfResources,
+                (_, err) => err,
+            })?;
+
+        if !in_range(address) {
+            // The reserved range is exhausted and the GCD found memory below it; give that memory back.
+            log::error!(
+                "Reserved range {:#x?} for memory type {:#x} is exhausted, failing allocation of {len:#x} bytes.",
+                range,
+                self.memory_type()
+            );
+            if let Err(err) = self.gcd.free_memory_space(address, len) {
+                log::error!("Failed to free out-of-range allocation at {address:#x}: {err:?}");
+                debug_assert!(false);
+            }
+            return Err(EfiError::OutOfResources);
+        }
+
+        Ok(address)
+    }
+
+    /// The GCD maps every new allocation non-executable. If the memory protection policy does not require NX for
+    /// this allocator's memory type, make the freshly allocated range executable again.
+    fn apply_memory_protection_policy(&self, address: usize, len: usize) {
+        let memory_type = self.memory_type();
+        if self.gcd.memory_protection_policy().nx_enabled_for(memory_type) {
+            return;
+        }
+
+        let attributes = match self.gcd.get_memory_descriptor_for_address(address as efi::PhysicalAddress) {
+            Ok(descriptor) if descriptor.attributes & efi::MEMORY_XP != 0 => descriptor.attributes & !efi::MEMORY_XP,
+            _ => return,
+        };
+
+        match self.gcd.set_memory_space_attributes(address, len, attributes) {
+            // NotReady is expected before paging is initialized; the page table is built from the GCD attributes.
+            Ok(()) | Err(EfiError::NotReady) => (),
+            Err(err) => {
+                log::error!(
+                    "Failed to clear NX on {address:#x} for len {len:#x} for memory type {memory_type:#x}: {err:?}"
+                );
+                debug_assert!(false);
+            }
+        }
+    }
+
     /// Frees the block of pages at the given address of the given size.
     /// ## Safety
     /// Caller must ensure that the given address corresponds to a valid block of pages that was allocated with
@@ -681,6 +771,36 @@ impl SpinLockedFixedSizeBlockAllocator {
     /// This routine will return Err(efi::Status::ALREADY_STARTED) if it is called more than once.
     ///
     pub fn reserve_memory_pages(&self, pages: usize) -> Result<(), EfiError> {
+        self.reserve_memory_pages_with_strategy(DEFAULT_ALLOCATION_STRATEGY, pages)
+    }
+
+    /// Reserves a range of memory of the given size in pages at exactly the given address for this allocator.
+    ///
+    /// See [Self::reserve_memory_pages]. The address must be aligned to the page allocation granularity of this
+    /// allocator.
+    pub fn reserve_memory_pages_at(&self, address: usize, pages: usize) -> Result<(), EfiError> {
+        self.reserve_memory_pages_with_strategy(AllocationStrategy::Address(address), pages)
+    }
+
+    /// Restricts all further page allocations and pool expansions of this allocator to its reserved range.
+    ///
+    /// Once confined, the allocator never grows outside of the range set up by [Self::reserve_memory_pages] or
+    /// [Self::reserve_memory_pages_at], so the memory map entries for its memory type stay at the same addresses from
+    /// boot to boot. Returns [`EfiError::NotStarted`] if no range has been reserved.
+    pub fn confine_to_reserved_range(&self) -> Result<(), EfiError> {
+        let mut inner = self.lock();
+        if inner.reserved_range.is_none() {
+            Err(EfiError::NotStarted)?;
+        }
+        inner.confined_to_reserved_range = true;
+        Ok(())
+    }
+
+    fn reserve_memory_pages_with_strategy(
+        &self,
+        allocation_strategy: AllocationStrategy,
+        pages: usize,
+    ) -> Result<(), EfiError> {
         if self.lock().reserved_range.is_some() {
             Err(EfiError::AlreadyStarted)?;
         }
@@ -697,7 +817,7 @@ impl SpinLockedFixedSizeBlockAllocator {
         // Allocate then free a block of the requested length in the GCD while preserving ownership.
         // This, in effect, reserves this region in the GCD for use by this allocator.
         let reserved_block_addr = self.gcd.allocate_memory_space(
-            DEFAULT_ALLOCATION_STRATEGY,
+            allocation_strategy,
             GcdMemoryType::SystemMemory,
             page_shift_from_alignment(granularity)?,
             reserved_block_len,
@@ -786,17 +906,13 @@ unsafe impl Allocator for SpinLockedFixedSizeBlockAllocator {
                 // Allocate additional memory through the GCD, returning AllocError
                 // if the GCD returns an error
                 let start_address: usize = self
-                    .gcd
-                    .allocate_memory_space(
+                    .allocate_backing_memory(
                         DEFAULT_ALLOCATION_STRATEGY,
-                        GcdMemoryType::SystemMemory,
                         page_shift_from_alignment(required_alignment).map_err(|_| {
                             debug_assert!(false);
                             AllocError
                         })?,
                         allocation_size,
-                        self.handle,
-                        None,
                     )
                     .map_err(|err| {
                         log::error!(
@@ -805,6 +921,8 @@ unsafe impl Allocator for SpinLockedFixedSizeBlockAllocator {
                         AllocError
                     })?;
 
+                self.apply_memory_protection_policy(start_address, allocation_size);
+
                 // Expand the FSB using the allocated memory region
                 let allocated_ptr = NonNull::new(start_address as *mut u8).ok_or_else(|| {
                     debug_assert!(false);
@@ -1471,6 +1589,57 @@ mod tests {
         });
     }
 
+    #[test]
+    fn confined_allocator_should_only_allocate_from_reserved_range() {
+        with_granularity_modulation(|granularity| {
+            with_locked_state(|| {
+                static GCD: SpinLockedGcd = SpinLockedGcd::new(None);
+
+                let address = init_gcd(&GCD, 0x1000000);
+
+                let fsb = SpinLockedFixedSizeBlockAllocator::new(
+                    &GCD,
+                    1 as _,
+                    memory_type_info(efi::RUNTIME_SERVICES_DATA),
+                    granularity,
+                );
+
+                assert_eq!(fsb.confine_to_reserved_range(), Err(EfiError::NotStarted));
+
+                let bin_pages = 0x200;
+                let bin_base = align_up(address as usize + 0x100000, granularity).unwrap();
+                fsb.reserve_memory_pages_at(bin_base, bin_pages).unwrap();
+                fsb.confine_to_reserved_range().unwrap();
+                let bin = bin_base as u64..(bin_base + uefi_pages_to_size!(bin_pages)) as u64;
+                assert_eq!(fsb.reserved_range(), Some(bin.clone()));
+
+                // page allocations come from the bin regardless of strategy.
+                let pages = fsb.allocate_pages(AllocationStrategy::BottomUp(None), 4, UEFI_PAGE_SIZE).unwrap();
+                assert!(bin.contains(&(pages.cast::<u8>().as_ptr() as u64)));
+
+                // pool expansion comes from the bin as well.
+                let pool = fsb.allocate(Layout::from_size_align(0x100, 8).unwrap()).unwrap();
+                assert!(bin.contains(&(pool.cast::<u8>().as_ptr() as u64)));
+
+                // requests that cannot be satisfied from the bin fail rather than spilling outside of it.
+                let outside = align_up(address as usize + 0x800000, granularity).unwrap();
+                assert_eq!(
+                    fsb.allocate_pages(AllocationStrategy::Address(outside), 1, UEFI_PAGE_SIZE),
+                    Err(EfiError::NotFound)
+                );
+                assert_eq!(
+                    fsb.allocate_pages(AllocationStrategy::TopDown(None), bin_pages, UEFI_PAGE_SIZE),
+                    Err(EfiError::OutOfResources)
+                );
+
+                // pages freed back go back to the bin.
+                unsafe { fsb.free_pages(pages.cast::<u8>().as_ptr() as usize, 4).unwrap() };
+                let pages = fsb.allocate_pages(AllocationStrategy::TopDown(None), 4, UEFI_PAGE_SIZE).unwrap();
+                assert!(bin.contains(&(pages.cast::<u8>().as_ptr() as u64)));
+            });
+        });
+    }
+
     #[test]
     fn test_allocator_commands_with_invalid_parameters() {
         with_locked_state(|| {
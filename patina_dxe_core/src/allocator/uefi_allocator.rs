@@ -33,6 +33,17 @@ struct AllocationInfo {
     layout: Layout,
 }
 
+// The offset of a pool buffer from its AllocationInfo header, calculated once at compile time.
+const POOL_HEADER_OFFSET: usize = {
+    match Layout::new::<AllocationInfo>().extend(match Layout::from_size_align(0, UEFI_POOL_ALIGN) {
+        Ok(layout) => layout,
+        Err(_) => panic!("Base Offset calculation error in free_pool"),
+    }) {
+        Ok((_, offset)) => offset,
+        Err(_) => panic!("Base Offset calculation error in free_pool"),
+    }
+};
+
 /// UEFI Allocator
 ///
 /// Wraps a [`SpinLockedFixedSizeBlockAllocator`] to provide additional UEFI-specific functionality:
@@ -101,6 +112,20 @@ impl UefiAllocator {
         self.allocator.reserve_memory_pages(pages)
     }
 
+    /// Reserves a range of memory of the given size in pages at exactly the given address for this allocator.
+    ///
+    /// See [`SpinLockedFixedSizeBlockAllocator::reserve_memory_pages_at`]
+    pub fn reserve_memory_pages_at(&self, address: usize, pages: usize) -> Result<(), EfiError> {
+        self.allocator.reserve_memory_pages_at(address, pages)
+    }
+
+    /// Restricts all further allocations of this allocator to its reserved range.
+    ///
+    /// See [`SpinLockedFixedSizeBlockAllocator::confine_to_reserved_range`]
+    pub fn confine_to_reserved_range(&self) -> Result<(), EfiError> {
+        self.allocator.confine_to_reserved_range()
+    }
+
     /// Returns an iterator over the memory ranges managed by this allocator.
     /// Returns an empty iterator if the allocator has no memory ranges.
     pub(crate) fn get_memory_ranges(&self) -> impl Iterator<Item = Range<efi::PhysicalAddress>> {
@@ -149,23 +174,15 @@ impl UefiAllocator {
     ///
     /// Caller must guarantee that `buffer` was originally allocated by [`Self::allocate_pool`]
     pub unsafe fn free_pool(&self, buffer: *mut c_void) -> Result<(), EfiError> {
-        // Calculate the offset once at compile time
-        const OFFSET: usize = {
-            match Layout::new::<AllocationInfo>().extend(match Layout::from_size_align(0, UEFI_POOL_ALIGN) {
-                Ok(layout) => layout,
-                Err(_) => panic!("Base Offset calculation error in free_pool"),
-            }) {
-                Ok((_, offset)) => offset,
-                Err(_) => panic!("Base Offset calculation error in free_pool"),
-            }
-        };
-
         //TODO: trusting that "buffer" is legit is pretty naive - but performant. Presently the allocator doesn't have
         //tracking mechanisms that permit the validation of the pointer (hence the unsafe).
 
         // SAFETY: Caller must follow safety contract defined by this function.
         let mut ptr = unsafe {
-            NonNull::new(buffer).ok_or(EfiError::InvalidParameter)?.byte_sub(OFFSET).cast::<AllocationInfo>()
+            NonNull::new(buffer)
+                .ok_or(EfiError::InvalidParameter)?
+                .byte_sub(POOL_HEADER_OFFSET)
+                .cast::<AllocationInfo>()
         };
 
         // SAFETY: Caller must follow safety contract defined by this function.
@@ -189,6 +206,19 @@ impl UefiAllocator {
         Ok(())
     }
 
+    /// Returns the size of the pool buffer allocated by [`Self::allocate_pool`] of any [`UefiAllocator`], or `None` if
+    /// `buffer` does not have a valid pool header.
+    ///
+    /// ## Safety
+    ///
+    /// Caller must guarantee that `buffer` was originally allocated by [`Self::allocate_pool`] and has not been freed.
+    pub unsafe fn pool_size(buffer: *mut c_void) -> Option<usize> {
+        // SAFETY: Caller must follow safety contract defined by this function.
+        let allocation_info =
+            unsafe { NonNull::new(buffer)?.byte_sub(POOL_HEADER_OFFSET).cast::<AllocationInfo>().as_ref() };
+        (allocation_info.signature == POOL_SIG).then(|| allocation_info.layout.size() - POOL_HEADER_OFFSET)
+    }
+
     /// Attempts to allocate the given number of pages according to the given allocation strategy.
     /// Valid allocation strategies are:
     /// - BottomUp(None): Allocate the block of pages from the lowest available free memory.
@@ -229,7 +259,6 @@ impl UefiAllocator {
     }
 
     /// Returns the allocator stats
-    #[allow(dead_code)]
     pub fn stats(&self) -> AllocationStatistics {
         self.allocator.stats()
     }
@@ -389,8 +418,10 @@ mod tests {
 
                 let mut buffer: *mut c_void = core::ptr::null_mut();
                 assert!(unsafe { ua.allocate_pool(0x1000, core::ptr::addr_of_mut!(buffer)) }.is_ok());
+                assert_eq!(unsafe { UefiAllocator::pool_size(buffer) }, Some(0x1000));
 
                 assert!(unsafe { ua.free_pool(buffer) }.is_ok());
+                assert_eq!(unsafe { UefiAllocator::pool_size(buffer) }, None);
 
                 let (_, offset) = Layout::new::<AllocationInfo>()
                     .extend(
@@ -0,0 +1,297 @@
+//! DXE Core Component Performance Accounting
+//!
+//! Measures every run of a Patina component: the wall time of the run, the number of times the component has been run
+//! so far, and the change in outstanding pool allocations and in pages allocated through the
+//! [MemoryManager](patina::component::service::memory::MemoryManager) service. Runs that executed the component's entry
+//! point are emitted as FBPT records, alongside the driver load and start records, when the
+//! [ComponentRun](patina::performance::Measurement::ComponentRun) measurement is enabled.
+//!
+//! Runs are measured by a [MeasuringExecutor] wrapping the executor the component dispatcher runs each batch with.
+//! Allocations are counted for the whole system, so they are only attributed exactly to a component when it runs alone,
+//! as it does with the default sequential executor.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::cell::RefCell;
+
+use mu_rust_helpers::{
+    guid::CALLER_ID,
+    perf_timer::{Arch, ArchFunctionality},
+};
+use patina::{
+    component::{Component, MetaData, Storage, UnsafeStorageCell, schedule::Executor},
+    error::Result,
+    performance::{
+        logging::{perf_component_allocations, perf_component_run},
+        measurement::create_performance_measurement,
+    },
+};
+
+use crate::{allocator, memory_manager};
+
+/// A single run of a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Run {
+    /// The timer tick the run started at.
+    pub start: u64,
+    /// The timer tick the run ended at.
+    pub end: u64,
+    /// The change in outstanding pool allocations during the run.
+    pub pool_allocations: isize,
+    /// The change in pages allocated through the memory manager during the run.
+    pub pages: isize,
+}
+
+/// Returns the number of outstanding pool allocations and of pages allocated through the memory manager.
+fn allocations() -> (usize, usize) {
+    (allocator::outstanding_pool_allocations(), memory_manager::allocated_pages())
+}
+
+/// A component whose run is measured, for the duration of a single batch.
+pub(crate) struct Measured<'a> {
+    component: &'a mut dyn Component,
+    run: Option<Run>,
+}
+
+impl<'a> Measured<'a> {
+    pub(crate) fn new(component: &'a mut dyn Component) -> Self {
+        Self { component, run: None }
+    }
+
+    /// Returns the measured run, if the component was run.
+    pub(crate) fn run(&self) -> Option<Run> {
+        self.run
+    }
+}
+
+impl Component for Measured<'_> {
+    unsafe fn run_unsafe(&mut self, storage: UnsafeStorageCell) -> Result<bool> {
+        let (pool_before, pages_before) = allocations();
+        let start = Arch::cpu_count();
+        // SAFETY: The caller upholds the safety requirements of the measured component.
+        let result = unsafe { self.component.run_unsafe(storage) };
+        let end = Arch::cpu_count();
+        let (pool_after, pages_after) = allocations();
+
+        self.run = Some(Run {
+            start,
+            end,
+            pool_allocations: pool_after.wrapping_sub(pool_before) as isize,
+            pages: pages_after.wrapping_sub(pages_before) as isize,
+        });
+        result
+    }
+
+    fn initialize(&mut self, storage: &mut Storage) {
+        self.component.initialize(storage)
+    }
+
+    fn metadata(&self) -> &MetaData {
+        self.component.metadata()
+    }
+
+    fn runs_once(&self) -> bool {
+        self.component.runs_once()
+    }
+}
+
+/// The number of times each component has been run.
+#[derive(Debug, Default)]
+pub(crate) struct PerfTracker {
+    attempts: BTreeMap<&'static str, u64>,
+}
+
+impl PerfTracker {
+    /// Records a run of the component named `name`, and returns how many times it has been run, including this run.
+    /// `executed` is whether the component's entry point was executed, rather than its parameters not being available.
+    pub(crate) fn record(&mut self, name: &'static str, run: Run, executed: bool) -> u64 {
+        let attempt = self.attempts.entry(name).or_insert(0);
+        *attempt += 1;
+        if !executed {
+            return *attempt;
+        }
+
+        log::debug!(
+            "Component Perf: Id = [{name:?}] Attempt = [{attempt}] Time = [{} us] Pool = [{:+}] Pages = [{:+}]",
+            ticks_to_us(run.end.wrapping_sub(run.start)),
+            run.pool_allocations,
+            run.pages,
+        );
+        perf_component_run(name, &CALLER_ID, run.start, run.end, *attempt, create_performance_measurement);
+        perf_component_allocations(
+            name,
+            &CALLER_ID,
+            run.end,
+            run.pool_allocations,
+            run.pages,
+            create_performance_measurement,
+        );
+        *attempt
+    }
+
+    /// Returns how many times the component named `name` has been run.
+    #[cfg(test)]
+    pub(crate) fn attempts(&self, name: &str) -> u64 {
+        self.attempts.get(name).copied().unwrap_or_default()
+    }
+}
+
+/// An [Executor] that measures every component run by the executor it wraps.
+pub(crate) struct MeasuringExecutor {
+    executor: Box<dyn Executor>,
+    perf: RefCell<PerfTracker>,
+}
+
+impl MeasuringExecutor {
+    pub(crate) fn new(executor: impl Executor + 'static) -> Self {
+        Self { executor: Box::new(executor), perf: RefCell::new(PerfTracker::default()) }
+    }
+}
+
+impl Executor for MeasuringExecutor {
+    unsafe fn execute(&self, storage: UnsafeStorageCell, batch: &mut [&mut dyn Component]) -> Vec<Result<bool>> {
+        let mut measured: Vec<Measured> = batch.iter_mut().map(|component| Measured::new(&mut **component)).collect();
+        let mut components: Vec<&mut dyn Component> =
+            measured.iter_mut().map(|component| component as &mut dyn Component).collect();
+        // SAFETY: The caller upholds the safety requirements of the wrapped executor, and measuring a component does
+        // not access the storage.
+        let results = unsafe { self.executor.execute(storage, &mut components) };
+        drop(components);
+
+        let mut perf = self.perf.borrow_mut();
+        for (component, result) in measured.iter().zip(&results) {
+            if let Some(run) = component.run() {
+                perf.record(component.metadata().name(), run, !matches!(result, Ok(false)));
+            }
+        }
+        results
+    }
+}
+
+/// Converts a number of performance counter ticks to microseconds.
+fn ticks_to_us(ticks: u64) -> u64 {
+    match Arch::perf_frequency() {
+        0 => 0,
+        frequency => (ticks as u128 * 1_000_000 / frequency as u128) as u64,
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::{allocator::core_allocate_pool, memory_manager::CoreMemoryManager, test_support};
+    use alloc::vec::Vec;
+    use patina::component::{
+        IntoComponent,
+        schedule::Sequential,
+        service::{
+            Service,
+            memory::{AllocationOptions, MemoryManager},
+        },
+    };
+    use r_efi::efi;
+
+    #[derive(IntoComponent)]
+    struct Allocating;
+
+    impl Allocating {
+        fn entry_point(self) -> patina::error::Result<()> {
+            let pages = CoreMemoryManager.allocate_pages(2, AllocationOptions::new()).unwrap();
+            let _ = pages.into_raw_slice::<u8>();
+            core_allocate_pool(efi::BOOT_SERVICES_DATA, 64).unwrap();
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct Waiting;
+
+    impl Waiting {
+        fn entry_point(self, _service: Service<u32>) -> patina::error::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_runs_are_measured_and_attempts_counted() {
+        test_support::with_global_lock(|| {
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::init_test_protocol_db();
+                test_support::reset_allocators();
+            }
+
+            let mut storage = Storage::new();
+            let mut allocating = Allocating.into_component();
+            let mut waiting = Waiting.into_component();
+            allocating.initialize(&mut storage);
+            waiting.initialize(&mut storage);
+
+            let mut tracker = PerfTracker::default();
+            let mut measured = [Measured::new(allocating.as_mut()), Measured::new(waiting.as_mut())];
+            // SAFETY: The components are run one at a time, with exclusive access to the storage.
+            let results: Vec<_> = measured
+                .iter_mut()
+                .map(|component| unsafe { component.run_unsafe(UnsafeStorageCell::from(&mut storage)) })
+                .collect();
+            assert_eq!(results, [Ok(true), Ok(false)]);
+
+            let allocated = measured[0].run().unwrap();
+            assert!(allocated.end >= allocated.start);
+            assert_eq!(allocated.pages, 2);
+            assert!(allocated.pool_allocations >= 1);
+            assert_eq!(measured[1].run().unwrap().pages, 0);
+            for (component, result) in measured.iter().zip(results) {
+                assert_eq!(
+                    tracker.record(component.metadata().name(), component.run().unwrap(), result == Ok(true)),
+                    1
+                );
+            }
+
+            // Components that are not yet dispatchable are counted on every attempt.
+            let mut measured = Measured::new(waiting.as_mut());
+            // SAFETY: The component is run alone, with exclusive access to the storage.
+            assert_eq!(unsafe { measured.run_unsafe(UnsafeStorageCell::from(&mut storage)) }, Ok(false));
+            assert_eq!(tracker.record(measured.metadata().name(), measured.run().unwrap(), false), 2);
+
+            assert_eq!(tracker.attempts(allocating.metadata().name()), 1);
+            assert_eq!(tracker.attempts(waiting.metadata().name()), 2);
+            assert_eq!(tracker.attempts("unknown"), 0);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_measuring_executor_records_every_run() {
+        test_support::with_global_lock(|| {
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::init_test_protocol_db();
+                test_support::reset_allocators();
+            }
+
+            let mut storage = Storage::new();
+            let mut allocating = Allocating.into_component();
+            let mut waiting = Waiting.into_component();
+            allocating.initialize(&mut storage);
+            waiting.initialize(&mut storage);
+
+            let executor = MeasuringExecutor::new(Sequential);
+            let mut batch = [allocating.as_mut() as &mut dyn Component, waiting.as_mut() as &mut dyn Component];
+            // SAFETY: The components are run one at a time, with exclusive access to the storage.
+            let results = unsafe { executor.execute(UnsafeStorageCell::from(&mut storage), &mut batch) };
+            assert_eq!(results, [Ok(true), Ok(false)]);
+
+            let perf = executor.perf.borrow();
+            assert_eq!(perf.attempts(allocating.metadata().name()), 1);
+            assert_eq!(perf.attempts(waiting.metadata().name()), 1);
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,422 @@
+//! DXE Core Triggered Components
+//!
+//! Components registered with [Core::with_triggered_component](crate::Core::with_triggered_component) are not
+//! executed by the dispatch loop. Instead, the core creates an event for each of them when it starts, and executes the
+//! component from the event's notification function every time the [Trigger] fires.
+//!
+//! Triggered components share the core's [Storage] with dispatched components. Only one component may access the
+//! storage at a time, so a trigger that fires while the dispatch loop is executing components is deferred, and the
+//! component is executed once the dispatch iteration completes. Multiple firings of a trigger while it is deferred
+//! are coalesced into a single execution.
+//!
+//! All triggered components are executed at [Trigger::TPL]. If a component returns at any other TPL, the error is
+//! logged and the TPL is raised or restored back to [Trigger::TPL].
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    ffi::c_void,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+use patina::{
+    component::{Component, IntoComponent, Storage, trigger::Trigger},
+    error::EfiError,
+};
+use r_efi::efi;
+
+use crate::{
+    events::{self, EVENT_DB},
+    protocols::PROTOCOL_DB,
+    tpl_lock,
+};
+
+/// Components registered with the core, along with the trigger that executes them.
+pub(crate) type TriggeredComponents = Vec<(Trigger, Box<dyn Component>)>;
+
+/// A component that is executed when its trigger fires.
+struct TriggeredComponent {
+    trigger: Trigger,
+    component: UnsafeCell<Box<dyn Component>>,
+    event: AtomicPtr<c_void>,
+    pending: AtomicBool,
+}
+
+// SAFETY: The component is only accessed by the holder of STORAGE_IN_USE.
+unsafe impl Sync for TriggeredComponent {}
+unsafe impl Send for TriggeredComponent {}
+
+impl TriggeredComponent {
+    /// Executes the component.
+    ///
+    /// The caller must hold STORAGE_IN_USE and be executing at [Trigger::TPL].
+    fn run(&'static self, storage: &mut Storage) {
+        // SAFETY: The caller holds STORAGE_IN_USE, which guards access to every triggered component.
+        let component = unsafe { &mut *self.component.get() };
+        let name = component.metadata().name();
+        let trigger = self.trigger;
+
+        let result = component.run(storage);
+
+        let tpl = events::current_tpl();
+        if tpl != Trigger::TPL {
+            log::error!("Triggered: Id = [{name:?}] returned at TPL {tpl:#x?}, expected {:#x?}.", Trigger::TPL);
+            if tpl > Trigger::TPL {
+                events::restore_tpl(Trigger::TPL);
+            } else {
+                events::raise_tpl(Trigger::TPL);
+            }
+        }
+
+        match result {
+            Ok(true) => log::info!("Triggered: Id = [{name:?}] Trigger = [{trigger:?}] Status = [Success]"),
+            Ok(false) => log::warn!(
+                "Triggered: Id = [{name:?}] Trigger = [{trigger:?}] Status = [Not Dispatched] Param = [{:?}]",
+                component.metadata().failed_param()
+            ),
+            Err(err) => {
+                log::error!("Triggered: Id = [{name:?}] Trigger = [{trigger:?}] Status = [Failed] Error = [{err:?}]")
+            }
+        }
+
+        if component.runs_once() && !matches!(result, Ok(false)) {
+            self.disarm();
+        }
+    }
+
+    /// Closes the trigger's event and stops tracking the component. The component itself is leaked, as its event may
+    /// still be in the process of being dispatched.
+    fn disarm(&'static self) {
+        let event = self.event.swap(ptr::null_mut(), Ordering::SeqCst);
+        if !event.is_null()
+            && let Err(err) = EVENT_DB.close_event(event)
+        {
+            log::warn!("Could not close the trigger event for a triggered component: {err:?}");
+        }
+        TRIGGERED.lock().retain(|entry| !ptr::eq(*entry, self));
+    }
+}
+
+static TRIGGERED: tpl_lock::TplMutex<Vec<&'static TriggeredComponent>> =
+    tpl_lock::TplMutex::new(efi::TPL_NOTIFY, Vec::new(), "TriggeredComponentLock");
+
+/// The storage shared by dispatched and triggered components. Null until triggers are armed.
+static STORAGE: AtomicPtr<Storage> = AtomicPtr::new(ptr::null_mut());
+
+/// Set while a component (dispatched or triggered) has exclusive access to [STORAGE].
+static STORAGE_IN_USE: AtomicBool = AtomicBool::new(false);
+
+/// Creates the events for the triggered components and starts sharing `storage` with them.
+///
+/// `storage` must remain valid until [release_storage] is called.
+pub(crate) fn arm(components: TriggeredComponents, storage: &mut Storage) {
+    STORAGE.store(storage as *mut Storage, Ordering::SeqCst);
+
+    for (trigger, component) in components {
+        let name = component.metadata().name();
+        let entry: &'static TriggeredComponent = Box::leak(Box::new(TriggeredComponent {
+            trigger,
+            component: UnsafeCell::new(component),
+            event: AtomicPtr::new(ptr::null_mut()),
+            pending: AtomicBool::new(false),
+        }));
+
+        // The component is tracked before the event exists, so that a trigger that fires immediately finds it.
+        TRIGGERED.lock().push(entry);
+        match create_trigger_event(entry) {
+            Ok(event) => {
+                entry.event.store(event, Ordering::SeqCst);
+                log::info!("Armed: Id = [{name:?}] Trigger = [{trigger:?}]");
+            }
+            Err(err) => {
+                log::error!(
+                    "Failed to arm triggered component: Id = [{name:?}] Trigger = [{trigger:?}] Error = [{err:?}]"
+                );
+                entry.disarm();
+            }
+        }
+    }
+}
+
+/// Stops sharing the storage with triggered components. Triggers that fire afterwards are ignored.
+pub(crate) fn release_storage() {
+    with_exclusive_storage(|| STORAGE.store(ptr::null_mut(), Ordering::SeqCst));
+}
+
+/// Runs `f`, which accesses the storage directly, while no triggered component may execute. Triggers that fire while
+/// `f` is running are executed once it returns.
+pub(crate) fn with_exclusive_storage<R>(f: impl FnOnce() -> R) -> R {
+    let claimed = STORAGE_IN_USE.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok();
+    debug_assert!(claimed, "Storage accessed by the core while a triggered component is executing.");
+    let result = f();
+    if claimed {
+        STORAGE_IN_USE.store(false, Ordering::Release);
+        run_pending();
+    }
+    result
+}
+
+fn create_trigger_event(entry: &'static TriggeredComponent) -> Result<efi::Event, EfiError> {
+    let context = Some(entry as *const TriggeredComponent as *mut c_void);
+    match entry.trigger {
+        Trigger::EventGroup(group) => {
+            EVENT_DB.create_event(efi::EVT_NOTIFY_SIGNAL, Trigger::TPL, Some(trigger_notify), context, Some(group))
+        }
+        Trigger::ProtocolNotify(protocol) => {
+            let event =
+                EVENT_DB.create_event(efi::EVT_NOTIFY_SIGNAL, Trigger::TPL, Some(trigger_notify), context, None)?;
+            PROTOCOL_DB.register_protocol_notify(protocol, event).map(|_| event).inspect_err(|_| {
+                let _ = EVENT_DB.close_event(event);
+            })
+        }
+        Trigger::Periodic(period) => {
+            let event = EVENT_DB.create_event(
+                efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL,
+                Trigger::TPL,
+                Some(trigger_notify),
+                context,
+                None,
+            )?;
+            EfiError::status_to_result(events::set_timer(event, efi::TIMER_PERIODIC, period))
+                .map(|_| event)
+                .inspect_err(|_| {
+                    let _ = EVENT_DB.close_event(event);
+                })
+        }
+    }
+}
+
+extern "efiapi" fn trigger_notify(_event: efi::Event, context: *mut c_void) {
+    // SAFETY: The context is the leaked entry the event was created for.
+    let entry = unsafe { &*(context as *const TriggeredComponent) };
+    entry.pending.store(true, Ordering::SeqCst);
+    run_pending();
+}
+
+/// Executes every triggered component whose trigger has fired, unless the storage is currently in use.
+fn run_pending() {
+    if events::current_tpl() > Trigger::TPL {
+        return;
+    }
+    if STORAGE_IN_USE.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+        return;
+    }
+    let storage = STORAGE.load(Ordering::SeqCst);
+    if storage.is_null() {
+        STORAGE_IN_USE.store(false, Ordering::Release);
+        return;
+    }
+
+    let old_tpl = events::raise_tpl(Trigger::TPL);
+    let entries = TRIGGERED.lock().clone();
+    for entry in entries {
+        if entry.pending.swap(false, Ordering::SeqCst) {
+            // SAFETY: STORAGE is valid until it is released, and STORAGE_IN_USE grants exclusive access to it.
+            entry.run(unsafe { &mut *storage });
+        }
+    }
+    // Release the storage before restoring the TPL, so triggers notified by the restore can execute.
+    STORAGE_IN_USE.store(false, Ordering::Release);
+    events::restore_tpl(old_tpl);
+}
+
+/// Calls the ExitBootServices hook of the services in storage. Registered by the core on
+/// [Trigger::EXIT_BOOT_SERVICES], after all other triggered components.
+#[derive(IntoComponent, Default)]
+pub(crate) struct ServiceTeardown;
+
+impl ServiceTeardown {
+    fn entry_point(self, storage: &mut Storage) -> patina::error::Result<()> {
+        storage.exit_boot_services();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use patina::component::service::{IntoService, lifecycle::ServiceLifecycle};
+
+    const TEST_GROUP: efi::Guid =
+        efi::Guid::from_fields(0x7a2c4f10, 0x5e3b, 0x4d61, 0x9c, 0x0e, &[0x48, 0x13, 0xa2, 0x6b, 0xd7, 0x35]);
+
+    static RUN_MANY_COUNT: AtomicU32 = AtomicU32::new(0);
+    static RUN_ONCE_COUNT: AtomicU32 = AtomicU32::new(0);
+    static OBSERVED_TPL: AtomicU32 = AtomicU32::new(0);
+
+    #[derive(IntoComponent)]
+    struct RunMany;
+
+    impl RunMany {
+        fn entry_point(&mut self) -> patina::error::Result<()> {
+            OBSERVED_TPL.store(events::current_tpl() as u32, Ordering::SeqCst);
+            RUN_MANY_COUNT.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct RunOnce;
+
+    impl RunOnce {
+        fn entry_point(self) -> patina::error::Result<()> {
+            RUN_ONCE_COUNT.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct LeaksTpl;
+
+    impl LeaksTpl {
+        fn entry_point(&self) -> patina::error::Result<()> {
+            events::raise_tpl(efi::TPL_NOTIFY);
+            Ok(())
+        }
+    }
+
+    fn dispatch() {
+        events::restore_tpl(events::raise_tpl(efi::TPL_HIGH_LEVEL));
+    }
+
+    fn with_triggers(components: fn() -> TriggeredComponents, f: fn()) {
+        test_support::with_global_lock(|| {
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+            }
+            RUN_MANY_COUNT.store(0, Ordering::SeqCst);
+            RUN_ONCE_COUNT.store(0, Ordering::SeqCst);
+
+            let storage = Box::leak(Box::new(Storage::new()));
+            let components = components()
+                .into_iter()
+                .map(|(trigger, mut component)| {
+                    component.initialize(storage);
+                    (trigger, component)
+                })
+                .collect();
+            arm(components, storage);
+
+            f();
+
+            let entries = TRIGGERED.lock().clone();
+            for entry in entries {
+                entry.disarm();
+            }
+            release_storage();
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_event_group_trigger_runs_component_each_time_at_tpl_callback() {
+        with_triggers(
+            || vec![(Trigger::EventGroup(TEST_GROUP), RunMany.into_component())],
+            || {
+                EVENT_DB.signal_group(TEST_GROUP);
+                dispatch();
+                EVENT_DB.signal_group(TEST_GROUP);
+                dispatch();
+                assert_eq!(RUN_MANY_COUNT.load(Ordering::SeqCst), 2);
+                assert_eq!(OBSERVED_TPL.load(Ordering::SeqCst), efi::TPL_CALLBACK as u32);
+                assert_eq!(events::current_tpl(), efi::TPL_APPLICATION);
+            },
+        );
+    }
+
+    #[test]
+    fn test_component_that_consumes_self_is_disarmed_after_running() {
+        with_triggers(
+            || vec![(Trigger::EventGroup(TEST_GROUP), RunOnce.into_component())],
+            || {
+                EVENT_DB.signal_group(TEST_GROUP);
+                dispatch();
+                assert!(TRIGGERED.lock().is_empty());
+
+                EVENT_DB.signal_group(TEST_GROUP);
+                dispatch();
+                assert_eq!(RUN_ONCE_COUNT.load(Ordering::SeqCst), 1);
+            },
+        );
+    }
+
+    #[test]
+    fn test_trigger_is_deferred_while_storage_is_in_use() {
+        with_triggers(
+            || vec![(Trigger::EventGroup(TEST_GROUP), RunMany.into_component())],
+            || {
+                with_exclusive_storage(|| {
+                    EVENT_DB.signal_group(TEST_GROUP);
+                    dispatch();
+                    EVENT_DB.signal_group(TEST_GROUP);
+                    dispatch();
+                    assert_eq!(RUN_MANY_COUNT.load(Ordering::SeqCst), 0);
+                });
+                assert_eq!(RUN_MANY_COUNT.load(Ordering::SeqCst), 1);
+            },
+        );
+    }
+
+    #[test]
+    fn test_periodic_trigger_runs_component_when_timer_expires() {
+        with_triggers(
+            || vec![(Trigger::Periodic(10), RunMany.into_component())],
+            || {
+                EVENT_DB.timer_tick(u64::MAX / 2);
+                dispatch();
+                assert_eq!(RUN_MANY_COUNT.load(Ordering::SeqCst), 1);
+            },
+        );
+    }
+
+    #[test]
+    fn test_tpl_is_restored_when_component_leaks_a_raised_tpl() {
+        with_triggers(
+            || vec![(Trigger::EventGroup(TEST_GROUP), LeaksTpl.into_component())],
+            || {
+                EVENT_DB.signal_group(TEST_GROUP);
+                dispatch();
+                assert_eq!(events::current_tpl(), efi::TPL_APPLICATION);
+            },
+        );
+    }
+
+    #[test]
+    fn test_service_teardown_calls_exit_boot_services_hooks() {
+        static TORN_DOWN: AtomicU32 = AtomicU32::new(0);
+
+        trait TestService {}
+
+        #[derive(IntoService)]
+        #[service(dyn TestService)]
+        #[lifecycle]
+        struct TestServiceImpl;
+
+        impl TestService for TestServiceImpl {}
+
+        impl ServiceLifecycle for TestServiceImpl {
+            fn on_exit_boot_services(&self) {
+                TORN_DOWN.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mut storage = Storage::new();
+        storage.add_service(TestServiceImpl);
+        let mut component = ServiceTeardown.into_component();
+        component.initialize(&mut storage);
+
+        assert_eq!(component.run(&mut storage), Ok(true));
+        assert_eq!(TORN_DOWN.load(Ordering::SeqCst), 1);
+    }
+}
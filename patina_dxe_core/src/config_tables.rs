@@ -8,6 +8,7 @@
 //!
 pub(crate) mod debug_image_info_table;
 pub(crate) mod memory_attributes_table;
+pub(crate) mod table_manager;
 
 use alloc::{boxed::Box, vec};
 use core::{ffi::c_void, ptr::slice_from_raw_parts_mut};
@@ -118,6 +119,9 @@ pub fn core_install_configuration_table(
     //since we modified the system table, re-calculate CRC.
     efi_system_table.checksum();
 
+    //track the change for the configuration table manager.
+    table_manager::record_change(vendor_guid, vendor_table);
+
     //signal the table guid as an event group
     EVENT_DB.signal_group(vendor_guid);
 
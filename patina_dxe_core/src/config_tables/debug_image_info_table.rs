@@ -7,8 +7,12 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 extern crate alloc;
-use alloc::{boxed::Box, vec, vec::Vec};
-use patina::base::UEFI_PAGE_SIZE;
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use patina::{
+    base::UEFI_PAGE_SIZE,
+    component::service::{IntoService, debug_image::DebugImageRegistry},
+    error::EfiError,
+};
 
 use core::{
     ffi::c_void,
@@ -20,6 +24,7 @@ use core::{
 
 use crate::{
     GCD, config_tables::core_install_configuration_table, gcd::AllocateType, protocol_db, systemtables::EfiSystemTable,
+    tpl_lock::TplMutex,
 };
 
 use patina::pi::dxe_services::GcdMemoryType;
@@ -313,3 +318,262 @@ pub(crate) fn core_remove_debug_image_info_entry(image_handle: efi::Handle) {
         )
     };
 }
+
+/// A code region that was not loaded by the core as a PE image (e.g. an MM image or an option ROM), described by a
+/// synthetic loaded image so that debuggers walking the table can resolve it.
+struct DebugRegion {
+    name: String,
+    image_info: Box<efi::protocols::loaded_image::Protocol>,
+    // Referenced by image_info.file_path.
+    _file_path: Box<[u8]>,
+}
+
+// SAFETY: the pointers in the synthetic loaded image only reference data owned by the region itself.
+unsafe impl Send for DebugRegion {}
+
+static DEBUG_REGIONS: TplMutex<Vec<DebugRegion>> = TplMutex::new(efi::TPL_NOTIFY, Vec::new(), "DebugRegionLock");
+
+// Builds a device path with a single MEDIA_FILEPATH_DP node holding `name`, so that the region is named in the table.
+fn file_path_device_path(name: &str) -> Result<Box<[u8]>, EfiError> {
+    use efi::protocols::device_path::{End, Media, TYPE_END, TYPE_MEDIA};
+
+    let path: Vec<u16> = name.encode_utf16().chain(core::iter::once(0)).collect();
+    let node_length = u16::try_from(4 + path.len() * size_of::<u16>()).map_err(|_| EfiError::InvalidParameter)?;
+
+    let mut device_path = Vec::with_capacity(node_length as usize + 4);
+    device_path.extend_from_slice(&[TYPE_MEDIA, Media::SUBTYPE_FILE_PATH]);
+    device_path.extend_from_slice(&node_length.to_le_bytes());
+    path.iter().for_each(|char| device_path.extend_from_slice(&char.to_le_bytes()));
+    device_path.extend_from_slice(&[TYPE_END, End::SUBTYPE_ENTIRE, 4, 0]);
+    Ok(device_path.into_boxed_slice())
+}
+
+/// Adds a synthetic entry to the EFI_DEBUG_IMAGE_INFO_TABLE_GUID table for a code region the core did not load, and
+/// notifies the debugger of it.
+pub(crate) fn core_new_debug_region_entry(name: &str, base: u64, size: u64) -> Result<(), EfiError> {
+    let end = base.checked_add(size).filter(|_| size != 0).ok_or(EfiError::InvalidParameter)?;
+
+    let mut regions = DEBUG_REGIONS.lock();
+    let overlaps = regions.iter().any(|region| {
+        let region_base = region.image_info.image_base as u64;
+        base < region_base + region.image_info.image_size && region_base < end
+    });
+    if overlaps {
+        return Err(EfiError::AlreadyStarted);
+    }
+
+    let file_path = file_path_device_path(name)?;
+    let image_info = Box::new(efi::protocols::loaded_image::Protocol {
+        revision: efi::protocols::loaded_image::REVISION,
+        parent_handle: protocol_db::DXE_CORE_HANDLE,
+        system_table: core::ptr::null_mut(),
+        device_handle: core::ptr::null_mut(),
+        file_path: file_path.as_ptr() as *mut efi::protocols::device_path::Protocol,
+        reserved: core::ptr::null_mut(),
+        load_options_size: 0,
+        load_options: core::ptr::null_mut(),
+        image_base: base as *mut c_void,
+        image_size: size,
+        image_code_type: efi::BOOT_SERVICES_CODE,
+        image_data_type: efi::BOOT_SERVICES_DATA,
+        unload: None,
+    });
+
+    // The synthetic loaded image is unique to the region, so its address doubles as the entry's image handle.
+    let image_info_ptr = image_info.as_ref() as *const efi::protocols::loaded_image::Protocol;
+    core_new_debug_image_info_entry(
+        EfiDebugImageInfoNormal::EFI_DEBUG_IMAGE_INFO_TYPE_NORMAL,
+        image_info_ptr,
+        image_info_ptr as efi::Handle,
+    );
+    regions.push(DebugRegion { name: String::from(name), image_info, _file_path: file_path });
+    drop(regions);
+
+    log::info!("Registered debug region {name} at {base:#x} ({size:#x} bytes)");
+    patina_debugger::notify_module_load(name, base as usize, size as usize);
+    Ok(())
+}
+
+/// Removes the synthetic entry for the code region at `base` from the EFI_DEBUG_IMAGE_INFO_TABLE_GUID table.
+pub(crate) fn core_remove_debug_region_entry(base: u64) -> Result<(), EfiError> {
+    let mut regions = DEBUG_REGIONS.lock();
+    let index =
+        regions.iter().position(|region| region.image_info.image_base as u64 == base).ok_or(EfiError::NotFound)?;
+    let region = regions.remove(index);
+    core_remove_debug_image_info_entry(region.image_info.as_ref() as *const _ as efi::Handle);
+    Ok(())
+}
+
+/// Calls `f` with the name, base address and size of every registered code region.
+///
+/// This uses `try_lock` so that it is safe to call from a failure context; if the regions are being modified, no
+/// regions are reported.
+pub(crate) fn for_each_debug_region(mut f: impl FnMut(&str, u64, u64)) {
+    let Some(regions) = DEBUG_REGIONS.try_lock() else {
+        return;
+    };
+    for region in regions.iter() {
+        f(&region.name, region.image_info.image_base as u64, region.image_info.image_size);
+    }
+}
+
+/// Adds a synthetic entry for the sub-range of the core's image that holds each component, so that debuggers walking
+/// the table can attribute component code to the component.
+///
+/// `components` holds the name and [code address](patina::component::Component::code_address) of each component.
+/// The linker lays out component code among the rest of the core, so a component's sub-range starts at its code
+/// address and extends to the next component's code address (or the end of the core's image); it identifies the
+/// component that an address most likely belongs to rather than bounding all of the component's code. Components
+/// whose code is not inside the core's image, or shares its address with another component, are skipped.
+pub(crate) fn core_new_component_debug_entries(core_base: u64, core_size: u64, mut components: Vec<(&str, u64)>) {
+    let core_end = core_base.saturating_add(core_size);
+    components.retain(|&(name, address)| {
+        let contained = (core_base..core_end).contains(&address);
+        if !contained {
+            log::debug!("Component {name} code at {address:#x} is outside of the core's image, skipping.");
+        }
+        contained
+    });
+    components.sort_by_key(|&(_, address)| address);
+
+    for (index, &(name, address)) in components.iter().enumerate() {
+        if index > 0 && components[index - 1].1 == address {
+            log::debug!("Component {name} shares its code address {address:#x} with another component, skipping.");
+            continue;
+        }
+        let end =
+            components[index + 1..].iter().map(|&(_, next)| next).find(|&next| next != address).unwrap_or(core_end);
+        if let Err(err) = core_new_debug_region_entry(name, address, end - address) {
+            log::error!("Failed to register debug entry for component {name}: {err:?}");
+        }
+    }
+}
+
+/// The core implementation of the [DebugImageRegistry] service.
+#[derive(IntoService)]
+#[service(dyn DebugImageRegistry)]
+pub(crate) struct CoreDebugImageRegistry;
+
+impl DebugImageRegistry for CoreDebugImageRegistry {
+    fn register_region(&self, name: &str, base: u64, size: u64) -> patina::error::Result<()> {
+        core_new_debug_region_entry(name, base, size)
+    }
+
+    fn unregister_region(&self, base: u64) -> patina::error::Result<()> {
+        core_remove_debug_region_entry(base)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::{
+        systemtables::{SYSTEM_TABLE, init_system_table},
+        test_support,
+    };
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+                init_system_table();
+            }
+            initialize_debug_image_info_table(SYSTEM_TABLE.lock().as_mut().unwrap());
+            DEBUG_REGIONS.lock().clear();
+            f();
+        })
+        .unwrap();
+    }
+
+    fn table_entries() -> Vec<&'static efi::protocols::loaded_image::Protocol> {
+        // SAFETY: the table was initialized by with_locked_state.
+        let metadata_table = unsafe { &*METADATA_TABLE.load(Ordering::SeqCst) };
+        metadata_table.slice[..metadata_table.table.table_size as usize]
+            .iter()
+            // SAFETY: every entry up to table_size is a normal image entry created by this module.
+            .map(|entry| unsafe { &*(*entry.normal_image).loaded_image_protocol_instance })
+            .collect()
+    }
+
+    #[test]
+    fn test_region_entries_are_added_and_removed() {
+        with_locked_state(|| {
+            assert_eq!(CoreDebugImageRegistry.register_region("OptionRom", 0x10_0000, 0x2000), Ok(()));
+
+            let entries = table_entries();
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].image_base as u64, 0x10_0000);
+            assert_eq!(entries[0].image_size, 0x2000);
+            assert_eq!(entries[0].parent_handle, protocol_db::DXE_CORE_HANDLE);
+
+            // The region is named by a MEDIA_FILEPATH_DP node.
+            // SAFETY: the file path was built by file_path_device_path.
+            let node = unsafe { core::slice::from_raw_parts(entries[0].file_path as *const u8, 4 + 20 + 4) };
+            assert_eq!(node[..4], [0x04, 0x04, 24, 0]);
+            let name: Vec<u16> = node[4..22].chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+            assert_eq!(String::from_utf16(&name).unwrap(), "OptionRom");
+            assert_eq!(node[24..], [0x7f, 0xff, 4, 0]);
+
+            let mut regions = Vec::new();
+            for_each_debug_region(|name, base, size| regions.push((String::from(name), base, size)));
+            assert_eq!(regions, [(String::from("OptionRom"), 0x10_0000, 0x2000)]);
+
+            assert_eq!(CoreDebugImageRegistry.unregister_region(0x10_0000), Ok(()));
+            assert!(table_entries().is_empty());
+            assert_eq!(CoreDebugImageRegistry.unregister_region(0x10_0000), Err(EfiError::NotFound));
+        });
+    }
+
+    #[test]
+    fn test_component_entries_are_sub_ranges_of_the_core() {
+        with_locked_state(|| {
+            core_new_component_debug_entries(
+                0x40_0000,
+                0x1_0000,
+                vec![
+                    ("Second", 0x40_8000),
+                    ("First", 0x40_2000),
+                    ("Folded", 0x40_8000),
+                    ("Outside", 0x80_0000),
+                    ("Third", 0x40_c000),
+                ],
+            );
+
+            let mut regions = Vec::new();
+            for_each_debug_region(|name, base, size| regions.push((String::from(name), base, size)));
+            assert_eq!(
+                regions,
+                [
+                    (String::from("First"), 0x40_2000, 0x6000),
+                    (String::from("Second"), 0x40_8000, 0x4000),
+                    (String::from("Third"), 0x40_c000, 0x4000),
+                ]
+            );
+
+            let entries = table_entries();
+            assert_eq!(entries.len(), 3);
+            assert!(entries.iter().all(|entry| entry.parent_handle == protocol_db::DXE_CORE_HANDLE));
+        });
+    }
+
+    #[test]
+    fn test_invalid_regions_are_rejected() {
+        with_locked_state(|| {
+            assert_eq!(CoreDebugImageRegistry.register_region("Empty", 0x1000, 0), Err(EfiError::InvalidParameter));
+            assert_eq!(
+                CoreDebugImageRegistry.register_region("Wraps", u64::MAX - 0xFFF, 0x2000),
+                Err(EfiError::InvalidParameter)
+            );
+
+            assert_eq!(CoreDebugImageRegistry.register_region("MmImage", 0x20_0000, 0x4000), Ok(()));
+            assert_eq!(
+                CoreDebugImageRegistry.register_region("Overlap", 0x20_3000, 0x1000),
+                Err(EfiError::AlreadyStarted)
+            );
+            assert_eq!(CoreDebugImageRegistry.register_region("Adjacent", 0x20_4000, 0x1000), Ok(()));
+            assert_eq!(table_entries().len(), 2);
+        });
+    }
+}
@@ -0,0 +1,366 @@
+//! DXE Core Configuration Table Manager
+//!
+//! Produces the [ConfigTableManager] service on top of [core_install_configuration_table]. The owner, size and version
+//! of each table are tracked alongside the system table; tables installed with `InstallConfigurationTable()` are
+//! tracked too, but have no owner or size. Change notifications are delivered through the GUID event group that
+//! `InstallConfigurationTable()` already signals.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+use core::{ffi::c_void, slice};
+use patina::{
+    component::service::{
+        IntoService,
+        config_table::{
+            ConfigTableError, ConfigTableInfo, ConfigTableManager, ConfigTableNotify, TableChecksum, TableInstall,
+        },
+    },
+    error::EfiError,
+};
+use r_efi::efi;
+
+use crate::{
+    config_tables::core_install_configuration_table,
+    events::EVENT_DB,
+    systemtables::{EfiSystemTable, SYSTEM_TABLE},
+    tpl_lock::TplMutex,
+};
+
+#[derive(Default)]
+struct TableMetadata {
+    // The table the owner and size below describe.
+    address: usize,
+    owner: Option<String>,
+    size: Option<usize>,
+    version: u32,
+}
+
+static TABLE_METADATA: TplMutex<BTreeMap<efi::Guid, TableMetadata>> =
+    TplMutex::new(efi::TPL_NOTIFY, BTreeMap::new(), "ConfigTableMetadataLock");
+
+/// Records that the table under `guid` was installed, replaced or removed.
+///
+/// Called by [core_install_configuration_table] for every change, regardless of who made it. A change to a different
+/// table than the one that was recorded drops its owner and size, as they describe the previous table.
+pub(crate) fn record_change(guid: efi::Guid, table: *mut c_void) {
+    let mut metadata = TABLE_METADATA.lock();
+    let entry = metadata.entry(guid).or_default();
+    entry.version = entry.version.wrapping_add(1);
+    if entry.address != table as usize {
+        entry.address = table as usize;
+        entry.owner = None;
+        entry.size = None;
+    }
+}
+
+fn table_info(guid: efi::Guid, table: *mut c_void) -> ConfigTableInfo {
+    let metadata = TABLE_METADATA.lock();
+    let mut info = ConfigTableInfo { guid, table, size: None, owner: None, version: 0 };
+    if let Some(entry) = metadata.get(&guid) {
+        info.version = entry.version;
+        if entry.address == table as usize {
+            info.size = entry.size;
+            info.owner = entry.owner.clone();
+        }
+    }
+    info
+}
+
+fn installed_tables(st: &EfiSystemTable) -> &[efi::ConfigurationTable] {
+    let system_table = st.as_ref();
+    if system_table.configuration_table.is_null() {
+        return &[];
+    }
+    // Safety: the configuration table is maintained by the core and is valid for the number of entries.
+    unsafe { slice::from_raw_parts(system_table.configuration_table, system_table.number_of_table_entries) }
+}
+
+fn find_table(guid: efi::Guid) -> Option<ConfigTableInfo> {
+    let st = SYSTEM_TABLE.lock();
+    let table = installed_tables(st.as_ref()?).iter().find(|table| table.vendor_guid == guid)?.vendor_table;
+    Some(table_info(guid, table))
+}
+
+fn validate_checksum(table: &[u8], checksum: TableChecksum) -> Result<(), ConfigTableError> {
+    let valid = match checksum {
+        TableChecksum::None => true,
+        TableChecksum::Sum8 => table.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0,
+        TableChecksum::Crc32 { offset } => {
+            let field = offset.checked_add(size_of::<u32>()).filter(|end| *end <= table.len());
+            let Some(end) = field else {
+                return Err(ConfigTableError::InvalidTable);
+            };
+            let expected = u32::from_le_bytes(table[offset..end].try_into().unwrap());
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(&table[..offset]);
+            hasher.update(&[0; size_of::<u32>()]);
+            hasher.update(&table[end..]);
+            hasher.finalize() == expected
+        }
+    };
+    if valid { Ok(()) } else { Err(ConfigTableError::ChecksumMismatch) }
+}
+
+// Only the owner that installed a table through the service may change it; tables with no owner are open to all.
+fn check_owner(guid: efi::Guid, owner: &str, st: &EfiSystemTable) -> Result<(), ConfigTableError> {
+    let Some(table) = installed_tables(st).iter().find(|table| table.vendor_guid == guid) else {
+        return Ok(());
+    };
+    match table_info(guid, table.vendor_table).owner {
+        Some(current) if current != owner => Err(ConfigTableError::NotOwner),
+        _ => Ok(()),
+    }
+}
+
+fn to_config_table_error(err: EfiError) -> ConfigTableError {
+    match err {
+        EfiError::NotFound => ConfigTableError::NotFound,
+        EfiError::OutOfResources => ConfigTableError::OutOfResources,
+        _ => ConfigTableError::InternalError,
+    }
+}
+
+struct NotifyRegistration {
+    guid: efi::Guid,
+    notify: ConfigTableNotify,
+}
+
+extern "efiapi" fn table_changed(_event: efi::Event, context: *mut c_void) {
+    // Safety: the context is the registration leaked by register_notify, which is never freed.
+    let registration = unsafe { &*(context as *const NotifyRegistration) };
+    (registration.notify)(find_table(registration.guid));
+}
+
+/// The core implementation of the [ConfigTableManager] service.
+#[derive(IntoService)]
+#[service(dyn ConfigTableManager)]
+pub(crate) struct CoreConfigTableManager;
+
+impl ConfigTableManager for CoreConfigTableManager {
+    unsafe fn install(&self, owner: &str, table: TableInstall) -> Result<(), ConfigTableError> {
+        if table.table.is_null() || table.size == 0 {
+            return Err(ConfigTableError::InvalidTable);
+        }
+        // Safety: the caller guarantees the table is valid for reads of `size` bytes.
+        let bytes = unsafe { slice::from_raw_parts(table.table as *const u8, table.size) };
+        validate_checksum(bytes, table.checksum)?;
+
+        let mut st = SYSTEM_TABLE.lock();
+        let st = st.as_mut().ok_or(ConfigTableError::InternalError)?;
+        check_owner(table.guid, owner, st)?;
+        core_install_configuration_table(table.guid, table.table, st).map_err(to_config_table_error)?;
+
+        let mut metadata = TABLE_METADATA.lock();
+        let entry = metadata.entry(table.guid).or_default();
+        entry.owner = Some(String::from(owner));
+        entry.size = Some(table.size);
+        log::info!("{owner} installed configuration table {:?} ({} bytes)", table.guid, table.size);
+        Ok(())
+    }
+
+    fn uninstall(&self, owner: &str, guid: efi::Guid) -> Result<(), ConfigTableError> {
+        let mut st = SYSTEM_TABLE.lock();
+        let st = st.as_mut().ok_or(ConfigTableError::InternalError)?;
+        check_owner(guid, owner, st)?;
+        core_install_configuration_table(guid, core::ptr::null_mut(), st).map_err(to_config_table_error)
+    }
+
+    fn tables(&self) -> Vec<ConfigTableInfo> {
+        let st = SYSTEM_TABLE.lock();
+        let Some(st) = st.as_ref() else {
+            return Vec::new();
+        };
+        installed_tables(st).iter().map(|table| table_info(table.vendor_guid, table.vendor_table)).collect()
+    }
+
+    fn table(&self, guid: efi::Guid) -> Option<ConfigTableInfo> {
+        find_table(guid)
+    }
+
+    fn register_notify(&self, guid: efi::Guid, notify: ConfigTableNotify) -> Result<(), ConfigTableError> {
+        let registration = Box::into_raw(Box::new(NotifyRegistration { guid, notify }));
+        EVENT_DB
+            .create_event(
+                efi::EVT_NOTIFY_SIGNAL,
+                efi::TPL_CALLBACK,
+                Some(table_changed),
+                Some(registration as *mut c_void),
+                Some(guid),
+            )
+            .map(|_| ())
+            .map_err(|err| {
+                // Safety: the registration was not handed to an event, so it is still owned here.
+                drop(unsafe { Box::from_raw(registration) });
+                to_config_table_error(err)
+            })
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::{events, systemtables::init_system_table, test_support};
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use patina::component::service::config_table::ConfigurationTable;
+
+    const TABLE_GUID: efi::Guid =
+        efi::Guid::from_fields(0x3e1b6c62, 0x0d3f, 0x4a8e, 0x8c, 0x4e, &[0x2a, 0x5d, 0x19, 0x77, 0x60, 0xb1]);
+
+    #[repr(C)]
+    struct Sum8Table {
+        data: [u8; 3],
+        checksum: u8,
+    }
+
+    impl Sum8Table {
+        fn new(data: [u8; 3]) -> Self {
+            let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+            Self { data, checksum: 0u8.wrapping_sub(sum) }
+        }
+    }
+
+    // Safety: Sum8Table is repr(C) and only used by these tests.
+    unsafe impl ConfigurationTable for Sum8Table {
+        const GUID: efi::Guid = TABLE_GUID;
+        const CHECKSUM: TableChecksum = TableChecksum::Sum8;
+    }
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+                init_system_table();
+            }
+            TABLE_METADATA.lock().clear();
+            f();
+        })
+        .unwrap();
+    }
+
+    fn manager() -> &'static dyn ConfigTableManager {
+        &CoreConfigTableManager
+    }
+
+    #[test]
+    fn test_install_tracks_owner_size_and_version() {
+        with_locked_state(|| {
+            let table = Box::leak(Box::new(Sum8Table::new([1, 2, 3])));
+            let address = table as *mut Sum8Table as *mut c_void;
+            assert_eq!(manager().install_table("owner", table), Ok(()));
+
+            let info = manager().table(TABLE_GUID).unwrap();
+            assert_eq!(info.table, address);
+            assert_eq!(info.owner.as_deref(), Some("owner"));
+            assert_eq!(info.size, Some(size_of::<Sum8Table>()));
+            assert_eq!(info.version, 1);
+            assert!(manager().tables().contains(&info));
+
+            // Replacing the table bumps the version.
+            let table = Box::leak(Box::new(Sum8Table::new([4, 5, 6])));
+            assert_eq!(manager().install_table("owner", table), Ok(()));
+            assert_eq!(manager().table(TABLE_GUID).unwrap().version, 2);
+        });
+    }
+
+    #[test]
+    fn test_only_the_owner_can_change_a_table() {
+        with_locked_state(|| {
+            let table = Box::leak(Box::new(Sum8Table::new([1, 2, 3])));
+            assert_eq!(manager().install_table("owner", table), Ok(()));
+
+            let table = Box::leak(Box::new(Sum8Table::new([4, 5, 6])));
+            assert_eq!(manager().install_table("intruder", table), Err(ConfigTableError::NotOwner));
+            assert_eq!(manager().uninstall_table::<Sum8Table>("intruder"), Err(ConfigTableError::NotOwner));
+
+            assert_eq!(manager().uninstall_table::<Sum8Table>("owner"), Ok(()));
+            assert_eq!(manager().table(TABLE_GUID), None);
+            assert_eq!(manager().uninstall_table::<Sum8Table>("owner"), Err(ConfigTableError::NotFound));
+        });
+    }
+
+    #[test]
+    fn test_tables_installed_directly_have_no_owner() {
+        with_locked_state(|| {
+            let table = Box::leak(Box::new(Sum8Table::new([1, 2, 3])));
+            assert_eq!(manager().install_table("owner", table), Ok(()));
+
+            // A direct replacement drops the owner, so the table is open to anyone again.
+            let raw = Box::leak(Box::new(0u64)) as *mut u64 as *mut c_void;
+            core_install_configuration_table(TABLE_GUID, raw, SYSTEM_TABLE.lock().as_mut().unwrap()).unwrap();
+            let info = manager().table(TABLE_GUID).unwrap();
+            assert_eq!((info.table, info.owner, info.size, info.version), (raw, None, None, 2));
+
+            let table = Box::leak(Box::new(Sum8Table::new([4, 5, 6])));
+            assert_eq!(manager().install_table("other", table), Ok(()));
+        });
+    }
+
+    #[test]
+    fn test_install_validates_the_table() {
+        with_locked_state(|| {
+            let mut table = Sum8Table::new([1, 2, 3]);
+            table.checksum = table.checksum.wrapping_add(1);
+            let install = TableInstall {
+                guid: TABLE_GUID,
+                table: &mut table as *mut Sum8Table as *mut c_void,
+                size: size_of::<Sum8Table>(),
+                checksum: TableChecksum::Sum8,
+            };
+            assert_eq!(unsafe { manager().install("owner", install) }, Err(ConfigTableError::ChecksumMismatch));
+
+            let empty = TableInstall { size: 0, ..install };
+            assert_eq!(unsafe { manager().install("owner", empty) }, Err(ConfigTableError::InvalidTable));
+            let null = TableInstall { table: core::ptr::null_mut(), ..install };
+            assert_eq!(unsafe { manager().install("owner", null) }, Err(ConfigTableError::InvalidTable));
+            assert_eq!(manager().table(TABLE_GUID), None);
+        });
+    }
+
+    #[test]
+    fn test_crc32_checksum() {
+        let mut table = [0u8; 16];
+        table[..8].copy_from_slice(b"PATINA!!");
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&table);
+        table[8..12].copy_from_slice(&hasher.finalize().to_le_bytes());
+
+        assert_eq!(validate_checksum(&table, TableChecksum::Crc32 { offset: 8 }), Ok(()));
+        assert_eq!(
+            validate_checksum(&table, TableChecksum::Crc32 { offset: 4 }),
+            Err(ConfigTableError::ChecksumMismatch)
+        );
+        assert_eq!(validate_checksum(&table, TableChecksum::Crc32 { offset: 13 }), Err(ConfigTableError::InvalidTable));
+    }
+
+    #[test]
+    fn test_register_notify_reports_changes() {
+        with_locked_state(|| {
+            static LAST_VERSION: AtomicU32 = AtomicU32::new(u32::MAX);
+            events::restore_tpl(efi::TPL_APPLICATION);
+            assert_eq!(
+                manager().register_notify(
+                    TABLE_GUID,
+                    Box::new(|info| LAST_VERSION.store(info.map_or(0, |info| info.version), Ordering::SeqCst)),
+                ),
+                Ok(())
+            );
+            let dispatch = || events::restore_tpl(events::raise_tpl(efi::TPL_HIGH_LEVEL));
+
+            let table = Box::leak(Box::new(Sum8Table::new([1, 2, 3])));
+            assert_eq!(manager().install_table("owner", table), Ok(()));
+            dispatch();
+            assert_eq!(LAST_VERSION.load(Ordering::SeqCst), 1);
+
+            assert_eq!(manager().uninstall_table::<Sum8Table>("owner"), Ok(()));
+            dispatch();
+            assert_eq!(LAST_VERSION.load(Ordering::SeqCst), 0);
+        });
+    }
+}
@@ -0,0 +1,532 @@
+//! DXE Core Crash Records
+//!
+//! Failures that end in a reset (a watchdog timer expiry) or a panic (an unhandled exception) are only reported on the
+//! serial port, which is lost on production units. This module captures a [CrashRecord] describing the code that was
+//! running when the failure happened:
+//!
+//! - the reason: the watchdog period or the exception type,
+//! - the TPL it was running at,
+//! - the image that was running (if any),
+//! - the saved registers (for exceptions),
+//! - a stack trace, with each frame resolved to a module and offset,
+//! - the list of loaded modules, and
+//! - the tail of the log (if a [LogTail] service is registered).
+//!
+//! The record is logged and, if a [CrashRecordStore] service is registered, persisted so it survives the reset. On the
+//! next boot, the persisted record is logged again, published as a configuration table
+//! ([CRASH_RECORD_TABLE_GUID]) and then cleared from the store.
+//!
+//! Watchdog expiry is captured by the core Watchdog Timer Architectural Protocol ([watchdog_hook]). A watchdog produced
+//! by the platform is left alone, as its single handler slot belongs to the platform; it is only covered if its expiry
+//! raises an exception, which is captured by [exception_hook].
+//!
+//! The watchdog expires from a timer event notification, so the state of the hung code is taken from the most recent
+//! timer interrupt, as recorded by the core Timer Architectural Protocol. If the platform produces its own Timer
+//! Architectural Protocol no interrupted context is available and the record does not contain a stack trace.
+//!
+//! The failing code may hold any lock and may have left the allocator unusable, so a record is serialized into a
+//! buffer reserved up front, and locks are only ever tried: a part of the record whose lock is held (e.g. the loaded
+//! images) is left out.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{
+    ffi::c_void,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+
+use patina::component::service::{
+    Service,
+    crash_record::{CRASH_RECORD_TABLE_GUID, CrashReason, CrashRecord, CrashRecordStore, CrashRecordWriter, LogTail},
+};
+use patina_internal_cpu::interrupts::{ExceptionContext, ExceptionContextRegisters, ExceptionType};
+use r_efi::efi;
+
+use crate::{allocator::core_allocate_pool, config_tables, events, image, systemtables::SYSTEM_TABLE, tpl_lock};
+
+/// The maximum number of bytes of log output included in a crash record.
+const MAX_LOG_TAIL: usize = 4096;
+
+/// The size of the buffer crash records are captured into. Frames and modules that do not fit are left out.
+const MAX_RECORD_SIZE: usize = 16 * 1024;
+
+/// Captures and persists crash records.
+pub struct CrashRecorder {
+    interrupted: AtomicBool,
+    interrupted_pc: AtomicU64,
+    interrupted_sp: AtomicU64,
+    interrupted_tpl: AtomicUsize,
+    store: tpl_lock::TplMutex<Option<Service<dyn CrashRecordStore>>>,
+    log_tail: tpl_lock::TplMutex<Option<Service<dyn LogTail>>>,
+    buffer: tpl_lock::TplMutex<[u8; MAX_RECORD_SIZE]>,
+}
+
+/// The global crash recorder instance.
+pub static CRASH_RECORDER: CrashRecorder = CrashRecorder::new();
+
+impl CrashRecorder {
+    /// Creates a new crash recorder with no interrupted context and no services.
+    pub const fn new() -> Self {
+        Self {
+            interrupted: AtomicBool::new(false),
+            interrupted_pc: AtomicU64::new(0),
+            interrupted_sp: AtomicU64::new(0),
+            interrupted_tpl: AtomicUsize::new(efi::TPL_APPLICATION),
+            store: tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, None, "CrashRecordStoreLock"),
+            log_tail: tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, None, "CrashRecordLogTailLock"),
+            buffer: tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, [0; MAX_RECORD_SIZE], "CrashRecordBufferLock"),
+        }
+    }
+
+    /// Registers the service used to persist crash records.
+    pub fn set_store(&self, store: Service<dyn CrashRecordStore>) {
+        *self.store.lock() = Some(store);
+    }
+
+    /// Registers the service used to include recent log output in crash records.
+    pub fn set_log_tail(&self, log_tail: Service<dyn LogTail>) {
+        *self.log_tail.lock() = Some(log_tail);
+    }
+
+    /// Records the state of the code interrupted by a timer interrupt. Called on every timer interrupt, so it only
+    /// stores the values needed to describe the interrupted code later.
+    pub fn record_interrupted_context(&self, context: &ExceptionContext) {
+        self.interrupted_pc.store(context.program_counter(), Ordering::Relaxed);
+        self.interrupted_sp.store(context.stack_pointer(), Ordering::Relaxed);
+        self.interrupted_tpl.store(events::current_tpl(), Ordering::Relaxed);
+        self.interrupted.store(true, Ordering::Release);
+    }
+
+    /// Captures and reports a crash record describing the code interrupted by the most recent timer interrupt, or the
+    /// current code if no timer interrupt has been recorded.
+    pub fn capture(&self, reason: CrashReason) {
+        if self.interrupted.load(Ordering::Acquire) {
+            let pc = self.interrupted_pc.load(Ordering::Relaxed);
+            let sp = self.interrupted_sp.load(Ordering::Relaxed);
+            self.capture_and_report(reason, self.interrupted_tpl.load(Ordering::Relaxed), Some((pc, sp)), None)
+        } else {
+            self.capture_and_report(reason, events::current_tpl(), None, None)
+        }
+    }
+
+    /// Captures and reports a crash record describing the code that took `exception_type`, as saved in `context`.
+    pub fn capture_exception(&self, exception_type: ExceptionType, context: &ExceptionContext) {
+        self.capture_and_report(
+            CrashReason::Exception { exception_type: exception_type as u64 },
+            events::current_tpl(),
+            Some((context.program_counter(), context.stack_pointer())),
+            Some(context),
+        )
+    }
+
+    // Captures a record into the reserved buffer, logging it along the way, and persists it through the registered
+    // [CrashRecordStore], if any.
+    fn capture_and_report(
+        &self,
+        reason: CrashReason,
+        tpl: efi::Tpl,
+        pc_sp: Option<(u64, u64)>,
+        context: Option<&ExceptionContext>,
+    ) {
+        let Some(mut buffer) = self.buffer.try_lock() else {
+            log::error!("A crash record is already being captured, discarding {reason:?}.");
+            return;
+        };
+        let Some(record) = self.write_record(&mut buffer[..], reason, tpl, pc_sp, context) else {
+            log::error!("The crash record does not fit in {MAX_RECORD_SIZE} bytes.");
+            return;
+        };
+
+        let Some(store) = self.store.try_lock() else {
+            log::error!("The crash record store is in use, the crash record will not be persisted.");
+            return;
+        };
+        let Some(store) = store.as_ref() else {
+            log::error!("No crash record store registered, the crash record will not be persisted.");
+            return;
+        };
+        match store.store(record) {
+            Ok(()) => log::error!("Crash record persisted."),
+            Err(err) => log::error!("Failed to persist the crash record: {err:?}"),
+        }
+    }
+
+    fn write_record<'a>(
+        &self,
+        buffer: &'a mut [u8],
+        reason: CrashReason,
+        tpl: efi::Tpl,
+        pc_sp: Option<(u64, u64)>,
+        context: Option<&ExceptionContext>,
+    ) -> Option<&'a [u8]> {
+        log::error!("Crash record: {reason:?}");
+        log::error!("  TPL: {tpl:#x}");
+        let mut writer = match image::current_running_image() {
+            Some(handle) => image::with_image_name(handle, |name| {
+                log::error!("  Running image: {}", name.unwrap_or("<unknown>"));
+                CrashRecordWriter::new(buffer, reason, tpl, name)
+            }),
+            None => {
+                log::error!("  Running image: <DXE Core>");
+                CrashRecordWriter::new(buffer, reason, tpl, None)
+            }
+        }?;
+
+        if let Some(context) = context {
+            context.for_each_register(&mut |name, value| {
+                log::error!("  {name:>6}: {value:#018X}");
+                writer.push_register(name, value);
+            });
+        }
+
+        log::error!("  # PC                 Call Site");
+        if let Some((pc, sp)) = pc_sp {
+            capture_frames(pc, sp, &mut writer);
+        }
+
+        log::error!("  Loaded modules:");
+        let mut push_module = |name: Option<&str>, base: u64, size: u64| {
+            log::error!("    {base:016X} {size:08X} {}", name.unwrap_or("<no name>"));
+            writer.push_module(name, base, size);
+        };
+        image::for_each_loaded_image(&mut push_module);
+        config_tables::debug_image_info_table::for_each_debug_region(|name, base, size| {
+            push_module(Some(name), base, size)
+        });
+
+        let log_tail = self.log_tail.try_lock();
+        Some(writer.finish(|buffer| match log_tail.as_deref() {
+            Some(Some(log_tail)) => {
+                let len = buffer.len().min(MAX_LOG_TAIL);
+                log_tail.tail(&mut buffer[..len])
+            }
+            _ => 0,
+        }))
+    }
+
+    /// Logs and publishes the crash record persisted by the previous boot, if any, and clears it from the store.
+    pub fn publish_previous_record(&self) {
+        let Some(store) = self.store.lock().clone() else {
+            return;
+        };
+        let Some(bytes) = store.retrieve() else {
+            return;
+        };
+
+        match CrashRecord::from_bytes(&bytes) {
+            Some(record) => {
+                log::error!("The previous boot ended in a crash.");
+                log_record(&record);
+                if let Err(err) = install_crash_record_table(&bytes) {
+                    log::error!("Failed to publish the crash record from the previous boot: {err:?}");
+                }
+            }
+            None => log::warn!("Discarding invalid crash record from the previous boot."),
+        }
+
+        if let Err(err) = store.clear() {
+            log::error!("Failed to clear the crash record from the previous boot: {err:?}");
+        }
+    }
+}
+
+impl Default for CrashRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The watchdog hook called by the core Watchdog Timer Architectural Protocol. Captures and reports a crash record
+/// before the handler registered by the platform (if any) is called and the watchdog resets the system.
+pub fn watchdog_hook(period: u64) {
+    log::error!("Watchdog timer expired after {period} (100ns units), capturing crash record.");
+    CRASH_RECORDER.capture(CrashReason::WatchdogTimeout { period });
+}
+
+/// The unhandled exception hook set by the core. Captures and reports a crash record before the system panics.
+pub fn exception_hook(exception_type: ExceptionType, context: &ExceptionContext) {
+    log::error!("Capturing crash record for unhandled exception {exception_type:#X}.");
+    CRASH_RECORDER.capture_exception(exception_type, context);
+}
+
+fn log_record(record: &CrashRecord) {
+    log::error!("Crash record: {:?}", record.reason);
+    log::error!("  TPL: {:#x}", record.tpl);
+    log::error!("  Running image: {}", record.running_image.as_deref().unwrap_or("<DXE Core>"));
+    for register in &record.registers {
+        log::error!("  {:>6}: {:#018X}", register.name, register.value);
+    }
+    log::error!("  # PC                 Call Site");
+    for (i, frame) in record.frames.iter().enumerate() {
+        log::error!(
+            "  {i} {:016X}   {}+{:X}",
+            frame.pc,
+            frame.module.as_deref().unwrap_or("<no module>"),
+            frame.offset
+        );
+    }
+    log::error!("  Loaded modules:");
+    for module in &record.modules {
+        log::error!("    {:016X} {:08X} {}", module.base, module.size, module.name.as_deref().unwrap_or("<no name>"));
+    }
+    for line in record.log_tail.lines() {
+        log::error!("  | {line}");
+    }
+}
+
+/// Installs `record` as the crash record configuration table: a `u32` length followed by the record bytes. The table
+/// is allocated as ACPI reclaim memory so the OS can reclaim it once it has been consumed.
+fn install_crash_record_table(record: &[u8]) -> Result<(), patina::error::EfiError> {
+    let length = record.len() as u32;
+    let table = core_allocate_pool(efi::ACPI_RECLAIM_MEMORY, size_of::<u32>() + record.len())? as *mut u8;
+    // Safety: table was just allocated with room for the length and the record.
+    unsafe {
+        table.cast::<u32>().write_unaligned(length);
+        core::ptr::copy_nonoverlapping(record.as_ptr(), table.add(size_of::<u32>()), record.len());
+    }
+
+    let mut st = SYSTEM_TABLE.lock();
+    let st = st.as_mut().ok_or(patina::error::EfiError::NotReady)?;
+    config_tables::core_install_configuration_table(CRASH_RECORD_TABLE_GUID, table as *mut c_void, st)
+}
+
+#[cfg(not(test))]
+fn capture_frames(pc: u64, sp: u64, writer: &mut CrashRecordWriter) {
+    let mut index = 0;
+    // SAFETY: pc and sp were taken from a context saved by an interrupt or exception, which the stack trace module
+    // must trust. It does its best not to fault on a corrupt stack.
+    let result = unsafe {
+        patina_stacktrace::StackTrace::walk_with(pc, sp, |frame| {
+            let mut push = |module: Option<&str>| push_frame(writer, index, frame.pc, module, frame.pc_rva);
+            match frame.image_name {
+                Some(name) => push(Some(name)),
+                None => image::with_image_name_containing(frame.pc as usize, push),
+            }
+            index += 1;
+        })
+    };
+    if let Err(err) = result {
+        log::warn!("Crash record stack trace is incomplete: {err}");
+    }
+}
+
+// Walking the stack of the host test process is not supported, so tests only record the faulting frame.
+#[cfg(test)]
+fn capture_frames(pc: u64, _sp: u64, writer: &mut CrashRecordWriter) {
+    push_frame(writer, 0, pc, None, pc);
+}
+
+fn push_frame(writer: &mut CrashRecordWriter, index: usize, pc: u64, module: Option<&str>, offset: u64) {
+    log::error!("  {index} {pc:016X}   {}+{offset:X}", module.unwrap_or("<no module>"));
+    writer.push_frame(pc, module, offset);
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::{systemtables::init_system_table, test_support};
+    use patina::error::Result;
+    use std::sync::Mutex;
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+                init_system_table();
+            }
+            PERSISTED.lock().unwrap().take();
+            f();
+        })
+        .unwrap();
+    }
+
+    struct TestLogTail;
+
+    impl LogTail for TestLogTail {
+        fn tail(&self, buffer: &mut [u8]) -> usize {
+            assert_eq!(buffer.len(), MAX_LOG_TAIL);
+            let tail = b"last line\n";
+            buffer[..tail.len()].copy_from_slice(tail);
+            tail.len()
+        }
+    }
+
+    // Stands in for the variable or reserved memory region that survives the reset.
+    static PERSISTED: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+    struct TestStore;
+
+    impl CrashRecordStore for TestStore {
+        fn store(&self, record: &[u8]) -> Result<()> {
+            *PERSISTED.lock().unwrap() = Some(record.to_vec());
+            Ok(())
+        }
+
+        fn retrieve(&self) -> Option<Vec<u8>> {
+            PERSISTED.lock().unwrap().clone()
+        }
+
+        fn clear(&self) -> Result<()> {
+            PERSISTED.lock().unwrap().take();
+            Ok(())
+        }
+    }
+
+    fn crash_record_table() -> Option<Vec<u8>> {
+        let st = SYSTEM_TABLE.lock();
+        let st = st.as_ref().unwrap().as_ref();
+        if st.configuration_table.is_null() {
+            return None;
+        }
+        // Safety: the configuration table is maintained by the core and is valid for the number of entries.
+        let tables = unsafe { core::slice::from_raw_parts(st.configuration_table, st.number_of_table_entries) };
+        let table = tables.iter().find(|table| table.vendor_guid == CRASH_RECORD_TABLE_GUID)?.vendor_table as *const u8;
+        // Safety: the crash record table is a u32 length followed by that many bytes.
+        unsafe {
+            let length = table.cast::<u32>().read_unaligned() as usize;
+            Some(core::slice::from_raw_parts(table.add(size_of::<u32>()), length).to_vec())
+        }
+    }
+
+    fn persisted_record() -> Option<CrashRecord> {
+        PERSISTED.lock().unwrap().as_deref().map(|bytes| CrashRecord::from_bytes(bytes).unwrap())
+    }
+
+    #[test]
+    fn test_capture_records_interrupted_tpl_and_log_tail() {
+        with_locked_state(|| {
+            let recorder = CrashRecorder::new();
+            recorder.set_store(Service::mock(Box::new(TestStore)));
+
+            // Before any timer interrupt the current TPL is used.
+            events::restore_tpl(efi::TPL_APPLICATION);
+            recorder.capture(CrashReason::WatchdogTimeout { period: 10 });
+            let record = persisted_record().unwrap();
+            assert_eq!(record.tpl, efi::TPL_APPLICATION);
+            assert!(record.frames.is_empty());
+            assert!(record.log_tail.is_empty());
+
+            let old_tpl = events::raise_tpl(efi::TPL_CALLBACK);
+            // SAFETY: the context is only read for its program counter and stack pointer.
+            let context: ExceptionContext = unsafe { core::mem::zeroed() };
+            recorder.record_interrupted_context(&context);
+            events::restore_tpl(old_tpl);
+
+            recorder.set_log_tail(Service::mock(Box::new(TestLogTail)));
+
+            recorder.capture(CrashReason::WatchdogTimeout { period: 10 });
+            let record = persisted_record().unwrap();
+            assert_eq!(record.reason, CrashReason::WatchdogTimeout { period: 10 });
+            assert_eq!(record.tpl, efi::TPL_CALLBACK);
+            assert_eq!(record.running_image, None);
+            assert!(record.registers.is_empty());
+            assert_eq!(record.frames.len(), 1);
+            assert_eq!(record.log_tail, "last line\n");
+        });
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_capture_exception_records_the_saved_context() {
+        with_locked_state(|| {
+            let recorder = CrashRecorder::new();
+            recorder.set_store(Service::mock(Box::new(TestStore)));
+            events::restore_tpl(efi::TPL_APPLICATION);
+
+            // SAFETY: an all zero context is a valid (if meaningless) register state.
+            let mut context: ExceptionContext = unsafe { core::mem::zeroed() };
+            context.rip = 0xDEAD_0000;
+            context.rsp = 0x8000;
+            context.rax = 0x42;
+            context.cr2 = 0xBAD;
+
+            recorder.capture_exception(0xE, &context);
+            let record = persisted_record().unwrap();
+            assert_eq!(record.reason, CrashReason::Exception { exception_type: 0xE });
+            assert_eq!(record.tpl, efi::TPL_APPLICATION);
+            let register = |name: &str| record.registers.iter().find(|r| r.name == name).map(|r| r.value);
+            assert_eq!(register("RIP"), Some(0xDEAD_0000));
+            assert_eq!(register("RSP"), Some(0x8000));
+            assert_eq!(register("RAX"), Some(0x42));
+            assert_eq!(register("CR2"), Some(0xBAD));
+            assert_eq!(record.frames[0].pc, 0xDEAD_0000);
+        });
+    }
+
+    #[test]
+    fn test_capture_without_a_store_only_logs_the_record() {
+        with_locked_state(|| {
+            let recorder = CrashRecorder::new();
+            recorder.capture(CrashReason::WatchdogTimeout { period: 1234 });
+            assert!(PERSISTED.lock().unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_capture_skips_what_is_locked() {
+        with_locked_state(|| {
+            let recorder = CrashRecorder::new();
+            recorder.set_store(Service::mock(Box::new(TestStore)));
+            recorder.set_log_tail(Service::mock(Box::new(TestLogTail)));
+
+            // A held log tail lock only leaves the log tail out.
+            let log_tail = recorder.log_tail.lock();
+            recorder.capture(CrashReason::WatchdogTimeout { period: 1 });
+            drop(log_tail);
+            let record = persisted_record().unwrap();
+            assert_eq!(record.reason, CrashReason::WatchdogTimeout { period: 1 });
+            assert!(record.log_tail.is_empty());
+
+            // Nothing is persisted while the store or the buffer is in use.
+            let store = recorder.store.lock();
+            recorder.capture(CrashReason::WatchdogTimeout { period: 2 });
+            drop(store);
+            assert_eq!(persisted_record().unwrap().reason, CrashReason::WatchdogTimeout { period: 1 });
+
+            let buffer = recorder.buffer.lock();
+            recorder.capture(CrashReason::WatchdogTimeout { period: 3 });
+            drop(buffer);
+            assert_eq!(persisted_record().unwrap().reason, CrashReason::WatchdogTimeout { period: 1 });
+        });
+    }
+
+    #[test]
+    fn test_previous_record_is_published_and_cleared() {
+        with_locked_state(|| {
+            let recorder = CrashRecorder::new();
+            recorder.set_store(Service::mock(Box::new(TestStore)));
+
+            // Nothing is published if no record was persisted.
+            recorder.publish_previous_record();
+            assert_eq!(crash_record_table(), None);
+
+            // SAFETY: an all zero context is a valid (if meaningless) register state.
+            let context: ExceptionContext = unsafe { core::mem::zeroed() };
+            recorder.capture_exception(0xD, &context);
+            let persisted = PERSISTED.lock().unwrap().clone();
+
+            recorder.publish_previous_record();
+            assert_eq!(crash_record_table(), persisted);
+            assert!(PERSISTED.lock().unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_invalid_previous_record_is_discarded() {
+        with_locked_state(|| {
+            let recorder = CrashRecorder::new();
+            recorder.set_store(Service::mock(Box::new(TestStore)));
+            *PERSISTED.lock().unwrap() = Some(b"not a crash record".to_vec());
+
+            recorder.publish_previous_record();
+            assert_eq!(crash_record_table(), None);
+            assert!(PERSISTED.lock().unwrap().is_none());
+        });
+    }
+}
@@ -6,11 +6,17 @@
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
+mod connect_trace;
+
+pub use connect_trace::CONNECT_TRACE;
+use connect_trace::{Attempt, CandidateSource};
+
 use alloc::{
     collections::{BTreeMap, BTreeSet},
     vec::Vec,
 };
 use core::ptr::NonNull;
+use mu_rust_helpers::perf_timer::{Arch, ArchFunctionality};
 use patina::{
     error::EfiError,
     performance::{
@@ -196,30 +202,37 @@ fn core_connect_single_controller(
     //1. Context Override
     let mut driver_candidates = Vec::new();
     driver_candidates.extend(get_bindings_for_handles(driver_handles));
+    CONNECT_TRACE.record_candidates(&driver_candidates, CandidateSource::Context);
 
     //2. Platform Driver Override
     let mut platform_override_drivers = get_platform_driver_override_bindings(controller_handle);
     platform_override_drivers.retain(|x| !driver_candidates.contains(x));
+    CONNECT_TRACE.record_candidates(&platform_override_drivers, CandidateSource::PlatformOverride);
     driver_candidates.append(&mut platform_override_drivers);
 
     //3. Driver Family Override Search
     let mut family_override_drivers = get_family_override_bindings();
     family_override_drivers.retain(|x| !driver_candidates.contains(x));
+    CONNECT_TRACE.record_candidates(&family_override_drivers, CandidateSource::FamilyOverride);
     driver_candidates.append(&mut family_override_drivers);
 
     //4. Bus Specific Driver Override
     let mut bus_override_drivers = get_bus_specific_override_bindings(controller_handle);
     bus_override_drivers.retain(|x| !driver_candidates.contains(x));
+    CONNECT_TRACE.record_candidates(&bus_override_drivers, CandidateSource::BusSpecificOverride);
     driver_candidates.append(&mut bus_override_drivers);
 
     //5. Driver Binding Search
     let mut driver_bindings = get_all_driver_bindings();
     driver_bindings.retain(|x| !driver_candidates.contains(x));
+    CONNECT_TRACE.record_candidates(&driver_bindings, CandidateSource::DriverBinding);
     driver_candidates.append(&mut driver_bindings);
 
     //loop until no more drivers can be started on handle.
     let mut one_started = false;
+    let mut pass = 0;
     loop {
+        pass += 1;
         let mut started_drivers = Vec::new();
         for driver_binding_interface in driver_candidates.clone() {
             let driver_binding = unsafe { &mut *(driver_binding_interface) };
@@ -232,8 +245,10 @@ fn core_connect_single_controller(
             );
 
             //driver claims support; attempt to start it.
+            let supported_begin = Arch::cpu_count();
             match (driver_binding.supported)(driver_binding_interface, controller_handle, device_path) {
                 efi::Status::SUCCESS => {
+                    let supported_ticks = Arch::cpu_count().wrapping_sub(supported_begin);
                     perf_driver_binding_support_end(
                         driver_binding.driver_binding_handle,
                         controller_handle,
@@ -248,11 +263,18 @@ fn core_connect_single_controller(
                         create_performance_measurement,
                     );
 
-                    if (driver_binding.start)(driver_binding_interface, controller_handle, device_path)
-                        == efi::Status::SUCCESS
-                    {
+                    let start_begin = Arch::cpu_count();
+                    let start_status = (driver_binding.start)(driver_binding_interface, controller_handle, device_path);
+                    if start_status == efi::Status::SUCCESS {
                         one_started = true;
                     }
+                    CONNECT_TRACE.record_attempt(Attempt {
+                        driver_binding_handle: driver_binding.driver_binding_handle,
+                        pass,
+                        supported: efi::Status::SUCCESS,
+                        supported_ticks,
+                        start: Some((start_status, Arch::cpu_count().wrapping_sub(start_begin))),
+                    });
 
                     perf_driver_binding_start_end(
                         driver_binding.driver_binding_handle,
@@ -260,7 +282,14 @@ fn core_connect_single_controller(
                         create_performance_measurement,
                     );
                 }
-                _ => {
+                status => {
+                    CONNECT_TRACE.record_attempt(Attempt {
+                        driver_binding_handle: driver_binding.driver_binding_handle,
+                        pass,
+                        supported: status,
+                        supported_ticks: Arch::cpu_count().wrapping_sub(supported_begin),
+                        start: None,
+                    });
                     perf_driver_binding_support_end(
                         driver_binding.driver_binding_handle,
                         controller_handle,
@@ -317,17 +346,27 @@ pub unsafe fn core_connect_controller(
     remaining_device_path: Option<*mut efi::protocols::device_path::Protocol>,
     recursive: bool,
 ) -> Result<(), EfiError> {
-    authenticate_connect(handle, remaining_device_path, recursive)?;
+    CONNECT_TRACE.begin(handle, recursive);
+    if let Err(err) = authenticate_connect(handle, remaining_device_path, recursive) {
+        CONNECT_TRACE.end(err.into());
+        return Err(err);
+    }
 
     let return_status = core_connect_single_controller(handle, driver_handles, remaining_device_path);
 
     if recursive {
-        for child in PROTOCOL_DB.get_child_handles(handle) {
+        let children = PROTOCOL_DB.get_child_handles(handle);
+        CONNECT_TRACE.record_children(&children);
+        for child in children {
             //ignore the return value to match behavior of edk2 reference.
             _ = unsafe { core_connect_controller(child, Vec::new(), None, true) };
         }
     }
 
+    CONNECT_TRACE.end(match return_status {
+        Ok(()) => efi::Status::SUCCESS,
+        Err(err) => err.into(),
+    });
     return_status
 }
 
@@ -0,0 +1,536 @@
+//! DXE Core ConnectController Tracing
+//!
+//! ConnectController combines the context, platform, family and bus specific overrides with driver binding version
+//! ordering to decide which driver manages a controller, but none of that decision is visible when the wrong driver
+//! ends up claiming a device. When enabled, this module records each ConnectController call:
+//!
+//! - the candidate driver bindings in priority order, along with the source of their priority ([CandidateSource]),
+//! - the `Supported()` result of every candidate and how long it took,
+//! - the `Start()` result of the drivers that were started and how long it took, and
+//! - the children that were connected recursively.
+//!
+//! A summary is logged for each controller once its connect completes, and the most recent records are kept for the
+//! `connects` debugger command.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use mu_rust_helpers::perf_timer::{Arch, ArchFunctionality};
+use patina::error::EfiError;
+use r_efi::efi;
+
+use crate::{image, protocols::PROTOCOL_DB, tpl_lock};
+
+/// The number of completed connect records kept for the debugger.
+const MAX_HISTORY: usize = 32;
+
+/// Where a candidate driver binding's priority came from, per UEFI Spec 2.10 section 7.3.12.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandidateSource {
+    /// The driver image handle was passed to ConnectController by the caller.
+    Context,
+    /// Returned by the EFI_PLATFORM_DRIVER_OVERRIDE_PROTOCOL.
+    PlatformOverride,
+    /// Ordered by the EFI_DRIVER_FAMILY_OVERRIDE_PROTOCOL version.
+    FamilyOverride,
+    /// Returned by the EFI_BUS_SPECIFIC_DRIVER_OVERRIDE_PROTOCOL on the controller.
+    BusSpecificOverride,
+    /// Ordered by the driver binding version.
+    DriverBinding,
+}
+
+impl fmt::Display for CandidateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CandidateSource::Context => "context override",
+            CandidateSource::PlatformOverride => "platform override",
+            CandidateSource::FamilyOverride => "family override",
+            CandidateSource::BusSpecificOverride => "bus specific override",
+            CandidateSource::DriverBinding => "driver binding version",
+        })
+    }
+}
+
+/// A driver binding considered by ConnectController.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    /// The handle the driver binding is installed on.
+    pub driver_binding_handle: efi::Handle,
+    /// The image that produced the driver binding.
+    pub image_handle: efi::Handle,
+    /// The driver binding version.
+    pub version: u32,
+    /// The driver family override version, if the driver produces the protocol.
+    pub family_version: Option<u32>,
+    /// The source of the candidate's priority.
+    pub source: CandidateSource,
+}
+
+/// The result of offering a controller to a candidate driver binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attempt {
+    /// The handle the driver binding is installed on.
+    pub driver_binding_handle: efi::Handle,
+    /// The pass over the candidate list the attempt was made in, starting at 1.
+    pub pass: usize,
+    /// The status returned by `Supported()`.
+    pub supported: efi::Status,
+    /// The number of performance counter ticks spent in `Supported()`.
+    pub supported_ticks: u64,
+    /// The status returned by `Start()` and the ticks spent in it, if the driver was started.
+    pub start: Option<(efi::Status, u64)>,
+}
+
+/// The trace of a single ConnectController call on one controller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectRecord {
+    /// The controller being connected.
+    pub controller: efi::Handle,
+    /// The nesting depth of the call, 0 for calls that are not made while connecting another controller.
+    pub depth: usize,
+    /// Whether the children of the controller are connected recursively.
+    pub recursive: bool,
+    /// The candidate driver bindings in priority order.
+    pub candidates: Vec<Candidate>,
+    /// Every `Supported()` call made, in order.
+    pub attempts: Vec<Attempt>,
+    /// The children connected recursively.
+    pub children: Vec<efi::Handle>,
+    /// The status ConnectController returned for this controller.
+    pub status: efi::Status,
+}
+
+impl ConnectRecord {
+    fn new(controller: efi::Handle, depth: usize, recursive: bool) -> Self {
+        Self {
+            controller,
+            depth,
+            recursive,
+            candidates: Vec::new(),
+            attempts: Vec::new(),
+            children: Vec::new(),
+            status: efi::Status::SUCCESS,
+        }
+    }
+
+    /// Returns the attempts whose driver was successfully started.
+    pub fn started(&self) -> impl Iterator<Item = &Attempt> {
+        self.attempts.iter().filter(|attempt| matches!(attempt.start, Some((efi::Status::SUCCESS, _))))
+    }
+
+    fn candidate(&self, driver_binding_handle: efi::Handle) -> Option<&Candidate> {
+        self.candidates.iter().find(|candidate| candidate.driver_binding_handle == driver_binding_handle)
+    }
+
+    /// Returns a displayable summary of the record.
+    pub fn summary(&self) -> impl fmt::Display + '_ {
+        Summary(self)
+    }
+}
+
+/// Displays the driver binding handle along with the file name of the image that produced it.
+struct DriverLabel<'a>(&'a ConnectRecord, efi::Handle);
+
+impl fmt::Display for DriverLabel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.1)?;
+        let Some(candidate) = self.0.candidate(self.1) else {
+            return Ok(());
+        };
+        image::with_image_name(candidate.image_handle, |name| match name {
+            Some(name) => write!(f, " ({name})"),
+            None => Ok(()),
+        })
+    }
+}
+
+/// Displays a status by name.
+struct StatusName(efi::Status);
+
+impl fmt::Display for StatusName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match EfiError::status_to_result(self.0) {
+            Ok(()) => f.write_str("Success"),
+            Err(err) => write!(f, "{err:?}"),
+        }
+    }
+}
+
+/// Displays a number of performance counter ticks in microseconds.
+struct Elapsed(u64);
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match Arch::perf_frequency() {
+            0 => write!(f, "{} ticks", self.0),
+            frequency => write!(f, "{} us", (self.0 as u128 * 1_000_000 / frequency as u128)),
+        }
+    }
+}
+
+struct Summary<'a>(&'a ConnectRecord);
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let record = self.0;
+        let indent = record.depth * 2;
+        write!(f, "{:indent$}ConnectController({:?})", "", record.controller)?;
+        if record.recursive {
+            f.write_str(" recursive")?;
+        }
+        writeln!(f, ": {}", StatusName(record.status))?;
+
+        for (index, candidate) in record.candidates.iter().enumerate() {
+            write!(
+                f,
+                "{:indent$}  candidate {}: {} version {:#x}",
+                "",
+                index + 1,
+                DriverLabel(record, candidate.driver_binding_handle),
+                candidate.version
+            )?;
+            if let Some(family_version) = candidate.family_version {
+                write!(f, " family version {family_version:#x}")?;
+            }
+            writeln!(f, " [{}]", candidate.source)?;
+        }
+
+        for attempt in &record.attempts {
+            write!(
+                f,
+                "{:indent$}  pass {}: {} Supported: {} ({})",
+                "",
+                attempt.pass,
+                DriverLabel(record, attempt.driver_binding_handle),
+                StatusName(attempt.supported),
+                Elapsed(attempt.supported_ticks)
+            )?;
+            if let Some((status, ticks)) = attempt.start {
+                write!(f, ", Start: {} ({})", StatusName(status), Elapsed(ticks))?;
+            }
+            writeln!(f)?;
+        }
+
+        write!(f, "{:indent$}  started:", "")?;
+        let mut any = false;
+        for attempt in record.started() {
+            write!(f, " {}", DriverLabel(record, attempt.driver_binding_handle))?;
+            any = true;
+        }
+        if !any {
+            f.write_str(" none")?;
+        }
+        if !record.children.is_empty() {
+            write!(f, "\n{:indent$}  children: {:x?}", "", record.children)?;
+        }
+        Ok(())
+    }
+}
+
+struct TraceState {
+    /// The connects in progress, innermost last.
+    stack: Vec<ConnectRecord>,
+    /// The most recently completed connects, oldest first.
+    history: VecDeque<ConnectRecord>,
+}
+
+// SAFETY: The handles in the trace are only used as identifiers and never dereferenced.
+unsafe impl Send for TraceState {}
+
+/// Records the decisions made by ConnectController.
+pub struct ConnectTrace {
+    enabled: AtomicBool,
+    state: tpl_lock::TplMutex<TraceState>,
+}
+
+impl ConnectTrace {
+    /// Creates a new, disabled, trace.
+    pub const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            state: tpl_lock::TplMutex::new(
+                efi::TPL_NOTIFY,
+                TraceState { stack: Vec::new(), history: VecDeque::new() },
+                "ConnectTraceLock",
+            ),
+        }
+    }
+
+    /// Enables tracing.
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether tracing is enabled.
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Begins the trace of a connect of `controller`.
+    pub fn begin(&self, controller: efi::Handle, recursive: bool) {
+        if !self.enabled() {
+            return;
+        }
+        let mut state = self.state.lock();
+        let depth = state.stack.len();
+        state.stack.push(ConnectRecord::new(controller, depth, recursive));
+    }
+
+    /// Records the driver bindings added to the candidate list from `source`.
+    pub fn record_candidates(
+        &self,
+        driver_bindings: &[*mut efi::protocols::driver_binding::Protocol],
+        source: CandidateSource,
+    ) {
+        if !self.enabled() || driver_bindings.is_empty() {
+            return;
+        }
+        // Query the drivers before taking the lock, since the family override protocol calls into the driver.
+        let candidates: Vec<Candidate> = driver_bindings
+            .iter()
+            .map(|&driver_binding| {
+                // SAFETY: the driver bindings come from the protocol database and are valid for the connect.
+                let driver_binding = unsafe { &*driver_binding };
+                Candidate {
+                    driver_binding_handle: driver_binding.driver_binding_handle,
+                    image_handle: driver_binding.image_handle,
+                    version: driver_binding.version,
+                    family_version: family_version(driver_binding.driver_binding_handle),
+                    source,
+                }
+            })
+            .collect();
+        if let Some(record) = self.state.lock().stack.last_mut() {
+            record.candidates.extend(candidates);
+        }
+    }
+
+    /// Records the result of offering the controller to a driver binding.
+    pub fn record_attempt(&self, attempt: Attempt) {
+        if !self.enabled() {
+            return;
+        }
+        if let Some(record) = self.state.lock().stack.last_mut() {
+            record.attempts.push(attempt);
+        }
+    }
+
+    /// Records the children that are about to be connected recursively.
+    pub fn record_children(&self, children: &[efi::Handle]) {
+        if !self.enabled() {
+            return;
+        }
+        if let Some(record) = self.state.lock().stack.last_mut() {
+            record.children.extend_from_slice(children);
+        }
+    }
+
+    /// Ends the trace of the innermost connect in progress, logging its summary.
+    pub fn end(&self, status: efi::Status) {
+        if !self.enabled() {
+            return;
+        }
+        let Some(mut record) = self.state.lock().stack.pop() else {
+            return;
+        };
+        record.status = status;
+        log::info!("{}", record.summary());
+
+        let mut state = self.state.lock();
+        if state.history.len() == MAX_HISTORY {
+            state.history.pop_front();
+        }
+        state.history.push_back(record);
+    }
+
+    /// Invokes `f` with each of the most recently completed connects, oldest first. Returns false if the trace is
+    /// currently locked.
+    pub fn try_for_each_record(&self, mut f: impl FnMut(&ConnectRecord)) -> bool {
+        let Some(state) = self.state.try_lock() else {
+            return false;
+        };
+        state.history.iter().for_each(&mut f);
+        true
+    }
+}
+
+impl Default for ConnectTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the driver family override version of the driver installed on `driver_binding_handle`, if any.
+fn family_version(driver_binding_handle: efi::Handle) -> Option<u32> {
+    let protocol = PROTOCOL_DB
+        .get_interface_for_handle(driver_binding_handle, efi::protocols::driver_family_override::PROTOCOL_GUID)
+        .ok()? as *mut efi::protocols::driver_family_override::Protocol;
+    // SAFETY: the interface was installed with the driver family override guid.
+    let family_override = unsafe { protocol.as_mut() }?;
+    Some((family_override.get_version)(protocol))
+}
+
+/// The global ConnectController trace.
+pub static CONNECT_TRACE: ConnectTrace = ConnectTrace::new();
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            // SAFETY: Test code - initializing test infrastructure within global lock
+            unsafe {
+                test_support::init_test_protocol_db();
+            }
+            f();
+        })
+        .unwrap();
+    }
+
+    extern "efiapi" fn mock_supported(
+        _this: *mut efi::protocols::driver_binding::Protocol,
+        _controller_handle: efi::Handle,
+        _remaining_device_path: *mut efi::protocols::device_path::Protocol,
+    ) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_stop(
+        _this: *mut efi::protocols::driver_binding::Protocol,
+        _controller_handle: efi::Handle,
+        _num_children: usize,
+        _child_handle_buffer: *mut efi::Handle,
+    ) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    fn driver_binding(handle: usize, version: u32) -> efi::protocols::driver_binding::Protocol {
+        efi::protocols::driver_binding::Protocol {
+            version,
+            supported: mock_supported,
+            start: mock_supported,
+            stop: mock_stop,
+            driver_binding_handle: handle as efi::Handle,
+            image_handle: handle as efi::Handle,
+        }
+    }
+
+    #[test]
+    fn disabled_trace_should_record_nothing() {
+        with_locked_state(|| {
+            let trace = ConnectTrace::new();
+            trace.begin(0x10 as efi::Handle, false);
+            trace.end(efi::Status::SUCCESS);
+
+            let mut count = 0;
+            assert!(trace.try_for_each_record(|_| count += 1));
+            assert_eq!(0, count);
+        });
+    }
+
+    #[test]
+    fn trace_should_record_candidates_attempts_and_result() {
+        with_locked_state(|| {
+            let trace = ConnectTrace::new();
+            trace.enable();
+
+            let mut platform = driver_binding(0x100, 0x10);
+            let mut generic = driver_binding(0x200, 0x20);
+
+            trace.begin(0x10 as efi::Handle, true);
+            trace.record_candidates(&[&mut platform as *mut _], CandidateSource::PlatformOverride);
+            trace.record_candidates(&[&mut generic as *mut _], CandidateSource::DriverBinding);
+            trace.record_attempt(Attempt {
+                driver_binding_handle: 0x100 as efi::Handle,
+                pass: 1,
+                supported: efi::Status::SUCCESS,
+                supported_ticks: 1,
+                start: Some((efi::Status::SUCCESS, 2)),
+            });
+            trace.record_attempt(Attempt {
+                driver_binding_handle: 0x200 as efi::Handle,
+                pass: 1,
+                supported: efi::Status::UNSUPPORTED,
+                supported_ticks: 1,
+                start: None,
+            });
+            trace.record_children(&[0x11 as efi::Handle]);
+            trace.end(efi::Status::SUCCESS);
+
+            let mut records = Vec::new();
+            assert!(trace.try_for_each_record(|record| records.push(record.clone())));
+            assert_eq!(1, records.len());
+
+            let record = &records[0];
+            assert_eq!(0x10 as efi::Handle, record.controller);
+            assert!(record.recursive);
+            assert_eq!(
+                vec![CandidateSource::PlatformOverride, CandidateSource::DriverBinding],
+                record.candidates.iter().map(|c| c.source).collect::<Vec<_>>()
+            );
+            assert_eq!(0x10, record.candidates[0].version);
+            assert_eq!(None, record.candidates[0].family_version);
+            assert_eq!(
+                vec![0x100 as efi::Handle],
+                record.started().map(|a| a.driver_binding_handle).collect::<Vec<_>>()
+            );
+            assert_eq!(vec![0x11 as efi::Handle], record.children);
+
+            let summary = alloc::format!("{}", record.summary());
+            assert!(summary.contains("ConnectController(0x10) recursive: Success"));
+            assert!(summary.contains("[platform override]"));
+            assert!(summary.contains("Supported: Unsupported"));
+            assert!(summary.contains("children: [0x11]"));
+        });
+    }
+
+    #[test]
+    fn nested_connects_should_be_recorded_separately() {
+        with_locked_state(|| {
+            let trace = ConnectTrace::new();
+            trace.enable();
+
+            trace.begin(0x10 as efi::Handle, true);
+            trace.record_children(&[0x11 as efi::Handle]);
+            trace.begin(0x11 as efi::Handle, true);
+            trace.end(efi::Status::NOT_FOUND);
+            trace.end(efi::Status::SUCCESS);
+
+            let mut records = Vec::new();
+            trace.try_for_each_record(|record| records.push((record.controller, record.depth, record.status)));
+            assert_eq!(
+                vec![(0x11 as efi::Handle, 1, efi::Status::NOT_FOUND), (0x10 as efi::Handle, 0, efi::Status::SUCCESS)],
+                records
+            );
+        });
+    }
+
+    #[test]
+    fn history_should_keep_the_most_recent_records() {
+        with_locked_state(|| {
+            let trace = ConnectTrace::new();
+            trace.enable();
+
+            for controller in 1..=MAX_HISTORY + 1 {
+                trace.begin(controller as efi::Handle, false);
+                trace.end(efi::Status::SUCCESS);
+            }
+
+            let mut controllers = Vec::new();
+            trace.try_for_each_record(|record| controllers.push(record.controller as usize));
+            assert_eq!((2..=MAX_HISTORY + 1).collect::<Vec<_>>(), controllers);
+        });
+    }
+}
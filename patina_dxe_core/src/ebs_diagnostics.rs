@@ -0,0 +1,415 @@
+//! DXE Core ExitBootServices Diagnostics
+//!
+//! ExitBootServices fails with `EFI_INVALID_PARAMETER` whenever the memory map changed after the caller retrieved its
+//! map key, which gives the caller no indication of what changed the map or why. When enabled, this module records
+//! every GCD memory map change along with the event notify function that was running when it happened, so that a
+//! failed (or successful) ExitBootServices call can be followed by a summary identifying:
+//!
+//! - the structured reason the memory map could not be terminated ([ExitBootServicesFailure]),
+//! - which PRE_EBS / BEFORE_EXIT_BOOT_SERVICES callbacks allocated or freed memory,
+//! - timers that are still armed, and
+//! - DMA mappings that were never unmapped (if a [DmaMappingTracker] service is registered).
+//!
+//! The summary is produced after the memory space has been locked, so nothing in this module allocates once
+//! diagnostics have been enabled; change records are stored in a buffer whose capacity is reserved up front.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use patina::{
+    component::service::{
+        Service,
+        dma::{DmaMapping, DmaMappingTracker},
+    },
+    error::EfiError,
+};
+use r_efi::efi;
+
+use crate::{events::EVENT_DB, gcd::MapChangeType, image, tpl_lock};
+
+/// The maximum number of memory map changes recorded between two GetMemoryMap calls.
+const MAX_RECORDED_CHANGES: usize = 64;
+
+/// The portion of boot that a memory map change was made in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Normal boot, outside of ExitBootServices.
+    Boot,
+    /// The PRE_EBS event group is being signaled.
+    PreExitBootServices,
+    /// The EFI_EVENT_GROUP_BEFORE_EXIT_BOOT_SERVICES event group is being signaled.
+    BeforeExitBootServices,
+}
+
+/// A single recorded change to the GCD memory map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapChange {
+    /// The phase the change was made in.
+    pub phase: Phase,
+    /// The address of the event notify function that was executing when the change was made, or 0 if the change was
+    /// not made from an event notification.
+    pub notify_function: usize,
+    /// The kind of change that was made.
+    pub change: MapChangeType,
+}
+
+/// The reason a call to ExitBootServices failed to terminate the memory map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitBootServicesFailure {
+    /// GetMemoryMap was never called with a map key, so the caller could not have had a valid key.
+    NoMemoryMap,
+    /// The key passed to ExitBootServices was not the key returned by the most recent GetMemoryMap call.
+    StaleMapKey {
+        /// The key returned by the most recent GetMemoryMap call.
+        last_map_key: usize,
+    },
+    /// The key was current when it was returned, but the memory map changed before ExitBootServices terminated it.
+    MemoryMapChanged {
+        /// The number of recorded changes since the key was returned.
+        changes: usize,
+        /// The number of changes that could not be recorded because the change buffer was full.
+        dropped: usize,
+    },
+    /// The key was current and no memory map change was observed. This typically means the map changed before
+    /// diagnostics were enabled.
+    Unattributed,
+}
+
+struct State {
+    phase: Phase,
+    last_map_key: Option<usize>,
+    changes: Vec<MapChange>,
+}
+
+/// Records memory map changes and produces the ExitBootServices summary.
+pub struct ExitBootServicesDiagnostics {
+    enabled: AtomicBool,
+    current_notify: AtomicUsize,
+    dropped_changes: AtomicUsize,
+    state: tpl_lock::TplMutex<State>,
+    dma_mapping_tracker: tpl_lock::TplMutex<Option<Service<dyn DmaMappingTracker>>>,
+}
+
+/// The global ExitBootServices diagnostics instance.
+pub static EBS_DIAGNOSTICS: ExitBootServicesDiagnostics = ExitBootServicesDiagnostics::new();
+
+impl ExitBootServicesDiagnostics {
+    /// Creates a new, disabled, diagnostics instance.
+    pub const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            current_notify: AtomicUsize::new(0),
+            dropped_changes: AtomicUsize::new(0),
+            state: tpl_lock::TplMutex::new(
+                efi::TPL_HIGH_LEVEL,
+                State { phase: Phase::Boot, last_map_key: None, changes: Vec::new() },
+                "EbsDiagnosticsLock",
+            ),
+            dma_mapping_tracker: tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, None, "EbsDmaTrackerLock"),
+        }
+    }
+
+    /// Enables recording. The change buffer is allocated here so that recording never allocates.
+    pub fn enable(&self) {
+        self.state.lock().changes.reserve_exact(MAX_RECORDED_CHANGES);
+        self.enabled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true if diagnostics have been enabled.
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Registers the service used to report outstanding DMA mappings.
+    pub fn set_dma_mapping_tracker(&self, tracker: Service<dyn DmaMappingTracker>) {
+        *self.dma_mapping_tracker.lock() = Some(tracker);
+    }
+
+    /// Marks `notify_function` as the currently executing event notify function. Returns the previous value, which
+    /// must be passed to [exit_notify](Self::exit_notify) once the notify function returns.
+    pub fn enter_notify(&self, notify_function: efi::EventNotify) -> usize {
+        self.current_notify.swap(notify_function as usize, Ordering::SeqCst)
+    }
+
+    /// Restores the notify function that was executing before the matching [enter_notify](Self::enter_notify).
+    pub fn exit_notify(&self, previous: usize) {
+        self.current_notify.store(previous, Ordering::SeqCst);
+    }
+
+    /// Sets the phase that subsequent memory map changes are attributed to.
+    pub fn set_phase(&self, phase: Phase) {
+        if self.enabled() {
+            self.state.lock().phase = phase;
+        }
+    }
+
+    /// Records that GetMemoryMap returned `map_key`. Changes recorded prior to this point no longer affect the key.
+    pub fn record_memory_map_key(&self, map_key: usize) {
+        if !self.enabled() {
+            return;
+        }
+        if let Some(mut state) = self.state.try_lock() {
+            state.last_map_key = Some(map_key);
+            state.changes.clear();
+            self.dropped_changes.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// Records a change to the GCD memory map.
+    pub fn record_map_change(&self, change: MapChangeType) {
+        if !self.enabled() {
+            return;
+        }
+        let notify_function = self.current_notify.load(Ordering::SeqCst);
+        // The map change callback can be invoked while this lock is held (e.g. a notify function firing on lock
+        // release), so never block here; a change that cannot be recorded is counted instead.
+        match self.state.try_lock() {
+            Some(mut state) if state.changes.len() < state.changes.capacity() => {
+                let phase = state.phase;
+                state.changes.push(MapChange { phase, notify_function, change });
+            }
+            _ => {
+                self.dropped_changes.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Determines why terminating the memory map with `map_key` failed.
+    pub fn classify_failure(&self, map_key: usize) -> ExitBootServicesFailure {
+        let state = self.state.lock();
+        let dropped = self.dropped_changes.load(Ordering::SeqCst);
+        match state.last_map_key {
+            None => ExitBootServicesFailure::NoMemoryMap,
+            Some(last_map_key) if last_map_key != map_key => ExitBootServicesFailure::StaleMapKey { last_map_key },
+            Some(_) if state.changes.is_empty() && dropped == 0 => ExitBootServicesFailure::Unattributed,
+            Some(_) => ExitBootServicesFailure::MemoryMapChanged { changes: state.changes.len(), dropped },
+        }
+    }
+
+    fn change(&self, index: usize) -> Option<MapChange> {
+        self.state.lock().changes.get(index).copied()
+    }
+
+    /// Logs the ExitBootServices summary for a call with `map_key` that terminated the memory map with `result`.
+    pub fn report(&self, map_key: usize, result: Result<(), EfiError>) {
+        if !self.enabled() {
+            return;
+        }
+
+        match result {
+            Ok(()) => log::info!("ExitBootServices summary: memory map terminated with key {map_key:#x}."),
+            Err(err) => log::error!(
+                "ExitBootServices summary: failed to terminate memory map with key {map_key:#x} ({err:?}): {:?}",
+                self.classify_failure(map_key)
+            ),
+        }
+
+        // Memory map changes are only relevant to the caller if they invalidated the key, but changes made by
+        // ExitBootServices callbacks are always worth reporting as they are made after the caller's last
+        // GetMemoryMap call.
+        let mut index = 0;
+        while let Some(change) = self.change(index) {
+            index += 1;
+            if result.is_ok() && change.phase == Phase::Boot {
+                continue;
+            }
+            let level = if result.is_ok() { log::Level::Warn } else { log::Level::Error };
+            if change.notify_function == 0 {
+                log::log!(level, "  {:?} during {:?} outside of an event notification.", change.change, change.phase);
+                continue;
+            }
+            image::with_image_name_containing(change.notify_function, |name| {
+                log::log!(
+                    level,
+                    "  {:?} during {:?} by notify function {:#x} ({}).",
+                    change.change,
+                    change.phase,
+                    change.notify_function,
+                    name.unwrap_or("unknown image")
+                )
+            });
+        }
+        let dropped = self.dropped_changes.load(Ordering::SeqCst);
+        if dropped > 0 {
+            log::warn!("  {dropped} additional memory map change(s) were not recorded.");
+        }
+
+        EVENT_DB.for_each_armed_timer(|timer| {
+            log::warn!(
+                "  Timer event {:?} is still armed (trigger time: {}, period: {:?}, notify function: {:#x}).",
+                timer.event,
+                timer.trigger_time,
+                timer.period,
+                timer.notify_function.map_or(0, |f| f as usize),
+            )
+        });
+
+        for mapping in self.outstanding_dma_mappings() {
+            log::warn!(
+                "  DMA mapping {:#x} -> {:#x} ({:#x} bytes) was never unmapped.",
+                mapping.host_address,
+                mapping.device_address,
+                mapping.number_of_bytes
+            );
+        }
+    }
+
+    /// Returns the DMA mappings the registered tracker reports as never unmapped.
+    fn outstanding_dma_mappings(&self) -> Vec<DmaMapping> {
+        let tracker = self.dma_mapping_tracker.lock().clone();
+        match tracker {
+            Some(tracker) => (0..tracker.outstanding_mapping_count())
+                .filter_map(|index| tracker.outstanding_mapping(index))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            f();
+        })
+        .unwrap();
+    }
+
+    extern "efiapi" fn test_notify(_event: efi::Event, _context: *mut core::ffi::c_void) {}
+
+    #[test]
+    fn disabled_diagnostics_should_not_record() {
+        with_locked_state(|| {
+            let diagnostics = ExitBootServicesDiagnostics::new();
+            diagnostics.record_memory_map_key(0x1234);
+            diagnostics.record_map_change(MapChangeType::AllocateMemorySpace);
+            assert_eq!(diagnostics.classify_failure(0x1234), ExitBootServicesFailure::NoMemoryMap);
+            assert_eq!(diagnostics.change(0), None);
+        });
+    }
+
+    #[test]
+    fn map_changes_should_be_attributed_to_the_running_notify_function() {
+        with_locked_state(|| {
+            let diagnostics = ExitBootServicesDiagnostics::new();
+            diagnostics.enable();
+            diagnostics.record_map_change(MapChangeType::AddMemorySpace);
+            diagnostics.record_memory_map_key(0x1234);
+
+            diagnostics.set_phase(Phase::PreExitBootServices);
+            let previous = diagnostics.enter_notify(test_notify);
+            diagnostics.record_map_change(MapChangeType::AllocateMemorySpace);
+            diagnostics.exit_notify(previous);
+            diagnostics.record_map_change(MapChangeType::FreeMemorySpace);
+
+            // changes before the map key was returned are discarded.
+            assert_eq!(
+                diagnostics.change(0),
+                Some(MapChange {
+                    phase: Phase::PreExitBootServices,
+                    notify_function: test_notify as usize,
+                    change: MapChangeType::AllocateMemorySpace
+                })
+            );
+            assert_eq!(
+                diagnostics.change(1),
+                Some(MapChange {
+                    phase: Phase::PreExitBootServices,
+                    notify_function: 0,
+                    change: MapChangeType::FreeMemorySpace
+                })
+            );
+            assert_eq!(diagnostics.change(2), None);
+        });
+    }
+
+    #[test]
+    fn classify_failure_should_identify_the_reason() {
+        with_locked_state(|| {
+            let diagnostics = ExitBootServicesDiagnostics::new();
+            diagnostics.enable();
+            assert_eq!(diagnostics.classify_failure(0x1234), ExitBootServicesFailure::NoMemoryMap);
+
+            diagnostics.record_memory_map_key(0x1234);
+            assert_eq!(
+                diagnostics.classify_failure(0x5678),
+                ExitBootServicesFailure::StaleMapKey { last_map_key: 0x1234 }
+            );
+            assert_eq!(diagnostics.classify_failure(0x1234), ExitBootServicesFailure::Unattributed);
+
+            diagnostics.record_map_change(MapChangeType::AllocateMemorySpace);
+            assert_eq!(
+                diagnostics.classify_failure(0x1234),
+                ExitBootServicesFailure::MemoryMapChanged { changes: 1, dropped: 0 }
+            );
+        });
+    }
+
+    #[test]
+    fn changes_beyond_capacity_should_be_counted_as_dropped() {
+        with_locked_state(|| {
+            let diagnostics = ExitBootServicesDiagnostics::new();
+            diagnostics.enable();
+            diagnostics.record_memory_map_key(0x1234);
+            let capacity = diagnostics.state.lock().changes.capacity();
+            for _ in 0..capacity + 3 {
+                diagnostics.record_map_change(MapChangeType::FreeMemorySpace);
+            }
+            assert_eq!(
+                diagnostics.classify_failure(0x1234),
+                ExitBootServicesFailure::MemoryMapChanged { changes: capacity, dropped: 3 }
+            );
+            assert_eq!(diagnostics.state.lock().changes.capacity(), capacity);
+
+            // a new map key starts a new window.
+            diagnostics.record_memory_map_key(0x5678);
+            assert_eq!(diagnostics.classify_failure(0x5678), ExitBootServicesFailure::Unattributed);
+        });
+    }
+
+    #[test]
+    fn report_should_query_the_dma_mapping_tracker() {
+        const MAPPING: DmaMapping =
+            DmaMapping { host_address: 0x1000, device_address: 0x8000_1000, number_of_bytes: 0x200 };
+        static QUERIES: AtomicUsize = AtomicUsize::new(0);
+
+        struct TestTracker;
+        impl DmaMappingTracker for TestTracker {
+            fn outstanding_mapping_count(&self) -> usize {
+                QUERIES.fetch_add(1, Ordering::SeqCst);
+                1
+            }
+            fn outstanding_mapping(&self, index: usize) -> Option<DmaMapping> {
+                (index == 0).then_some(MAPPING)
+            }
+        }
+
+        with_locked_state(|| {
+            let diagnostics = ExitBootServicesDiagnostics::new();
+            diagnostics.enable();
+            diagnostics.set_dma_mapping_tracker(Service::mock(Box::new(TestTracker)));
+            diagnostics.record_memory_map_key(0x1234);
+            diagnostics.set_phase(Phase::BeforeExitBootServices);
+            let previous = diagnostics.enter_notify(test_notify);
+            diagnostics.record_map_change(MapChangeType::AllocateMemorySpace);
+            diagnostics.exit_notify(previous);
+
+            QUERIES.store(0, Ordering::SeqCst);
+            diagnostics.report(0x1234, Err(EfiError::InvalidParameter));
+            diagnostics.report(0x1234, Ok(()));
+            assert_eq!(QUERIES.load(Ordering::SeqCst), 2);
+
+            assert_eq!(diagnostics.outstanding_dma_mappings(), [MAPPING]);
+        });
+    }
+}
@@ -151,6 +151,19 @@ impl fmt::Debug for EventNotification {
     }
 }
 
+/// A timer event that is armed to fire.
+#[derive(Debug, Clone, Copy)]
+pub struct ArmedTimer {
+    /// event handle
+    pub event: efi::Event,
+    /// system time at which the timer next fires
+    pub trigger_time: u64,
+    /// period of the timer, if it is periodic
+    pub period: Option<u64>,
+    /// notification function
+    pub notify_function: Option<efi::EventNotify>,
+}
+
 //This type is necessary because the HeapSort used to order BTreeSet is not stable with respect
 //to insertion order. So we have to tag each event notification as it is added so that we can
 //use insertion order as part of the element comparison.
@@ -728,6 +741,24 @@ impl SpinLockedEventDb {
     pub fn is_valid(&self, event: efi::Event) -> bool {
         self.lock().is_valid(event)
     }
+
+    /// Calls `f` for every timer event that is currently armed.
+    ///
+    /// The event database is locked while `f` runs, so `f` must not call back into the event database.
+    pub fn for_each_armed_timer(&self, mut f: impl FnMut(ArmedTimer)) {
+        for event in self.lock().events.values() {
+            if let Some(trigger_time) = event.trigger_time
+                && event.event_type.is_timer()
+            {
+                f(ArmedTimer {
+                    event: event.efi_event(),
+                    trigger_time,
+                    period: event.period,
+                    notify_function: event.notify_function,
+                });
+            }
+        }
+    }
 }
 
 unsafe impl Send for SpinLockedEventDb {}
@@ -1673,6 +1704,42 @@ mod tests {
         });
     }
 
+    #[test]
+    fn for_each_armed_timer_should_visit_only_armed_timers() {
+        with_locked_state(|| {
+            static SPIN_LOCKED_EVENT_DB: SpinLockedEventDb = SpinLockedEventDb::new();
+            let create_timer = || {
+                SPIN_LOCKED_EVENT_DB
+                    .create_event(
+                        efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL,
+                        efi::TPL_NOTIFY,
+                        Some(test_notify_function),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+            };
+            let one_shot = create_timer();
+            let periodic = create_timer();
+            let _unarmed = create_timer();
+
+            SPIN_LOCKED_EVENT_DB.set_timer(one_shot, TimerDelay::Relative, Some(0x100), None).unwrap();
+            SPIN_LOCKED_EVENT_DB.set_timer(periodic, TimerDelay::Periodic, Some(0x200), Some(0x200)).unwrap();
+
+            let mut timers = Vec::new();
+            SPIN_LOCKED_EVENT_DB.for_each_armed_timer(|timer| timers.push(timer));
+            assert_eq!(timers.len(), 2);
+            assert_eq!((timers[0].event, timers[0].trigger_time, timers[0].period), (one_shot, 0x100, None));
+            assert_eq!((timers[1].event, timers[1].trigger_time, timers[1].period), (periodic, 0x200, Some(0x200)));
+
+            // a one-shot timer is disarmed once it fires.
+            SPIN_LOCKED_EVENT_DB.timer_tick(0x100);
+            let mut timers = Vec::new();
+            SPIN_LOCKED_EVENT_DB.for_each_armed_timer(|timer| timers.push(timer.event));
+            assert_eq!(timers, vec![periodic]);
+        });
+    }
+
     #[test]
     fn periodic_timers_should_rearm_after_tick() {
         with_locked_state(|| {
@@ -18,6 +18,7 @@ use patina::pi::protocols::timer;
 use patina_internal_cpu::interrupts;
 
 use crate::{
+    ebs_diagnostics::EBS_DIAGNOSTICS,
     event_db::{SpinLockedEventDb, TimerDelay},
     gcd,
     protocols::PROTOCOL_DB,
@@ -209,6 +210,11 @@ pub extern "efiapi" fn set_timer(event: efi::Event, timer_type: efi::TimerDelay,
     }
 }
 
+/// Returns the current TPL.
+pub fn current_tpl() -> efi::Tpl {
+    CURRENT_TPL.load(Ordering::SeqCst)
+}
+
 pub extern "efiapi" fn raise_tpl(new_tpl: efi::Tpl) -> efi::Tpl {
     assert!(new_tpl <= efi::TPL_HIGH_LEVEL, "Invalid attempt to raise TPL above TPL_HIGH_LEVEL");
 
@@ -234,6 +240,9 @@ pub extern "efiapi" fn restore_tpl(new_tpl: efi::Tpl) {
     );
 
     if new_tpl < prev_tpl {
+        // checked once rather than per notification, so the dispatch loop does no diagnostics work when disabled.
+        let diagnostics_enabled = EBS_DIAGNOSTICS.enabled();
+
         // loop over any pending event notifications. Note: more notifications can be queued in the course of servicing
         // the current set of notifies; this will continue looping as long as there are any pending notifications, even
         // if they were queued after the loop started.
@@ -276,7 +285,11 @@ pub extern "efiapi" fn restore_tpl(new_tpl: efi::Tpl) {
             //callbacks as "unsafe", and the r_efi definition for EventNotify would need to
             //change.
             if let Some(notify_function) = event.notify_function {
+                let previous_notify = diagnostics_enabled.then(|| EBS_DIAGNOSTICS.enter_notify(notify_function));
                 (notify_function)(event.event, notify_context);
+                if let Some(previous_notify) = previous_notify {
+                    EBS_DIAGNOSTICS.exit_notify(previous_notify);
+                }
             }
         }
     }
@@ -314,6 +327,7 @@ static EVENT_DB_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
 /// This callback is invoked whenever the GCD changes, and will signal the required UEFI event group.
 pub fn gcd_map_change(map_change_type: gcd::MapChangeType) {
+    EBS_DIAGNOSTICS.record_map_change(map_change_type);
     if EVENT_DB_INITIALIZED.load(Ordering::SeqCst) {
         match map_change_type {
             gcd::MapChangeType::AddMemorySpace
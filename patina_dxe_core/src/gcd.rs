@@ -26,7 +26,7 @@ use patina::base::{UEFI_PAGE_SIZE, align_range};
 
 use crate::GCD;
 
-pub use spin_locked_gcd::{AllocateType, MapChangeType, SpinLockedGcd};
+pub use spin_locked_gcd::{AllocateType, MapChangeType, SpecialPurposeMemoryPolicy, SpinLockedGcd};
 
 pub fn init_gcd(physical_hob_list: *const c_void) {
     let mut free_memory_start: u64 = 0;
@@ -151,6 +151,13 @@ pub fn add_hob_resource_descriptors_to_gcd(hob_list: &HobList) {
                 {
                     gcd_mem_type = GcdMemoryType::Persistent;
                 }
+
+                if resource_attributes & hob::EFI_RESOURCE_ATTRIBUTE_SPECIAL_PURPOSE != 0
+                    && matches!(gcd_mem_type, GcdMemoryType::SystemMemory | GcdMemoryType::MoreReliable)
+                    && GCD.special_purpose_memory_policy() == SpecialPurposeMemoryPolicy::Reserved
+                {
+                    gcd_mem_type = GcdMemoryType::Reserved;
+                }
             }
             hob::EFI_RESOURCE_MEMORY_MAPPED_IO | hob::EFI_RESOURCE_FIRMWARE_DEVICE => {
                 resource_attributes = res_desc.resource_attribute;
@@ -160,6 +167,12 @@ pub fn add_hob_resource_descriptors_to_gcd(hob_list: &HobList) {
                 resource_attributes = res_desc.resource_attribute;
                 gcd_mem_type = GcdMemoryType::Reserved;
             }
+            hob::EFI_RESOURCE_MEMORY_UNACCEPTED => {
+                // Unaccepted memory is tracked as such until it is accepted on demand, at which point it is converted
+                // to system memory. See [SpinLockedGcd::accept_memory].
+                resource_attributes = res_desc.resource_attribute;
+                gcd_mem_type = GcdMemoryType::Unaccepted;
+            }
             hob::EFI_RESOURCE_IO => {
                 log::info!(
                     "Mapping io range {:#x?} as {:?}",
@@ -195,8 +208,9 @@ pub fn add_hob_resource_descriptors_to_gcd(hob_list: &HobList) {
             // Extract cache attributes and add ReadProtect for system memory.
             // If we are processing V1 HOBs, the cache attributes will be 0; that information is not passed.
             let mut memory_attributes = cache_attributes & efi::CACHE_ATTRIBUTE_MASK;
-            if gcd_mem_type == GcdMemoryType::SystemMemory {
-                // Force all system memory to be RP by default (since none is allocated yet)
+            if matches!(gcd_mem_type, GcdMemoryType::SystemMemory | GcdMemoryType::Unaccepted) {
+                // Force all system memory to be RP by default (since none is allocated yet). Unaccepted memory must not
+                // be touched until it has been accepted, so it is RP as well.
                 memory_attributes |= efi::MEMORY_RP;
             }
 
@@ -446,4 +460,99 @@ mod tests {
             add_resource_descriptors_should_add_resource_descriptors(&hob_list, physical_hob_list as u64);
         });
     }
+
+    #[cfg(not(feature = "v1_resource_descriptor_support"))]
+    mod v2_resource_descriptors {
+        use patina::pi::{
+            dxe_services::GcdMemoryType,
+            hob::{self, Hob, HobList},
+        };
+        use r_efi::efi;
+
+        use super::{MEM_SIZE, with_locked_state};
+        use crate::{
+            GCD,
+            gcd::{SpecialPurposeMemoryPolicy, add_hob_resource_descriptors_to_gcd, init_gcd},
+            test_support::build_test_hob_list,
+        };
+
+        fn system_memory_resource_descriptor(
+            physical_start: u64,
+            resource_length: u64,
+            extra_attributes: u32,
+        ) -> hob::ResourceDescriptorV2 {
+            hob::ResourceDescriptorV2 {
+                v1: hob::ResourceDescriptor {
+                    header: hob::header::Hob {
+                        r#type: hob::RESOURCE_DESCRIPTOR2,
+                        length: core::mem::size_of::<hob::ResourceDescriptorV2>() as u16,
+                        reserved: 0,
+                    },
+                    owner: efi::Guid::from_fields(0, 0, 0, 0, 0, &[0u8; 6]),
+                    resource_type: hob::EFI_RESOURCE_SYSTEM_MEMORY,
+                    resource_attribute: hob::TESTED_MEMORY_ATTRIBUTES
+                        | hob::EFI_RESOURCE_ATTRIBUTE_WRITE_BACK_CACHEABLE
+                        | extra_attributes,
+                    physical_start,
+                    resource_length,
+                },
+                attributes: efi::MEMORY_WB,
+            }
+        }
+
+        #[test]
+        fn special_purpose_and_persistent_memory_should_be_added_per_policy() {
+            with_locked_state(|| {
+                let physical_hob_list = build_test_hob_list(MEM_SIZE);
+                init_gcd(physical_hob_list);
+
+                let mut test_hob_list = HobList::default();
+                test_hob_list.discover_hobs(physical_hob_list);
+                let phit = test_hob_list
+                    .iter()
+                    .find_map(|hob| if let Hob::Handoff(handoff) = hob { Some(*handoff) } else { None })
+                    .unwrap();
+
+                let sp_conventional = system_memory_resource_descriptor(
+                    0x2000_0000,
+                    0x100000,
+                    hob::EFI_RESOURCE_ATTRIBUTE_SPECIAL_PURPOSE,
+                );
+                let sp_reserved = system_memory_resource_descriptor(
+                    0x3000_0000,
+                    0x100000,
+                    hob::EFI_RESOURCE_ATTRIBUTE_SPECIAL_PURPOSE,
+                );
+                let persistent = system_memory_resource_descriptor(
+                    0x4000_0000,
+                    0x100000,
+                    hob::EFI_RESOURCE_ATTRIBUTE_PERSISTENT | hob::EFI_RESOURCE_ATTRIBUTE_PERSISTABLE,
+                );
+
+                let mut hob_list = HobList::default();
+                hob_list.push(Hob::Handoff(phit));
+                hob_list.push(Hob::ResourceDescriptorV2(&sp_conventional));
+                hob_list.push(Hob::ResourceDescriptorV2(&persistent));
+                add_hob_resource_descriptors_to_gcd(&hob_list);
+
+                GCD.set_special_purpose_memory_policy(SpecialPurposeMemoryPolicy::Reserved);
+                let mut hob_list = HobList::default();
+                hob_list.push(Hob::Handoff(phit));
+                hob_list.push(Hob::ResourceDescriptorV2(&sp_reserved));
+                add_hob_resource_descriptors_to_gcd(&hob_list);
+
+                let descriptor = GCD.get_memory_descriptor_for_address(0x2000_0000).unwrap();
+                assert_eq!(descriptor.memory_type, GcdMemoryType::SystemMemory);
+                assert_ne!(descriptor.capabilities & efi::MEMORY_SP, 0);
+
+                let descriptor = GCD.get_memory_descriptor_for_address(0x3000_0000).unwrap();
+                assert_eq!(descriptor.memory_type, GcdMemoryType::Reserved);
+                assert_ne!(descriptor.capabilities & efi::MEMORY_SP, 0);
+
+                let descriptor = GCD.get_memory_descriptor_for_address(0x4000_0000).unwrap();
+                assert_eq!(descriptor.memory_type, GcdMemoryType::Persistent);
+                assert_ne!(descriptor.capabilities & efi::MEMORY_NV, 0);
+            });
+        }
+    }
 }
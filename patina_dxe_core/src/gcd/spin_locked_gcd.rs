@@ -13,7 +13,8 @@ use patina::{base::DEFAULT_CACHE_ATTR, error::EfiError};
 
 use mu_rust_helpers::function;
 use patina::{
-    base::{SIZE_4GB, UEFI_PAGE_MASK, UEFI_PAGE_SHIFT, UEFI_PAGE_SIZE, align_up},
+    base::{SIZE_2MB, SIZE_4GB, UEFI_PAGE_MASK, UEFI_PAGE_SHIFT, UEFI_PAGE_SIZE, align_down, align_up},
+    component::service::{Service, memory::MemoryAcceptance},
     guids::CACHE_ATTRIBUTE_CHANGE_EVENT_GROUP,
     pi::{
         dxe_services::{self, GcdMemoryType},
@@ -25,8 +26,8 @@ use patina_internal_collections::{Error as SliceError, Rbt, SliceKey, node_size}
 use r_efi::efi;
 
 use crate::{
-    GCD, allocator::DEFAULT_ALLOCATION_STRATEGY, ensure, error, events::EVENT_DB, protocol_db,
-    protocol_db::INVALID_HANDLE, tpl_lock,
+    GCD, allocator::DEFAULT_ALLOCATION_STRATEGY, ensure, error, events::EVENT_DB,
+    memory_protection::MemoryProtectionPolicy, protocol_db, protocol_db::INVALID_HANDLE, tpl_lock,
 };
 use patina_internal_cpu::paging::create_cpu_paging;
 use patina_paging::{MemoryAttributes, PageTable, PtError, PtResult, page_allocator::PageAllocator};
@@ -48,6 +49,10 @@ const IO_BLOCK_SLICE_SIZE: usize = IO_BLOCK_SLICE_LEN * node_size::<IoBlock>();
 
 const PAGE_POOL_CAPACITY: usize = 512;
 
+/// The minimum amount of unaccepted memory accepted at once when an allocation cannot be satisfied from system memory.
+/// Accepting in larger chunks amortizes the cost of acceptance across subsequent allocations.
+const MEMORY_ACCEPTANCE_CHUNK_SIZE: usize = SIZE_2MB;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum InternalError {
     MemoryBlock(MemoryBlockError),
@@ -67,6 +72,21 @@ pub enum AllocateType {
     Address(usize),
 }
 
+/// Selects how specific-purpose memory is presented. Specific-purpose memory is system memory described with
+/// `EFI_RESOURCE_ATTRIBUTE_SPECIAL_PURPOSE` (e.g. HBM or CXL attached memory). Regardless of the policy, it always
+/// carries the `EFI_MEMORY_SP` capability and is never allocated, not even by address, until the capability is
+/// cleared.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialPurposeMemoryPolicy {
+    /// Add specific-purpose memory as system memory. It is reported as `EfiConventionalMemory` with `EFI_MEMORY_SP`
+    /// in the memory map, leaving it to the OS to decide how to use it.
+    #[default]
+    Conventional,
+    /// Add specific-purpose memory as reserved memory. It is reported as `EfiReservedMemoryType` with `EFI_MEMORY_SP`
+    /// in the memory map.
+    Reserved,
+}
+
 #[derive(Clone, Copy)]
 struct GcdAttributeConversionEntry {
     attribute: u32,
@@ -74,7 +94,7 @@ struct GcdAttributeConversionEntry {
     memory: bool,
 }
 
-const ATTRIBUTE_CONVERSION_TABLE: [GcdAttributeConversionEntry; 15] = [
+const ATTRIBUTE_CONVERSION_TABLE: [GcdAttributeConversionEntry; 16] = [
     GcdAttributeConversionEntry {
         attribute: hob::EFI_RESOURCE_ATTRIBUTE_UNCACHEABLE,
         capability: efi::MEMORY_UC,
@@ -145,6 +165,11 @@ const ATTRIBUTE_CONVERSION_TABLE: [GcdAttributeConversionEntry; 15] = [
         capability: hob::EFI_MEMORY_MORE_RELIABLE,
         memory: true,
     },
+    GcdAttributeConversionEntry {
+        attribute: hob::EFI_RESOURCE_ATTRIBUTE_SPECIAL_PURPOSE,
+        capability: efi::MEMORY_SP,
+        memory: true,
+    },
     GcdAttributeConversionEntry { attribute: 0, capability: 0, memory: false },
 ];
 
@@ -289,6 +314,10 @@ struct GCD {
     default_attributes: u64,
     /// Whether to prioritize 32-bit memory allocations
     prioritize_32_bit_memory: bool,
+    /// The memory protection policy the core was configured with
+    memory_protection_policy: MemoryProtectionPolicy,
+    /// How specific-purpose memory described by resource descriptor HOBs is added to the GCD
+    special_purpose_memory_policy: SpecialPurposeMemoryPolicy,
 }
 
 impl GCD {
@@ -319,6 +348,8 @@ impl GCD {
             free_memory_space_fn: Self::free_memory_space_worker,
             default_attributes: efi::MEMORY_XP,
             prioritize_32_bit_memory: false,
+            memory_protection_policy: MemoryProtectionPolicy::new(),
+            special_purpose_memory_policy: SpecialPurposeMemoryPolicy::Conventional,
         }
     }
 
@@ -679,7 +710,8 @@ impl GCD {
             }
             ensure!(addr + len <= max_address, EfiError::NotFound);
 
-            if mb.as_ref().memory_type != memory_type {
+            // specific-purpose memory is never handed out for general-purpose allocations
+            if mb.as_ref().memory_type != memory_type || mb.as_ref().capabilities & efi::MEMORY_SP != 0 {
                 current = memory_blocks.next_idx(idx);
                 continue;
             }
@@ -768,7 +800,8 @@ impl GCD {
                 continue;
             }
 
-            if mb.as_ref().memory_type != memory_type {
+            // specific-purpose memory is never handed out for general-purpose allocations
+            if mb.as_ref().memory_type != memory_type || mb.as_ref().capabilities & efi::MEMORY_SP != 0 {
                 current = memory_blocks.prev_idx(idx);
                 continue;
             }
@@ -832,6 +865,8 @@ impl GCD {
             block.as_ref().memory_type == memory_type && address == address & (usize::MAX << align_shift),
             EfiError::NotFound
         );
+        // as with bottom-up and top-down allocations, specific-purpose memory is not handed out
+        ensure!(block.as_ref().capabilities & efi::MEMORY_SP == 0, EfiError::NotFound);
 
         match Self::split_state_transition_at_idx(
             memory_blocks,
@@ -1038,6 +1073,95 @@ impl GCD {
         Ok(())
     }
 
+    /// Converts an unaccepted range of memory to system memory once it has been accepted by the platform.
+    ///
+    /// The range must be fully contained in a single unaccepted descriptor. Cache attributes are preserved and the
+    /// converted range is marked RP, as it is free system memory.
+    fn convert_unaccepted_memory(&mut self, base_address: usize, len: usize) -> Result<(), EfiError> {
+        let descriptor = self.get_memory_descriptor_for_address(base_address as efi::PhysicalAddress)?;
+        ensure!(
+            descriptor.memory_type == dxe_services::GcdMemoryType::Unaccepted
+                && (base_address + len) as u64 <= descriptor.base_address + descriptor.length,
+            EfiError::InvalidParameter
+        );
+
+        log::trace!(target: "allocations", "[{}] Converting accepted memory at {:#x}", function!(), base_address);
+        log::trace!(target: "allocations", "[{}]   Length: {:#x}\n", function!(), len);
+
+        // the presence bits only describe the state of non-system memory, so they do not carry over
+        let capabilities =
+            descriptor.capabilities & !(hob::EFI_MEMORY_PRESENT | hob::EFI_MEMORY_INITIALIZED | hob::EFI_MEMORY_TESTED);
+
+        self.remove_memory_space(base_address, len)?;
+        unsafe { self.add_memory_space(dxe_services::GcdMemoryType::SystemMemory, base_address, len, capabilities) }?;
+        self.set_memory_space_attributes(
+            base_address,
+            len,
+            (descriptor.attributes & efi::CACHE_ATTRIBUTE_MASK) | efi::MEMORY_RP,
+        )
+    }
+
+    /// Finds a range of unaccepted memory that, once accepted, is large enough to satisfy an allocation of `len` bytes
+    /// with the given alignment. The range is at least [MEMORY_ACCEPTANCE_CHUNK_SIZE] bytes unless the unaccepted
+    /// descriptor it is carved from is smaller.
+    ///
+    /// For [AllocateType::Address] the requested range itself is returned; it may span already accepted memory.
+    fn find_unaccepted_range(
+        &self,
+        allocate_type: AllocateType,
+        align_shift: usize,
+        len: usize,
+    ) -> Option<(usize, usize)> {
+        let (max_address, top_down) = match allocate_type {
+            AllocateType::Address(address) => {
+                let base_address = align_down(address, UEFI_PAGE_SIZE).ok()?;
+                let end = align_up(address.checked_add(len)?, UEFI_PAGE_SIZE).ok()?;
+                return Some((base_address, end - base_address));
+            }
+            AllocateType::BottomUp(max_address) => (max_address, false),
+            AllocateType::TopDown(max_address) => (max_address, true),
+        };
+
+        let limit = align_down(
+            max_address.map_or(self.maximum_address, |max| max.saturating_add(1)).min(self.maximum_address),
+            UEFI_PAGE_SIZE,
+        )
+        .ok()?;
+        let chunk_size =
+            align_up(len.checked_add(1 << align_shift)?, UEFI_PAGE_SIZE).ok()?.max(MEMORY_ACCEPTANCE_CHUNK_SIZE);
+
+        let mut found = None;
+        let mut current = self.memory_blocks.first_idx();
+        while let Some(idx) = current {
+            current = self.memory_blocks.next_idx(idx);
+
+            let MemoryBlock::Unallocated(descriptor) = self.memory_blocks.get_with_idx(idx)? else {
+                continue;
+            };
+            if descriptor.memory_type != dxe_services::GcdMemoryType::Unaccepted
+                || descriptor.capabilities & efi::MEMORY_SP != 0
+            {
+                continue;
+            }
+
+            let start = descriptor.base_address as usize;
+            let end = ((descriptor.base_address + descriptor.length) as usize).min(limit);
+            if end <= start || end - start < len {
+                continue;
+            }
+
+            if top_down {
+                // keep searching, the highest range wins
+                found = Some((start.max(end.saturating_sub(chunk_size)), end));
+            } else {
+                found = Some((start, end.min(start.saturating_add(chunk_size))));
+                break;
+            }
+        }
+
+        found.map(|(start, end)| (start, end - start))
+    }
+
     /// This service returns the descriptor for the given physical address.
     pub fn get_memory_descriptor_for_address(
         &mut self,
@@ -1779,7 +1903,7 @@ impl From<io_block::Error> for InternalError {
 }
 
 /// Describes the kind of GCD map change that triggered the callback.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MapChangeType {
     AddMemorySpace,
     RemoveMemorySpace,
@@ -1799,6 +1923,7 @@ pub struct SpinLockedGcd {
     memory_change_callback: Option<MapChangeCallback>,
     memory_type_info_table: [EFiMemoryTypeInformation; 17],
     page_table: tpl_lock::TplMutex<Option<Box<dyn PageTable>>>,
+    memory_acceptance: tpl_lock::TplMutex<Option<Service<dyn MemoryAcceptance>>>,
 }
 
 impl SpinLockedGcd {
@@ -1821,6 +1946,8 @@ impl SpinLockedGcd {
                     free_memory_space_fn: GCD::free_memory_space_worker,
                     default_attributes: efi::MEMORY_XP,
                     prioritize_32_bit_memory: false,
+                    memory_protection_policy: MemoryProtectionPolicy::new(),
+                    special_purpose_memory_policy: SpecialPurposeMemoryPolicy::Conventional,
                 },
                 "GcdMemLock",
             ),
@@ -1850,6 +1977,7 @@ impl SpinLockedGcd {
                 EFiMemoryTypeInformation { memory_type: 16 /*EfiMaxMemoryType*/, number_of_pages: 0 },
             ],
             page_table: tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, None, "GcdPageTableLock"),
+            memory_acceptance: tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, None, "GcdMemAcceptLock"),
         }
     }
 
@@ -1857,6 +1985,131 @@ impl SpinLockedGcd {
         self.memory.lock().prioritize_32_bit_memory = value;
     }
 
+    /// Sets the memory protection policy consulted by the GCD, the allocators and the image loader.
+    pub fn set_memory_protection_policy(&self, policy: MemoryProtectionPolicy) {
+        self.memory.lock().memory_protection_policy = policy;
+    }
+
+    /// Returns the memory protection policy.
+    pub fn memory_protection_policy(&self) -> MemoryProtectionPolicy {
+        self.memory.lock().memory_protection_policy
+    }
+
+    /// Sets how specific-purpose memory described by resource descriptor HOBs is added to the GCD.
+    pub fn set_special_purpose_memory_policy(&self, policy: SpecialPurposeMemoryPolicy) {
+        self.memory.lock().special_purpose_memory_policy = policy;
+    }
+
+    /// Returns the specific-purpose memory policy.
+    pub fn special_purpose_memory_policy(&self) -> SpecialPurposeMemoryPolicy {
+        self.memory.lock().special_purpose_memory_policy
+    }
+
+    /// Sets the platform service used to accept unaccepted memory. Once set, unaccepted memory is accepted on demand
+    /// when a system memory allocation cannot otherwise be satisfied.
+    pub fn set_memory_acceptance(&self, memory_acceptance: Service<dyn MemoryAcceptance>) {
+        *self.memory_acceptance.lock() = Some(memory_acceptance);
+    }
+
+    /// Returns true if a platform service to accept unaccepted memory has been set.
+    pub fn memory_acceptance_supported(&self) -> bool {
+        self.memory_acceptance.lock().is_some()
+    }
+
+    /// Accepts the given range of memory, converting any unaccepted memory in the range to system memory.
+    ///
+    /// Portions of the range that are already system memory are left untouched. Nothing is accepted if any part of
+    /// the range is neither unaccepted nor system memory. The GCD stays locked while the memory acceptance service runs,
+    /// so the service must not call back into the GCD.
+    ///
+    /// # Errors
+    ///
+    /// - [`EfiError::InvalidParameter`]: the range is empty, not page aligned, or contains memory that is neither
+    ///   unaccepted nor system memory.
+    /// - [`EfiError::Unsupported`]: no memory acceptance service has been set.
+    /// - Any error returned by the memory acceptance service.
+    pub fn accept_memory(&self, base_address: usize, len: usize) -> Result<(), EfiError> {
+        ensure!(
+            len > 0 && (base_address & UEFI_PAGE_MASK) == 0 && (len & UEFI_PAGE_MASK) == 0,
+            EfiError::InvalidParameter
+        );
+        let end = base_address.checked_add(len).ok_or(EfiError::InvalidParameter)?;
+        let memory_acceptance = self.memory_acceptance.lock().clone().ok_or(EfiError::Unsupported)?;
+
+        // The GCD lock is held from validation until the last chunk is converted, so that the range cannot change
+        // between the two passes.
+        let mut memory = self.memory.lock();
+
+        // validate the whole range before accepting any of it
+        let mut address = base_address;
+        while address < end {
+            let descriptor = memory.get_memory_descriptor_for_address(address as efi::PhysicalAddress)?;
+            ensure!(
+                matches!(
+                    descriptor.memory_type,
+                    dxe_services::GcdMemoryType::Unaccepted | dxe_services::GcdMemoryType::SystemMemory
+                ),
+                EfiError::InvalidParameter
+            );
+            address = end.min((descriptor.base_address + descriptor.length) as usize);
+        }
+
+        let mut accepted = false;
+        let mut address = base_address;
+        let result = loop {
+            if address >= end {
+                break Ok(());
+            }
+            let descriptor = match memory.get_memory_descriptor_for_address(address as efi::PhysicalAddress) {
+                Ok(descriptor) => descriptor,
+                Err(err) => break Err(err),
+            };
+            let chunk_end = end.min((descriptor.base_address + descriptor.length) as usize);
+
+            if descriptor.memory_type == dxe_services::GcdMemoryType::Unaccepted {
+                log::info!("Accepting memory {address:#x?} of length {:#x?}", chunk_end - address);
+                if let Err(err) = memory_acceptance
+                    .accept_memory(address as efi::PhysicalAddress, (chunk_end - address) as u64)
+                    .and_then(|_| memory.convert_unaccepted_memory(address, chunk_end - address))
+                {
+                    break Err(err);
+                }
+                accepted = true;
+            }
+
+            address = chunk_end;
+        };
+        drop(memory);
+
+        // the callback reads the GCD, so it is only invoked once the lock is released
+        if accepted && let Some(callback) = self.memory_change_callback {
+            callback(MapChangeType::AddMemorySpace);
+        }
+
+        result
+    }
+
+    /// Accepts enough unaccepted memory to satisfy a system memory allocation that could not otherwise be satisfied.
+    /// Returns true if memory was accepted.
+    fn accept_memory_for_allocation(&self, allocate_type: AllocateType, alignment: usize, len: usize) -> bool {
+        if !self.memory_acceptance_supported() {
+            return false;
+        }
+
+        let Some((base_address, length)) = self.memory.lock().find_unaccepted_range(allocate_type, alignment, len)
+        else {
+            return false;
+        };
+
+        match self.accept_memory(base_address, length) {
+            Ok(_) => true,
+            Err(err) => {
+                log::error!("Failed to accept memory {base_address:#x?} of length {length:#x?}: {err:?}");
+                false
+            }
+        }
+    }
+
     /// Returns a reference to the memory type information table.
     pub const fn memory_type_info_table(&self) -> &[EFiMemoryTypeInformation; 17] {
         &self.memory_type_info_table
@@ -2013,8 +2266,11 @@ impl SpinLockedGcd {
         let (mut mem, mut io) = (self.memory.lock(), self.io.lock());
         mem.maximum_address = 0;
         mem.memory_blocks = Rbt::new();
+        mem.memory_protection_policy = MemoryProtectionPolicy::new();
+        mem.special_purpose_memory_policy = SpecialPurposeMemoryPolicy::Conventional;
         io.maximum_address = 0;
         io.io_blocks = Rbt::new();
+        *self.memory_acceptance.lock() = None;
     }
 
     /// Initializes the underlying memory GCD and I/O GCD with the given address bits.
@@ -2189,8 +2445,9 @@ impl SpinLockedGcd {
         }
 
         // make sure we didn't map page 0 if it was reserved or MMIO, we are using this for null pointer detection
-        // only do this if page 0 actually exists
-        if let Ok(descriptor) = self.get_memory_descriptor_for_address(0)
+        // only do this if page 0 actually exists and the platform has not opted out of null pointer detection
+        if self.memory_protection_policy().null_pointer_detection()
+            && let Ok(descriptor) = self.get_memory_descriptor_for_address(0)
             && descriptor.memory_type != GcdMemoryType::NonExistent
             && let Err(err) = self.set_memory_space_attributes(0, UEFI_PAGE_SIZE, efi::MEMORY_RP)
         {
@@ -2268,7 +2525,7 @@ impl SpinLockedGcd {
         image_handle: efi::Handle,
         device_handle: Option<efi::Handle>,
     ) -> Result<usize, EfiError> {
-        let result = self.memory.lock().allocate_memory_space(
+        let mut result = self.memory.lock().allocate_memory_space(
             allocate_type,
             memory_type,
             alignment,
@@ -2276,6 +2533,20 @@ impl SpinLockedGcd {
             image_handle,
             device_handle,
         );
+        // if system memory is exhausted, accept more memory (if any is unaccepted) and try again
+        if matches!(result, Err(EfiError::NotFound | EfiError::OutOfResources))
+            && memory_type == dxe_services::GcdMemoryType::SystemMemory
+            && self.accept_memory_for_allocation(allocate_type, alignment, len)
+        {
+            result = self.memory.lock().allocate_memory_space(
+                allocate_type,
+                memory_type,
+                alignment,
+                len,
+                image_handle,
+                device_handle,
+            );
+        }
         if result.is_ok() {
             // if we successfully allocated memory, we want to set the range as NX. For any standard data, we should
             // always have NX set and no consumer needs to update it. If a code region is going to be allocated
@@ -3624,6 +3895,8 @@ mod tests {
             free_memory_space_fn: GCD::free_memory_space_worker,
             default_attributes: efi::MEMORY_XP,
             prioritize_32_bit_memory: false,
+            memory_protection_policy: MemoryProtectionPolicy::new(),
+            special_purpose_memory_policy: SpecialPurposeMemoryPolicy::Conventional,
         };
         assert_eq!(Err(EfiError::NotReady), gcd.set_memory_space_attributes(0, 0x50000, 0b1111));
 
@@ -4331,4 +4604,355 @@ mod tests {
         );
         assert!(res.is_ok(), "Failed to fallback to higher memory as expected");
     }
+
+    #[test]
+    fn get_capabilities_should_convert_special_purpose_and_persistable() {
+        let capabilities = get_capabilities(
+            dxe_services::GcdMemoryType::SystemMemory,
+            (hob::EFI_RESOURCE_ATTRIBUTE_SPECIAL_PURPOSE
+                | hob::EFI_RESOURCE_ATTRIBUTE_PERSISTABLE
+                | hob::EFI_RESOURCE_ATTRIBUTE_WRITE_BACK_CACHEABLE) as u64,
+        );
+        assert_eq!(capabilities, efi::MEMORY_SP | efi::MEMORY_NV | efi::MEMORY_WB);
+    }
+
+    #[test]
+    fn special_purpose_memory_should_not_be_allocated() {
+        let (mut gcd, _) = create_gcd();
+        unsafe { gcd.add_memory_space(dxe_services::GcdMemoryType::SystemMemory, 0x1000000, 0x100000, efi::MEMORY_SP) }
+            .unwrap();
+
+        for allocate_type in [AllocateType::BottomUp(None), AllocateType::TopDown(None)] {
+            assert_eq!(
+                gcd.allocate_memory_space(
+                    allocate_type,
+                    dxe_services::GcdMemoryType::SystemMemory,
+                    UEFI_PAGE_SHIFT,
+                    0x1000,
+                    1 as _,
+                    None,
+                ),
+                Err(EfiError::OutOfResources)
+            );
+        }
+
+        for address in [0x1000000, 0x1080000] {
+            assert_eq!(
+                gcd.allocate_memory_space(
+                    AllocateType::Address(address),
+                    dxe_services::GcdMemoryType::SystemMemory,
+                    UEFI_PAGE_SHIFT,
+                    0x1000,
+                    1 as _,
+                    None,
+                ),
+                Err(EfiError::NotFound)
+            );
+        }
+
+        // once the platform clears the capability, the memory is available for general-purpose allocation
+        gcd.set_memory_space_capabilities(0x1000000, 0x100000, efi::MEMORY_ACCESS_MASK | efi::MEMORY_RUNTIME).unwrap();
+        assert_eq!(
+            gcd.allocate_memory_space(
+                AllocateType::Address(0x1080000),
+                dxe_services::GcdMemoryType::SystemMemory,
+                UEFI_PAGE_SHIFT,
+                0x1000,
+                1 as _,
+                None,
+            ),
+            Ok(0x1080000)
+        );
+        assert_eq!(
+            gcd.allocate_memory_space(
+                AllocateType::BottomUp(None),
+                dxe_services::GcdMemoryType::SystemMemory,
+                UEFI_PAGE_SHIFT,
+                0x1000,
+                1 as _,
+                None,
+            ),
+            Ok(0x1000000)
+        );
+    }
+
+    struct TestMemoryAcceptance {
+        accepted: std::sync::Arc<std::sync::Mutex<Vec<(u64, u64)>>>,
+        result: Result<(), EfiError>,
+    }
+
+    impl MemoryAcceptance for TestMemoryAcceptance {
+        fn accept_memory(&self, start: efi::PhysicalAddress, size: u64) -> Result<(), EfiError> {
+            self.result?;
+            self.accepted.lock().unwrap().push((start, size));
+            Ok(())
+        }
+    }
+
+    const UNACCEPTED_BASE: usize = 0x1000_0000_0000;
+    const UNACCEPTED_SIZE: usize = 0x800000;
+
+    // Sets up a GCD whose system memory is fully consumed by the GCD itself, followed by an unaccepted range.
+    fn init_unaccepted_gcd(
+        gcd: &SpinLockedGcd,
+        result: Result<(), EfiError>,
+    ) -> std::sync::Arc<std::sync::Mutex<Vec<(u64, u64)>>> {
+        let mem = unsafe { get_memory(MEMORY_BLOCK_SLICE_SIZE) };
+        gcd.init(48, 16);
+        unsafe {
+            gcd.add_memory_space(
+                dxe_services::GcdMemoryType::SystemMemory,
+                mem.as_ptr() as usize,
+                MEMORY_BLOCK_SLICE_SIZE,
+                efi::MEMORY_WB,
+            )
+            .unwrap();
+            gcd.add_memory_space(
+                dxe_services::GcdMemoryType::Unaccepted,
+                UNACCEPTED_BASE,
+                UNACCEPTED_SIZE,
+                efi::MEMORY_WB | hob::EFI_MEMORY_PRESENT,
+            )
+            .unwrap();
+        }
+        // paging is not initialized, but the GCD is still updated
+        assert_eq!(
+            gcd.set_memory_space_attributes(UNACCEPTED_BASE, UNACCEPTED_SIZE, efi::MEMORY_WB | efi::MEMORY_RP),
+            Err(EfiError::NotReady)
+        );
+
+        let accepted = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        gcd.set_memory_acceptance(Service::mock(Box::new(TestMemoryAcceptance { accepted: accepted.clone(), result })));
+        accepted
+    }
+
+    #[test]
+    fn accept_memory_should_fail_without_memory_acceptance_service() {
+        with_locked_state(|| {
+            static GCD: SpinLockedGcd = SpinLockedGcd::new(None);
+            init_unaccepted_gcd(&GCD, Ok(()));
+            *GCD.memory_acceptance.lock() = None;
+
+            assert!(!GCD.memory_acceptance_supported());
+            assert_eq!(GCD.accept_memory(UNACCEPTED_BASE, 0x1000), Err(EfiError::Unsupported));
+            assert_eq!(
+                GCD.allocate_memory_space(
+                    AllocateType::BottomUp(None),
+                    dxe_services::GcdMemoryType::SystemMemory,
+                    UEFI_PAGE_SHIFT,
+                    0x1000,
+                    1 as _,
+                    None,
+                ),
+                Err(EfiError::OutOfResources)
+            );
+        });
+    }
+
+    #[test]
+    fn accept_memory_should_convert_unaccepted_memory_to_system_memory() {
+        with_locked_state(|| {
+            static CALLBACK_INVOKED: AtomicBool = AtomicBool::new(false);
+            fn map_callback(map_change_type: MapChangeType) {
+                if map_change_type == MapChangeType::AddMemorySpace {
+                    CALLBACK_INVOKED.store(true, core::sync::atomic::Ordering::SeqCst);
+                }
+            }
+            static GCD: SpinLockedGcd = SpinLockedGcd::new(Some(map_callback));
+            let accepted = init_unaccepted_gcd(&GCD, Ok(()));
+            CALLBACK_INVOKED.store(false, core::sync::atomic::Ordering::SeqCst);
+
+            GCD.accept_memory(UNACCEPTED_BASE + 0x1000, 0x2000).unwrap();
+            assert_eq!(*accepted.lock().unwrap(), [((UNACCEPTED_BASE + 0x1000) as u64, 0x2000)]);
+            assert!(CALLBACK_INVOKED.load(core::sync::atomic::Ordering::SeqCst));
+
+            let descriptor = GCD.get_memory_descriptor_for_address((UNACCEPTED_BASE + 0x1000) as u64).unwrap();
+            assert_eq!(descriptor.memory_type, dxe_services::GcdMemoryType::SystemMemory);
+            assert_eq!(descriptor.base_address, (UNACCEPTED_BASE + 0x1000) as u64);
+            assert_eq!(descriptor.length, 0x2000);
+            assert_eq!(descriptor.attributes, efi::MEMORY_WB | efi::MEMORY_RP);
+            assert_eq!(descriptor.capabilities & hob::EFI_MEMORY_PRESENT, 0);
+
+            let descriptor = GCD.get_memory_descriptor_for_address(UNACCEPTED_BASE as u64).unwrap();
+            assert_eq!(descriptor.memory_type, dxe_services::GcdMemoryType::Unaccepted);
+            assert_eq!(descriptor.length, 0x1000);
+
+            // accepting a range that is partially accepted only accepts the remainder
+            GCD.accept_memory(UNACCEPTED_BASE, 0x4000).unwrap();
+            assert_eq!(
+                accepted.lock().unwrap()[1..],
+                [(UNACCEPTED_BASE as u64, 0x1000), ((UNACCEPTED_BASE + 0x3000) as u64, 0x1000)]
+            );
+            let descriptor = GCD.get_memory_descriptor_for_address(UNACCEPTED_BASE as u64).unwrap();
+            assert_eq!(descriptor.memory_type, dxe_services::GcdMemoryType::SystemMemory);
+            assert_eq!(descriptor.length, 0x4000);
+        });
+    }
+
+    #[test]
+    fn accept_memory_should_reject_invalid_ranges() {
+        with_locked_state(|| {
+            static GCD: SpinLockedGcd = SpinLockedGcd::new(None);
+            let accepted = init_unaccepted_gcd(&GCD, Ok(()));
+
+            assert_eq!(GCD.accept_memory(UNACCEPTED_BASE + 1, 0x1000), Err(EfiError::InvalidParameter));
+            assert_eq!(GCD.accept_memory(UNACCEPTED_BASE, 0x1001), Err(EfiError::InvalidParameter));
+            assert_eq!(GCD.accept_memory(UNACCEPTED_BASE, 0), Err(EfiError::InvalidParameter));
+
+            // the range extends past the unaccepted memory into non-existent memory
+            assert_eq!(
+                GCD.accept_memory(UNACCEPTED_BASE + UNACCEPTED_SIZE - 0x1000, 0x2000),
+                Err(EfiError::InvalidParameter)
+            );
+            assert!(accepted.lock().unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn accept_memory_should_hold_the_gcd_lock_while_accepting() {
+        static GCD: SpinLockedGcd = SpinLockedGcd::new(None);
+        static LOCK_HELD: AtomicBool = AtomicBool::new(false);
+
+        struct LockCheckingAcceptance;
+
+        impl MemoryAcceptance for LockCheckingAcceptance {
+            fn accept_memory(&self, _start: efi::PhysicalAddress, _size: u64) -> Result<(), EfiError> {
+                LOCK_HELD.store(GCD.memory.try_lock().is_none(), core::sync::atomic::Ordering::SeqCst);
+                Ok(())
+            }
+        }
+
+        with_locked_state(|| {
+            init_unaccepted_gcd(&GCD, Ok(()));
+            GCD.set_memory_acceptance(Service::mock(Box::new(LockCheckingAcceptance)));
+
+            GCD.accept_memory(UNACCEPTED_BASE, 0x1000).unwrap();
+            assert!(LOCK_HELD.load(core::sync::atomic::Ordering::SeqCst));
+            assert_eq!(
+                GCD.get_memory_descriptor_for_address(UNACCEPTED_BASE as u64).unwrap().memory_type,
+                dxe_services::GcdMemoryType::SystemMemory
+            );
+        });
+    }
+
+    #[test]
+    fn allocate_memory_space_should_accept_memory_on_demand() {
+        with_locked_state(|| {
+            static GCD: SpinLockedGcd = SpinLockedGcd::new(None);
+            let accepted = init_unaccepted_gcd(&GCD, Ok(()));
+
+            let address = GCD
+                .allocate_memory_space(
+                    AllocateType::BottomUp(None),
+                    dxe_services::GcdMemoryType::SystemMemory,
+                    UEFI_PAGE_SHIFT,
+                    0x3000,
+                    1 as _,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(address, UNACCEPTED_BASE);
+            assert_eq!(*accepted.lock().unwrap(), [(UNACCEPTED_BASE as u64, MEMORY_ACCEPTANCE_CHUNK_SIZE as u64)]);
+
+            // the accepted chunk satisfies subsequent allocations without accepting more memory
+            GCD.allocate_memory_space(
+                AllocateType::BottomUp(None),
+                dxe_services::GcdMemoryType::SystemMemory,
+                UEFI_PAGE_SHIFT,
+                0x3000,
+                1 as _,
+                None,
+            )
+            .unwrap();
+            assert_eq!(accepted.lock().unwrap().len(), 1);
+
+            let address = GCD
+                .allocate_memory_space(
+                    AllocateType::TopDown(None),
+                    dxe_services::GcdMemoryType::SystemMemory,
+                    UEFI_PAGE_SHIFT,
+                    MEMORY_ACCEPTANCE_CHUNK_SIZE,
+                    1 as _,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(address, UNACCEPTED_BASE + UNACCEPTED_SIZE - MEMORY_ACCEPTANCE_CHUNK_SIZE);
+            assert_eq!(
+                accepted.lock().unwrap()[1],
+                (
+                    (UNACCEPTED_BASE + UNACCEPTED_SIZE - MEMORY_ACCEPTANCE_CHUNK_SIZE - UEFI_PAGE_SIZE) as u64,
+                    (MEMORY_ACCEPTANCE_CHUNK_SIZE + UEFI_PAGE_SIZE) as u64
+                )
+            );
+
+            let address = GCD
+                .allocate_memory_space(
+                    AllocateType::Address(UNACCEPTED_BASE + 0x400000),
+                    dxe_services::GcdMemoryType::SystemMemory,
+                    UEFI_PAGE_SHIFT,
+                    0x1000,
+                    1 as _,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(address, UNACCEPTED_BASE + 0x400000);
+            assert_eq!(accepted.lock().unwrap()[2], ((UNACCEPTED_BASE + 0x400000) as u64, 0x1000));
+
+            // unaccepted memory is never handed out for other memory types
+            assert_eq!(
+                GCD.allocate_memory_space(
+                    AllocateType::BottomUp(None),
+                    dxe_services::GcdMemoryType::Reserved,
+                    UEFI_PAGE_SHIFT,
+                    0x1000,
+                    1 as _,
+                    None,
+                ),
+                Err(EfiError::OutOfResources)
+            );
+            assert_eq!(accepted.lock().unwrap().len(), 3);
+        });
+    }
+
+    #[test]
+    fn allocate_memory_space_should_respect_max_address_when_accepting_memory() {
+        with_locked_state(|| {
+            static GCD: SpinLockedGcd = SpinLockedGcd::new(None);
+            let accepted = init_unaccepted_gcd(&GCD, Ok(()));
+
+            let result = GCD.allocate_memory_space(
+                AllocateType::TopDown(Some(UNACCEPTED_BASE + 0x1fff)),
+                dxe_services::GcdMemoryType::SystemMemory,
+                UEFI_PAGE_SHIFT,
+                0x2000,
+                1 as _,
+                None,
+            );
+            assert_eq!(result, Ok(UNACCEPTED_BASE));
+            assert_eq!(*accepted.lock().unwrap(), [(UNACCEPTED_BASE as u64, 0x2000)]);
+        });
+    }
+
+    #[test]
+    fn allocate_memory_space_should_fail_if_memory_acceptance_fails() {
+        with_locked_state(|| {
+            static GCD: SpinLockedGcd = SpinLockedGcd::new(None);
+            init_unaccepted_gcd(&GCD, Err(EfiError::DeviceError));
+
+            let result = GCD.allocate_memory_space(
+                AllocateType::BottomUp(None),
+                dxe_services::GcdMemoryType::SystemMemory,
+                UEFI_PAGE_SHIFT,
+                0x1000,
+                1 as _,
+                None,
+            );
+            assert_eq!(result, Err(EfiError::OutOfResources));
+            assert_eq!(GCD.accept_memory(UNACCEPTED_BASE, 0x1000), Err(EfiError::DeviceError));
+
+            let descriptor = GCD.get_memory_descriptor_for_address(UNACCEPTED_BASE as u64).unwrap();
+            assert_eq!(descriptor.memory_type, dxe_services::GcdMemoryType::Unaccepted);
+            assert_eq!(descriptor.length, UNACCEPTED_SIZE as u64);
+        });
+    }
 }
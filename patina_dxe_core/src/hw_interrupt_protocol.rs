@@ -40,11 +40,11 @@ type HardwareInterruptEnd = unsafe extern "efiapi" fn(*mut EfiHardwareInterruptP
 /// C struct for the Hardware Interrupt protocol.
 #[repr(C)]
 pub struct EfiHardwareInterruptProtocol<'a> {
-    register_interrupt_source: HardwareInterruptRegister,
+    pub(crate) register_interrupt_source: HardwareInterruptRegister,
     enable_interrupt_source: HardwareInterruptEnable,
     disable_interrupt_source: HardwareInterruptDisable,
     get_interrupt_source_state: HardwareInterruptGetState,
-    end_of_interrupt: HardwareInterruptEnd,
+    pub(crate) end_of_interrupt: HardwareInterruptEnd,
 
     // Internal rust access only! Does not exist in C definition.
     hw_interrupt_handler: &'a HwInterruptProtocolHandler,
@@ -36,6 +36,7 @@ use crate::{
     dxe_services::{self, core_set_memory_space_attributes},
     events::EVENT_DB,
     filesystems::SimpleFile,
+    memory_protection::{CompatibilityModeTrigger, ImageProtection},
     pecoff::{self, UefiPeInfo, relocation::RelocationBlock},
     protocol_db,
     protocols::{
@@ -72,6 +73,7 @@ struct ImageStack {
     stack: *const [u8],
     len: usize,
     allocated_pages: usize,
+    guarded: bool,
 }
 
 impl ImageStack {
@@ -87,13 +89,19 @@ impl ImageStack {
 
         // attempt to set the memory space attributes for the stack guard page.
         // if we fail, we should still try to continue to boot
-        // the stack grows downwards, so stack here is the guard page
+        // the stack grows downwards, so stack here is the guard page. The page is still reserved when the memory
+        // protection policy disables stack guards so that the stack layout does not change.
+        let guarded = crate::GCD.memory_protection_policy().stack_guard();
         let attributes = match dxe_services::core_get_memory_space_descriptor(stack) {
             Ok(descriptor) => descriptor.attributes,
             Err(_) => DEFAULT_CACHE_ATTR,
         };
-        if let Err(err) =
-            dxe_services::core_set_memory_space_attributes(stack, UEFI_PAGE_SIZE as u64, attributes | efi::MEMORY_RP)
+        if guarded
+            && let Err(err) = dxe_services::core_set_memory_space_attributes(
+                stack,
+                UEFI_PAGE_SIZE as u64,
+                attributes | efi::MEMORY_RP,
+            )
         {
             log::error!("Failed to set memory space attributes for stack guard page: {err:?}");
             // unfortunately, this needs to be commented out for now, because the tests have gotten too complex
@@ -106,6 +114,7 @@ impl ImageStack {
             stack: core::ptr::slice_from_raw_parts_mut((stack + (UEFI_PAGE_SIZE as u64)) as *mut u8, len),
             len,
             allocated_pages,
+            guarded,
         })
     }
 }
@@ -124,8 +133,9 @@ impl Drop for ImageStack {
             };
 
             attributes |= efi::MEMORY_XP;
-            if let Err(err) =
-                dxe_services::core_set_memory_space_attributes(stack_addr, UEFI_PAGE_SIZE as u64, attributes)
+            if self.guarded
+                && let Err(err) =
+                    dxe_services::core_set_memory_space_attributes(stack_addr, UEFI_PAGE_SIZE as u64, attributes)
             {
                 log::error!("Failed to set memory space attributes for stack guard page: {err:?}");
                 // unfortunately, this needs to be commented out for now, because the tests have gotten too complex
@@ -178,6 +188,17 @@ struct PrivateImageData {
 
 impl PrivateImageData {
     fn new(image_info: efi::protocols::loaded_image::Protocol, pe_info: &UefiPeInfo) -> Result<Self, EfiError> {
+        // the image base is aligned up to the section alignment below, which requires a power of two. A relaxed image
+        // protection policy lets through alignments that are not a multiple of the page size, so check before
+        // allocating.
+        if !pe_info.section_alignment.is_power_of_two() {
+            log::error!(
+                "core_load_pe_image_failed: section alignment of {:#x?} is not a power of two",
+                pe_info.section_alignment
+            );
+            return Err(EfiError::LoadError);
+        }
+
         // Allocate pages for the image to be loaded into. We use pages here instead of a pool because we are going to
         // set memory attributes on this range and it is not valid to set attributes on pool backed memory.
         let mut image_base_page: efi::PhysicalAddress = 0;
@@ -562,13 +583,19 @@ fn install_dxe_core_image(hob_list: &HobList, system_table: &mut EfiSystemTable)
     };
     assert_eq!(handle, protocol_db::DXE_CORE_HANDLE);
 
-    // register the core image with the debug image info configuration table
+    // register the core image with the debug image info configuration table. Components are registered as
+    // sub-ranges of this entry once they have all been added to the core.
     initialize_debug_image_info_table(system_table);
     core_new_debug_image_info_entry(
         EfiDebugImageInfoNormal::EFI_DEBUG_IMAGE_INFO_TYPE_NORMAL,
         image_info_ptr as *const efi::protocols::loaded_image::Protocol,
         handle,
     );
+    patina_debugger::notify_module_load(
+        pe_info.filename.as_deref().unwrap_or("DxeCore"),
+        dxe_core_hob.alloc_descriptor.memory_base_address as usize,
+        dxe_core_hob.alloc_descriptor.memory_length as usize,
+    );
 
     // record this handle as the new dxe_core handle.
     private_data.dxe_core_image_handle = handle;
@@ -602,14 +629,31 @@ fn core_load_pe_image(
     let alignment = pe_info.section_alignment as usize; // Need to align the base address with section alignment via overallocation
     let size = pe_info.size_of_image as usize;
 
-    // the section alignment must be at least the size of a page
-    if !alignment.is_multiple_of(UEFI_PAGE_SIZE) || alignment == 0 {
-        log::error!(
-            "core_load_pe_image_failed: section alignment of {alignment:#x?} is not a (non-zero) multiple of page size {UEFI_PAGE_SIZE:#x?}",
-        );
+    let policy = crate::GCD.memory_protection_policy();
+
+    // the section alignment must be at least the size of a page for sections to be individually protected. A relaxed
+    // image protection policy loads such images with the whole image mapped RWX instead.
+    if alignment == 0 {
+        log::error!("core_load_pe_image_failed: section alignment is zero");
         debug_assert!(false);
         return Err(EfiError::LoadError);
     }
+    let protectable = alignment.is_multiple_of(UEFI_PAGE_SIZE);
+    if !protectable {
+        match policy.image_protection() {
+            ImageProtection::Strict => {
+                log::error!(
+                    "core_load_pe_image_failed: section alignment of {alignment:#x?} is not a multiple of page size {UEFI_PAGE_SIZE:#x?}",
+                );
+                debug_assert!(false);
+                return Err(EfiError::LoadError);
+            }
+            ImageProtection::Relaxed => log::error!(
+                "Section alignment of {alignment:#x?} for {} is not a multiple of page size {UEFI_PAGE_SIZE:#x?}. Loading the image without section protections.",
+                pe_info.filename.as_deref().unwrap_or("Unknown"),
+            ),
+        }
+    }
 
     // the size of the image must be a multiple of the section alignment per PE/COFF spec
     if !size.is_multiple_of(alignment) {
@@ -676,9 +720,23 @@ fn core_load_pe_image(
     match pe_info.image_type {
         EFI_IMAGE_SUBSYSTEM_EFI_APPLICATION if !pe_info.nx_compat => {
             // we are trying to load an application image that is not NX compatible, likely a bootloader
-            // if we are configured to allow compatibility mode, we need to activate it now. Otherwise, just continue
-            // to load the image
-            activate_compatibility_mode(&private_info)?;
+            // if we are configured to allow compatibility mode, we need to activate it now. Otherwise, fail to load
+            // the image
+            match policy.compatibility_mode() {
+                CompatibilityModeTrigger::NonNxCompatApplication => activate_compatibility_mode(&private_info)?,
+                CompatibilityModeTrigger::Never => {
+                    log::error!(
+                        "Attempting to load {} that is not NX compatible. Compatibility mode is disabled by the memory protection policy, not loading image.",
+                        pe_info.filename.as_deref().unwrap_or("Unknown")
+                    );
+                    return Err(EfiError::LoadError);
+                }
+            }
+        }
+        _ if !protectable => {
+            // the relaxed policy case from above: the sections cannot be protected individually, so map the whole
+            // image RWX.
+            map_image_rwx(&private_info);
         }
         _ => {
             // finally, update the GCD attributes for this image so that code sections have RO set and data sections
@@ -690,13 +748,8 @@ fn core_load_pe_image(
     Ok(private_info)
 }
 
-#[cfg(feature = "compatibility_mode_allowed")]
-/// Activates compatibility mode for an image that is not NX compatible if the feature flag is set to allow compat mode
-/// This function will map the image as RWX in the GCD and initiate compatibility mode in the GCD
-fn activate_compatibility_mode(private_info: &PrivateImageData) -> Result<(), EfiError> {
-    log::error!("Attempting to load an application image that is not NX compatible. Activating compatibility mode.");
-    crate::gcd::activate_compatibility_mode();
-    // for this image map all mem RWX preserving cache attributes if we find them
+/// Maps all pages of the image as RWX, preserving cache attributes if we find them.
+fn map_image_rwx(private_info: &PrivateImageData) {
     let stripped_attrs = dxe_services::core_get_memory_space_descriptor(private_info.image_base_page)
         .map(|desc| desc.attributes & efi::CACHE_ATTRIBUTE_MASK)
         .unwrap_or(DEFAULT_CACHE_ATTR);
@@ -714,6 +767,15 @@ fn activate_compatibility_mode(private_info: &PrivateImageData) -> Result<(), Ef
         );
         debug_assert!(false);
     }
+}
+
+#[cfg(feature = "compatibility_mode_allowed")]
+/// Activates compatibility mode for an image that is not NX compatible if the feature flag is set to allow compat mode
+/// This function will map the image as RWX in the GCD and initiate compatibility mode in the GCD
+fn activate_compatibility_mode(private_info: &PrivateImageData) -> Result<(), EfiError> {
+    log::error!("Attempting to load an application image that is not NX compatible. Activating compatibility mode.");
+    crate::gcd::activate_compatibility_mode();
+    map_image_rwx(private_info);
     Ok(())
 }
 
@@ -1497,6 +1559,56 @@ extern "efiapi" fn exit(
     efi::Status::ACCESS_DENIED
 }
 
+/// Invokes `f` with the file name of the loaded image containing `address`, or `None` if no loaded image contains
+/// it (or the image has no file name). Does not allocate.
+pub fn with_image_name_containing<R>(address: usize, f: impl FnOnce(Option<&str>) -> R) -> R {
+    let Some(private_data) = PRIVATE_IMAGE_DATA.try_lock() else {
+        return f(None);
+    };
+    let address = address as u64;
+    let name = private_data
+        .private_image_data
+        .values()
+        .find(|image| {
+            let base = image.image_info.image_base as u64;
+            address >= base && address - base < image.image_info.image_size
+        })
+        .and_then(|image| image.pe_info.filename.as_deref());
+    f(name)
+}
+
+/// Invokes `f` with the file name of the loaded image with the given image handle, or `None` if the handle is not a
+/// loaded image (or the image has no file name). Does not allocate.
+pub fn with_image_name<R>(image_handle: efi::Handle, f: impl FnOnce(Option<&str>) -> R) -> R {
+    let Some(private_data) = PRIVATE_IMAGE_DATA.try_lock() else {
+        return f(None);
+    };
+    f(private_data.private_image_data.get(&image_handle).and_then(|image| image.pe_info.filename.as_deref()))
+}
+
+/// Invokes `f` with the file name, base address and size of each loaded image. Does nothing if the image data is
+/// locked.
+pub fn for_each_loaded_image(mut f: impl FnMut(Option<&str>, u64, u64)) {
+    let Some(private_data) = PRIVATE_IMAGE_DATA.try_lock() else {
+        return;
+    };
+    for image in private_data.private_image_data.values() {
+        f(image.pe_info.filename.as_deref(), image.image_info.image_base as u64, image.image_info.image_size);
+    }
+}
+
+/// Returns the base address and size of the DXE core's image, or `None` if it has not been installed.
+pub(crate) fn dxe_core_image_range() -> Option<(u64, u64)> {
+    let private_data = PRIVATE_IMAGE_DATA.lock();
+    let image = private_data.private_image_data.get(&private_data.dxe_core_image_handle)?;
+    Some((image.image_info.image_base as u64, image.image_info.image_size))
+}
+
+/// Returns the handle of the image that is currently executing, or `None` if the DXE core itself is executing.
+pub fn current_running_image() -> Option<efi::Handle> {
+    PRIVATE_IMAGE_DATA.try_lock().and_then(|private_data| private_data.current_running_image)
+}
+
 /// Initializes image services for the DXE core.
 pub fn init_image_support(hob_list: &HobList, system_table: &mut EfiSystemTable) {
     // initialize system table entry in private global.
@@ -1529,7 +1641,7 @@ pub fn init_image_support(hob_list: &HobList, system_table: &mut EfiSystemTable)
 #[coverage(off)]
 mod tests {
     extern crate std;
-    use super::{empty_image_info, get_buffer_by_file_path, load_image};
+    use super::{PrivateImageData, UefiPeInfo, empty_image_info, get_buffer_by_file_path, load_image};
     use crate::{
         image::{PRIVATE_IMAGE_DATA, exit, start_image, unload_image},
         protocol_db,
@@ -1620,6 +1732,17 @@ mod tests {
         });
     }
 
+    #[test]
+    fn private_image_data_should_reject_a_section_alignment_that_is_not_a_power_of_two() {
+        with_locked_state(|| {
+            let mut image_info = empty_image_info();
+            image_info.image_size = 0x3000;
+            let pe_info = UefiPeInfo { section_alignment: 0x1800, size_of_image: 0x3000, ..Default::default() };
+
+            assert!(matches!(PrivateImageData::new(image_info, &pe_info), Err(EfiError::LoadError)));
+        });
+    }
+
     #[test]
     fn load_image_should_authenticate_the_image_with_security_arch() {
         with_locked_state(|| {
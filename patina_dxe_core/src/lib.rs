@@ -36,12 +36,16 @@
 extern crate alloc;
 
 mod allocator;
+mod component_perf;
+mod component_triggers;
 mod config_tables;
 mod cpu_arch_protocol;
+mod crash_record;
 mod decompress;
 mod dispatcher;
 mod driver_services;
 mod dxe_services;
+mod ebs_diagnostics;
 mod event_db;
 mod events;
 mod filesystems;
@@ -50,15 +54,22 @@ mod gcd;
 #[cfg(all(target_os = "uefi", target_arch = "aarch64"))]
 mod hw_interrupt_protocol;
 mod image;
+mod memory_accept_protocol;
 mod memory_attributes_protocol;
 mod memory_manager;
+mod memory_protection;
+mod metronome_arch_protocol;
 mod misc_boot_services;
 mod pecoff;
+mod protocol_audit;
 mod protocol_db;
 mod protocols;
 mod runtime;
 mod systemtables;
+mod table_integrity;
+mod timer_arch_protocol;
 mod tpl_lock;
+mod watchdog_arch_protocol;
 
 #[cfg(test)]
 #[macro_use]
@@ -68,12 +79,29 @@ pub mod test_support;
 use core::{ffi::c_void, ptr, str::FromStr};
 
 use alloc::{boxed::Box, vec::Vec};
+use config_tables::{
+    debug_image_info_table::{self, CoreDebugImageRegistry},
+    table_manager::CoreConfigTableManager,
+};
 use gcd::SpinLockedGcd;
 use memory_manager::CoreMemoryManager;
 use mu_rust_helpers::{function, guid::CALLER_ID};
 use patina::{
     boot_services::StandardBootServices,
-    component::{Component, IntoComponent, Storage, service::IntoService},
+    component::{
+        IntoComponent, Storage,
+        dispatch::Dispatcher,
+        failure::FailurePolicy,
+        order::Order,
+        schedule::{self, Executor},
+        service::{
+            IntoService,
+            crash_record::{CrashRecordStore, LogTail},
+            dma::DmaMappingTracker,
+            memory::MemoryAcceptance,
+        },
+        trigger::Trigger,
+    },
     error::{self, Result},
     performance::{
         logging::{perf_function_begin, perf_function_end},
@@ -87,12 +115,21 @@ use patina::{
     runtime_services::StandardRuntimeServices,
 };
 use patina_ffs::section::SectionExtractor;
-use patina_internal_cpu::{cpu::EfiCpu, interrupts::Interrupts};
+use patina_internal_cpu::{
+    cpu::EfiCpu,
+    interrupts::{self, Interrupts},
+    timer::EfiTimer,
+};
 use protocols::PROTOCOL_DB;
 use r_efi::efi;
 
 use crate::config_tables::memory_attributes_table;
 
+pub use gcd::SpecialPurposeMemoryPolicy;
+pub use memory_protection::{CompatibilityModeTrigger, ImageProtection, MemoryProtectionPolicy, PolicyError};
+pub use protocol_audit::ProtocolAuditMode;
+pub use table_integrity::TableIntegrityMode;
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! ensure {
@@ -174,9 +211,13 @@ pub struct NoAlloc;
 /// be directly registered with the [Core::with_service] method. If not, there is no guarantee that the service will
 /// be available before the core needs it.
 ///
-/// | Service Trait                           | Description                                      |
-/// |-----------------------------------------|--------------------------------------------------|
-/// | [patina_ffs::section::SectionExtractor] | FW volume section extraction w/ decompression    |
+/// | Service Trait                                                | Description                                    |
+/// |--------------------------------------------------------------|------------------------------------------------|
+/// | [patina_ffs::section::SectionExtractor]                      | FW volume section extraction w/ decompression  |
+/// | [patina::component::service::memory::MemoryAcceptance]       | Acceptance of unaccepted memory (e.g. TDX/SNP) |
+/// | [patina::component::service::dma::DmaMappingTracker]         | Outstanding DMA mapping reporting at EBS       |
+/// | [patina::component::service::crash_record::CrashRecordStore] | Persisting crash records across resets         |
+/// | [patina::component::service::crash_record::LogTail]          | Recent log output included in crash records    |
 ///
 /// ## Examples
 ///
@@ -198,7 +239,8 @@ pub struct NoAlloc;
 pub struct Core<MemoryState> {
     physical_hob_list: *const c_void,
     hob_list: HobList<'static>,
-    components: Vec<Box<dyn Component>>,
+    dispatcher: Dispatcher,
+    triggered_components: component_triggers::TriggeredComponents,
     storage: Storage,
     _memory_state: core::marker::PhantomData<MemoryState>,
 }
@@ -208,7 +250,12 @@ impl Default for Core<NoAlloc> {
         Core {
             physical_hob_list: core::ptr::null(),
             hob_list: HobList::default(),
-            components: Vec::new(),
+            dispatcher: {
+                let mut dispatcher = Dispatcher::new();
+                dispatcher.set_executor(component_perf::MeasuringExecutor::new(schedule::Sequential));
+                dispatcher
+            },
+            triggered_components: Vec::new(),
             storage: Storage::new(),
             _memory_state: core::marker::PhantomData,
         }
@@ -257,6 +304,65 @@ impl Core<NoAlloc> {
         patina_debugger::add_monitor_command("gcd", "Prints the GCD", |_, out| {
             let _ = write!(out, "GCD -\n{GCD}");
         });
+        patina_debugger::add_monitor_command(
+            "handles",
+            "Prints the handle graph. Usage: handles [text|json|dot] [handle]",
+            |args, out| {
+                let format = args.next().unwrap_or("text");
+                let Some(format) = protocol_db::GraphFormat::from_name(format) else {
+                    let _ = write!(out, "Unknown format: {format}");
+                    return;
+                };
+                // Optionally restrict the output to a single handle.
+                let handle = match args.next() {
+                    Some(handle) => match usize::from_str_radix(handle.trim_start_matches("0x"), 16) {
+                        Ok(value) => Some(value),
+                        Err(_) => {
+                            let _ = write!(out, "Unknown handle: {handle}");
+                            return;
+                        }
+                    },
+                    None => None,
+                };
+                // Written straight from the database, as the debugger must not allocate.
+                match PROTOCOL_DB.try_write_handle_graph(out, format, handle) {
+                    Some(Ok(_)) => (),
+                    Some(Err(_)) => {
+                        let _ = write!(out, "Unknown handle: {:#x}", handle.unwrap_or_default());
+                    }
+                    None => {
+                        let _ = write!(out, "Protocol database is locked.");
+                    }
+                }
+            },
+        );
+        patina_debugger::add_monitor_command(
+            "protocols",
+            "Prints each installed protocol and the handles it is installed on",
+            |_, out| {
+                if PROTOCOL_DB.try_write_protocols(out).is_none() {
+                    let _ = write!(out, "Protocol database is locked.");
+                }
+            },
+        );
+        patina_debugger::add_monitor_command(
+            "connects",
+            "Prints the most recent ConnectController traces, if tracing is enabled",
+            |_, out| {
+                if !driver_services::CONNECT_TRACE.enabled() {
+                    let _ = write!(out, "ConnectController tracing is not enabled.");
+                    return;
+                }
+                if !driver_services::CONNECT_TRACE.try_for_each_record(|record| {
+                    let _ = writeln!(out, "{}", record.summary());
+                }) {
+                    let _ = write!(out, "ConnectController trace is locked.");
+                }
+            },
+        );
+
+        // Capture a crash record for exceptions that have no handler before the system panics.
+        interrupts::set_unhandled_exception_hook(crash_record::exception_hook);
 
         // Initialize the debugger if it is enabled.
         patina_debugger::initialize(&mut interrupt_manager);
@@ -266,11 +372,14 @@ impl Core<NoAlloc> {
         self.storage.add_service(cpu);
         self.storage.add_service(interrupt_manager);
         self.storage.add_service(CoreMemoryManager);
+        self.storage.add_service(CoreConfigTableManager);
+        self.storage.add_service(CoreDebugImageRegistry);
 
         Core {
             physical_hob_list,
             hob_list: self.hob_list,
-            components: self.components,
+            dispatcher: self.dispatcher,
+            triggered_components: self.triggered_components,
             storage: self.storage,
             _memory_state: core::marker::PhantomData,
         }
@@ -303,6 +412,82 @@ impl Core<NoAlloc> {
         GCD.prioritize_32_bit_memory(true);
         self
     }
+
+    /// Selects the memory protections applied by the core. See [MemoryProtectionPolicy] for the available presets.
+    ///
+    /// Must be called prior to [`Core::init_memory`]. A policy that fails
+    /// [validation](MemoryProtectionPolicy::validate) is logged and replaced with the
+    /// [default policy](MemoryProtectionPolicy::new).
+    ///
+    /// ## Example
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .with_memory_protection_policy(patina_dxe_core::MemoryProtectionPolicy::debug())
+    ///   .init_memory(physical_hob_list)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_memory_protection_policy(self, policy: MemoryProtectionPolicy) -> Self {
+        let policy = match policy.validate() {
+            Ok(()) => policy,
+            Err(err) => {
+                log::error!("Invalid memory protection policy {policy:?}: {err}. Using the default policy.");
+                MemoryProtectionPolicy::default()
+            }
+        };
+        // Like prioritize_32_bit_memory, the policy lives in the GCD so that it is in place before the first
+        // allocation is made.
+        GCD.set_memory_protection_policy(policy);
+        self
+    }
+
+    /// Selects whether specific-purpose memory (`EFI_RESOURCE_ATTRIBUTE_SPECIAL_PURPOSE`) is presented as conventional
+    /// or reserved memory. See [SpecialPurposeMemoryPolicy]; the default is
+    /// [Conventional](SpecialPurposeMemoryPolicy::Conventional).
+    ///
+    /// Must be called prior to [`Core::init_memory`].
+    ///
+    /// ## Example
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .with_special_purpose_memory_policy(patina_dxe_core::SpecialPurposeMemoryPolicy::Reserved)
+    ///   .init_memory(physical_hob_list)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_special_purpose_memory_policy(self, policy: SpecialPurposeMemoryPolicy) -> Self {
+        GCD.set_special_purpose_memory_policy(policy);
+        self
+    }
+
+    /// Keeps the runtime memory map stable from boot to boot to support S4 (hibernate) resume.
+    ///
+    /// Runtime Services Code, Runtime Services Data and ACPI NVS memory is allocated only from buckets sized by the
+    /// platform's MEMORY_TYPE_INFO HOB. The buckets are placed back to back in a single region, ahead of any other
+    /// bucket, so their addresses are deterministic; allocations that do not fit fail rather than fragmenting the
+    /// map. At ExitBootServices the runtime memory layout is compared against the one recorded by the previous boot
+    /// and any difference is logged before the new layout is recorded.
+    ///
+    /// Must be called prior to [`Core::init_memory`].
+    ///
+    /// ## Example
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .with_stable_runtime_memory_map()
+    ///   .init_memory(physical_hob_list)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_stable_runtime_memory_map(self) -> Self {
+        allocator::set_stable_runtime_memory_map(true);
+        self
+    }
 }
 
 impl Core<Alloc> {
@@ -316,14 +501,238 @@ impl Core<Alloc> {
     /// Registers a component with the core, that will be dispatched during the driver execution phase.
     #[inline(always)]
     pub fn with_component<I>(mut self, component: impl IntoComponent<I>) -> Self {
-        self.insert_component(self.components.len(), component.into_component());
+        self.dispatcher.add_component(component.into_component(), &mut self.storage);
+        self
+    }
+
+    /// Registers ordering constraints for a component, which are validated and applied before any component is
+    /// dispatched. See the [order](patina::component::order) module for more details.
+    ///
+    /// ``` rust,no_run
+    /// # use patina::component::{IntoComponent, order::Order};
+    /// # #[derive(IntoComponent)]
+    /// # struct Producer;
+    /// # impl Producer {
+    /// #     fn entry_point(self) -> patina::error::Result<()> { Ok(()) }
+    /// # }
+    /// # #[derive(IntoComponent)]
+    /// # struct Consumer;
+    /// # impl Consumer {
+    /// #     fn entry_point(self) -> patina::error::Result<()> { Ok(()) }
+    /// # }
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .init_memory(physical_hob_list)
+    ///   .with_component(Consumer)
+    ///   .with_component(Producer)
+    ///   .with_component_order(Order::of::<Consumer>().after::<Producer>())
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_component_order(mut self, order: Order) -> Self {
+        self.dispatcher.add_order(order);
         self
     }
 
-    /// Inserts a component at the given index. If no index is provided, the component is added to the end of the list.
-    fn insert_component(&mut self, idx: usize, mut component: Box<dyn Component>) {
+    /// Sets what the core does when the component created from `C` fails. Components without a policy use
+    /// [FailurePolicy::DisableDependents].
+    ///
+    /// ``` rust,no_run
+    /// # use patina::component::{IntoComponent, failure::FailurePolicy};
+    /// # #[derive(IntoComponent)]
+    /// # struct Critical;
+    /// # impl Critical {
+    /// #     fn entry_point(self) -> patina::error::Result<()> { Ok(()) }
+    /// # }
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .init_memory(physical_hob_list)
+    ///   .with_component(Critical)
+    ///   .with_component_failure_policy::<Critical>(FailurePolicy::Halt)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_component_failure_policy<C: 'static>(mut self, policy: FailurePolicy) -> Self {
+        self.dispatcher.set_failure_policy(core::any::type_name::<C>(), policy);
+        self
+    }
+
+    /// Sets the executor used to run each batch of conflict-free components during dispatch. Defaults to
+    /// [Sequential](schedule::Sequential).
+    ///
+    /// Host-based tests can use the `ThreadPool` executor of the [schedule](patina::component::schedule) module,
+    /// available with the `std` feature, to run the components of a batch concurrently. This surfaces components that
+    /// access resources they did not declare, provided the components and the resources they access are thread safe
+    /// (see `ThreadPool::new`).
+    ///
+    /// ``` rust,no_run
+    /// # use patina::component::schedule::Sequential;
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .init_memory(physical_hob_list)
+    ///   .with_component_executor(Sequential)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_component_executor(mut self, executor: impl Executor + 'static) -> Self {
+        self.dispatcher.set_executor(component_perf::MeasuringExecutor::new(executor));
+        self
+    }
+
+    /// Registers a component with the core, that will be executed every time `trigger` fires rather than during the
+    /// driver execution phase.
+    ///
+    /// Triggered components are executed at `TPL_CALLBACK`. See the [trigger](patina::component::trigger) module for
+    /// more details.
+    ///
+    /// ``` rust,no_run
+    /// # use patina::component::{IntoComponent, trigger::Trigger};
+    /// # #[derive(IntoComponent)]
+    /// # struct LockDown;
+    /// # impl LockDown {
+    /// #     fn entry_point(self) -> patina::error::Result<()> { Ok(()) }
+    /// # }
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .init_memory(physical_hob_list)
+    ///   .with_triggered_component(Trigger::END_OF_DXE, LockDown)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_triggered_component<I>(mut self, trigger: Trigger, component: impl IntoComponent<I>) -> Self {
+        let mut component = component.into_component();
         component.initialize(&mut self.storage);
-        self.components.insert(idx, component);
+        self.triggered_components.push((trigger, component));
+        self
+    }
+
+    /// Registers a human readable name for a protocol GUID, used by the `handles` and `protocols` debugger commands
+    /// when displaying the protocol database. Well-known UEFI and PI protocols are named by default.
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// # let my_protocol_guid = r_efi::efi::Guid::from_fields(0, 0, 0, 0, 0, &[0; 6]);
+    /// patina_dxe_core::Core::default()
+    ///   .init_memory(physical_hob_list)
+    ///   .with_protocol_name(my_protocol_guid, "MyProtocol")
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_protocol_name(self, guid: efi::Guid, name: &'static str) -> Self {
+        protocol_db::register_guid_name(guid, name);
+        self
+    }
+
+    /// Sets how the core audits protocol interface usage for driver-model bugs. Auditing is disabled by default.
+    ///
+    /// When enabled, the core records the image that installed each protocol interface, warns when an uninstalled
+    /// interface pointer is passed back to the protocol services, warns with the outstanding open records when an
+    /// uninstall is denied because the interface is still open, and reports protocols still open at
+    /// ExitBootServices. See [ProtocolAuditMode] for the available modes.
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .init_memory(physical_hob_list)
+    ///   .with_protocol_auditing(patina_dxe_core::ProtocolAuditMode::Log)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_protocol_auditing(self, mode: ProtocolAuditMode) -> Self {
+        protocol_audit::PROTOCOL_AUDIT.set_mode(mode);
+        self
+    }
+
+    /// Sets how the core monitors the Boot Services and Runtime Services tables for hooks. Monitoring is disabled by
+    /// default.
+    ///
+    /// When enabled, the core snapshots the service tables once it has initialized them, and checks them after each
+    /// dispatch iteration, at ReadyToBoot and at ExitBootServices. Each changed pointer is reported along with the
+    /// image that contains the new target. See [TableIntegrityMode] for the available modes.
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .init_memory(physical_hob_list)
+    ///   .with_table_integrity_monitoring(patina_dxe_core::TableIntegrityMode::Revert)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_table_integrity_monitoring(self, mode: TableIntegrityMode) -> Self {
+        table_integrity::TABLE_INTEGRITY.set_mode(mode);
+        self
+    }
+
+    /// Enables ExitBootServices diagnostics.
+    ///
+    /// When enabled, the core records every change to the memory map along with the event notify function that made
+    /// it, and logs a summary whenever ExitBootServices is called. The summary contains the structured reason a
+    /// failed call could not terminate the memory map, the memory map changes made by PRE_EBS and
+    /// EFI_EVENT_GROUP_BEFORE_EXIT_BOOT_SERVICES callbacks, any timers that are still armed, and any DMA mappings
+    /// still outstanding (if a [DmaMappingTracker] service is registered).
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .init_memory(physical_hob_list)
+    ///   .with_exit_boot_services_diagnostics()
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_exit_boot_services_diagnostics(self) -> Self {
+        ebs_diagnostics::EBS_DIAGNOSTICS.enable();
+        self
+    }
+
+    /// Enables ConnectController tracing.
+    ///
+    /// When enabled, the core logs a summary of every controller connect: the candidate driver bindings in priority
+    /// order along with the override (or driver binding version) their priority came from, the result and duration
+    /// of each `Supported()` and `Start()` call, the drivers that were started and the children that were connected
+    /// recursively. The most recent summaries can also be displayed with the `connects` debugger command.
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .init_memory(physical_hob_list)
+    ///   .with_connect_controller_tracing()
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_connect_controller_tracing(self) -> Self {
+        driver_services::CONNECT_TRACE.enable();
+        self
+    }
+
+    /// Produces the Timer, Watchdog Timer and Metronome Architectural Protocols from the core rather than from platform
+    /// drivers.
+    ///
+    /// The Timer and Metronome Architectural Protocols are built on the architecture's
+    /// [Timer](patina_internal_cpu::timer::Timer) service: the local APIC timer and TSC on x64, and the generic timer
+    /// on AArch64. The Watchdog Timer Architectural Protocol is a software watchdog driven by a timer event, which
+    /// resets the system on expiry. Platforms that enable this must not also dispatch drivers producing these
+    /// protocols.
+    ///
+    /// The timer hardware is only initialized, and the [Timer](patina_internal_cpu::timer::Timer) service only
+    /// registered, when this is called, so that it is left untouched for platform timer drivers otherwise.
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .init_memory(physical_hob_list)
+    ///   .with_timer_arch_protocols()
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    #[allow(clippy::default_constructed_unit_structs)]
+    pub fn with_timer_arch_protocols(self) -> Self {
+        let mut timer = EfiTimer::default();
+        timer.initialize().expect("Failed to initialize Timer!");
+
+        self.with_service(timer)
+            .with_component(timer_arch_protocol::TimerArchProtocolInstaller::default())
+            .with_component(watchdog_arch_protocol::WatchdogArchProtocolInstaller::default())
+            .with_component(metronome_arch_protocol::MetronomeArchProtocolInstaller::default())
     }
 
     /// Adds a configuration value to the Core's storage. All configuration is locked by default. If a component is
@@ -357,33 +766,6 @@ impl Core<Alloc> {
         }
     }
 
-    /// Attempts to dispatch all components.
-    ///
-    /// This method will exit once no components remain or no components were dispatched during a full iteration.
-    fn dispatch_components(&mut self) -> bool {
-        let len = self.components.len();
-        self.components.retain_mut(|component| {
-            // Ok(true): Dispatchable and dispatched returning success
-            // Ok(false): Not dispatchable at this time.
-            // Err(e): Dispatchable and dispatched returning failure
-            let name = component.metadata().name();
-            log::trace!("Dispatch Start: Id = [{name:?}]");
-            !match component.run(&mut self.storage) {
-                Ok(true) => {
-                    log::info!("Dispatched: Id = [{name:?}] Status = [Success]");
-                    true
-                }
-                Ok(false) => false,
-                Err(err) => {
-                    log::error!("Dispatched: Id = [{name:?}] Status = [Failed] Error = [{err:?}]");
-                    debug_assert!(false);
-                    true // Component dispatched, even if it did fail, so remove from self.components to avoid re-dispatch.
-                }
-            }
-        });
-        len != self.components.len()
-    }
-
     /// Performs a combined dispatch of Patina components and UEFI drivers.
     ///
     /// This function will continue to loop and perform dispatching until no components have been dispatched in a full
@@ -395,12 +777,15 @@ impl Core<Alloc> {
         perf_function_begin(function!(), &CALLER_ID, create_performance_measurement);
         loop {
             // Patina component dispatch
-            let dispatched = self.dispatch_components();
+            let dispatched =
+                component_triggers::with_exclusive_storage(|| self.dispatcher.dispatch(&mut self.storage))?;
 
             // UEFI driver dispatch
             let dispatched = dispatched
                 || dispatcher::dispatch().inspect_err(|err| log::error!("UEFI Driver Dispatch error: {err:?}"))?;
 
+            table_integrity::TABLE_INTEGRITY.check(table_integrity::CheckPoint::Dispatch);
+
             if !dispatched {
                 break;
             }
@@ -410,34 +795,6 @@ impl Core<Alloc> {
         Ok(())
     }
 
-    fn display_components_not_dispatched(&self) {
-        if !self.components.is_empty() {
-            let name_len = "name".len();
-            let param_len = "failed_param".len();
-
-            let max_name_len = self.components.iter().map(|c| c.metadata().name().len()).max().unwrap_or(name_len);
-            let max_param_len = self
-                .components
-                .iter()
-                .map(|c| c.metadata().failed_param().map(|s| s.len()).unwrap_or(0))
-                .max()
-                .unwrap_or(param_len);
-
-            log::warn!("Components not dispatched:");
-            log::warn!("{:-<max_name_len$} {:-<max_param_len$}", "", "");
-            log::warn!("{:<max_name_len$} {:<max_param_len$}", "name", "failed_param");
-
-            for component in &self.components {
-                let metadata = component.metadata();
-                log::warn!(
-                    "{:<max_name_len$} {:<max_param_len$}",
-                    metadata.name(),
-                    metadata.failed_param().unwrap_or("")
-                );
-            }
-        }
-    }
-
     /// Returns the length of the HOB list.
     /// Clippy gets unhappy if we call get_c_hob_list_size directly, because it gets confused, thinking
     /// get_c_hob_list_size is not marked unsafe, but it is
@@ -520,11 +877,47 @@ impl Core<Alloc> {
     /// Registers core provided components
     #[allow(clippy::default_constructed_unit_structs)]
     fn add_core_components(&mut self) {
-        self.insert_component(0, decompress::DecompressProtocolInstaller::default().into_component());
-        self.insert_component(0, systemtables::SystemTableChecksumInstaller::default().into_component());
-        self.insert_component(0, cpu_arch_protocol::CpuArchProtocolInstaller::default().into_component());
+        self.dispatcher.insert_component(
+            0,
+            decompress::DecompressProtocolInstaller::default().into_component(),
+            &mut self.storage,
+        );
+        self.dispatcher.insert_component(
+            0,
+            systemtables::SystemTableChecksumInstaller::default().into_component(),
+            &mut self.storage,
+        );
+        self.dispatcher.insert_component(
+            0,
+            cpu_arch_protocol::CpuArchProtocolInstaller::default().into_component(),
+            &mut self.storage,
+        );
         #[cfg(all(target_os = "uefi", target_arch = "aarch64"))]
-        self.insert_component(0, hw_interrupt_protocol::HwInterruptProtocolInstaller::default().into_component());
+        self.dispatcher.insert_component(
+            0,
+            hw_interrupt_protocol::HwInterruptProtocolInstaller::default().into_component(),
+            &mut self.storage,
+        );
+        self.triggered_components
+            .push((Trigger::EXIT_BOOT_SERVICES, component_triggers::ServiceTeardown::default().into_component()));
+    }
+
+    /// Registers the sub-range of the core's image holding each component with the debug image info table.
+    fn register_component_debug_entries(&self) {
+        let Some((core_base, core_size)) = image::dxe_core_image_range() else {
+            log::warn!("DXE core image not installed, components are not registered with the debug image info table.");
+            return;
+        };
+        let components = self
+            .dispatcher
+            .components()
+            .iter()
+            .chain(self.triggered_components.iter().map(|(_, component)| component))
+            .filter_map(|component| {
+                component.code_address().map(|address| (component.metadata().name(), address as u64))
+            })
+            .collect();
+        debug_image_info_table::core_new_component_debug_entries(core_base, core_size, components);
     }
 
     /// Starts the core, dispatching all drivers.
@@ -537,6 +930,8 @@ impl Core<Alloc> {
         self.initialize_system_table()?;
         log::info!("Finished.");
 
+        table_integrity::TABLE_INTEGRITY.snapshot();
+
         log::info!("Parsing HOB list for Guided HOBs.");
         self.parse_hobs();
         log::info!("Finished.");
@@ -547,17 +942,50 @@ impl Core<Alloc> {
             fv::register_section_extractor(extractor);
         }
 
+        if let Some(memory_acceptance) = self.storage.get_service::<dyn MemoryAcceptance>() {
+            log::debug!("Memory Acceptance service found, registering with GCD.");
+            GCD.set_memory_acceptance(memory_acceptance);
+            memory_accept_protocol::install_memory_accept_protocol();
+        }
+
+        if let Some(dma_mapping_tracker) = self.storage.get_service::<dyn DmaMappingTracker>() {
+            log::debug!("DMA Mapping Tracker service found, registering with ExitBootServices diagnostics.");
+            ebs_diagnostics::EBS_DIAGNOSTICS.set_dma_mapping_tracker(dma_mapping_tracker);
+        }
+
+        if let Some(store) = self.storage.get_service::<dyn CrashRecordStore>() {
+            log::debug!("Crash Record Store service found, registering with the crash recorder.");
+            crash_record::CRASH_RECORDER.set_store(store);
+        }
+
+        if let Some(log_tail) = self.storage.get_service::<dyn LogTail>() {
+            log::debug!("Log Tail service found, registering with the crash recorder.");
+            crash_record::CRASH_RECORDER.set_log_tail(log_tail);
+        }
+
+        crash_record::CRASH_RECORDER.publish_previous_record();
+
+        self.register_component_debug_entries();
+
+        log::info!("Arming triggered components");
+        component_triggers::arm(core::mem::take(&mut self.triggered_components), &mut self.storage);
+        log::info!("Finished.");
+
         log::info!("Parsing FVs from FV HOBs");
         fv::parse_hob_fvs(&self.hob_list)?;
         log::info!("Finished.");
 
+        log::info!("Validating component dispatch order");
+        self.dispatcher.order(&mut self.storage)?;
+        log::info!("Finished.");
+
         log::info!("Dispatching Drivers");
         self.core_dispatcher()?;
-        self.storage.lock_configs();
+        component_triggers::with_exclusive_storage(|| self.storage.lock_configs());
         self.core_dispatcher()?;
         log::info!("Finished Dispatching Drivers");
 
-        self.display_components_not_dispatched();
+        self.dispatcher.report();
 
         core_display_missing_arch_protocols();
 
@@ -565,6 +993,8 @@ impl Core<Alloc> {
 
         call_bds();
 
+        component_triggers::release_storage();
+
         log::info!("Finished");
         Ok(())
     }
@@ -0,0 +1,81 @@
+//! DXE Core Memory Accept Protocol
+//!
+//! Produces the `EDKII_MEMORY_ACCEPT_PROTOCOL` when the platform provides a
+//! [MemoryAcceptance](patina::component::service::memory::MemoryAcceptance) service, allowing drivers and OS loaders
+//! to accept unaccepted memory through the GCD.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::boxed::Box;
+use core::ffi::c_void;
+use mu_rust_helpers::function;
+use patina::{
+    base::UEFI_PAGE_MASK,
+    uefi_protocol::memory_accept::{EDKII_MEMORY_ACCEPT_PROTOCOL_GUID, EdkiiMemoryAcceptProtocol},
+};
+use r_efi::efi;
+
+use crate::{GCD, protocols::PROTOCOL_DB};
+
+extern "efiapi" fn accept_memory(
+    _this: *mut EdkiiMemoryAcceptProtocol,
+    start_address: efi::PhysicalAddress,
+    size: usize,
+) -> efi::Status {
+    // We can only accept memory on page aligned addresses and lengths
+    if size == 0 || (start_address & UEFI_PAGE_MASK as u64) != 0 || (size & UEFI_PAGE_MASK) != 0 {
+        log::error!("start_address and size must be page aligned and non-zero in {}", function!());
+        return efi::Status::INVALID_PARAMETER;
+    }
+
+    match GCD.accept_memory(start_address as usize, size) {
+        Ok(_) => efi::Status::SUCCESS,
+        Err(err) => {
+            log::error!(
+                "Failed to accept memory {:#x} of length {:#x}: {:?} in {}",
+                start_address,
+                size,
+                err,
+                function!()
+            );
+            err.into()
+        }
+    }
+}
+
+/// This function is called by the DXE Core to install the protocol.
+pub(crate) fn install_memory_accept_protocol() {
+    let interface = Box::into_raw(Box::new(EdkiiMemoryAcceptProtocol { accept_memory })) as *mut c_void;
+
+    if let Err(e) = PROTOCOL_DB.install_protocol_interface(None, EDKII_MEMORY_ACCEPT_PROTOCOL_GUID, interface) {
+        log::error!("Failed to install EDKII_MEMORY_ACCEPT_PROTOCOL_GUID: {e:?}");
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn accept_memory_should_validate_parameters() {
+        test_support::with_global_lock(|| {
+            unsafe { GCD.reset() };
+            GCD.init(48, 16);
+
+            let this = core::ptr::null_mut();
+            assert_eq!(accept_memory(this, 0x1001, 0x1000), efi::Status::INVALID_PARAMETER);
+            assert_eq!(accept_memory(this, 0x1000, 0x1001), efi::Status::INVALID_PARAMETER);
+            assert_eq!(accept_memory(this, 0x1000, 0), efi::Status::INVALID_PARAMETER);
+
+            // no memory acceptance service has been provided
+            assert_eq!(accept_memory(this, 0x1000, 0x1000), efi::Status::UNSUPPORTED);
+        })
+        .unwrap();
+    }
+}
@@ -7,6 +7,7 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 use alloc::boxed::Box;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use patina::{
     base::{UEFI_PAGE_MASK, UEFI_PAGE_SIZE},
     component::service::{
@@ -28,6 +29,14 @@ use crate::{
     dxe_services,
 };
 
+/// The number of pages allocated through the memory manager service that have not been freed.
+static ALLOCATED_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of pages allocated through the memory manager service that have not been freed.
+pub(crate) fn allocated_pages() -> usize {
+    ALLOCATED_PAGES.load(Ordering::Relaxed)
+}
+
 /// Structure for wrapper rust allocator APIs.
 #[derive(IntoService)]
 #[service(dyn MemoryManager)]
@@ -64,6 +73,7 @@ impl MemoryManager for CoreMemoryManager {
 
         match result {
             Ok(_) => {
+                ALLOCATED_PAGES.fetch_add(page_count, Ordering::Relaxed);
                 let allocation = unsafe {
                     PageAllocation::new(address as usize, page_count, &CoreMemoryManager)
                         .map_err(|_| MemoryError::InternalError)?
@@ -78,7 +88,10 @@ impl MemoryManager for CoreMemoryManager {
     unsafe fn free_pages(&self, address: usize, page_count: usize) -> Result<(), MemoryError> {
         let result = core_free_pages(address as efi::PhysicalAddress, page_count);
         match result {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                ALLOCATED_PAGES.fetch_sub(page_count, Ordering::Relaxed);
+                Ok(())
+            }
             Err(EfiError::NotFound) => Err(MemoryError::InvalidAddress),
             Err(_) => Err(MemoryError::InternalError),
         }
@@ -0,0 +1,314 @@
+//! DXE Core Memory Protection Policy
+//!
+//! Describes the memory protections applied by the core. The policy is handed to the core once, before memory is
+//! initialized, via [Core::with_memory_protection_policy](crate::Core::with_memory_protection_policy) and is then
+//! consulted by the GCD, the allocators and the image loader.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::fmt::Display;
+
+use r_efi::efi;
+
+/// How the image loader reacts to an image whose sections cannot be individually protected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProtection {
+    /// Refuse to load images whose section alignment is not a multiple of the page size.
+    Strict,
+    /// Log an error and load the image with the whole image mapped RWX.
+    Relaxed,
+}
+
+/// Conditions that cause the core to enter compatibility mode.
+///
+/// Entering compatibility mode requires the `compatibility_mode_allowed` feature; see
+/// [MemoryProtectionPolicy::validate].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompatibilityModeTrigger {
+    /// Never enter compatibility mode. EFI applications that are not NX compatible fail to load.
+    Never,
+    /// Enter compatibility mode when an EFI application that is not NX compatible is loaded.
+    NonNxCompatApplication,
+}
+
+/// Reasons a [MemoryProtectionPolicy] is rejected by [MemoryProtectionPolicy::validate].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyError {
+    /// The NX mask selects a memory type that cannot be allocated.
+    InvalidMemoryType(efi::MemoryType),
+    /// The policy requests compatibility mode, but the `compatibility_mode_allowed` feature is not enabled.
+    CompatibilityModeNotAllowed,
+}
+
+impl Display for PolicyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PolicyError::InvalidMemoryType(memory_type) => {
+                write!(f, "memory type {memory_type:#x} is not a valid NX policy target")
+            }
+            PolicyError::CompatibilityModeNotAllowed => {
+                write!(f, "compatibility mode requires the `compatibility_mode_allowed` feature")
+            }
+        }
+    }
+}
+
+/// Memory types that may be selected in [MemoryProtectionPolicy::with_nx_memory_types]. These are the types that can be
+/// handed out by AllocatePages/AllocatePool.
+const ALLOCATABLE_MEMORY_TYPES: u32 = (1 << efi::RESERVED_MEMORY_TYPE)
+    | (1 << efi::LOADER_CODE)
+    | (1 << efi::LOADER_DATA)
+    | (1 << efi::BOOT_SERVICES_CODE)
+    | (1 << efi::BOOT_SERVICES_DATA)
+    | (1 << efi::RUNTIME_SERVICES_CODE)
+    | (1 << efi::RUNTIME_SERVICES_DATA)
+    | (1 << efi::UNUSABLE_MEMORY)
+    | (1 << efi::ACPI_RECLAIM_MEMORY)
+    | (1 << efi::ACPI_MEMORY_NVS)
+    | (1 << efi::MEMORY_MAPPED_IO)
+    | (1 << efi::MEMORY_MAPPED_IO_PORT_SPACE)
+    | (1 << efi::PAL_CODE);
+
+/// Memory types that hold code. These are left executable by the [compatible](MemoryProtectionPolicy::compatible)
+/// preset.
+const CODE_MEMORY_TYPES: u32 =
+    (1 << efi::LOADER_CODE) | (1 << efi::BOOT_SERVICES_CODE) | (1 << efi::RUNTIME_SERVICES_CODE) | (1 << efi::PAL_CODE);
+
+/// The set of memory protections applied by the core.
+///
+/// The default policy is [strict](Self::strict), except that builds with the `compatibility_mode_allowed` feature keep
+/// entering compatibility mode for non NX compatible applications.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, ImageProtection, MemoryProtectionPolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let policy = MemoryProtectionPolicy::strict().with_image_protection(ImageProtection::Relaxed);
+///
+/// Core::default()
+///     .with_memory_protection_policy(policy)
+///     .init_memory(physical_hob_list)
+///     .start()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryProtectionPolicy {
+    nx_memory_types: u32,
+    image_protection: ImageProtection,
+    null_pointer_detection: bool,
+    compatibility_mode: CompatibilityModeTrigger,
+    stack_guard: bool,
+}
+
+impl Default for MemoryProtectionPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryProtectionPolicy {
+    /// Returns the default policy. See [MemoryProtectionPolicy] for details.
+    pub const fn new() -> Self {
+        let policy = Self::strict();
+        if cfg!(feature = "compatibility_mode_allowed") {
+            policy.with_compatibility_mode(CompatibilityModeTrigger::NonNxCompatApplication)
+        } else {
+            policy
+        }
+    }
+
+    /// All allocations are NX, images that cannot be protected fail to load, page 0 is unmapped, stacks have guard
+    /// pages and compatibility mode is never entered.
+    pub const fn strict() -> Self {
+        Self {
+            nx_memory_types: ALLOCATABLE_MEMORY_TYPES,
+            image_protection: ImageProtection::Strict,
+            null_pointer_detection: true,
+            compatibility_mode: CompatibilityModeTrigger::Never,
+            stack_guard: true,
+        }
+    }
+
+    /// Code memory types are left executable, images that cannot be protected are loaded RWX and non NX compatible
+    /// applications enter compatibility mode. Requires the `compatibility_mode_allowed` feature.
+    pub const fn compatible() -> Self {
+        Self {
+            nx_memory_types: ALLOCATABLE_MEMORY_TYPES & !CODE_MEMORY_TYPES,
+            image_protection: ImageProtection::Relaxed,
+            null_pointer_detection: true,
+            compatibility_mode: CompatibilityModeTrigger::NonNxCompatApplication,
+            stack_guard: true,
+        }
+    }
+
+    /// The protections of [strict](Self::strict), but images that cannot be protected are logged and loaded instead of
+    /// rejected, so that every offending image is reported in a single boot.
+    pub const fn debug() -> Self {
+        Self::strict().with_image_protection(ImageProtection::Relaxed)
+    }
+
+    /// Sets whether newly allocated memory of `memory_type` is mapped non-executable.
+    pub const fn with_nx(mut self, memory_type: efi::MemoryType, nx: bool) -> Self {
+        if memory_type < u32::BITS {
+            if nx {
+                self.nx_memory_types |= 1 << memory_type;
+            } else {
+                self.nx_memory_types &= !(1 << memory_type);
+            }
+        }
+        self
+    }
+
+    /// Replaces the set of memory types mapped non-executable on allocation. Bit `n` selects memory type `n`.
+    pub const fn with_nx_memory_types(mut self, nx_memory_types: u32) -> Self {
+        self.nx_memory_types = nx_memory_types;
+        self
+    }
+
+    /// Sets how images that cannot be protected are handled.
+    pub const fn with_image_protection(mut self, image_protection: ImageProtection) -> Self {
+        self.image_protection = image_protection;
+        self
+    }
+
+    /// Sets whether page 0 is left unmapped to catch null pointer dereferences.
+    pub const fn with_null_pointer_detection(mut self, enabled: bool) -> Self {
+        self.null_pointer_detection = enabled;
+        self
+    }
+
+    /// Sets when compatibility mode is entered.
+    pub const fn with_compatibility_mode(mut self, trigger: CompatibilityModeTrigger) -> Self {
+        self.compatibility_mode = trigger;
+        self
+    }
+
+    /// Sets whether the DXE core stack and image entry point stacks get a read protected guard page.
+    pub const fn with_stack_guard(mut self, enabled: bool) -> Self {
+        self.stack_guard = enabled;
+        self
+    }
+
+    /// Returns true if newly allocated memory of `memory_type` is mapped non-executable.
+    pub const fn nx_enabled_for(&self, memory_type: efi::MemoryType) -> bool {
+        memory_type < u32::BITS && self.nx_memory_types & (1 << memory_type) != 0
+    }
+
+    /// Returns the image protection strictness.
+    pub const fn image_protection(&self) -> ImageProtection {
+        self.image_protection
+    }
+
+    /// Returns true if page 0 is left unmapped.
+    pub const fn null_pointer_detection(&self) -> bool {
+        self.null_pointer_detection
+    }
+
+    /// Returns the compatibility mode trigger.
+    pub const fn compatibility_mode(&self) -> CompatibilityModeTrigger {
+        self.compatibility_mode
+    }
+
+    /// Returns true if stacks get a guard page.
+    pub const fn stack_guard(&self) -> bool {
+        self.stack_guard
+    }
+
+    /// Checks that the policy is internally consistent and supported by this build.
+    pub fn validate(&self) -> Result<(), PolicyError> {
+        let invalid = self.nx_memory_types & !ALLOCATABLE_MEMORY_TYPES;
+        if invalid != 0 {
+            return Err(PolicyError::InvalidMemoryType(invalid.trailing_zeros()));
+        }
+
+        if self.compatibility_mode != CompatibilityModeTrigger::Never && !cfg!(feature = "compatibility_mode_allowed") {
+            return Err(PolicyError::CompatibilityModeNotAllowed);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_policy_protects_everything() {
+        let policy = MemoryProtectionPolicy::strict();
+        assert!(policy.validate().is_ok());
+        for memory_type in [efi::LOADER_CODE, efi::LOADER_DATA, efi::BOOT_SERVICES_DATA, efi::RUNTIME_SERVICES_CODE] {
+            assert!(policy.nx_enabled_for(memory_type));
+        }
+        assert_eq!(policy.image_protection(), ImageProtection::Strict);
+        assert_eq!(policy.compatibility_mode(), CompatibilityModeTrigger::Never);
+        assert!(policy.null_pointer_detection());
+        assert!(policy.stack_guard());
+    }
+
+    #[test]
+    fn debug_policy_only_relaxes_image_protection() {
+        let policy = MemoryProtectionPolicy::debug();
+        assert!(policy.validate().is_ok());
+        assert_eq!(policy, MemoryProtectionPolicy::strict().with_image_protection(ImageProtection::Relaxed));
+    }
+
+    #[test]
+    fn compatible_policy_leaves_code_executable() {
+        let policy = MemoryProtectionPolicy::compatible();
+        assert!(!policy.nx_enabled_for(efi::LOADER_CODE));
+        assert!(!policy.nx_enabled_for(efi::BOOT_SERVICES_CODE));
+        assert!(policy.nx_enabled_for(efi::LOADER_DATA));
+        assert!(policy.nx_enabled_for(efi::BOOT_SERVICES_DATA));
+
+        if cfg!(feature = "compatibility_mode_allowed") {
+            assert!(policy.validate().is_ok());
+        } else {
+            assert_eq!(policy.validate(), Err(PolicyError::CompatibilityModeNotAllowed));
+        }
+    }
+
+    #[test]
+    fn default_policy_follows_compatibility_feature() {
+        let policy = MemoryProtectionPolicy::default();
+        assert!(policy.validate().is_ok());
+        if cfg!(feature = "compatibility_mode_allowed") {
+            assert_eq!(policy.compatibility_mode(), CompatibilityModeTrigger::NonNxCompatApplication);
+        } else {
+            assert_eq!(policy, MemoryProtectionPolicy::strict());
+        }
+    }
+
+    #[test]
+    fn with_nx_toggles_a_single_memory_type() {
+        let policy = MemoryProtectionPolicy::strict().with_nx(efi::BOOT_SERVICES_CODE, false);
+        assert!(!policy.nx_enabled_for(efi::BOOT_SERVICES_CODE));
+        assert!(policy.nx_enabled_for(efi::BOOT_SERVICES_DATA));
+        assert!(policy.with_nx(efi::BOOT_SERVICES_CODE, true).nx_enabled_for(efi::BOOT_SERVICES_CODE));
+
+        // out of range memory types are ignored rather than overflowing the mask.
+        assert_eq!(policy.with_nx(0x8000_0000, true), policy);
+        assert!(!policy.nx_enabled_for(0x8000_0000));
+    }
+
+    #[test]
+    fn validate_rejects_invalid_policies() {
+        let policy = MemoryProtectionPolicy::strict().with_nx(efi::CONVENTIONAL_MEMORY, true);
+        assert_eq!(policy.validate(), Err(PolicyError::InvalidMemoryType(efi::CONVENTIONAL_MEMORY)));
+
+        let policy =
+            MemoryProtectionPolicy::strict().with_compatibility_mode(CompatibilityModeTrigger::NonNxCompatApplication);
+        if cfg!(feature = "compatibility_mode_allowed") {
+            assert!(policy.validate().is_ok());
+        } else {
+            assert_eq!(policy.validate(), Err(PolicyError::CompatibilityModeNotAllowed));
+        }
+    }
+}
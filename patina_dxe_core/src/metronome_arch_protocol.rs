@@ -0,0 +1,127 @@
+//! DXE Core Metronome Architectural Protocol
+//!
+//! Produces the Metronome Architectural Protocol on top of the [Timer] service by busy waiting on the free running
+//! counter.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::boxed::Box;
+use patina::{
+    boot_services::{BootServices, StandardBootServices},
+    component::{IntoComponent, service::Service},
+    error::Result,
+    pi::protocols::metronome::{PROTOCOL_GUID, Protocol},
+    uefi_protocol::ProtocolInterface,
+};
+use patina_internal_cpu::timer::Timer;
+use r_efi::efi;
+
+/// The metronome tick period in 100ns units (1us).
+const TICK_PERIOD: u32 = 10;
+
+#[repr(C)]
+pub struct EfiMetronomeArchProtocolImpl {
+    protocol: Protocol,
+
+    // private data
+    timer: &'static dyn Timer,
+}
+
+unsafe impl ProtocolInterface for EfiMetronomeArchProtocolImpl {
+    const PROTOCOL_GUID: efi::Guid = PROTOCOL_GUID;
+}
+
+// Helper function to convert a raw pointer to a reference.
+fn get_impl_ref<'a>(this: *const Protocol) -> &'a EfiMetronomeArchProtocolImpl {
+    if this.is_null() {
+        panic!("Null pointer passed to get_impl_ref()");
+    }
+
+    unsafe { &*(this as *const EfiMetronomeArchProtocolImpl) }
+}
+
+// EfiMetronomeArchProtocolImpl function pointers implementations.
+
+extern "efiapi" fn wait_for_tick(this: *const Protocol, tick_number: u32) -> efi::Status {
+    let metronome = get_impl_ref(this);
+    let timer = metronome.timer;
+
+    if timer.frequency() == 0 {
+        return efi::Status::DEVICE_ERROR;
+    }
+
+    let ticks = timer.period_to_ticks(tick_number as u64 * metronome.protocol.tick_period as u64);
+    let start = timer.counter();
+    while timer.counter().wrapping_sub(start) < ticks {
+        core::hint::spin_loop();
+    }
+
+    efi::Status::SUCCESS
+}
+
+impl EfiMetronomeArchProtocolImpl {
+    fn new(timer: &'static dyn Timer) -> Self {
+        Self {
+            protocol: Protocol { wait_for_tick, tick_period: TICK_PERIOD },
+
+            // private data
+            timer,
+        }
+    }
+}
+
+/// This component installs the metronome arch protocol
+#[derive(IntoComponent, Default)]
+pub(crate) struct MetronomeArchProtocolInstaller;
+
+impl MetronomeArchProtocolInstaller {
+    fn entry_point(self, timer: Service<dyn Timer>, bs: StandardBootServices) -> Result<()> {
+        let interface = Box::leak(Box::new(EfiMetronomeArchProtocolImpl::new(*timer)));
+
+        bs.install_protocol_interface(None, interface)
+            .inspect_err(|_| log::error!("Failed to install EFI_METRONOME_ARCH_PROTOCOL"))?;
+        log::info!("installed EFI_METRONOME_ARCH_PROTOCOL_GUID");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use patina_internal_cpu::timer::EfiTimerNull;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+    };
+
+    #[test]
+    fn test_wait_for_tick_waits_for_the_fake_clock() {
+        let clock: &'static EfiTimerNull = Box::leak(Box::new(EfiTimerNull::new()));
+        let metronome = EfiMetronomeArchProtocolImpl::new(clock);
+
+        // No time needs to pass to wait for zero ticks.
+        assert_eq!(wait_for_tick(&metronome.protocol, 0), efi::Status::SUCCESS);
+
+        let done: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
+        let waiter = thread::spawn(move || {
+            let metronome = EfiMetronomeArchProtocolImpl::new(clock);
+            let status = wait_for_tick(&metronome.protocol, 5);
+            done.store(true, Ordering::SeqCst);
+            status
+        });
+
+        // Drive the fake clock until the wait completes, which must not happen before 5 ticks (50 x 100ns) elapse.
+        while !done.load(Ordering::SeqCst) {
+            clock.advance(1);
+            thread::yield_now();
+        }
+        assert_eq!(waiter.join().unwrap(), efi::Status::SUCCESS);
+        assert!(clock.counter() >= 5 * TICK_PERIOD as u64);
+    }
+}
@@ -14,12 +14,20 @@ use core::{
 use patina::{
     guids,
     pi::{protocols, status_code},
+    runtime_services::StandardRuntimeServices,
 };
 use patina_internal_cpu::interrupts;
 use r_efi::efi;
 
 use crate::{
-    GCD, allocator::terminate_memory_map, events::EVENT_DB, protocols::PROTOCOL_DB, systemtables::SYSTEM_TABLE,
+    GCD,
+    allocator::{self, terminate_memory_map},
+    ebs_diagnostics::{EBS_DIAGNOSTICS, Phase},
+    events::EVENT_DB,
+    protocol_audit::PROTOCOL_AUDIT,
+    protocols::PROTOCOL_DB,
+    systemtables::SYSTEM_TABLE,
+    table_integrity::{CheckPoint, TABLE_INTEGRITY},
 };
 
 static METRONOME_ARCH_PTR: AtomicPtr<protocols::metronome::Protocol> = AtomicPtr::new(core::ptr::null_mut());
@@ -134,16 +142,45 @@ extern "efiapi" fn watchdog_arch_available(event: efi::Event, _context: *mut c_v
     }
 }
 
+// This callback is invoked at ReadyToBoot when the stable runtime memory map is enabled. The layout is read and
+// recorded here, as reading and writing the variable allocates memory and would invalidate the map key the OS loader
+// passes to ExitBootServices; the final layout is compared against it in ExitBootServices.
+extern "efiapi" fn record_runtime_memory_layout(event: efi::Event, _context: *mut c_void) {
+    if let Err(status_err) = EVENT_DB.close_event(event) {
+        log::warn!("Could not close event for record_runtime_memory_layout due to error {status_err:?}");
+    }
+
+    let runtime_services_ptr =
+        SYSTEM_TABLE.lock().as_ref().map(|st| st.runtime_services() as *const efi::RuntimeServices);
+    match runtime_services_ptr {
+        Some(runtime_services_ptr) => {
+            // SAFETY: the runtime services table is allocated for the life of the system table.
+            let runtime_services = StandardRuntimeServices::new(unsafe { &*runtime_services_ptr });
+            allocator::record_runtime_memory_layout(&runtime_services);
+        }
+        None => log::error!("System table not initialized, unable to record the runtime memory layout."),
+    }
+}
+
 pub extern "efiapi" fn exit_boot_services(_handle: efi::Handle, map_key: usize) -> efi::Status {
     static EXIT_BOOT_SERVICES_CALLED: AtomicBool = AtomicBool::new(false);
 
     log::info!("EBS initiated.");
     // Pre-exit boot services and before exit boot services are only signaled once
     if !EXIT_BOOT_SERVICES_CALLED.load(Ordering::SeqCst) {
+        EBS_DIAGNOSTICS.set_phase(Phase::PreExitBootServices);
         EVENT_DB.signal_group(PRE_EBS_GUID);
 
         // Signal the event group before exit boot services
+        EBS_DIAGNOSTICS.set_phase(Phase::BeforeExitBootServices);
         EVENT_DB.signal_group(efi::EVENT_GROUP_BEFORE_EXIT_BOOT_SERVICES);
+        EBS_DIAGNOSTICS.set_phase(Phase::Boot);
+
+        PROTOCOL_AUDIT.report_open_protocols(&PROTOCOL_DB);
+        TABLE_INTEGRITY.check(CheckPoint::ExitBootServices);
+        if allocator::stable_runtime_memory_map() {
+            allocator::check_runtime_memory_layout();
+        }
 
         EXIT_BOOT_SERVICES_CALLED.store(true, Ordering::SeqCst);
     }
@@ -168,6 +205,7 @@ pub extern "efiapi" fn exit_boot_services(_handle: efi::Handle, map_key: usize)
         Err(err) => {
             log::error!("Failed to terminate memory map: {err:?}");
             GCD.unlock_memory_space();
+            EBS_DIAGNOSTICS.report(map_key, Err(err));
             EVENT_DB.signal_group(guids::EBS_FAILED);
             return err.into();
         }
@@ -175,6 +213,7 @@ pub extern "efiapi" fn exit_boot_services(_handle: efi::Handle, map_key: usize)
 
     // Signal Exit Boot Services
     EVENT_DB.signal_group(efi::EVENT_GROUP_EXIT_BOOT_SERVICES);
+    EBS_DIAGNOSTICS.report(map_key, Ok(()));
 
     // Initialize StatusCode and send EFI_SW_BS_PC_EXIT_BOOT_SERVICES
     match PROTOCOL_DB.locate_protocol(protocols::status_code::PROTOCOL_GUID) {
@@ -240,6 +279,19 @@ pub fn init_misc_boot_services_support(bs: &mut efi::BootServices) {
     PROTOCOL_DB
         .register_protocol_notify(protocols::watchdog::PROTOCOL_GUID, event)
         .expect("Failed to register protocol notify on metronome available.");
+
+    //set up call back to record the runtime memory layout before the OS loader takes the memory map.
+    if allocator::stable_runtime_memory_map() {
+        EVENT_DB
+            .create_event(
+                efi::EVT_NOTIFY_SIGNAL,
+                efi::TPL_CALLBACK,
+                Some(record_runtime_memory_layout),
+                None,
+                Some(efi::EVENT_GROUP_READY_TO_BOOT),
+            )
+            .expect("Failed to create runtime memory layout record callback.");
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,429 @@
+//! DXE Core Protocol Interface Auditing
+//!
+//! Opt-in detection of protocol interface misuse by drivers. When enabled (see [ProtocolAuditMode]), the core:
+//!
+//! - records the image that installed each protocol interface,
+//! - remembers recently uninstalled interfaces, and warns when one of them is later passed back to the protocol
+//!   services (a stale interface pointer),
+//! - in [ProtocolAuditMode::Poison] mode, marks page-aligned uninstalled interfaces non-present so that any later
+//!   access faults at the point of use, otherwise logs the uninstall,
+//! - warns with the outstanding open-protocol records when an uninstall fails because the interface is still open,
+//! - reports protocols that are still open at ExitBootServices, flagging records whose agent no longer exists.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::collections::{BTreeMap, VecDeque};
+use core::{
+    ffi::c_void,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use mu_rust_helpers::guid::guid_fmt;
+use patina::{base::UEFI_PAGE_SIZE, error::EfiError};
+use r_efi::efi;
+
+use crate::{
+    GCD, image,
+    protocol_db::{OpenProtocolInformation, SpinLockedProtocolDb},
+    tpl_lock,
+};
+
+/// The number of uninstalled interfaces remembered for stale pointer detection.
+const MAX_RETIRED_INTERFACES: usize = 32;
+
+/// Selects how the core audits protocol interface usage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolAuditMode {
+    /// Protocol interface usage is not audited.
+    Disabled,
+    /// Misuse is logged.
+    Log,
+    /// Misuse is logged, and page-aligned interfaces are marked non-present (`EFI_MEMORY_RP`) when they are
+    /// uninstalled, so that use of a stale interface pointer faults immediately. The page is restored when the
+    /// interface is installed again or its memory is freed.
+    ///
+    /// This assumes a page-aligned interface is the only data on its page. A driver that places other data on the
+    /// same page will fault when it accesses that data, so this mode is intended for debugging only.
+    Poison,
+}
+
+impl ProtocolAuditMode {
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Log,
+            2 => Self::Poison,
+            _ => Self::Disabled,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RetiredInterface {
+    handle: efi::Handle,
+    protocol: efi::Guid,
+    interface: *mut c_void,
+    installer: Option<efi::Handle>,
+    uninstaller: Option<efi::Handle>,
+}
+
+struct AuditState {
+    retired: VecDeque<RetiredInterface>,
+    // page address -> attributes prior to poisoning.
+    poisoned: BTreeMap<u64, u64>,
+}
+
+// AuditState only stores interface pointers for comparison and is accessed through a mutex guard, so it is safe to
+// mark it send.
+unsafe impl Send for AuditState {}
+
+/// Tracks protocol interface usage for a protocol database.
+pub struct ProtocolAudit {
+    mode: AtomicU8,
+    state: tpl_lock::TplMutex<AuditState>,
+}
+
+/// The global protocol audit instance, auditing [PROTOCOL_DB](crate::protocols::PROTOCOL_DB).
+pub static PROTOCOL_AUDIT: ProtocolAudit = ProtocolAudit::new();
+
+/// Invokes `f` with a printable name for an image handle.
+fn with_image_label<R>(image_handle: Option<efi::Handle>, f: impl FnOnce(&str) -> R) -> R {
+    match image_handle {
+        Some(image_handle) => image::with_image_name(image_handle, |name| f(name.unwrap_or("unknown image"))),
+        None => f("DXE core"),
+    }
+}
+
+impl ProtocolAudit {
+    /// Creates a new, disabled, protocol audit.
+    pub const fn new() -> Self {
+        Self {
+            mode: AtomicU8::new(ProtocolAuditMode::Disabled as u8),
+            state: tpl_lock::TplMutex::new(
+                efi::TPL_NOTIFY,
+                AuditState { retired: VecDeque::new(), poisoned: BTreeMap::new() },
+                "ProtocolAuditLock",
+            ),
+        }
+    }
+
+    /// Sets the audit mode.
+    pub fn set_mode(&self, mode: ProtocolAuditMode) {
+        self.mode.store(mode as u8, Ordering::SeqCst);
+    }
+
+    /// Returns the audit mode.
+    pub fn mode(&self) -> ProtocolAuditMode {
+        ProtocolAuditMode::from_u8(self.mode.load(Ordering::SeqCst))
+    }
+
+    /// Returns true if auditing is enabled.
+    pub fn enabled(&self) -> bool {
+        self.mode() != ProtocolAuditMode::Disabled
+    }
+
+    /// Records that `installer` installed `interface` for `protocol` on `handle`.
+    pub fn record_install(
+        &self,
+        db: &SpinLockedProtocolDb,
+        handle: efi::Handle,
+        protocol: efi::Guid,
+        interface: *mut c_void,
+        installer: Option<efi::Handle>,
+    ) {
+        if !self.enabled() {
+            return;
+        }
+        let _ = db.set_protocol_installer(handle, protocol, installer);
+
+        // The interface is live again, so it is no longer stale.
+        self.state.lock().retired.retain(|retired| retired.interface != interface);
+        self.unpoison_range(interface as u64, 1);
+    }
+
+    /// Records that `uninstaller` uninstalled `interface` for `protocol` from `handle`, which was installed by
+    /// `installer`. Must be called after the interface has been removed from the database.
+    pub fn record_uninstall(
+        &self,
+        handle: efi::Handle,
+        protocol: efi::Guid,
+        interface: *mut c_void,
+        installer: Option<efi::Handle>,
+        uninstaller: Option<efi::Handle>,
+    ) {
+        if !self.enabled() || interface.is_null() {
+            return;
+        }
+
+        {
+            let mut state = self.state.lock();
+            if state.retired.len() == MAX_RETIRED_INTERFACES {
+                state.retired.pop_front();
+            }
+            state.retired.push_back(RetiredInterface { handle, protocol, interface, installer, uninstaller });
+        }
+
+        let page_aligned = (interface as usize).is_multiple_of(UEFI_PAGE_SIZE);
+        if self.mode() == ProtocolAuditMode::Poison && page_aligned && self.poison_page(interface as u64).is_ok() {
+            log::info!(
+                "Protocol audit: poisoned uninstalled {:?} interface {interface:#x?} on handle {handle:#x?}.",
+                guid_fmt!(protocol)
+            );
+            return;
+        }
+        with_image_label(installer, |name| {
+            log::info!(
+                "Protocol audit: {:?} interface {interface:#x?} installed by {name} was uninstalled from handle {handle:#x?}.",
+                guid_fmt!(protocol)
+            )
+        });
+    }
+
+    /// Warns if `interface` is an interface for `protocol` that has been uninstalled. Returns true if it is stale.
+    pub fn check_stale_interface(&self, protocol: efi::Guid, interface: *mut c_void) -> bool {
+        if !self.enabled() || interface.is_null() {
+            return false;
+        }
+        let retired = self
+            .state
+            .lock()
+            .retired
+            .iter()
+            .rev()
+            .find(|retired| retired.protocol == protocol && retired.interface == interface)
+            .copied();
+        let Some(retired) = retired else {
+            return false;
+        };
+        // Image names are resolved one at a time, as each lookup holds the image lock.
+        with_image_label(retired.installer, |installer| {
+            log::warn!(
+                "Protocol audit: stale {:?} interface {interface:#x?} used. It was installed on handle {:#x?} by {installer}",
+                guid_fmt!(protocol),
+                retired.handle
+            )
+        });
+        with_image_label(retired.uninstaller, |uninstaller| log::warn!("  and uninstalled by {uninstaller}."));
+        true
+    }
+
+    /// Warns that uninstalling `protocol` from `handle` was denied because it is still open.
+    pub fn report_uninstall_denied(&self, db: &SpinLockedProtocolDb, handle: efi::Handle, protocol: efi::Guid) {
+        if !self.enabled() {
+            return;
+        }
+        let installer = db.get_protocol_installer(handle, protocol).unwrap_or(None);
+        with_image_label(installer, |name| {
+            log::warn!(
+                "Protocol audit: uninstall of {:?} (installed by {name}) from handle {handle:#x?} denied; the interface is still open:",
+                guid_fmt!(protocol)
+            )
+        });
+        for usage in db.get_open_protocol_information_by_protocol(handle, protocol).unwrap_or_default() {
+            log_open_protocol(log::Level::Warn, &usage);
+        }
+    }
+
+    /// Reports every protocol that is still open. Intended to be called at ExitBootServices. Does not allocate.
+    pub fn report_open_protocols(&self, db: &SpinLockedProtocolDb) {
+        if !self.enabled() {
+            return;
+        }
+        let mut open = 0;
+        let mut orphaned = 0;
+        db.for_each_open_protocol(|handle, protocol, usage, agent_present| {
+            open += 1;
+            if agent_present {
+                log::trace!("Protocol audit: {:?} on handle {handle:#x?} is open at ExitBootServices.", guid_fmt!(*protocol));
+            } else {
+                orphaned += 1;
+                log::warn!(
+                    "Protocol audit: {:?} on handle {handle:#x?} is still open by agent {:#x?}, which no longer exists.",
+                    guid_fmt!(*protocol),
+                    usage.agent_handle
+                );
+            }
+        });
+        log::info!("Protocol audit: {open} protocol open record(s) at ExitBootServices, {orphaned} orphaned.");
+    }
+
+    /// Restores any poisoned pages in `[base, base + len)`. Must be called before memory in that range is freed.
+    pub fn unpoison_range(&self, base: u64, len: u64) {
+        if self.mode() != ProtocolAuditMode::Poison {
+            return;
+        }
+        let page_base = base & !(UEFI_PAGE_SIZE as u64 - 1);
+        let end = base.saturating_add(len);
+        loop {
+            // The GCD must not be called with the audit lock held, as it may call back into the protocol services.
+            let entry = {
+                let mut state = self.state.lock();
+                let page = state.poisoned.range(page_base..end).next().map(|(&page, &attributes)| (page, attributes));
+                if let Some((page, _)) = page {
+                    state.poisoned.remove(&page);
+                }
+                page
+            };
+            let Some((page, attributes)) = entry else {
+                break;
+            };
+            if let Err(err) = GCD.set_memory_space_attributes(page as usize, UEFI_PAGE_SIZE, attributes) {
+                log::error!("Protocol audit: failed to restore poisoned page {page:#x}: {err:?}");
+            }
+        }
+    }
+
+    fn poison_page(&self, page: u64) -> Result<(), EfiError> {
+        let descriptor = GCD.get_memory_descriptor_for_address(page)?;
+        GCD.set_memory_space_attributes(page as usize, UEFI_PAGE_SIZE, descriptor.attributes | efi::MEMORY_RP)?;
+        self.state.lock().poisoned.insert(page, descriptor.attributes);
+        Ok(())
+    }
+}
+
+fn log_open_protocol(level: log::Level, usage: &OpenProtocolInformation) {
+    with_image_label(usage.agent_handle, |agent| {
+        log::log!(
+            level,
+            "  agent: {agent} ({:#x?}) controller: {:#x?} attributes: {:#x} open count: {}",
+            usage.agent_handle,
+            usage.controller_handle,
+            usage.attributes,
+            usage.open_count
+        )
+    });
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use std::string::String;
+
+    use super::*;
+    use crate::{protocol_db::GraphFormat, test_support};
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            f();
+        })
+        .unwrap();
+    }
+
+    const TEST_GUID: efi::Guid =
+        efi::Guid::from_fields(0x0e896c7a, 0x57dc, 0x4987, 0xbc, 0x22, &[0xab, 0xc3, 0xa8, 0x26, 0x32, 0x10]);
+
+    #[test]
+    fn disabled_audit_should_not_record() {
+        with_locked_state(|| {
+            static SPIN_LOCKED_PROTOCOL_DB: SpinLockedProtocolDb = SpinLockedProtocolDb::new();
+            let audit = ProtocolAudit::new();
+            let interface = 0x1234 as *mut c_void;
+            let (handle, _) = SPIN_LOCKED_PROTOCOL_DB.install_protocol_interface(None, TEST_GUID, interface).unwrap();
+
+            audit.record_install(&SPIN_LOCKED_PROTOCOL_DB, handle, TEST_GUID, interface, Some(0x42 as efi::Handle));
+            assert_eq!(SPIN_LOCKED_PROTOCOL_DB.get_protocol_installer(handle, TEST_GUID), Ok(None));
+
+            audit.record_uninstall(handle, TEST_GUID, interface, None, None);
+            assert!(!audit.check_stale_interface(TEST_GUID, interface));
+        });
+    }
+
+    #[test]
+    fn install_should_record_the_installer() {
+        with_locked_state(|| {
+            static SPIN_LOCKED_PROTOCOL_DB: SpinLockedProtocolDb = SpinLockedProtocolDb::new();
+            let audit = ProtocolAudit::new();
+            audit.set_mode(ProtocolAuditMode::Log);
+            let interface = 0x1234 as *mut c_void;
+            let (handle, _) = SPIN_LOCKED_PROTOCOL_DB.install_protocol_interface(None, TEST_GUID, interface).unwrap();
+
+            audit.record_install(&SPIN_LOCKED_PROTOCOL_DB, handle, TEST_GUID, interface, Some(0x42 as efi::Handle));
+            assert_eq!(
+                SPIN_LOCKED_PROTOCOL_DB.get_protocol_installer(handle, TEST_GUID),
+                Ok(Some(0x42 as efi::Handle))
+            );
+            let mut text = String::new();
+            SPIN_LOCKED_PROTOCOL_DB
+                .try_write_handle_graph(&mut text, GraphFormat::Text, None)
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            assert!(text.contains("interface: 0x1234 installed by: 0x42\n"));
+        });
+    }
+
+    #[test]
+    fn uninstalled_interfaces_should_be_detected_as_stale_until_reinstalled() {
+        with_locked_state(|| {
+            static SPIN_LOCKED_PROTOCOL_DB: SpinLockedProtocolDb = SpinLockedProtocolDb::new();
+            let audit = ProtocolAudit::new();
+            audit.set_mode(ProtocolAuditMode::Log);
+            let interface = 0x1234 as *mut c_void;
+            let other_guid = efi::Guid::from_fields(0, 0, 0, 0, 0, &[0, 0, 0, 0, 0, 1]);
+
+            audit.record_uninstall(0x1 as efi::Handle, TEST_GUID, interface, None, None);
+            assert!(audit.check_stale_interface(TEST_GUID, interface));
+            assert!(!audit.check_stale_interface(other_guid, interface));
+            assert!(!audit.check_stale_interface(TEST_GUID, 0x5678 as *mut c_void));
+
+            let (handle, _) = SPIN_LOCKED_PROTOCOL_DB.install_protocol_interface(None, TEST_GUID, interface).unwrap();
+            audit.record_install(&SPIN_LOCKED_PROTOCOL_DB, handle, TEST_GUID, interface, None);
+            assert!(!audit.check_stale_interface(TEST_GUID, interface));
+        });
+    }
+
+    #[test]
+    fn retired_interfaces_should_be_bounded() {
+        with_locked_state(|| {
+            let audit = ProtocolAudit::new();
+            audit.set_mode(ProtocolAuditMode::Log);
+            for interface in 1..=MAX_RETIRED_INTERFACES + 1 {
+                audit.record_uninstall(0x1 as efi::Handle, TEST_GUID, (interface * 0x10) as *mut c_void, None, None);
+            }
+            assert_eq!(audit.state.lock().retired.len(), MAX_RETIRED_INTERFACES);
+            assert!(!audit.check_stale_interface(TEST_GUID, 0x10 as *mut c_void));
+            assert!(audit.check_stale_interface(TEST_GUID, ((MAX_RETIRED_INTERFACES + 1) * 0x10) as *mut c_void));
+        });
+    }
+
+    #[test]
+    fn poisoning_should_fall_back_to_logging_without_a_gcd() {
+        with_locked_state(|| {
+            let audit = ProtocolAudit::new();
+            audit.set_mode(ProtocolAuditMode::Poison);
+            let interface = 0x1000 as *mut c_void;
+            audit.record_uninstall(0x1 as efi::Handle, TEST_GUID, interface, None, None);
+            assert!(audit.state.lock().poisoned.is_empty());
+            assert!(audit.check_stale_interface(TEST_GUID, interface));
+        });
+    }
+
+    #[test]
+    fn report_open_protocols_should_count_orphaned_opens() {
+        with_locked_state(|| {
+            static SPIN_LOCKED_PROTOCOL_DB: SpinLockedProtocolDb = SpinLockedProtocolDb::new();
+            let audit = ProtocolAudit::new();
+            audit.set_mode(ProtocolAuditMode::Log);
+            let interface = 0x1234 as *mut c_void;
+            let (controller, _) =
+                SPIN_LOCKED_PROTOCOL_DB.install_protocol_interface(None, TEST_GUID, interface).unwrap();
+            let (agent, _) = SPIN_LOCKED_PROTOCOL_DB.install_protocol_interface(None, TEST_GUID, interface).unwrap();
+            SPIN_LOCKED_PROTOCOL_DB
+                .add_protocol_usage(controller, TEST_GUID, Some(agent), Some(controller), efi::OPEN_PROTOCOL_BY_DRIVER)
+                .unwrap();
+            SPIN_LOCKED_PROTOCOL_DB.uninstall_protocol_interface(agent, TEST_GUID, interface).unwrap();
+
+            let mut records = std::vec::Vec::new();
+            SPIN_LOCKED_PROTOCOL_DB.for_each_open_protocol(|handle, guid, usage, agent_present| {
+                records.push((handle, *guid, usage.agent_handle, agent_present))
+            });
+            assert_eq!(records, std::vec![(controller, TEST_GUID, Some(agent), false)]);
+            audit.report_open_protocols(&SPIN_LOCKED_PROTOCOL_DB);
+            audit.report_uninstall_denied(&SPIN_LOCKED_PROTOCOL_DB, controller, TEST_GUID);
+        });
+    }
+}
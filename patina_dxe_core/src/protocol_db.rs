@@ -10,17 +10,22 @@
 //!
 extern crate alloc;
 
+mod graph;
+
 use alloc::{
     collections::{BTreeMap, BTreeSet},
     vec,
     vec::Vec,
 };
-use core::{cmp::Ordering, ffi::c_void, hash::Hasher};
+use core::{cmp::Ordering, ffi::c_void, fmt, hash::Hasher};
 use patina::error::EfiError;
 use r_efi::efi;
 
 use crate::tpl_lock;
 
+pub use graph::{GraphFormat, register_guid_name};
+use graph::{HandleSource, ProtocolView, SingleHandle};
+
 //private UUID used to create the "well-known handles"
 const WELL_KNOWN_HANDLE_PROTOCOL_GUID: uuid::Uuid = uuid::Uuid::from_u128(0xfced7c96356e48cba9a9e089b2ddf49b);
 #[allow(dead_code)]
@@ -111,6 +116,7 @@ impl From<OpenProtocolInformation> for efi::OpenProtocolInformationEntry {
 
 struct ProtocolInstance {
     interface: *mut c_void,
+    installer: Option<efi::Handle>,
     opened_by_driver: bool,
     opened_by_exclusive: bool,
     usage: Vec<OpenProtocolInformation>,
@@ -265,8 +271,13 @@ impl ProtocolDb {
         }
 
         //create a new protocol instance to match the input.
-        let protocol_instance =
-            ProtocolInstance { interface, opened_by_driver: false, opened_by_exclusive: false, usage: Vec::new() };
+        let protocol_instance = ProtocolInstance {
+            interface,
+            installer: None,
+            opened_by_driver: false,
+            opened_by_exclusive: false,
+            usage: Vec::new(),
+        };
 
         //attempt to add the protocol to the set of protocols on this handle.
         let exists = handle_instance.insert(OrdGuid(protocol), protocol_instance);
@@ -363,6 +374,45 @@ impl ProtocolDb {
         Ok(instance.interface)
     }
 
+    fn set_protocol_installer(
+        &mut self,
+        handle: efi::Handle,
+        protocol: efi::Guid,
+        installer: Option<efi::Handle>,
+    ) -> Result<(), EfiError> {
+        let instance = self
+            .handles
+            .get_mut(&(handle as usize))
+            .and_then(|handle_instance| handle_instance.get_mut(&OrdGuid(protocol)))
+            .ok_or(EfiError::NotFound)?;
+        instance.installer = installer;
+        Ok(())
+    }
+
+    fn get_protocol_installer(
+        &self,
+        handle: efi::Handle,
+        protocol: efi::Guid,
+    ) -> Result<Option<efi::Handle>, EfiError> {
+        self.handles
+            .get(&(handle as usize))
+            .and_then(|handle_instance| handle_instance.get(&OrdGuid(protocol)))
+            .map(|instance| instance.installer)
+            .ok_or(EfiError::NotFound)
+    }
+
+    fn for_each_open_protocol(&self, mut f: impl FnMut(efi::Handle, &efi::Guid, &OpenProtocolInformation, bool)) {
+        for (&key, handle_instance) in &self.handles {
+            for (OrdGuid(guid), instance) in handle_instance.iter() {
+                for info in &instance.usage {
+                    let agent_present =
+                        info.agent_handle.is_none_or(|agent| self.handles.contains_key(&(agent as usize)));
+                    f(key as efi::Handle, guid, info, agent_present);
+                }
+            }
+        }
+    }
+
     fn validate_handle(&self, handle: efi::Handle) -> Result<(), EfiError> {
         let handle = handle as usize;
         //to be valid the handle must exist in the handle database (i.e. not have been deleted).
@@ -588,6 +638,33 @@ impl ProtocolDb {
     }
 }
 
+impl HandleSource for ProtocolDb {
+    fn handles(&self) -> impl Iterator<Item = usize> + '_ {
+        // Selects the next handle by creation order on each step, as sorting the handles would allocate.
+        let mut last_order = None;
+        core::iter::from_fn(move || {
+            let (&key, handle) = self
+                .handles
+                .iter()
+                .filter(|(_, handle)| last_order.is_none_or(|last| handle.order > last))
+                .min_by_key(|(_, handle)| handle.order)?;
+            last_order = Some(handle.order);
+            Some(key)
+        })
+    }
+
+    fn protocols(&self, handle: usize) -> impl Iterator<Item = ProtocolView<'_>> + '_ {
+        self.handles.get(&handle).into_iter().flat_map(|handle| {
+            handle.iter().map(|(&OrdGuid(guid), instance)| ProtocolView {
+                guid,
+                interface: instance.interface as usize,
+                installer: instance.installer.map(|installer| installer as usize),
+                open_info: &instance.usage,
+            })
+        })
+    }
+}
+
 /// Spin-Locked protocol database instance.
 ///
 /// This is the main access point for interaction with the protocol database.
@@ -740,6 +817,32 @@ impl SpinLockedProtocolDb {
         self.lock().get_interface_for_handle(handle, protocol)
     }
 
+    /// Records the image that installed the protocol on the given handle.
+    pub fn set_protocol_installer(
+        &self,
+        handle: efi::Handle,
+        protocol: efi::Guid,
+        installer: Option<efi::Handle>,
+    ) -> Result<(), EfiError> {
+        self.lock().set_protocol_installer(handle, protocol, installer)
+    }
+
+    /// Returns the image that installed the protocol on the given handle, if it was recorded.
+    pub fn get_protocol_installer(
+        &self,
+        handle: efi::Handle,
+        protocol: efi::Guid,
+    ) -> Result<Option<efi::Handle>, EfiError> {
+        self.lock().get_protocol_installer(handle, protocol)
+    }
+
+    /// Invokes `f` for every open-protocol record in the database with the handle and protocol the record is on, and
+    /// whether the agent that opened it still exists. The database is locked for the duration, so `f` must not call
+    /// back into the database. Does not allocate.
+    pub fn for_each_open_protocol(&self, f: impl FnMut(efi::Handle, &efi::Guid, &OpenProtocolInformation, bool)) {
+        self.lock().for_each_open_protocol(f)
+    }
+
     /// Returns Ok(()) if the handle is a valid handle, Err(Status::INVALID_PARAMETER) otherwise.
     pub fn validate_handle(&self, handle: efi::Handle) -> Result<(), EfiError> {
         self.lock().validate_handle(handle)
@@ -864,6 +967,34 @@ impl SpinLockedProtocolDb {
     pub fn get_child_handles(&self, parent_handle: efi::Handle) -> Vec<efi::Handle> {
         self.lock().get_child_handles(parent_handle)
     }
+
+    /// Writes every handle in the database with its protocols, open-protocol records and parent/child relationships to
+    /// `out` in `format`, restricted to `handle` if given.
+    ///
+    /// The graph is written directly from the database without allocating, so this can be used from contexts that may
+    /// have interrupted a database operation, such as the debugger. Returns `None` if the database is currently locked,
+    /// or `Some(Err(_))` if `handle` is not in the database.
+    pub fn try_write_handle_graph(
+        &self,
+        out: &mut dyn fmt::Write,
+        format: GraphFormat,
+        handle: Option<usize>,
+    ) -> Option<Result<fmt::Result, EfiError>> {
+        let db = self.inner.try_lock()?;
+        Some(match handle {
+            Some(handle) if !db.handles.contains_key(&handle) => Err(EfiError::NotFound),
+            Some(handle) => Ok(write!(out, "{}", format.render(&SingleHandle(&*db, handle)))),
+            None => Ok(write!(out, "{}", format.render(&*db))),
+        })
+    }
+
+    /// Writes each installed protocol and the handles it is installed on to `out`, directly from the database.
+    ///
+    /// Does not allocate, so it can be used from the debugger. Returns `None` if the database is currently locked.
+    pub fn try_write_protocols(&self, out: &mut dyn fmt::Write) -> Option<fmt::Result> {
+        let db = self.inner.try_lock()?;
+        Some(graph::write_protocols(out, &*db))
+    }
 }
 
 unsafe impl Send for SpinLockedProtocolDb {}
@@ -916,7 +1047,7 @@ impl Hasher for Xorshift64starHasher {
 mod tests {
     extern crate std;
     use core::str::FromStr;
-    use std::println;
+    use std::{format, println, string::String};
 
     use r_efi::efi;
     use uuid::Uuid;
@@ -954,6 +1085,7 @@ mod tests {
             assert_ne!(handle, core::ptr::null_mut::<c_void>());
             let test_instance = ProtocolInstance {
                 interface: interface1,
+                installer: None,
                 opened_by_driver: false,
                 opened_by_exclusive: false,
                 usage: Vec::new(),
@@ -2060,6 +2192,84 @@ mod tests {
         });
     }
 
+    #[test]
+    fn try_write_handle_graph_should_write_the_database() {
+        with_locked_state(|| {
+            static SPIN_LOCKED_PROTOCOL_DB: SpinLockedProtocolDb = SpinLockedProtocolDb::new();
+
+            let uuid1 = Uuid::from_str("0e896c7a-57dc-4987-bc22-abc3a8263210").unwrap();
+            let guid1 = efi::Guid::from_bytes(uuid1.as_bytes());
+            let uuid2 = Uuid::from_str("6296e36b-1b29-4f3a-8c37-5f20c5e6a5a8").unwrap();
+            let guid2 = efi::Guid::from_bytes(uuid2.as_bytes());
+            let interface1: *mut c_void = 0x1234 as *mut c_void;
+            let interface2: *mut c_void = 0x5678 as *mut c_void;
+
+            let (controller, _) = SPIN_LOCKED_PROTOCOL_DB.install_protocol_interface(None, guid1, interface1).unwrap();
+            let (driver, _) = SPIN_LOCKED_PROTOCOL_DB.install_protocol_interface(None, guid2, interface2).unwrap();
+            let (child, _) = SPIN_LOCKED_PROTOCOL_DB.install_protocol_interface(None, guid1, interface2).unwrap();
+            SPIN_LOCKED_PROTOCOL_DB
+                .add_protocol_usage(controller, guid1, Some(driver), Some(controller), efi::OPEN_PROTOCOL_BY_DRIVER)
+                .unwrap();
+            SPIN_LOCKED_PROTOCOL_DB
+                .add_protocol_usage(
+                    controller,
+                    guid1,
+                    Some(driver),
+                    Some(child),
+                    efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER,
+                )
+                .unwrap();
+
+            let mut text = String::new();
+            SPIN_LOCKED_PROTOCOL_DB
+                .try_write_handle_graph(&mut text, GraphFormat::Text, None)
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let handles: Vec<&str> = text.lines().filter(|line| line.starts_with("Handle")).collect();
+            assert_eq!(
+                handles,
+                vec![
+                    format!("Handle {:#x} children: [{:#x}]", controller as usize, child as usize),
+                    format!("Handle {:#x}", driver as usize),
+                    format!("Handle {:#x} parents: [{:#x}]", child as usize, controller as usize),
+                ]
+            );
+            assert!(text.contains(&format!(
+                "    agent: {:#x} controller: {:#x} attributes: BY_CHILD_CONTROLLER open count: 1\n",
+                driver as usize, child as usize
+            )));
+
+            let mut text = String::new();
+            SPIN_LOCKED_PROTOCOL_DB
+                .try_write_handle_graph(&mut text, GraphFormat::Text, Some(child as usize))
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            assert!(text.starts_with(&format!("Handle {:#x} parents: [{:#x}]\n", child as usize, controller as usize)));
+            assert!(!text.contains(&format!("Handle {:#x}", driver as usize)));
+            assert_eq!(
+                SPIN_LOCKED_PROTOCOL_DB.try_write_handle_graph(&mut text, GraphFormat::Text, Some(0xdead)).unwrap(),
+                Err(EfiError::NotFound)
+            );
+
+            let mut protocols = String::new();
+            SPIN_LOCKED_PROTOCOL_DB.try_write_protocols(&mut protocols).unwrap().unwrap();
+            assert!(protocols.contains(&format!(
+                "{} [{:x}, {:x}]\n",
+                patina::Guid::from_ref(&guid1),
+                controller as usize,
+                child as usize
+            )));
+            assert!(protocols.contains(&format!("{} [{:x}]\n", patina::Guid::from_ref(&guid2), driver as usize)));
+
+            // the graph cannot be written while the database is locked.
+            let _guard = SPIN_LOCKED_PROTOCOL_DB.lock();
+            assert!(SPIN_LOCKED_PROTOCOL_DB.try_write_handle_graph(&mut text, GraphFormat::Text, None).is_none());
+            assert!(SPIN_LOCKED_PROTOCOL_DB.try_write_protocols(&mut protocols).is_none());
+        });
+    }
+
     #[test]
     fn xorshift64starhasher_test_different_seeds() {
         let seed1 = 12345;
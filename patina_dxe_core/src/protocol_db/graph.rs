@@ -0,0 +1,656 @@
+//! Protocol Database Handle Graph Export
+//!
+//! This module exports the protocol database as a graph of every handle, the protocols installed on it, the
+//! open-protocol records for each protocol, and the parent/child relationships between handles (derived from
+//! `BY_CHILD_CONTROLLER` opens). The graph can be rendered as human readable text, JSON or a Graphviz DOT digraph
+//! ([GraphFormat]).
+//!
+//! The graph is written straight from the locked database
+//! ([SpinLockedProtocolDb::try_write_handle_graph](super::SpinLockedProtocolDb::try_write_handle_graph)) rather than
+//! from a snapshot, so that rendering it does not allocate and can be done from the debugger.
+//!
+//! Protocol GUIDs are named using a registry of well-known GUIDs that can be extended with [register_guid_name].
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::collections::BTreeMap;
+use core::fmt::{self, Display, Write};
+
+use patina::{Guid, pi::protocols as pi_protocols};
+use r_efi::efi;
+
+use super::{OpenProtocolInformation, OrdGuid};
+use crate::tpl_lock;
+
+/// Names for GUIDs that are well-known to the core.
+const WELL_KNOWN_GUID_NAMES: &[(efi::Guid, &str)] = &[
+    (efi::protocols::absolute_pointer::PROTOCOL_GUID, "AbsolutePointer"),
+    (efi::protocols::block_io::PROTOCOL_GUID, "BlockIo"),
+    (efi::protocols::bus_specific_driver_override::PROTOCOL_GUID, "BusSpecificDriverOverride"),
+    (efi::protocols::debug_support::PROTOCOL_GUID, "DebugSupport"),
+    (efi::protocols::debugport::PROTOCOL_GUID, "DebugPort"),
+    (efi::protocols::decompress::PROTOCOL_GUID, "Decompress"),
+    (efi::protocols::device_path::PROTOCOL_GUID, "DevicePath"),
+    (efi::protocols::device_path_from_text::PROTOCOL_GUID, "DevicePathFromText"),
+    (efi::protocols::device_path_to_text::PROTOCOL_GUID, "DevicePathToText"),
+    (efi::protocols::device_path_utilities::PROTOCOL_GUID, "DevicePathUtilities"),
+    (efi::protocols::disk_io::PROTOCOL_GUID, "DiskIo"),
+    (efi::protocols::disk_io2::PROTOCOL_GUID, "DiskIo2"),
+    (efi::protocols::driver_binding::PROTOCOL_GUID, "DriverBinding"),
+    (efi::protocols::driver_diagnostics2::PROTOCOL_GUID, "DriverDiagnostics2"),
+    (efi::protocols::driver_family_override::PROTOCOL_GUID, "DriverFamilyOverride"),
+    (efi::protocols::graphics_output::PROTOCOL_GUID, "GraphicsOutput"),
+    (efi::protocols::hii_database::PROTOCOL_GUID, "HiiDatabase"),
+    (efi::protocols::hii_font::PROTOCOL_GUID, "HiiFont"),
+    (efi::protocols::hii_font_ex::PROTOCOL_GUID, "HiiFontEx"),
+    (efi::protocols::hii_package_list::PROTOCOL_GUID, "HiiPackageList"),
+    (efi::protocols::hii_string::PROTOCOL_GUID, "HiiString"),
+    (efi::protocols::ip4::PROTOCOL_GUID, "Ip4"),
+    (efi::protocols::ip6::PROTOCOL_GUID, "Ip6"),
+    (efi::protocols::load_file::PROTOCOL_GUID, "LoadFile"),
+    (efi::protocols::load_file2::PROTOCOL_GUID, "LoadFile2"),
+    (efi::protocols::loaded_image::PROTOCOL_GUID, "LoadedImage"),
+    (efi::protocols::loaded_image_device_path::PROTOCOL_GUID, "LoadedImageDevicePath"),
+    (efi::protocols::managed_network::PROTOCOL_GUID, "ManagedNetwork"),
+    (efi::protocols::memory_attribute::PROTOCOL_GUID, "MemoryAttribute"),
+    (efi::protocols::mp_services::PROTOCOL_GUID, "MpServices"),
+    (efi::protocols::pci_io::PROTOCOL_GUID, "PciIo"),
+    (efi::protocols::platform_driver_override::PROTOCOL_GUID, "PlatformDriverOverride"),
+    (efi::protocols::rng::PROTOCOL_GUID, "Rng"),
+    (efi::protocols::shell::PROTOCOL_GUID, "Shell"),
+    (efi::protocols::shell_dynamic_command::PROTOCOL_GUID, "ShellDynamicCommand"),
+    (efi::protocols::shell_parameters::PROTOCOL_GUID, "ShellParameters"),
+    (efi::protocols::simple_file_system::PROTOCOL_GUID, "SimpleFileSystem"),
+    (efi::protocols::simple_network::PROTOCOL_GUID, "SimpleNetwork"),
+    (efi::protocols::simple_text_input::PROTOCOL_GUID, "SimpleTextInput"),
+    (efi::protocols::simple_text_input_ex::PROTOCOL_GUID, "SimpleTextInputEx"),
+    (efi::protocols::simple_text_output::PROTOCOL_GUID, "SimpleTextOutput"),
+    (efi::protocols::tcp4::PROTOCOL_GUID, "Tcp4"),
+    (efi::protocols::tcp6::PROTOCOL_GUID, "Tcp6"),
+    (efi::protocols::timestamp::PROTOCOL_GUID, "Timestamp"),
+    (efi::protocols::udp4::PROTOCOL_GUID, "Udp4"),
+    (efi::protocols::udp6::PROTOCOL_GUID, "Udp6"),
+    (pi_protocols::bds::PROTOCOL_GUID, "BdsArch"),
+    (pi_protocols::cpu_arch::PROTOCOL_GUID, "CpuArch"),
+    (pi_protocols::firmware_volume::PROTOCOL_GUID, "FirmwareVolume2"),
+    (pi_protocols::firmware_volume_block::PROTOCOL_GUID, "FirmwareVolumeBlock"),
+    (pi_protocols::metronome::PROTOCOL_GUID, "MetronomeArch"),
+    (pi_protocols::runtime::PROTOCOL_GUID, "RuntimeArch"),
+    (pi_protocols::security::PROTOCOL_GUID, "SecurityArch"),
+    (pi_protocols::security2::PROTOCOL_GUID, "Security2Arch"),
+    (pi_protocols::status_code::PROTOCOL_GUID, "StatusCodeRuntime"),
+    (pi_protocols::timer::PROTOCOL_GUID, "TimerArch"),
+    (pi_protocols::watchdog::PROTOCOL_GUID, "WatchdogTimerArch"),
+];
+
+static REGISTERED_GUID_NAMES: tpl_lock::TplMutex<BTreeMap<OrdGuid, &'static str>> =
+    tpl_lock::TplMutex::new(efi::TPL_NOTIFY, BTreeMap::new(), "GuidNameLock");
+
+/// Registers a human readable name for `guid`, used when exporting the handle graph. A registered name takes
+/// precedence over the well-known name for the same GUID.
+pub fn register_guid_name(guid: efi::Guid, name: &'static str) {
+    REGISTERED_GUID_NAMES.lock().insert(OrdGuid(guid), name);
+}
+
+/// Returns the human readable name for `guid`, if one is known.
+pub fn guid_name(guid: &efi::Guid) -> Option<&'static str> {
+    // The debugger may query names while the registry is locked, so fall back to the well-known names in that case.
+    if let Some(name) = REGISTERED_GUID_NAMES.try_lock().and_then(|names| names.get(&OrdGuid(*guid)).copied()) {
+        return Some(name);
+    }
+    WELL_KNOWN_GUID_NAMES.iter().find(|(known, _)| known == guid).map(|(_, name)| *name)
+}
+
+/// The formats the handle graph can be rendered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Indented text, similar to the UEFI shell `dh -v` command.
+    Text,
+    /// A JSON document.
+    Json,
+    /// A Graphviz DOT digraph. Solid edges point from parent to child handles; dashed edges point from an agent to
+    /// the handle it has opened a protocol on `BY_DRIVER`.
+    Dot,
+}
+
+impl GraphFormat {
+    /// Returns the format named `name` (`text`, `json` or `dot`).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Self::Text),
+            "json" => Some(Self::Json),
+            "dot" => Some(Self::Dot),
+            _ => None,
+        }
+    }
+
+    pub(super) fn render<S: HandleSource>(self, source: &S) -> impl Display + '_ {
+        Rendered(self, source)
+    }
+}
+
+struct Rendered<'a, S>(GraphFormat, &'a S);
+
+impl<S: HandleSource> Display for Rendered<'_, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            GraphFormat::Text => write_text(f, self.1),
+            GraphFormat::Json => write_json(f, self.1),
+            GraphFormat::Dot => write_dot(f, self.1),
+        }
+    }
+}
+
+/// A protocol instance as read by the renderers.
+pub(super) struct ProtocolView<'a> {
+    pub(super) guid: efi::Guid,
+    pub(super) interface: usize,
+    pub(super) installer: Option<usize>,
+    pub(super) open_info: &'a [OpenProtocolInformation],
+}
+
+/// The handles and protocols the renderers read.
+///
+/// Nothing here allocates, so that the graph can be rendered from the database in contexts that must not allocate.
+pub(super) trait HandleSource {
+    /// The handles, in creation order.
+    fn handles(&self) -> impl Iterator<Item = usize> + '_;
+
+    /// The protocols installed on `handle`, in GUID order.
+    fn protocols(&self, handle: usize) -> impl Iterator<Item = ProtocolView<'_>> + '_;
+
+    /// The handles `handle` has opened a protocol on `BY_CHILD_CONTROLLER`, in ascending order.
+    fn children(&self, handle: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut last = None;
+        core::iter::from_fn(move || {
+            let next = child_edges(self, handle).filter(|child| last.is_none_or(|last| *child > last)).min();
+            last = next;
+            next
+        })
+    }
+
+    /// The handles that have opened a protocol on `handle` `BY_CHILD_CONTROLLER`, in ascending order.
+    fn parents(&self, handle: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut last = None;
+        core::iter::from_fn(move || {
+            let next = self
+                .handles()
+                .filter(|parent| last.is_none_or(|last| *parent > last))
+                .filter(|&parent| child_edges(self, parent).any(|child| child == handle))
+                .min();
+            last = next;
+            next
+        })
+    }
+}
+
+fn child_edges<S: HandleSource + ?Sized>(source: &S, handle: usize) -> impl Iterator<Item = usize> + '_ {
+    source.protocols(handle).flat_map(|protocol| {
+        protocol.open_info.iter().filter_map(|info| {
+            if info.attributes & efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER != 0 {
+                info.controller_handle.map(|child| child as usize)
+            } else {
+                None
+            }
+        })
+    })
+}
+
+/// Restricts a source to a single handle, keeping its parents and children from the full source.
+pub(super) struct SingleHandle<'a, S>(pub(super) &'a S, pub(super) usize);
+
+impl<S: HandleSource> HandleSource for SingleHandle<'_, S> {
+    fn handles(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.handles().filter(|handle| *handle == self.1)
+    }
+
+    fn protocols(&self, handle: usize) -> impl Iterator<Item = ProtocolView<'_>> + '_ {
+        self.0.protocols(handle)
+    }
+
+    fn children(&self, handle: usize) -> impl Iterator<Item = usize> + '_ {
+        self.0.children(handle)
+    }
+
+    fn parents(&self, handle: usize) -> impl Iterator<Item = usize> + '_ {
+        self.0.parents(handle)
+    }
+}
+
+/// Writes each protocol in `source`, in GUID order, followed by the handles it is installed on.
+pub(super) fn write_protocols<S: HandleSource>(out: &mut dyn Write, source: &S) -> fmt::Result {
+    let installed = || source.handles().flat_map(|handle| source.protocols(handle).map(|protocol| protocol.guid));
+    let mut last: Option<OrdGuid> = None;
+    while let Some(guid) = installed().map(OrdGuid).filter(|guid| last.as_ref().is_none_or(|last| guid > last)).min() {
+        write!(out, "{} ", Guid::from_ref(&guid.0))?;
+        if let Some(name) = guid_name(&guid.0) {
+            write!(out, "{name} ")?;
+        }
+        out.write_char('[')?;
+        let handles =
+            source.handles().filter(|&handle| source.protocols(handle).any(|protocol| protocol.guid == guid.0));
+        for (index, handle) in handles.enumerate() {
+            if index > 0 {
+                out.write_str(", ")?;
+            }
+            write!(out, "{handle:x}")?;
+        }
+        out.write_str("]\n")?;
+        last = Some(guid);
+    }
+    Ok(())
+}
+
+/// Displays a GUID by name, followed by its value.
+struct GuidLabel<'a>(&'a efi::Guid);
+
+impl Display for GuidLabel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match guid_name(self.0) {
+            Some(name) => write!(f, "{name} ({})", Guid::from_ref(self.0)),
+            None => write!(f, "{}", Guid::from_ref(self.0)),
+        }
+    }
+}
+
+/// Displays open-protocol attributes as their `EFI_OPEN_PROTOCOL_*` names.
+struct OpenAttributes(u32);
+
+impl Display for OpenAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const NAMES: &[(u32, &str)] = &[
+            (efi::OPEN_PROTOCOL_BY_HANDLE_PROTOCOL, "BY_HANDLE_PROTOCOL"),
+            (efi::OPEN_PROTOCOL_GET_PROTOCOL, "GET_PROTOCOL"),
+            (efi::OPEN_PROTOCOL_TEST_PROTOCOL, "TEST_PROTOCOL"),
+            (efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER, "BY_CHILD_CONTROLLER"),
+            (efi::OPEN_PROTOCOL_BY_DRIVER, "BY_DRIVER"),
+            (efi::OPEN_PROTOCOL_EXCLUSIVE, "EXCLUSIVE"),
+        ];
+        let mut first = true;
+        for (_, name) in NAMES.iter().filter(|(bit, _)| self.0 & bit != 0) {
+            if !first {
+                f.write_char('|')?;
+            }
+            f.write_str(name)?;
+            first = false;
+        }
+        if first {
+            write!(f, "{:#x}", self.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays an optional handle as hex, or the given placeholder.
+struct OptionalHandle(Option<efi::Handle>, &'static str);
+
+impl Display for OptionalHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(handle) => write!(f, "{:#x}", handle as usize),
+            None => f.write_str(self.1),
+        }
+    }
+}
+
+fn write_handle_list(f: &mut fmt::Formatter<'_>, handles: impl Iterator<Item = usize>, quoted: bool) -> fmt::Result {
+    for (index, handle) in handles.enumerate() {
+        if index > 0 {
+            f.write_str(", ")?;
+        }
+        if quoted {
+            write!(f, "\"{handle:#x}\"")?;
+        } else {
+            write!(f, "{handle:#x}")?;
+        }
+    }
+    Ok(())
+}
+
+fn write_text<S: HandleSource>(f: &mut fmt::Formatter<'_>, source: &S) -> fmt::Result {
+    for handle in source.handles() {
+        write!(f, "Handle {handle:#x}")?;
+        if source.parents(handle).next().is_some() {
+            f.write_str(" parents: [")?;
+            write_handle_list(f, source.parents(handle), false)?;
+            f.write_char(']')?;
+        }
+        if source.children(handle).next().is_some() {
+            f.write_str(" children: [")?;
+            write_handle_list(f, source.children(handle), false)?;
+            f.write_char(']')?;
+        }
+        writeln!(f)?;
+        for protocol in source.protocols(handle) {
+            write!(f, "  {} interface: {:#x}", GuidLabel(&protocol.guid), protocol.interface)?;
+            if let Some(installer) = protocol.installer {
+                write!(f, " installed by: {installer:#x}")?;
+            }
+            writeln!(f)?;
+            for info in protocol.open_info {
+                writeln!(
+                    f,
+                    "    agent: {} controller: {} attributes: {} open count: {}",
+                    OptionalHandle(info.agent_handle, "none"),
+                    OptionalHandle(info.controller_handle, "none"),
+                    OpenAttributes(info.attributes),
+                    info.open_count
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_json<S: HandleSource>(f: &mut fmt::Formatter<'_>, source: &S) -> fmt::Result {
+    f.write_str("{\"handles\":[")?;
+    for (index, handle) in source.handles().enumerate() {
+        if index > 0 {
+            f.write_char(',')?;
+        }
+        write!(f, "{{\"handle\":\"{handle:#x}\",\"parents\":[")?;
+        write_handle_list(f, source.parents(handle), true)?;
+        f.write_str("],\"children\":[")?;
+        write_handle_list(f, source.children(handle), true)?;
+        f.write_str("],\"protocols\":[")?;
+        for (index, protocol) in source.protocols(handle).enumerate() {
+            if index > 0 {
+                f.write_char(',')?;
+            }
+            write!(f, "{{\"guid\":\"{}\",\"name\":", Guid::from_ref(&protocol.guid))?;
+            // Names can be registered by platforms, so they are escaped.
+            match guid_name(&protocol.guid) {
+                Some(name) => write!(f, "{}", JsonString(name))?,
+                None => f.write_str("null")?,
+            }
+            write!(
+                f,
+                ",\"interface\":\"{:#x}\",\"installer\":{},\"open_info\":[",
+                protocol.interface,
+                JsonHandle(protocol.installer.map(|installer| installer as efi::Handle))
+            )?;
+            for (index, info) in protocol.open_info.iter().enumerate() {
+                if index > 0 {
+                    f.write_char(',')?;
+                }
+                write!(
+                    f,
+                    "{{\"agent\":{},\"controller\":{},\"attributes\":{},\"open_count\":{}}}",
+                    JsonHandle(info.agent_handle),
+                    JsonHandle(info.controller_handle),
+                    info.attributes,
+                    info.open_count
+                )?;
+            }
+            f.write_str("]}")?;
+        }
+        f.write_str("]}")?;
+    }
+    f.write_str("]}")
+}
+
+struct JsonHandle(Option<efi::Handle>);
+
+impl Display for JsonHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(handle) => write!(f, "\"{:#x}\"", handle as usize),
+            None => f.write_str("null"),
+        }
+    }
+}
+
+/// Displays a string as a quoted JSON string, escaping quotes, backslashes and control characters.
+struct JsonString<'a>(&'a str);
+
+impl Display for JsonString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+/// Displays a string for use inside a quoted DOT label, escaping quotes and backslashes. Control characters are
+/// replaced with spaces.
+struct DotString<'a>(&'a str);
+
+impl Display for DotString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                c if c.is_control() => f.write_char(' ')?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_dot<S: HandleSource>(f: &mut fmt::Formatter<'_>, source: &S) -> fmt::Result {
+    writeln!(f, "digraph handles {{")?;
+    writeln!(f, "  node [shape=box];")?;
+    for handle in source.handles() {
+        write!(f, "  h{handle:x} [label=\"{handle:#x}")?;
+        for protocol in source.protocols(handle) {
+            match guid_name(&protocol.guid) {
+                Some(name) => write!(f, "\\n{}", DotString(name))?,
+                None => write!(f, "\\n{}", Guid::from_ref(&protocol.guid))?,
+            }
+        }
+        writeln!(f, "\"];")?;
+    }
+    for handle in source.handles() {
+        for child in source.children(handle) {
+            writeln!(f, "  h{handle:x} -> h{child:x};")?;
+        }
+    }
+    for handle in source.handles() {
+        for protocol in source.protocols(handle) {
+            for info in protocol.open_info.iter().filter(|info| info.attributes & efi::OPEN_PROTOCOL_BY_DRIVER != 0) {
+                if let Some(agent) = info.agent_handle {
+                    writeln!(
+                        f,
+                        "  h{:x} -> h{:x} [style=dashed, label=\"{}\"];",
+                        agent as usize,
+                        handle,
+                        OpenAttributes(info.attributes)
+                    )?;
+                }
+            }
+        }
+    }
+    writeln!(f, "}}")
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use std::{
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
+
+    use super::*;
+    use crate::test_support;
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            f();
+        })
+        .unwrap();
+    }
+
+    fn open(agent: usize, controller: usize, attributes: u32) -> OpenProtocolInformation {
+        OpenProtocolInformation {
+            agent_handle: Some(agent as efi::Handle),
+            controller_handle: Some(controller as efi::Handle),
+            attributes,
+            open_count: 1,
+        }
+    }
+
+    struct TestProtocol {
+        guid: efi::Guid,
+        interface: usize,
+        installer: Option<usize>,
+        open_info: Vec<OpenProtocolInformation>,
+    }
+
+    // Handles in creation order, with their protocols in GUID order.
+    struct TestGraph(Vec<(usize, Vec<TestProtocol>)>);
+
+    impl HandleSource for TestGraph {
+        fn handles(&self) -> impl Iterator<Item = usize> + '_ {
+            self.0.iter().map(|(handle, _)| *handle)
+        }
+
+        fn protocols(&self, handle: usize) -> impl Iterator<Item = ProtocolView<'_>> + '_ {
+            self.0.iter().filter(move |(key, _)| *key == handle).flat_map(|(_, protocols)| {
+                protocols.iter().map(|protocol| ProtocolView {
+                    guid: protocol.guid,
+                    interface: protocol.interface,
+                    installer: protocol.installer,
+                    open_info: &protocol.open_info,
+                })
+            })
+        }
+    }
+
+    // A bus controller (0x10) managed by a driver (0x30), which produced a child (0x20).
+    fn test_graph() -> TestGraph {
+        TestGraph(vec![
+            (
+                0x10,
+                vec![TestProtocol {
+                    guid: efi::protocols::pci_io::PROTOCOL_GUID,
+                    interface: 0x1000,
+                    installer: Some(0x30),
+                    open_info: vec![
+                        open(0x30, 0x10, efi::OPEN_PROTOCOL_BY_DRIVER),
+                        open(0x30, 0x20, efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER),
+                        open(0x30, 0x20, efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER),
+                    ],
+                }],
+            ),
+            (
+                0x20,
+                vec![TestProtocol {
+                    guid: efi::protocols::block_io::PROTOCOL_GUID,
+                    interface: 0x2000,
+                    installer: None,
+                    open_info: Vec::new(),
+                }],
+            ),
+            (
+                0x30,
+                vec![TestProtocol {
+                    guid: efi::protocols::driver_binding::PROTOCOL_GUID,
+                    interface: 0x3000,
+                    installer: None,
+                    open_info: Vec::new(),
+                }],
+            ),
+        ])
+    }
+
+    #[test]
+    fn sources_should_derive_parent_child_relationships() {
+        let graph = test_graph();
+        assert_eq!(graph.children(0x10).collect::<Vec<_>>(), vec![0x20]);
+        assert_eq!(graph.parents(0x10).count(), 0);
+        assert_eq!(graph.parents(0x20).collect::<Vec<_>>(), vec![0x10]);
+        assert_eq!(graph.children(0x30).count(), 0);
+        assert_eq!(graph.protocols(0x40).count(), 0);
+    }
+
+    #[test]
+    fn write_protocols_should_list_handles_per_protocol() {
+        let mut protocols = String::new();
+        write_protocols(&mut protocols, &test_graph()).unwrap();
+        assert_eq!(protocols.lines().count(), 3);
+        assert!(
+            protocols.contains(&format!("{} BlockIo [20]\n", Guid::from_ref(&efi::protocols::block_io::PROTOCOL_GUID)))
+        );
+    }
+
+    #[test]
+    fn guid_names_should_prefer_registered_names() {
+        with_locked_state(|| {
+            let guid =
+                efi::Guid::from_fields(0x1234_5678, 0x9abc, 0xdef0, 0x12, 0x34, &[0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0]);
+            assert_eq!(guid_name(&guid), None);
+            assert_eq!(guid_name(&efi::protocols::block_io::PROTOCOL_GUID), Some("BlockIo"));
+
+            register_guid_name(guid, "TestProtocol");
+            assert_eq!(guid_name(&guid), Some("TestProtocol"));
+            REGISTERED_GUID_NAMES.lock().clear();
+        });
+    }
+
+    #[test]
+    fn text_should_render_handles_protocols_and_open_info() {
+        let text = GraphFormat::Text.render(&test_graph()).to_string();
+        assert!(text.contains("Handle 0x10 children: [0x20]\n"));
+        assert!(text.contains("Handle 0x20 parents: [0x10]\n"));
+        assert!(text.contains(&format!(
+            "  PciIo ({}) interface: 0x1000 installed by: 0x30\n",
+            Guid::from_ref(&efi::protocols::pci_io::PROTOCOL_GUID)
+        )));
+        assert!(text.contains("    agent: 0x30 controller: 0x20 attributes: BY_CHILD_CONTROLLER open count: 1\n"));
+    }
+
+    #[test]
+    fn json_should_render_a_well_formed_document() {
+        let json = GraphFormat::Json.render(&test_graph()).to_string();
+        assert!(json.starts_with("{\"handles\":[{\"handle\":\"0x10\",\"parents\":[],\"children\":[\"0x20\"],"));
+        assert!(json.contains("\"name\":\"BlockIo\""));
+        assert!(json.contains("{\"agent\":\"0x30\",\"controller\":\"0x10\",\"attributes\":16,\"open_count\":1}"));
+        // braces and brackets are balanced.
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+        assert_eq!(json.matches('[').count(), json.matches(']').count());
+    }
+
+    #[test]
+    fn dot_should_render_child_and_driver_edges() {
+        let dot = GraphFormat::Dot.render(&test_graph()).to_string();
+        assert!(dot.starts_with("digraph handles {\n"));
+        assert!(dot.contains("  h20 [label=\"0x20\\nBlockIo\"];\n"));
+        assert!(dot.contains("  h10 -> h20;\n"));
+        assert!(dot.contains("  h30 -> h10 [style=dashed, label=\"BY_DRIVER\"];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn single_handle_should_keep_relationships_from_the_full_source() {
+        let graph = test_graph();
+        let text = GraphFormat::Text.render(&SingleHandle(&graph, 0x20)).to_string();
+        assert!(text.starts_with("Handle 0x20 parents: [0x10]\n"));
+        assert!(!text.contains("Handle 0x10"));
+    }
+
+    #[test]
+    fn registered_names_should_be_escaped() {
+        assert_eq!(JsonString("a\"b\\c\nd\u{1}").to_string(), "\"a\\\"b\\\\c\\nd\\u0001\"");
+        assert_eq!(DotString("a\"b\\c\nd").to_string(), "a\\\"b\\\\c d");
+    }
+}
@@ -19,6 +19,8 @@ use crate::{
     allocator::core_allocate_pool,
     driver_services::{core_connect_controller, core_disconnect_controller},
     events::{EVENT_DB, signal_event},
+    image,
+    protocol_audit::PROTOCOL_AUDIT,
     protocol_db::{DXE_CORE_HANDLE, SpinLockedProtocolDb},
     tpl_lock,
 };
@@ -32,6 +34,9 @@ pub fn core_install_protocol_interface(
 ) -> Result<efi::Handle, EfiError> {
     log::info!("InstallProtocolInterface: {:?} @ {:#x?}", guid_fmt!(protocol), interface);
     let (handle, notifies) = PROTOCOL_DB.install_protocol_interface(handle, protocol, interface)?;
+    if PROTOCOL_AUDIT.enabled() {
+        PROTOCOL_AUDIT.record_install(&PROTOCOL_DB, handle, protocol, interface, image::current_running_image());
+    }
 
     let mut closed_events = Vec::new();
 
@@ -81,9 +86,13 @@ pub fn core_uninstall_protocol_interface(
 
     // Check if the handle/protocol/interface triple is legitimate
     match PROTOCOL_DB.get_interface_for_handle(handle, protocol) {
-        Err(err) => return Err(err),
+        Err(err) => {
+            PROTOCOL_AUDIT.check_stale_interface(protocol, interface);
+            return Err(err);
+        }
         Ok(found_interface) => {
             if found_interface != interface {
+                PROTOCOL_AUDIT.check_stale_interface(protocol, interface);
                 return Err(EfiError::NotFound);
             }
         }
@@ -150,13 +159,19 @@ pub fn core_uninstall_protocol_interface(
     }
 
     if usage_close_status.is_err() || unclosed_usages {
+        PROTOCOL_AUDIT.report_uninstall_denied(&PROTOCOL_DB, handle, protocol);
         unsafe {
             let _result = core_connect_controller(handle, Vec::new(), None, true);
         }
         return Err(EfiError::AccessDenied);
     }
 
-    PROTOCOL_DB.uninstall_protocol_interface(handle, protocol, interface)
+    let installer = PROTOCOL_DB.get_protocol_installer(handle, protocol).unwrap_or(None);
+    PROTOCOL_DB.uninstall_protocol_interface(handle, protocol, interface)?;
+    if PROTOCOL_AUDIT.enabled() {
+        PROTOCOL_AUDIT.record_uninstall(handle, protocol, interface, installer, image::current_running_image());
+    }
+    Ok(())
 }
 
 extern "efiapi" fn uninstall_protocol_interface(
@@ -0,0 +1,451 @@
+//! DXE Core System Table Integrity Monitoring
+//!
+//! Opt-in detection of drivers that hook or overwrite the Boot Services and Runtime Services tables. When enabled
+//! (see [TableIntegrityMode]), the core snapshots the service tables once they are initialized, and compares them
+//! against the snapshot after each dispatch iteration, at ReadyToBoot and at ExitBootServices. Each change is
+//! attributed to the loaded image containing the new target and is either logged and accepted, or reverted.
+//!
+//! Runtime services are provided by architectural drivers that fill in the Runtime Services table themselves, so the
+//! first change to a runtime service that still holds the core's initial placeholder is expected, and is logged and
+//! accepted in every mode. Any later change to it is treated as a hook.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{
+    ffi::c_void,
+    fmt,
+    mem::size_of,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use r_efi::efi;
+
+use crate::{
+    events::EVENT_DB,
+    image,
+    systemtables::{EfiSystemTable, SYSTEM_TABLE},
+    tpl_lock,
+};
+
+/// The names of the Boot Services table entries, in table order.
+const BOOT_SERVICES: [&str; 44] = [
+    "RaiseTPL",
+    "RestoreTPL",
+    "AllocatePages",
+    "FreePages",
+    "GetMemoryMap",
+    "AllocatePool",
+    "FreePool",
+    "CreateEvent",
+    "SetTimer",
+    "WaitForEvent",
+    "SignalEvent",
+    "CloseEvent",
+    "CheckEvent",
+    "InstallProtocolInterface",
+    "ReinstallProtocolInterface",
+    "UninstallProtocolInterface",
+    "HandleProtocol",
+    "Reserved",
+    "RegisterProtocolNotify",
+    "LocateHandle",
+    "LocateDevicePath",
+    "InstallConfigurationTable",
+    "LoadImage",
+    "StartImage",
+    "Exit",
+    "UnloadImage",
+    "ExitBootServices",
+    "GetNextMonotonicCount",
+    "Stall",
+    "SetWatchdogTimer",
+    "ConnectController",
+    "DisconnectController",
+    "OpenProtocol",
+    "CloseProtocol",
+    "OpenProtocolInformation",
+    "ProtocolsPerHandle",
+    "LocateHandleBuffer",
+    "LocateProtocol",
+    "InstallMultipleProtocolInterfaces",
+    "UninstallMultipleProtocolInterfaces",
+    "CalculateCrc32",
+    "CopyMem",
+    "SetMem",
+    "CreateEventEx",
+];
+
+/// The names of the Runtime Services table entries, in table order.
+const RUNTIME_SERVICES: [&str; 14] = [
+    "GetTime",
+    "SetTime",
+    "GetWakeupTime",
+    "SetWakeupTime",
+    "SetVirtualAddressMap",
+    "ConvertPointer",
+    "GetVariable",
+    "GetNextVariableName",
+    "SetVariable",
+    "GetNextHighMonotonicCount",
+    "ResetSystem",
+    "UpdateCapsule",
+    "QueryCapsuleCapabilities",
+    "QueryVariableInfo",
+];
+
+const _: () =
+    assert!(size_of::<efi::BootServices>() == size_of::<efi::TableHeader>() + BOOT_SERVICES.len() * size_of::<usize>());
+const _: () = assert!(
+    size_of::<efi::RuntimeServices>() == size_of::<efi::TableHeader>() + RUNTIME_SERVICES.len() * size_of::<usize>()
+);
+
+/// Selects how the core monitors the integrity of the service tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableIntegrityMode {
+    /// The service tables are not monitored.
+    Disabled,
+    /// Changes are logged with the image they point into, and accepted.
+    Log,
+    /// Changes are logged with the image they point into, and the original pointer is restored.
+    Revert,
+}
+
+impl TableIntegrityMode {
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Log,
+            2 => Self::Revert,
+            _ => Self::Disabled,
+        }
+    }
+}
+
+/// The point at which the service tables are checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckPoint {
+    /// After an iteration of component and driver dispatch.
+    Dispatch,
+    /// At ReadyToBoot.
+    ReadyToBoot,
+    /// At ExitBootServices, before the boot services are torn down.
+    ExitBootServices,
+}
+
+impl fmt::Display for CheckPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dispatch => write!(f, "dispatch"),
+            Self::ReadyToBoot => write!(f, "ReadyToBoot"),
+            Self::ExitBootServices => write!(f, "ExitBootServices"),
+        }
+    }
+}
+
+struct Snapshot {
+    boot_services: *mut efi::BootServices,
+    runtime_services: *mut efi::RuntimeServices,
+    boot_entries: [usize; BOOT_SERVICES.len()],
+    runtime_entries: [usize; RUNTIME_SERVICES.len()],
+    // The runtime entries as initialized by the core, before any driver provided them.
+    initial_runtime_entries: [usize; RUNTIME_SERVICES.len()],
+}
+
+// The snapshot only refers to the core's own service tables and is accessed through a mutex guard, so it is safe to
+// mark it send.
+unsafe impl Send for Snapshot {}
+
+/// Returns the entries of a service table, which follow its header.
+///
+/// # Safety
+///
+/// `table` must point to a service table with `N` pointer sized entries following its header.
+unsafe fn entries<const N: usize>(table: *const c_void) -> *mut [usize; N] {
+    // SAFETY: the caller guarantees the entries follow the header.
+    unsafe { table.byte_add(size_of::<efi::TableHeader>()) as *mut [usize; N] }
+}
+
+fn report(point: CheckPoint, what: fmt::Arguments, old: usize, new: usize, action: &str) {
+    image::with_image_name_containing(new, |name| {
+        log::error!(
+            "System table integrity ({point}): {what} changed from {old:#x} to {new:#x} ({}), {action}.",
+            name.unwrap_or("unknown image")
+        )
+    });
+}
+
+/// Monitors the Boot Services and Runtime Services tables for changes.
+pub struct TableIntegrityMonitor {
+    mode: AtomicU8,
+    snapshot: tpl_lock::TplMutex<Option<Snapshot>>,
+}
+
+/// The global table integrity monitor, monitoring [SYSTEM_TABLE].
+pub static TABLE_INTEGRITY: TableIntegrityMonitor = TableIntegrityMonitor::new();
+
+extern "efiapi" fn ready_to_boot(_event: efi::Event, _context: *mut c_void) {
+    TABLE_INTEGRITY.check(CheckPoint::ReadyToBoot);
+}
+
+impl TableIntegrityMonitor {
+    /// Creates a new, disabled, monitor.
+    pub const fn new() -> Self {
+        Self {
+            mode: AtomicU8::new(TableIntegrityMode::Disabled as u8),
+            snapshot: tpl_lock::TplMutex::new(efi::TPL_NOTIFY, None, "TableIntegrityLock"),
+        }
+    }
+
+    /// Sets the monitoring mode.
+    pub fn set_mode(&self, mode: TableIntegrityMode) {
+        self.mode.store(mode as u8, Ordering::SeqCst);
+    }
+
+    /// Returns the monitoring mode.
+    pub fn mode(&self) -> TableIntegrityMode {
+        TableIntegrityMode::from_u8(self.mode.load(Ordering::SeqCst))
+    }
+
+    /// Returns true if monitoring is enabled.
+    pub fn enabled(&self) -> bool {
+        self.mode() != TableIntegrityMode::Disabled
+    }
+
+    /// Snapshots the service tables as initialized by the core, and registers the ReadyToBoot check. Must be called
+    /// once the system table is initialized.
+    pub fn snapshot(&self) {
+        if !self.enabled() {
+            return;
+        }
+
+        let (boot_services, runtime_services) = match SYSTEM_TABLE.lock().as_mut() {
+            Some(st) => (
+                st.boot_services_mut() as *mut efi::BootServices,
+                st.runtime_services_mut() as *mut efi::RuntimeServices,
+            ),
+            None => {
+                log::error!("System table integrity: system table is not initialized.");
+                return;
+            }
+        };
+        // SAFETY: both pointers refer to the core's initialized service tables.
+        let (boot_entries, runtime_entries) =
+            unsafe { (*entries(boot_services as *const c_void), *entries(runtime_services as *const c_void)) };
+        *self.snapshot.lock() = Some(Snapshot {
+            boot_services,
+            runtime_services,
+            boot_entries,
+            runtime_entries,
+            initial_runtime_entries: runtime_entries,
+        });
+
+        if let Err(err) = EVENT_DB.create_event(
+            efi::EVT_NOTIFY_SIGNAL,
+            efi::TPL_CALLBACK,
+            Some(ready_to_boot),
+            None,
+            Some(efi::EVENT_GROUP_READY_TO_BOOT),
+        ) {
+            log::error!("System table integrity: failed to register the ReadyToBoot check: {err:?}");
+        }
+    }
+
+    /// Compares the service tables against the snapshot, reporting (and in [TableIntegrityMode::Revert] mode,
+    /// reverting) every change. Returns the number of unexpected changes found.
+    pub fn check(&self, point: CheckPoint) -> usize {
+        if !self.enabled() {
+            return 0;
+        }
+        let revert = self.mode() == TableIntegrityMode::Revert;
+        let action = if revert { "reverting" } else { "accepting" };
+
+        let mut snapshot = self.snapshot.lock();
+        let Some(snapshot) = snapshot.as_mut() else {
+            return 0;
+        };
+        let mut st = SYSTEM_TABLE.lock();
+        let Some(st) = st.as_mut() else {
+            return 0;
+        };
+
+        let mut changes = Self::check_table_pointers(snapshot, st, point, revert, action);
+
+        // SAFETY: the snapshot refers to the core's service tables, which live as long as the system table.
+        let boot_entries = unsafe { &mut *entries::<{ BOOT_SERVICES.len() }>(snapshot.boot_services as *const c_void) };
+        for (index, name) in BOOT_SERVICES.iter().enumerate() {
+            let (old, new) = (snapshot.boot_entries[index], boot_entries[index]);
+            if old == new {
+                continue;
+            }
+            changes += 1;
+            report(point, format_args!("BootServices.{name}"), old, new, action);
+            if revert {
+                boot_entries[index] = old;
+            } else {
+                snapshot.boot_entries[index] = new;
+            }
+        }
+
+        // SAFETY: the snapshot refers to the core's service tables, which live as long as the system table.
+        let runtime_entries =
+            unsafe { &mut *entries::<{ RUNTIME_SERVICES.len() }>(snapshot.runtime_services as *const c_void) };
+        for (index, name) in RUNTIME_SERVICES.iter().enumerate() {
+            let (old, new) = (snapshot.runtime_entries[index], runtime_entries[index]);
+            if old == new {
+                continue;
+            }
+            if old == snapshot.initial_runtime_entries[index] {
+                image::with_image_name_containing(new, |image| {
+                    log::info!(
+                        "System table integrity ({point}): RuntimeServices.{name} provided at {new:#x} ({}).",
+                        image.unwrap_or("unknown image")
+                    )
+                });
+                snapshot.runtime_entries[index] = new;
+                continue;
+            }
+            changes += 1;
+            report(point, format_args!("RuntimeServices.{name}"), old, new, action);
+            if revert {
+                runtime_entries[index] = old;
+            } else {
+                snapshot.runtime_entries[index] = new;
+            }
+        }
+
+        if changes != 0 && revert {
+            st.checksum_all();
+        }
+        changes
+    }
+
+    // Checks that the system table still points at the core's service tables.
+    fn check_table_pointers(
+        snapshot: &mut Snapshot,
+        st: &mut EfiSystemTable,
+        point: CheckPoint,
+        revert: bool,
+        action: &str,
+    ) -> usize {
+        let mut changes = 0;
+        let system_table = st.as_mut();
+        if system_table.boot_services != snapshot.boot_services {
+            changes += 1;
+            let (old, new) = (snapshot.boot_services as usize, system_table.boot_services as usize);
+            report(point, format_args!("SystemTable.BootServices"), old, new, action);
+            if revert {
+                system_table.boot_services = snapshot.boot_services;
+            }
+        }
+        if system_table.runtime_services != snapshot.runtime_services {
+            changes += 1;
+            let (old, new) = (snapshot.runtime_services as usize, system_table.runtime_services as usize);
+            report(point, format_args!("SystemTable.RuntimeServices"), old, new, action);
+            if revert {
+                system_table.runtime_services = snapshot.runtime_services;
+            }
+        }
+        // In log mode the replacement tables are accepted, but the core's own tables continue to be monitored.
+        changes
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::{systemtables::init_system_table, test_support};
+
+    extern "efiapi" fn hooked_stall(_: usize) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn provided_reset_system(_: efi::ResetType, _: efi::Status, _: usize, _: *mut c_void) {}
+
+    extern "efiapi" fn hooked_reset_system(_: efi::ResetType, _: efi::Status, _: usize, _: *mut c_void) {}
+
+    fn with_monitor<F: Fn(&'static TableIntegrityMonitor) + std::panic::RefUnwindSafe>(mode: TableIntegrityMode, f: F) {
+        test_support::with_global_lock(|| {
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+                init_system_table();
+            }
+            let monitor: &'static TableIntegrityMonitor = Box::leak(Box::new(TableIntegrityMonitor::new()));
+            monitor.set_mode(mode);
+            monitor.snapshot();
+            f(monitor);
+        })
+        .unwrap();
+    }
+
+    fn with_st<R>(f: impl FnOnce(&mut EfiSystemTable) -> R) -> R {
+        f(SYSTEM_TABLE.lock().as_mut().unwrap())
+    }
+
+    #[test]
+    fn test_disabled_monitor_reports_nothing() {
+        with_monitor(TableIntegrityMode::Disabled, |monitor| {
+            with_st(|st| st.boot_services_mut().stall = hooked_stall);
+            assert_eq!(monitor.check(CheckPoint::Dispatch), 0);
+        });
+    }
+
+    #[test]
+    fn test_log_mode_reports_a_hook_once() {
+        with_monitor(TableIntegrityMode::Log, |monitor| {
+            assert_eq!(monitor.check(CheckPoint::Dispatch), 0);
+
+            with_st(|st| st.boot_services_mut().stall = hooked_stall);
+            assert_eq!(monitor.check(CheckPoint::Dispatch), 1);
+            assert_eq!(with_st(|st| st.boot_services().stall as usize), hooked_stall as usize);
+
+            // The hook was accepted, so it is not reported again.
+            assert_eq!(monitor.check(CheckPoint::ReadyToBoot), 0);
+        });
+    }
+
+    #[test]
+    fn test_revert_mode_restores_the_original_entry() {
+        with_monitor(TableIntegrityMode::Revert, |monitor| {
+            let original = with_st(|st| st.boot_services().stall as usize);
+            with_st(|st| st.boot_services_mut().stall = hooked_stall);
+
+            assert_eq!(monitor.check(CheckPoint::ExitBootServices), 1);
+            assert_eq!(with_st(|st| st.boot_services().stall as usize), original);
+
+            // The checksum was recalculated after the revert.
+            let crc = with_st(|st| st.boot_services().hdr.crc32);
+            with_st(|st| st.checksum_boot_services());
+            assert_eq!(with_st(|st| st.boot_services().hdr.crc32), crc);
+        });
+    }
+
+    #[test]
+    fn test_runtime_services_may_be_provided_once() {
+        with_monitor(TableIntegrityMode::Revert, |monitor| {
+            with_st(|st| st.runtime_services_mut().reset_system = provided_reset_system);
+            assert_eq!(monitor.check(CheckPoint::Dispatch), 0);
+
+            with_st(|st| st.runtime_services_mut().reset_system = hooked_reset_system);
+            assert_eq!(monitor.check(CheckPoint::Dispatch), 1);
+            assert_eq!(with_st(|st| st.runtime_services().reset_system as usize), provided_reset_system as usize);
+        });
+    }
+
+    #[test]
+    fn test_replaced_service_table_is_detected() {
+        with_monitor(TableIntegrityMode::Revert, |monitor| {
+            let original = with_st(|st| st.as_ref().boot_services);
+            let replacement: &'static mut efi::BootServices =
+                Box::leak(Box::new(unsafe { core::ptr::read(original as *const efi::BootServices) }));
+            with_st(|st| st.as_mut().boot_services = replacement);
+
+            assert_eq!(monitor.check(CheckPoint::Dispatch), 1);
+            assert_eq!(with_st(|st| st.as_ref().boot_services), original);
+        });
+    }
+}
@@ -0,0 +1,329 @@
+//! DXE Core Timer Architectural Protocol
+//!
+//! Produces the Timer Architectural Protocol on top of the [Timer] service. The periodic timer interrupt is routed to
+//! the handler registered by the event module, which is passed the time elapsed since the previous interrupt as
+//! measured by the free running counter, so that rounding of the programmed period does not cause the system time to
+//! drift.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicU64, Ordering};
+use patina::{
+    boot_services::{BootServices, StandardBootServices},
+    component::{IntoComponent, service::Service},
+    error::Result,
+    pi::protocols::timer::{EfiTimerNotify, PROTOCOL_GUID, Protocol},
+    uefi_protocol::ProtocolInterface,
+};
+use patina_internal_cpu::{
+    interrupts::{ExceptionContext, ExceptionType, HandlerType, InterruptHandler, InterruptManager},
+    timer::{Timer, TimerInterrupt},
+};
+use r_efi::efi;
+
+use crate::{crash_record::CRASH_RECORDER, tpl_lock::TplMutex};
+
+/// The period the timer is programmed with when the protocol is installed (10ms).
+const DEFAULT_TIMER_PERIOD: u64 = 100_000;
+
+struct TimerState {
+    notify_function: Option<EfiTimerNotify>,
+    last_counter: u64,
+}
+
+#[repr(C)]
+pub struct EfiTimerArchProtocolImpl {
+    protocol: Protocol,
+
+    // private data
+    timer: &'static dyn Timer,
+    period: AtomicU64,
+    state: TplMutex<TimerState>,
+}
+
+unsafe impl ProtocolInterface for EfiTimerArchProtocolImpl {
+    const PROTOCOL_GUID: efi::Guid = PROTOCOL_GUID;
+}
+
+// Helper function to convert a raw pointer to a reference.
+fn get_impl_ref<'a>(this: *const Protocol) -> &'a EfiTimerArchProtocolImpl {
+    if this.is_null() {
+        panic!("Null pointer passed to get_impl_ref()");
+    }
+
+    unsafe { &*(this as *const EfiTimerArchProtocolImpl) }
+}
+
+// EfiTimerArchProtocolImpl function pointers implementations.
+
+extern "efiapi" fn register_handler(this: *mut Protocol, notify_function: EfiTimerNotify) -> efi::Status {
+    let timer_arch = get_impl_ref(this);
+    let mut state = timer_arch.state.lock();
+
+    let notify_function_ptr = notify_function as *const ();
+    match (!notify_function_ptr.is_null(), state.notify_function.is_some()) {
+        (true, true) => efi::Status::ALREADY_STARTED,
+        (false, false) => efi::Status::INVALID_PARAMETER,
+        (true, false) => {
+            state.notify_function = Some(notify_function);
+            efi::Status::SUCCESS
+        }
+        (false, true) => {
+            state.notify_function = None;
+            efi::Status::SUCCESS
+        }
+    }
+}
+
+extern "efiapi" fn set_timer_period(this: *mut Protocol, timer_period: u64) -> efi::Status {
+    let timer_arch = get_impl_ref(this);
+
+    match timer_arch.set_period(timer_period) {
+        Ok(()) => efi::Status::SUCCESS,
+        Err(err) => err.into(),
+    }
+}
+
+extern "efiapi" fn get_timer_period(this: *mut Protocol, timer_period: *mut u64) -> efi::Status {
+    if timer_period.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let timer_arch = get_impl_ref(this);
+
+    // Safety: caller must ensure that timer_period is a valid pointer. It is null-checked above.
+    unsafe { timer_period.write_unaligned(timer_arch.period.load(Ordering::SeqCst)) };
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn generate_soft_interrupt(this: *mut Protocol) -> efi::Status {
+    get_impl_ref(this).tick();
+    efi::Status::SUCCESS
+}
+
+impl EfiTimerArchProtocolImpl {
+    fn new(timer: &'static dyn Timer) -> Self {
+        Self {
+            protocol: Protocol { register_handler, set_timer_period, get_timer_period, generate_soft_interrupt },
+
+            // private data
+            timer,
+            period: AtomicU64::new(0),
+            state: TplMutex::new(
+                efi::TPL_HIGH_LEVEL,
+                TimerState { notify_function: None, last_counter: 0 },
+                "Timer Arch Lock",
+            ),
+        }
+    }
+
+    /// Programs the timer hardware with the given period in 100ns units. A period of zero disables the timer.
+    fn set_period(&self, period: u64) -> Result<()> {
+        let mut state = self.state.lock();
+        let programmed = self.timer.set_period(period)?;
+        state.last_counter = self.timer.counter();
+        self.period.store(programmed, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Calls the registered notify function with the time elapsed since the previous tick.
+    fn tick(&self) {
+        let (notify_function, elapsed) = {
+            let mut state = self.state.lock();
+            let counter = self.timer.counter();
+            let elapsed = self.timer.ticks_to_period(counter.wrapping_sub(state.last_counter));
+            // Only consume the counter ticks that were accounted for, carrying the remainder to the next tick.
+            state.last_counter = state.last_counter.wrapping_add(self.timer.period_to_ticks(elapsed));
+            (state.notify_function, elapsed)
+        };
+
+        if let Some(notify_function) = notify_function {
+            notify_function(elapsed);
+        }
+    }
+}
+
+impl InterruptHandler for EfiTimerArchProtocolImpl {
+    fn handle_interrupt(&'static self, _exception_type: ExceptionType, context: &mut ExceptionContext) {
+        CRASH_RECORDER.record_interrupted_context(context);
+        self.timer.acknowledge();
+        self.tick();
+    }
+}
+
+#[cfg(all(target_os = "uefi", target_arch = "aarch64"))]
+mod hardware_interrupt {
+    use super::EfiTimerArchProtocolImpl;
+    use crate::{
+        crash_record::CRASH_RECORDER, hw_interrupt_protocol::EfiHardwareInterruptProtocol, protocols::PROTOCOL_DB,
+    };
+    use core::sync::atomic::{AtomicPtr, Ordering};
+    use patina::{error::EfiError, guids::HARDWARE_INTERRUPT_PROTOCOL};
+    use patina_internal_cpu::interrupts::ExceptionContext;
+
+    static TIMER_ARCH: AtomicPtr<EfiTimerArchProtocolImpl> = AtomicPtr::new(core::ptr::null_mut());
+    static HW_INTERRUPT: AtomicPtr<EfiHardwareInterruptProtocol> = AtomicPtr::new(core::ptr::null_mut());
+
+    extern "efiapi" fn timer_interrupt(interrupt_source: u64, context: &mut ExceptionContext) {
+        // Safety: both pointers are set before the interrupt source is registered and are never freed.
+        let (Some(timer_arch), Some(hw_interrupt)) = (unsafe { TIMER_ARCH.load(Ordering::SeqCst).as_ref() }, unsafe {
+            HW_INTERRUPT.load(Ordering::SeqCst).as_mut()
+        }) else {
+            return;
+        };
+        CRASH_RECORDER.record_interrupted_context(context);
+        timer_arch.timer.acknowledge();
+        // Safety: the hardware interrupt protocol pointer was located from the protocol database.
+        unsafe { (hw_interrupt.end_of_interrupt)(hw_interrupt, interrupt_source) };
+        timer_arch.tick();
+    }
+
+    /// Registers the timer interrupt with the Hardware Interrupt Protocol installed by the core.
+    pub(super) fn register(
+        interrupt_source: u64,
+        timer_arch: &'static EfiTimerArchProtocolImpl,
+    ) -> Result<(), EfiError> {
+        let hw_interrupt =
+            PROTOCOL_DB.locate_protocol(HARDWARE_INTERRUPT_PROTOCOL)? as *mut EfiHardwareInterruptProtocol;
+        TIMER_ARCH.store(timer_arch as *const _ as *mut _, Ordering::SeqCst);
+        HW_INTERRUPT.store(hw_interrupt, Ordering::SeqCst);
+
+        // Safety: the hardware interrupt protocol pointer was located from the protocol database.
+        let status =
+            unsafe { ((*hw_interrupt).register_interrupt_source)(hw_interrupt, interrupt_source, timer_interrupt) };
+        EfiError::status_to_result(status)
+    }
+}
+
+/// This component installs the timer arch protocol
+#[derive(IntoComponent, Default)]
+pub(crate) struct TimerArchProtocolInstaller;
+
+impl TimerArchProtocolInstaller {
+    fn entry_point(
+        self,
+        timer: Service<dyn Timer>,
+        interrupt_manager: Service<dyn InterruptManager>,
+        bs: StandardBootServices,
+    ) -> Result<()> {
+        let timer_arch: &'static EfiTimerArchProtocolImpl = Box::leak(Box::new(EfiTimerArchProtocolImpl::new(*timer)));
+
+        match timer.interrupt() {
+            TimerInterrupt::Exception(exception_type) => {
+                interrupt_manager.register_exception_handler(exception_type, HandlerType::Handler(timer_arch))?
+            }
+            #[cfg(all(target_os = "uefi", target_arch = "aarch64"))]
+            TimerInterrupt::HardwareInterrupt(interrupt_source) => {
+                hardware_interrupt::register(interrupt_source, timer_arch)?
+            }
+            #[cfg(not(all(target_os = "uefi", target_arch = "aarch64")))]
+            TimerInterrupt::HardwareInterrupt(interrupt_source) => {
+                log::error!("No hardware interrupt controller available for timer interrupt {interrupt_source}.");
+                return Err(patina::error::EfiError::Unsupported);
+            }
+        }
+
+        timer_arch.set_period(DEFAULT_TIMER_PERIOD)?;
+
+        // The interrupt handler holds a shared reference to the protocol, so the interface is installed by pointer.
+        let interface = timer_arch as *const EfiTimerArchProtocolImpl as *mut EfiTimerArchProtocolImpl;
+        // Safety: the protocol was leaked above and is never freed.
+        bs.install_protocol_interface(None, unsafe { &mut *interface })
+            .inspect_err(|_| log::error!("Failed to install EFI_TIMER_ARCH_PROTOCOL"))?;
+        log::info!("installed EFI_TIMER_ARCH_PROTOCOL_GUID");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicU64;
+    use patina_internal_cpu::timer::EfiTimerNull;
+
+    static ELAPSED: AtomicU64 = AtomicU64::new(0);
+
+    extern "efiapi" fn test_notify(time: u64) {
+        ELAPSED.fetch_add(time, Ordering::SeqCst);
+    }
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        crate::test_support::with_global_lock(|| {
+            f();
+        })
+        .unwrap();
+    }
+
+    fn fake_timer_arch() -> (&'static EfiTimerNull, &'static mut EfiTimerArchProtocolImpl) {
+        let clock: &'static EfiTimerNull = Box::leak(Box::new(EfiTimerNull::new()));
+        (clock, Box::leak(Box::new(EfiTimerArchProtocolImpl::new(clock))))
+    }
+
+    #[test]
+    fn test_register_handler() {
+        with_locked_state(|| {
+            let (_, timer_arch) = fake_timer_arch();
+            let this = &mut timer_arch.protocol as *mut Protocol;
+
+            assert_eq!(register_handler(this, test_notify), efi::Status::SUCCESS);
+            assert_eq!(register_handler(this, test_notify), efi::Status::ALREADY_STARTED);
+        });
+    }
+
+    #[test]
+    fn test_set_and_get_timer_period() {
+        with_locked_state(|| {
+            let (clock, timer_arch) = fake_timer_arch();
+            let this = &mut timer_arch.protocol as *mut Protocol;
+
+            assert_eq!(set_timer_period(this, 50_000), efi::Status::SUCCESS);
+            assert_eq!(clock.period(), 50_000);
+
+            let mut period = 0;
+            assert_eq!(get_timer_period(this, &mut period), efi::Status::SUCCESS);
+            assert_eq!(period, 50_000);
+            assert_eq!(get_timer_period(this, core::ptr::null_mut()), efi::Status::INVALID_PARAMETER);
+
+            assert_eq!(set_timer_period(this, 0), efi::Status::SUCCESS);
+            assert_eq!(get_timer_period(this, &mut period), efi::Status::SUCCESS);
+            assert_eq!(period, 0);
+        });
+    }
+
+    #[test]
+    fn test_interrupt_reports_elapsed_time() {
+        with_locked_state(|| {
+            let (clock, timer_arch) = fake_timer_arch();
+            let this = &mut timer_arch.protocol as *mut Protocol;
+            ELAPSED.store(0, Ordering::SeqCst);
+
+            assert_eq!(register_handler(this, test_notify), efi::Status::SUCCESS);
+            assert_eq!(set_timer_period(this, DEFAULT_TIMER_PERIOD), efi::Status::SUCCESS);
+
+            let timer_arch: &'static EfiTimerArchProtocolImpl = timer_arch;
+            // SAFETY: The exception context is plain register data, which the handler does not inspect.
+            let mut context: ExceptionContext = unsafe { core::mem::zeroed() };
+            clock.advance(DEFAULT_TIMER_PERIOD);
+            timer_arch.handle_interrupt(EfiTimerNull::VECTOR, &mut context);
+            assert_eq!(ELAPSED.load(Ordering::SeqCst), DEFAULT_TIMER_PERIOD);
+            assert_eq!(clock.acknowledged(), 1);
+
+            // A late interrupt reports the actual elapsed time rather than the programmed period.
+            clock.advance(DEFAULT_TIMER_PERIOD + 1234);
+            timer_arch.handle_interrupt(EfiTimerNull::VECTOR, &mut context);
+            assert_eq!(ELAPSED.load(Ordering::SeqCst), 2 * DEFAULT_TIMER_PERIOD + 1234);
+
+            // A soft interrupt calls the notify function without acknowledging the hardware.
+            clock.advance(10);
+            assert_eq!(generate_soft_interrupt(this), efi::Status::SUCCESS);
+            assert_eq!(ELAPSED.load(Ordering::SeqCst), 2 * DEFAULT_TIMER_PERIOD + 1244);
+            assert_eq!(clock.acknowledged(), 2);
+        });
+    }
+}
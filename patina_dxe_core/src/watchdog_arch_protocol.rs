@@ -0,0 +1,261 @@
+//! DXE Core Watchdog Timer Architectural Protocol
+//!
+//! Produces the Watchdog Timer Architectural Protocol as a software watchdog backed by a timer event, so it is driven
+//! by whichever Timer Architectural Protocol is installed. When the watchdog expires, a crash record is captured, the
+//! registered handler (if any) is called and the system is reset.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::boxed::Box;
+use core::{
+    ffi::c_void,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+use patina::{
+    boot_services::{BootServices, StandardBootServices},
+    component::IntoComponent,
+    error::Result,
+    pi::protocols::watchdog::{PROTOCOL_GUID, Protocol, WatchdogTimerNotify},
+    uefi_protocol::ProtocolInterface,
+};
+use r_efi::efi;
+
+use crate::{
+    crash_record,
+    events::{EVENT_DB, set_timer},
+    systemtables::SYSTEM_TABLE,
+    tpl_lock::TplMutex,
+};
+
+struct WatchdogState {
+    notify_function: Option<WatchdogTimerNotify>,
+    period: u64,
+}
+
+#[repr(C)]
+pub struct EfiWatchdogArchProtocolImpl {
+    protocol: Protocol,
+
+    // private data
+    event: AtomicPtr<c_void>,
+    state: TplMutex<WatchdogState>,
+}
+
+unsafe impl ProtocolInterface for EfiWatchdogArchProtocolImpl {
+    const PROTOCOL_GUID: efi::Guid = PROTOCOL_GUID;
+}
+
+// Helper function to convert a raw pointer to a reference.
+fn get_impl_ref<'a>(this: *const Protocol) -> &'a EfiWatchdogArchProtocolImpl {
+    if this.is_null() {
+        panic!("Null pointer passed to get_impl_ref()");
+    }
+
+    unsafe { &*(this as *const EfiWatchdogArchProtocolImpl) }
+}
+
+// EfiWatchdogArchProtocolImpl function pointers implementations.
+
+extern "efiapi" fn register_handler(this: *const Protocol, notify_function: WatchdogTimerNotify) -> efi::Status {
+    let watchdog = get_impl_ref(this);
+    let mut state = watchdog.state.lock();
+
+    let notify_function_ptr = notify_function as *const ();
+    match (!notify_function_ptr.is_null(), state.notify_function.is_some()) {
+        (true, true) => efi::Status::ALREADY_STARTED,
+        (false, false) => efi::Status::INVALID_PARAMETER,
+        (true, false) => {
+            state.notify_function = Some(notify_function);
+            efi::Status::SUCCESS
+        }
+        (false, true) => {
+            state.notify_function = None;
+            efi::Status::SUCCESS
+        }
+    }
+}
+
+extern "efiapi" fn set_timer_period(this: *const Protocol, timer_period: u64) -> efi::Status {
+    let watchdog = get_impl_ref(this);
+    let mut state = watchdog.state.lock();
+
+    let (timer_type, trigger_time) = match timer_period {
+        0 => (efi::TIMER_CANCEL, 0),
+        period => (efi::TIMER_RELATIVE, period),
+    };
+    let status = set_timer(watchdog.event.load(Ordering::SeqCst), timer_type, trigger_time);
+    if status == efi::Status::SUCCESS {
+        state.period = timer_period;
+    }
+    status
+}
+
+extern "efiapi" fn get_timer_period(this: *const Protocol, timer_period: *mut u64) -> efi::Status {
+    if timer_period.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let watchdog = get_impl_ref(this);
+
+    // Safety: caller must ensure that timer_period is a valid pointer. It is null-checked above.
+    unsafe { timer_period.write_unaligned(watchdog.state.lock().period) };
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn watchdog_expired(_event: efi::Event, context: *mut c_void) {
+    let watchdog = get_impl_ref(context as *const Protocol);
+    let (notify_function, period) = {
+        let state = watchdog.state.lock();
+        (state.notify_function, state.period)
+    };
+
+    crash_record::watchdog_hook(period);
+    if let Some(notify_function) = notify_function {
+        notify_function(period);
+    }
+
+    log::error!("Watchdog timer expired after {period} (100ns units). Resetting the system.");
+    let reset_system = SYSTEM_TABLE.lock().as_ref().map(|st| st.runtime_services().reset_system);
+    match reset_system {
+        Some(reset_system) => reset_system(efi::RESET_COLD, efi::Status::TIMEOUT, 0, core::ptr::null_mut()),
+        None => log::error!("System table is not available, unable to reset the system."),
+    }
+}
+
+impl EfiWatchdogArchProtocolImpl {
+    fn new() -> Self {
+        Self {
+            protocol: Protocol { register_handler, set_timer_period, get_timer_period },
+
+            // private data
+            event: AtomicPtr::new(core::ptr::null_mut()),
+            state: TplMutex::new(
+                efi::TPL_HIGH_LEVEL,
+                WatchdogState { notify_function: None, period: 0 },
+                "Watchdog Arch Lock",
+            ),
+        }
+    }
+
+    /// Creates the timer event used to detect watchdog expiry.
+    fn create_event(&'static self) -> Result<()> {
+        let event = EVENT_DB.create_event(
+            efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL,
+            efi::TPL_NOTIFY,
+            Some(watchdog_expired),
+            Some(self as *const Self as *mut c_void),
+            None,
+        )?;
+        self.event.store(event, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// This component installs the watchdog timer arch protocol
+#[derive(IntoComponent, Default)]
+pub(crate) struct WatchdogArchProtocolInstaller;
+
+impl WatchdogArchProtocolInstaller {
+    fn entry_point(self, bs: StandardBootServices) -> Result<()> {
+        let watchdog = Box::leak(Box::new(EfiWatchdogArchProtocolImpl::new()));
+        let interface = watchdog as *mut EfiWatchdogArchProtocolImpl;
+
+        // Safety: the protocol was leaked above and is never freed.
+        unsafe { &*interface }.create_event().inspect_err(|_| log::error!("Failed to create watchdog timer event"))?;
+
+        // Safety: the protocol was leaked above and is never freed.
+        bs.install_protocol_interface(None, unsafe { &mut *interface })
+            .inspect_err(|_| log::error!("Failed to install EFI_WATCHDOG_TIMER_ARCH_PROTOCOL"))?;
+        log::info!("installed EFI_WATCHDOG_TIMER_ARCH_PROTOCOL_GUID");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::{events, test_support};
+    use core::sync::atomic::AtomicU64;
+
+    static EXPIRED_PERIOD: AtomicU64 = AtomicU64::new(0);
+
+    extern "efiapi" fn test_notify(period: u64) {
+        EXPIRED_PERIOD.store(period, Ordering::SeqCst);
+    }
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            f();
+        })
+        .unwrap();
+    }
+
+    fn watchdog() -> &'static EfiWatchdogArchProtocolImpl {
+        let watchdog: &'static EfiWatchdogArchProtocolImpl = Box::leak(Box::new(EfiWatchdogArchProtocolImpl::new()));
+        watchdog.create_event().unwrap();
+        watchdog
+    }
+
+    fn armed_trigger_time(event: efi::Event) -> Option<u64> {
+        let mut trigger_time = None;
+        EVENT_DB.for_each_armed_timer(|timer| {
+            if timer.event == event {
+                trigger_time = Some(timer.trigger_time);
+            }
+        });
+        trigger_time
+    }
+
+    #[test]
+    fn test_register_handler() {
+        with_locked_state(|| {
+            let watchdog = watchdog();
+
+            assert_eq!(register_handler(&watchdog.protocol, test_notify), efi::Status::SUCCESS);
+            assert_eq!(register_handler(&watchdog.protocol, test_notify), efi::Status::ALREADY_STARTED);
+        });
+    }
+
+    #[test]
+    fn test_set_timer_period_arms_and_cancels_the_timer() {
+        with_locked_state(|| {
+            let watchdog = watchdog();
+            let event = watchdog.event.load(Ordering::SeqCst);
+
+            assert_eq!(set_timer_period(&watchdog.protocol, 50_000_000), efi::Status::SUCCESS);
+            assert!(armed_trigger_time(event).is_some());
+
+            let mut period = 0;
+            assert_eq!(get_timer_period(&watchdog.protocol, &mut period), efi::Status::SUCCESS);
+            assert_eq!(period, 50_000_000);
+            assert_eq!(get_timer_period(&watchdog.protocol, core::ptr::null_mut()), efi::Status::INVALID_PARAMETER);
+
+            assert_eq!(set_timer_period(&watchdog.protocol, 0), efi::Status::SUCCESS);
+            assert!(armed_trigger_time(event).is_none());
+            assert_eq!(get_timer_period(&watchdog.protocol, &mut period), efi::Status::SUCCESS);
+            assert_eq!(period, 0);
+        });
+    }
+
+    #[test]
+    fn test_expiry_calls_registered_handler() {
+        with_locked_state(|| {
+            let watchdog = watchdog();
+            EXPIRED_PERIOD.store(0, Ordering::SeqCst);
+            // Ensure we start from a low TPL so that signal_event will dispatch the notify.
+            events::restore_tpl(efi::TPL_APPLICATION);
+
+            assert_eq!(register_handler(&watchdog.protocol, test_notify), efi::Status::SUCCESS);
+            assert_eq!(set_timer_period(&watchdog.protocol, 1234), efi::Status::SUCCESS);
+
+            // Signal the event as the timer tick would on expiry.
+            assert_eq!(events::signal_event(watchdog.event.load(Ordering::SeqCst)), efi::Status::SUCCESS);
+            assert_eq!(EXPIRED_PERIOD.load(Ordering::SeqCst), 1234);
+        });
+    }
+}
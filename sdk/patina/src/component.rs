@@ -123,12 +123,19 @@
 //!
 extern crate alloc;
 
+#[cfg(feature = "serde")]
+pub mod config_description;
+pub mod dispatch;
+pub mod failure;
 pub mod hob;
 mod metadata;
+pub mod order;
 pub mod params;
+pub mod schedule;
 pub mod service;
 mod storage;
 mod struct_component;
+pub mod trigger;
 
 use crate::error::Result;
 
@@ -172,6 +179,20 @@ pub trait Component {
 
     /// Returns the metadata of the component. used in a multi-threaded context to schedule components.
     fn metadata(&self) -> &metadata::MetaData;
+
+    /// Returns whether the component consumes itself when it is run, and thus can only be run successfully once.
+    ///
+    /// This is used by the core to disarm [triggered](trigger) components after their first execution.
+    fn runs_once(&self) -> bool {
+        true
+    }
+
+    /// Returns the address of the code that runs the component, or `None` if the component has no code of its own.
+    ///
+    /// This is used by the core to describe the component to debuggers.
+    fn code_address(&self) -> Option<usize> {
+        None
+    }
 }
 
 /// A helper trait to convert an object into a [Component].
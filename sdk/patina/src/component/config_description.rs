@@ -0,0 +1,465 @@
+//! Loading [Config](crate::component::params::Config) values from a board specific config description.
+//!
+//! Config values are normally provided by platform code with `Core::with_config`, which means a DXE binary must be
+//! rebuilt to change them. A config description instead provides config values as data, so that the same binary can
+//! be configured differently for each board or SKU it is flashed on. The description can be provided in either, or
+//! both, of:
+//!
+//! - A GUIDed HOB named [ConfigDescriptionHob::HOB_GUID](crate::component::hob::FromHob::HOB_GUID), typically
+//!   produced by a pre-DXE phase that detected the board.
+//! - The raw section of a file in a firmware volume, whose name is provided to [ConfigLoader::with_file].
+//!
+//! A config description is a JSON document, mapping the [NAME](ConfigDescription::NAME) of each config to the schema
+//! version it was written for, and the config value itself:
+//!
+//! ```json
+//! {
+//!     "uart": { "version": 2, "config": { "base": 1016, "baud_rate": 115200 } }
+//! }
+//! ```
+//!
+//! A [ConfigLoader] component is registered for each config that can be described. It replaces the config value
+//! provided by the platform with the described one, before any component can access it. If both the HOB and the file
+//! describe a config, the HOB takes precedence. Configs that are not described keep the value provided by the platform.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use patina::component::config_description::{ConfigDescription, ConfigLoader, FieldError};
+//! use patina::BinaryGuid;
+//!
+//! #[derive(Default, serde::Deserialize)]
+//! #[serde(deny_unknown_fields)]
+//! struct UartConfig {
+//!     base: u16,
+//!     // Added in version 2 of the schema.
+//!     #[serde(default)]
+//!     baud_rate: u32,
+//! }
+//!
+//! impl ConfigDescription for UartConfig {
+//!     const NAME: &'static str = "uart";
+//!     const VERSION: u32 = 2;
+//!     const MIN_VERSION: u32 = 1;
+//!
+//!     fn validate(&self) -> Vec<FieldError> {
+//!         match self.baud_rate {
+//!             0 | 9600 | 115200 => Vec::new(),
+//!             _ => vec![FieldError::new("baud_rate", "unsupported baud rate")],
+//!         }
+//!     }
+//! }
+//!
+//! const BOARD_CONFIG_FILE: BinaryGuid = BinaryGuid::from_string("a1d3b7e2-5c49-4f0e-8b26-9e7d4c1f3a58");
+//!
+//! let loader = ConfigLoader::<UartConfig>::new().with_file(BOARD_CONFIG_FILE);
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+
+use alloc::{
+    borrow::ToOwned,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{ffi::c_void, fmt, marker::PhantomData, ptr};
+
+use r_efi::efi;
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate as patina;
+use crate::{
+    BinaryGuid, Guid, OwnedGuid,
+    boot_services::{BootServices, StandardBootServices, protocol_handler::HandleSearchType},
+    component::{
+        IntoComponent,
+        hob::{FromHob, Hob},
+        params::ConfigMut,
+    },
+    error::{EfiError, Result},
+    pi::{fw_fs::ffs::section::raw_type, protocols::firmware_volume},
+};
+
+/// A [Config](crate::component::params::Config) type that can be loaded from a config description.
+pub trait ConfigDescription: DeserializeOwned + Default + 'static {
+    /// The name of the config in the config description.
+    const NAME: &'static str;
+
+    /// The current version of the config's schema.
+    ///
+    /// The version should be incremented whenever fields are added to the config. New fields should have a
+    /// `#[serde(default)]` so that descriptions written for an older version can still be loaded.
+    const VERSION: u32;
+
+    /// The oldest version of the config's schema that can still be loaded.
+    const MIN_VERSION: u32 = Self::VERSION;
+
+    /// Validates a config value loaded from a config description, returning an error for each invalid field.
+    fn validate(&self) -> Vec<FieldError> {
+        Vec::new()
+    }
+}
+
+/// An invalid field of a described config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// The path of the field in the config, such as `ports[1].base`. The path is `.` for the config itself.
+    pub field: String,
+    /// A description of why the field is invalid.
+    pub message: String,
+}
+
+impl FieldError {
+    /// Creates a new field error.
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { field: field.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// An error that occurred while loading a config from a config description.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigDescriptionError {
+    /// The config description is not a valid document.
+    Malformed(String),
+    /// The config is described with a version of its schema that is not supported.
+    UnsupportedVersion {
+        /// The name of the config.
+        name: &'static str,
+        /// The version the config is described with.
+        version: u32,
+        /// The oldest supported version.
+        min: u32,
+        /// The newest supported version.
+        max: u32,
+    },
+    /// One or more fields of the config are invalid.
+    InvalidFields {
+        /// The name of the config.
+        name: &'static str,
+        /// An error for each invalid field.
+        errors: Vec<FieldError>,
+    },
+}
+
+impl fmt::Display for ConfigDescriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(message) => write!(f, "malformed config description: {message}"),
+            Self::UnsupportedVersion { name, version, min, max } => {
+                write!(f, "config '{name}' is described with version {version}, expected version {min} to {max}")
+            }
+            Self::InvalidFields { name, errors } => {
+                write!(f, "config '{name}' has {} invalid field(s)", errors.len())?;
+                errors.iter().try_for_each(|error| write!(f, "\n  {error}"))
+            }
+        }
+    }
+}
+
+/// A single described config, before it is deserialized to its [ConfigDescription] type.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    version: u32,
+    config: serde_json::Value,
+}
+
+/// A parsed config description document.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigDescriptions(BTreeMap<String, Entry>);
+
+impl ConfigDescriptions {
+    /// Parses a config description document.
+    pub fn parse(bytes: &[u8]) -> core::result::Result<Self, ConfigDescriptionError> {
+        // Descriptions read from a firmware volume section may be padded to the section alignment.
+        let end = bytes.iter().rposition(|byte| !matches!(byte, 0 | 0xFF)).map_or(0, |last| last + 1);
+        serde_json::from_slice(&bytes[..end])
+            .map(Self)
+            .map_err(|error| ConfigDescriptionError::Malformed(error.to_string()))
+    }
+
+    /// Adds the configs described by `other`, replacing the configs that are described by both.
+    pub fn merge(&mut self, other: Self) {
+        self.0.extend(other.0);
+    }
+
+    /// Returns true if `name` is described.
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Loads the config `C`, returning `None` if it is not described.
+    pub fn load<C: ConfigDescription>(&self) -> core::result::Result<Option<C>, ConfigDescriptionError> {
+        let Some(entry) = self.0.get(C::NAME) else {
+            return Ok(None);
+        };
+
+        if !(C::MIN_VERSION..=C::VERSION).contains(&entry.version) {
+            return Err(ConfigDescriptionError::UnsupportedVersion {
+                name: C::NAME,
+                version: entry.version,
+                min: C::MIN_VERSION,
+                max: C::VERSION,
+            });
+        }
+
+        let config = serde_path_to_error::deserialize::<_, C>(entry.config.clone()).map_err(|error| {
+            let errors = vec![FieldError::new(error.path().to_string(), error.inner().to_string())];
+            ConfigDescriptionError::InvalidFields { name: C::NAME, errors }
+        })?;
+
+        match config.validate() {
+            errors if errors.is_empty() => Ok(Some(config)),
+            errors => Err(ConfigDescriptionError::InvalidFields { name: C::NAME, errors }),
+        }
+    }
+}
+
+/// The contents of a config description HOB.
+#[derive(Debug, Clone)]
+pub struct ConfigDescriptionHob(pub Vec<u8>);
+
+impl FromHob for ConfigDescriptionHob {
+    const HOB_GUID: OwnedGuid = Guid::from_string("3b5e7c1a-9d24-4f6b-8a13-c2d7e9f40b58");
+
+    fn parse(bytes: &[u8]) -> Self {
+        Self(bytes.to_owned())
+    }
+}
+
+/// A component that loads the config `C` from the config description, before any component can access it.
+///
+/// See the [module](self) documentation for more information.
+#[derive(IntoComponent)]
+pub struct ConfigLoader<C: ConfigDescription> {
+    file: Option<BinaryGuid>,
+    _config: PhantomData<fn() -> C>,
+}
+
+impl<C: ConfigDescription> Default for ConfigLoader<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: ConfigDescription> ConfigLoader<C> {
+    /// Creates a loader that loads `C` from config description HOBs.
+    pub fn new() -> Self {
+        Self { file: None, _config: PhantomData }
+    }
+
+    /// Also loads `C` from the raw section of the firmware volume file named `file`. Config description HOBs take
+    /// precedence over the file.
+    pub fn with_file(mut self, file: BinaryGuid) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    fn entry_point(
+        self,
+        hobs: Option<Hob<ConfigDescriptionHob>>,
+        boot_services: Option<StandardBootServices>,
+        mut config: ConfigMut<C>,
+    ) -> Result<()> {
+        let mut descriptions = ConfigDescriptions::default();
+
+        if let (Some(file), Some(boot_services)) = (self.file, boot_services) {
+            match read_raw_section(&boot_services, file.as_efi_guid()) {
+                Some(bytes) => descriptions.merge(parse_or_log(&bytes)?),
+                None => log::info!("Config description file {file:?} not found."),
+            }
+        }
+
+        for hob in hobs.iter().flat_map(|hobs| hobs.iter()) {
+            descriptions.merge(parse_or_log(&hob.0)?);
+        }
+
+        match descriptions.load::<C>() {
+            Ok(Some(value)) => {
+                log::info!("Loaded config '{}' from the config description.", C::NAME);
+                *config = value;
+            }
+            Ok(None) => log::debug!("Config '{}' is not described, keeping the platform value.", C::NAME),
+            Err(error) => {
+                log::error!("Failed to load {error}");
+                config.lock();
+                return Err(EfiError::InvalidParameter);
+            }
+        }
+
+        config.lock();
+        Ok(())
+    }
+}
+
+/// Parses a config description, logging it if it is malformed.
+fn parse_or_log(bytes: &[u8]) -> Result<ConfigDescriptions> {
+    ConfigDescriptions::parse(bytes).map_err(|error| {
+        log::error!("Failed to load {error}");
+        EfiError::InvalidParameter
+    })
+}
+
+/// Reads the raw section of `file` from the first firmware volume that contains it.
+fn read_raw_section(boot_services: &impl BootServices, file: &efi::Guid) -> Option<Vec<u8>> {
+    let handles =
+        boot_services.locate_handle_buffer(HandleSearchType::ByProtocol(&firmware_volume::PROTOCOL_GUID)).ok()?;
+    handles.iter().find_map(|handle| {
+        // SAFETY: The interface of the firmware volume protocol is a `firmware_volume::Protocol`.
+        let protocol = unsafe { boot_services.handle_protocol_unchecked(*handle, &firmware_volume::PROTOCOL_GUID) }
+            .ok()? as *const firmware_volume::Protocol;
+
+        let mut buffer: *mut c_void = ptr::null_mut();
+        let mut size = 0;
+        let mut authentication_status = 0;
+        // SAFETY: The protocol was just located, and ReadSection allocates `buffer` when it is null.
+        let status = unsafe {
+            ((*protocol).read_section)(
+                protocol,
+                file,
+                raw_type::RAW,
+                0,
+                &mut buffer,
+                &mut size,
+                &mut authentication_status,
+            )
+        };
+        if status.is_error() || buffer.is_null() {
+            return None;
+        }
+
+        // SAFETY: ReadSection returned a buffer of `size` bytes.
+        let bytes = unsafe { core::slice::from_raw_parts(buffer as *const u8, size) }.to_vec();
+        if let Err(status) = boot_services.free_pool(buffer as *mut u8) {
+            log::warn!("Failed to free config description buffer: {status:?}");
+        }
+        Some(bytes)
+    })
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::component::{Storage, params::Config};
+
+    #[derive(IntoComponent)]
+    struct UartConsumer;
+
+    impl UartConsumer {
+        fn entry_point(self, config: Config<UartConfig>) -> Result<()> {
+            assert_eq!(config.base, 1016);
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Default, PartialEq, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct UartConfig {
+        base: u16,
+        #[serde(default)]
+        baud_rate: u32,
+    }
+
+    impl ConfigDescription for UartConfig {
+        const NAME: &'static str = "uart";
+        const VERSION: u32 = 2;
+        const MIN_VERSION: u32 = 1;
+
+        fn validate(&self) -> Vec<FieldError> {
+            let mut errors = Vec::new();
+            if self.base == 0 {
+                errors.push(FieldError::new("base", "must not be zero"));
+            }
+            if ![0, 9600, 115200].contains(&self.baud_rate) {
+                errors.push(FieldError::new("baud_rate", "unsupported baud rate"));
+            }
+            errors
+        }
+    }
+
+    fn load(document: &str) -> core::result::Result<Option<UartConfig>, ConfigDescriptionError> {
+        ConfigDescriptions::parse(document.as_bytes())?.load::<UartConfig>()
+    }
+
+    #[test]
+    fn test_described_config_is_loaded() {
+        assert_eq!(
+            load(r#"{ "uart": { "version": 2, "config": { "base": 1016, "baud_rate": 9600 } } }"#),
+            Ok(Some(UartConfig { base: 1016, baud_rate: 9600 }))
+        );
+        assert_eq!(
+            load(r#"{ "uart": { "version": 1, "config": { "base": 760 } } }"#),
+            Ok(Some(UartConfig { base: 760, baud_rate: 0 }))
+        );
+        assert_eq!(load(r#"{ "other": { "version": 1, "config": {} } }"#), Ok(None));
+
+        // Padding of the firmware volume section is ignored.
+        let mut padded = br#"{ "uart": { "version": 1, "config": { "base": 1 } } }"#.to_vec();
+        padded.extend([0xFF; 4]);
+        assert!(ConfigDescriptions::parse(&padded).unwrap().contains("uart"));
+    }
+
+    #[test]
+    fn test_unsupported_version_is_rejected() {
+        assert_eq!(
+            load(r#"{ "uart": { "version": 3, "config": { "base": 1016 } } }"#),
+            Err(ConfigDescriptionError::UnsupportedVersion { name: "uart", version: 3, min: 1, max: 2 })
+        );
+    }
+
+    #[test]
+    fn test_errors_are_reported_per_field() {
+        let Err(ConfigDescriptionError::InvalidFields { name: "uart", errors }) =
+            load(r#"{ "uart": { "version": 2, "config": { "base": "COM1" } } }"#)
+        else {
+            panic!("expected a field error");
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "base");
+
+        assert_eq!(
+            load(r#"{ "uart": { "version": 2, "config": { "base": 0, "baud_rate": 1 } } }"#),
+            Err(ConfigDescriptionError::InvalidFields {
+                name: "uart",
+                errors: vec![
+                    FieldError::new("base", "must not be zero"),
+                    FieldError::new("baud_rate", "unsupported baud rate")
+                ],
+            })
+        );
+
+        assert!(matches!(load("{ uart"), Err(ConfigDescriptionError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_loader_replaces_platform_config_before_it_is_used() {
+        let mut storage = Storage::new();
+        storage.add_config(UartConfig { base: 1, baud_rate: 0 });
+        ConfigDescriptionHob::register(br#"{ "uart": { "version": 1, "config": { "base": 1016 } } }"#, &mut storage);
+
+        let mut loader = ConfigLoader::<UartConfig>::new().into_component();
+        loader.initialize(&mut storage);
+
+        let mut consumer = UartConsumer.into_component();
+        consumer.initialize(&mut storage);
+
+        assert_eq!(consumer.run(&mut storage), Ok(false));
+        assert_eq!(loader.run(&mut storage), Ok(true));
+        assert_eq!(consumer.run(&mut storage), Ok(true));
+    }
+}
@@ -0,0 +1,476 @@
+//! Component dispatch.
+//!
+//! A [Dispatcher] owns the components that have not been dispatched yet, and dispatches them against a [Storage]:
+//!
+//! - [order](Dispatcher::order) sorts the components according to their [Order] constraints and the services they
+//!   consume, rejecting constraints that contain a cycle. See the [order] module.
+//! - [dispatch](Dispatcher::dispatch) runs every component that is not waiting on a predecessor once. The components
+//!   are split into [batches](super::schedule::batches) of components with compatible access requirements, and each
+//!   batch is run by the configured [Executor]. Components that fail are handled according to their [FailurePolicy].
+//!   See the [failure](super::failure) module.
+//!
+//! The DXE core dispatches its components with a [Dispatcher], as does the host-based `ComponentHarness` of the
+//! [test](crate::test) module, so components are ordered, scheduled and failed the same way in both.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use patina::component::{IntoComponent, Storage, dispatch::Dispatcher, order::Order};
+//!
+//! #[derive(IntoComponent)]
+//! struct First;
+//!
+//! impl First {
+//!     fn entry_point(self) -> patina::error::Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[derive(IntoComponent)]
+//! struct Second;
+//!
+//! impl Second {
+//!     fn entry_point(self) -> patina::error::Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut storage = Storage::new();
+//! let mut dispatcher = Dispatcher::new();
+//! dispatcher.add_component(Second.into_component(), &mut storage);
+//! dispatcher.add_component(First.into_component(), &mut storage);
+//! dispatcher.add_order(Order::of::<Second>().after::<First>());
+//!
+//! dispatcher.order(&mut storage).unwrap();
+//! while dispatcher.dispatch(&mut storage).unwrap() {}
+//! assert_eq!(dispatcher.dispatched(), [core::any::type_name::<First>(), core::any::type_name::<Second>()]);
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+
+use alloc::{boxed::Box, collections::BTreeSet, vec::Vec};
+
+use crate::{
+    component::{
+        Component, Storage, UnsafeStorageCell,
+        failure::{FailureAction, FailurePolicy, FailureTracker},
+        order::{self, DispatchOrder, Order},
+        schedule::{self, Executor, Sequential},
+    },
+    error::{EfiError, Result},
+};
+
+/// Dispatches components according to their ordering constraints and failure policies.
+pub struct Dispatcher {
+    components: Vec<Box<dyn Component>>,
+    orders: Vec<Order>,
+    dispatch_order: DispatchOrder,
+    failures: FailureTracker,
+    executor: Box<dyn Executor>,
+    dispatched: Vec<&'static str>,
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dispatcher {
+    /// Creates a dispatcher without any components, that runs each batch with the [Sequential] executor.
+    pub fn new() -> Self {
+        Self {
+            components: Vec::new(),
+            orders: Vec::new(),
+            dispatch_order: DispatchOrder::default(),
+            failures: FailureTracker::default(),
+            executor: Box::new(Sequential),
+            dispatched: Vec::new(),
+        }
+    }
+
+    /// Initializes `component` against `storage` and adds it after every component added so far.
+    pub fn add_component(&mut self, component: Box<dyn Component>, storage: &mut Storage) {
+        self.insert_component(self.components.len(), component, storage);
+    }
+
+    /// Initializes `component` against `storage` and inserts it at `index` in the registration order.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `index` is greater than the number of components that have not been dispatched.
+    pub fn insert_component(&mut self, index: usize, mut component: Box<dyn Component>, storage: &mut Storage) {
+        component.initialize(storage);
+        self.components.insert(index, component);
+    }
+
+    /// Adds ordering constraints for a component, which are applied by [order](Self::order).
+    pub fn add_order(&mut self, order: Order) {
+        self.orders.push(order);
+    }
+
+    /// Sets the failure policy of the component named `name`, as reported by [MetaData::name](super::MetaData::name).
+    pub fn set_failure_policy(&mut self, name: &'static str, policy: FailurePolicy) {
+        self.failures.set_policy(name, policy);
+    }
+
+    /// Sets the executor used to run each batch of components.
+    pub fn set_executor(&mut self, executor: impl Executor + 'static) {
+        self.executor = Box::new(executor);
+    }
+
+    /// Sorts the components into dispatch order, rejecting ordering constraints that contain a cycle.
+    ///
+    /// Must be called after every component and ordering constraint has been added, and before the first call to
+    /// [dispatch](Self::dispatch). The components are left in registration order if an error is returned.
+    pub fn order(&mut self, storage: &mut Storage) -> Result<()> {
+        match order::order_components(&mut self.components, &self.orders, storage) {
+            Ok(dispatch_order) => {
+                self.dispatch_order = dispatch_order;
+                Ok(())
+            }
+            Err(cycle) => {
+                log::error!("Component ordering constraints contain a cycle between: {cycle:?}");
+                Err(EfiError::InvalidParameter)
+            }
+        }
+    }
+
+    /// Makes a single attempt at dispatching every component that is not waiting on a predecessor.
+    ///
+    /// Deferred commands are applied before each batch (and by [Sequential], before each component). Returns whether
+    /// any progress was made, in which case the caller should dispatch again, or the error of a component whose
+    /// [FailurePolicy] halts dispatch.
+    pub fn dispatch(&mut self, storage: &mut Storage) -> Result<bool> {
+        let len = self.components.len();
+        let pending = DispatchOrder::pending(&self.components);
+        let mut ready = Vec::new();
+        for (index, component) in self.components.iter().enumerate() {
+            let name = component.metadata().name();
+            match self.dispatch_order.waiting_on(name, &pending) {
+                Some(predecessor) => log::trace!("Dispatch Deferred: Id = [{name:?}] After = [{predecessor:?}]"),
+                None => ready.push(index),
+            }
+        }
+        let batches = schedule::batches(ready.iter().map(|index| self.components[*index].metadata()));
+
+        let mut finished = BTreeSet::new();
+        let mut retried = false;
+        let mut failed = Vec::new();
+        let mut halt = None;
+        for batch in batches {
+            let batch: BTreeSet<usize> = batch.into_iter().map(|index| ready[index]).collect();
+            storage.apply_deferred();
+
+            let (indices, mut components): (Vec<usize>, Vec<&mut dyn Component>) = self
+                .components
+                .iter_mut()
+                .enumerate()
+                .filter(|(index, _)| batch.contains(index))
+                .map(|(index, component)| (index, component.as_mut() as &mut dyn Component))
+                .unzip();
+            components
+                .iter()
+                .for_each(|component| log::trace!("Dispatch Start: Id = [{:?}]", component.metadata().name()));
+            // SAFETY: The components of a batch are pairwise compatible, and the dispatcher has exclusive access to
+            // the storage while dispatching.
+            let results = unsafe { self.executor.execute(UnsafeStorageCell::from(&mut *storage), &mut components) };
+            let components: Vec<(&'static str, bool, bool)> = components
+                .iter()
+                .map(|component| {
+                    let metadata = component.metadata();
+                    (metadata.name(), component.runs_once(), metadata.access().has_deferred())
+                })
+                .collect();
+
+            // Ok(true): Dispatchable and dispatched returning success
+            // Ok(false): Not dispatchable at this time.
+            // Err(e): Dispatchable and dispatched returning failure
+            for ((index, (name, runs_once, produces)), result) in indices.into_iter().zip(components).zip(results) {
+                match result {
+                    Ok(true) => {
+                        log::info!("Dispatched: Id = [{name:?}] Status = [Success]");
+                        self.dispatched.push(name);
+                        finished.insert(index);
+                    }
+                    Ok(false) => {}
+                    Err(err) => match self.failures.on_failure(name, err, !runs_once) {
+                        FailureAction::Retry => retried = true,
+                        FailureAction::Disable => {
+                            if produces {
+                                self.failures.failed_producer(name);
+                            }
+                            failed.push(name);
+                            finished.insert(index);
+                        }
+                        FailureAction::Halt => {
+                            halt = halt.or(Some(err));
+                            finished.insert(index);
+                        }
+                    },
+                }
+            }
+            if halt.is_some() {
+                break;
+            }
+        }
+        storage.apply_deferred();
+
+        let mut index = 0;
+        self.components.retain(|_| {
+            index += 1;
+            !finished.contains(&(index - 1))
+        });
+
+        if let Some(err) = halt {
+            log::error!("Halting dispatch due to a component failure.");
+            return Err(err);
+        }
+
+        for failed in failed {
+            let dependents = self.dispatch_order.dependents(failed);
+            self.components.retain(|component| {
+                let name = component.metadata().name();
+                if dependents.contains(name) {
+                    self.failures.disable(name, failed);
+                    return false;
+                }
+                true
+            });
+        }
+
+        Ok(retried || len != self.components.len())
+    }
+
+    /// Returns the components that have not been dispatched, in dispatch order.
+    pub fn components(&self) -> &[Box<dyn Component>] {
+        &self.components
+    }
+
+    /// Returns the names of the components that were dispatched successfully, in the order they were dispatched.
+    pub fn dispatched(&self) -> &[&'static str] {
+        &self.dispatched
+    }
+
+    /// Returns the name and error of every component that failed without being retried, in the order they failed.
+    pub fn failures(&self) -> &[(&'static str, EfiError)] {
+        self.failures.failed()
+    }
+
+    /// Logs the components that failed, and the components that were not dispatched along with what held each of them
+    /// back.
+    pub fn report(&self) {
+        self.failures.report();
+
+        if self.components.is_empty() {
+            return;
+        }
+
+        let name_len = "name".len();
+        let param_len = "failed_param".len();
+
+        let max_name_len = self.components.iter().map(|c| c.metadata().name().len()).max().unwrap_or(name_len);
+        let max_param_len = self
+            .components
+            .iter()
+            .map(|c| c.metadata().failed_param().map(|s| s.len()).unwrap_or(0))
+            .max()
+            .unwrap_or(param_len);
+
+        log::warn!("Components not dispatched:");
+        log::warn!("{:-<max_name_len$} {:-<max_param_len$}", "", "");
+        log::warn!("{:<max_name_len$} {:<max_param_len$}", "name", "failed_param");
+
+        for component in &self.components {
+            let metadata = component.metadata();
+            log::warn!("{:<max_name_len$} {:<max_param_len$}", metadata.name(), metadata.failed_param().unwrap_or(""));
+        }
+
+        let pending = DispatchOrder::pending(&self.components);
+        for component in &self.components {
+            let name = component.metadata().name();
+            match self.dispatch_order.waiting_on(name, &pending).map(|pred| (pred, self.failures.failure_of(pred))) {
+                Some((predecessor, Some((failed, err)))) => {
+                    log::warn!("{name} is ordered after {predecessor}, which was blocked by {failed} ({err:?}).")
+                }
+                Some((predecessor, None)) => {
+                    log::warn!("{name} is ordered after {predecessor}, which was not dispatched.")
+                }
+                None => {
+                    if let Some(param) = component.metadata().failed_param().filter(|param| is_service_param(param)) {
+                        for (failed, err) in self.failures.failed_producers() {
+                            log::warn!("{name} is waiting on {param}, which {failed} may have produced ({err:?}).");
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns whether the parameter named `param` is a [Service](crate::component::service::Service).
+fn is_service_param(param: &str) -> bool {
+    let service = core::any::type_name::<crate::component::service::Service<()>>();
+    service
+        .strip_suffix("()>")
+        .is_some_and(|prefix| param.strip_prefix(prefix).is_some_and(|service| service.ends_with('>')))
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate as patina;
+    use crate::component::{
+        IntoComponent,
+        params::Commands,
+        service::{IntoService, Service},
+    };
+
+    trait Greeting {}
+
+    #[derive(IntoService)]
+    #[service(dyn Greeting)]
+    struct Hello;
+
+    impl Greeting for Hello {}
+
+    #[derive(IntoComponent)]
+    struct Producer;
+
+    impl Producer {
+        fn entry_point(self, mut commands: Commands) -> patina::error::Result<()> {
+            commands.add_service(Hello);
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct Consumer;
+
+    impl Consumer {
+        fn entry_point(self, _greeting: Service<dyn Greeting>) -> patina::error::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct Failing;
+
+    impl Failing {
+        fn entry_point(self) -> patina::error::Result<()> {
+            Err(EfiError::DeviceError)
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct FailingProducer;
+
+    impl FailingProducer {
+        fn entry_point(self, _commands: Commands) -> patina::error::Result<()> {
+            Err(EfiError::DeviceError)
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct AfterFailing;
+
+    impl AfterFailing {
+        fn entry_point(self) -> patina::error::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn name<C>() -> &'static str {
+        core::any::type_name::<C>()
+    }
+
+    #[test]
+    fn test_dispatch_follows_the_computed_order() {
+        let mut storage = Storage::new();
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.add_component(Consumer.into_component(), &mut storage);
+        dispatcher.add_component(Producer.into_component(), &mut storage);
+        dispatcher.add_order(Order::of::<Producer>().produces::<dyn Greeting>());
+
+        dispatcher.order(&mut storage).unwrap();
+        assert_eq!(dispatcher.components()[0].metadata().name(), name::<Producer>());
+        while dispatcher.dispatch(&mut storage).unwrap() {}
+
+        assert_eq!(dispatcher.dispatched(), [name::<Producer>(), name::<Consumer>()]);
+        assert!(dispatcher.components().is_empty());
+        dispatcher.report();
+    }
+
+    #[test]
+    fn test_dispatch_disables_the_dependents_of_failed_components() {
+        let mut storage = Storage::new();
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.add_component(AfterFailing.into_component(), &mut storage);
+        dispatcher.add_component(Failing.into_component(), &mut storage);
+        dispatcher.add_order(Order::of::<AfterFailing>().after::<Failing>());
+
+        dispatcher.order(&mut storage).unwrap();
+        while dispatcher.dispatch(&mut storage).unwrap() {}
+
+        assert!(dispatcher.dispatched().is_empty());
+        assert_eq!(dispatcher.failures(), [(name::<Failing>(), EfiError::DeviceError)]);
+        assert!(dispatcher.components().is_empty());
+    }
+
+    #[test]
+    fn test_service_consumers_are_tied_to_failed_producers() {
+        let mut storage = Storage::new();
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.add_component(Consumer.into_component(), &mut storage);
+        dispatcher.add_component(FailingProducer.into_component(), &mut storage);
+        dispatcher.add_component(Failing.into_component(), &mut storage);
+
+        dispatcher.order(&mut storage).unwrap();
+        while dispatcher.dispatch(&mut storage).unwrap() {}
+
+        // No producer was declared, so the consumer is not disabled, but it is reported against the failed
+        // component that could have produced its service.
+        let consumer = dispatcher.components()[0].metadata();
+        assert_eq!(consumer.name(), name::<Consumer>());
+        assert!(consumer.failed_param().is_some_and(is_service_param));
+        assert_eq!(
+            dispatcher.failures.failed_producers().collect::<Vec<_>>(),
+            [(name::<FailingProducer>(), EfiError::DeviceError)]
+        );
+        assert!(!is_service_param(core::any::type_name::<Commands>()));
+        dispatcher.report();
+    }
+
+    #[test]
+    fn test_dispatch_halts_on_components_with_the_halt_policy() {
+        let mut storage = Storage::new();
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.add_component(Failing.into_component(), &mut storage);
+        dispatcher.insert_component(0, AfterFailing.into_component(), &mut storage);
+        dispatcher.set_failure_policy(name::<Failing>(), FailurePolicy::Halt);
+
+        dispatcher.order(&mut storage).unwrap();
+        assert_eq!(dispatcher.dispatch(&mut storage), Err(EfiError::DeviceError));
+        assert_eq!(dispatcher.dispatched(), [name::<AfterFailing>()]);
+    }
+
+    #[test]
+    fn test_order_rejects_cycles() {
+        let mut storage = Storage::new();
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.add_component(Failing.into_component(), &mut storage);
+        dispatcher.add_component(AfterFailing.into_component(), &mut storage);
+        dispatcher.add_order(Order::of::<AfterFailing>().after::<Failing>());
+        dispatcher.add_order(Order::of::<Failing>().after::<AfterFailing>());
+
+        assert_eq!(dispatcher.order(&mut storage), Err(EfiError::InvalidParameter));
+        assert_eq!(dispatcher.components()[0].metadata().name(), name::<Failing>());
+    }
+}
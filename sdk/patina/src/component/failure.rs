@@ -0,0 +1,190 @@
+//! Component failure policies.
+//!
+//! A component fails when its entry point returns an error. The [FailurePolicy] registered for a component decides
+//! what the [Dispatcher](super::dispatch::Dispatcher) does next. Components without a registered policy use
+//! [FailurePolicy::DisableDependents]. The dispatcher also keeps track of failed components and of the components
+//! disabled because of them, so that its report can tie every undispatched component back to the failure that blocked
+//! it. A component waiting on a [Service](super::service::Service) that no component declared to produce is not ordered
+//! after anything, so it cannot be disabled; the report ties it to the failed components that could have produced the
+//! service (those with a [Commands](super::params::Commands) parameter) instead.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::error::EfiError;
+
+/// What the dispatcher does when a component's entry point returns an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailurePolicy {
+    /// The component is dispatched again on the next dispatch iteration, up to `attempts` more times. Once all
+    /// attempts have failed, [DisableDependents](FailurePolicy::DisableDependents) is applied.
+    ///
+    /// Only components whose entry point takes `&self` or `&mut self` can be retried. A component whose entry point
+    /// consumes `self` is treated as if its attempts were exhausted.
+    Retry {
+        /// The number of additional attempts.
+        attempts: u32,
+    },
+    /// The component is marked as failed, and every component ordered after it (see the
+    /// [order](super::order) module) is disabled rather than waiting forever.
+    #[default]
+    DisableDependents,
+    /// Dispatch is halted, returning the component's error. The core fails boot with it.
+    Halt,
+}
+
+/// What the dispatcher must do with a component that just failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FailureAction {
+    /// Keep the component, so that it is dispatched again.
+    Retry,
+    /// Drop the component and disable its dependents.
+    Disable,
+    /// Halt boot with the component's error.
+    Halt,
+}
+
+/// The failure policies and failure history of the registered components.
+#[derive(Debug, Default)]
+pub(crate) struct FailureTracker {
+    policies: BTreeMap<&'static str, FailurePolicy>,
+    attempts: BTreeMap<&'static str, u32>,
+    failed: Vec<(&'static str, EfiError)>,
+    disabled: Vec<(&'static str, &'static str)>,
+    producers: Vec<&'static str>,
+}
+
+impl FailureTracker {
+    /// Sets the failure policy for the component named `name`.
+    pub(crate) fn set_policy(&mut self, name: &'static str, policy: FailurePolicy) {
+        self.policies.insert(name, policy);
+    }
+
+    /// Records that the component named `name` failed with `err`, and returns what to do with it. `retryable` is
+    /// whether the component can be run again.
+    pub(crate) fn on_failure(&mut self, name: &'static str, err: EfiError, retryable: bool) -> FailureAction {
+        let action = match self.policies.get(name).copied().unwrap_or_default() {
+            FailurePolicy::Retry { attempts } if retryable => {
+                let attempt = self.attempts.entry(name).or_insert(0);
+                *attempt += 1;
+                if *attempt <= attempts {
+                    log::warn!(
+                        "Dispatched: Id = [{name:?}] Status = [Failed] Error = [{err:?}] Retry = [{attempt}/{attempts}]"
+                    );
+                    return FailureAction::Retry;
+                }
+                FailureAction::Disable
+            }
+            FailurePolicy::Retry { .. } | FailurePolicy::DisableDependents => FailureAction::Disable,
+            FailurePolicy::Halt => FailureAction::Halt,
+        };
+        log::error!("Dispatched: Id = [{name:?}] Status = [Failed] Error = [{err:?}] Action = [{action:?}]");
+        self.failed.push((name, err));
+        action
+    }
+
+    /// Records that the component named `name` was disabled because `failed` failed.
+    pub(crate) fn disable(&mut self, name: &'static str, failed: &'static str) {
+        log::error!("Disabled: Id = [{name:?}] Failed Dependency = [{failed:?}]");
+        self.disabled.push((name, failed));
+    }
+
+    /// Records that the failed component named `name` could have produced services.
+    pub(crate) fn failed_producer(&mut self, name: &'static str) {
+        self.producers.push(name);
+    }
+
+    /// Returns the name and error of every failed component that could have produced services.
+    pub(crate) fn failed_producers(&self) -> impl Iterator<Item = (&'static str, EfiError)> + '_ {
+        self.failed.iter().filter(|(failed, _)| self.producers.contains(failed)).copied()
+    }
+
+    /// Returns the name and error of every component that failed without being retried, in the order they failed.
+    pub(crate) fn failed(&self) -> &[(&'static str, EfiError)] {
+        &self.failed
+    }
+
+    /// Returns the failure that `name` was disabled for, or its own failure, if any.
+    pub(crate) fn failure_of(&self, name: &str) -> Option<(&'static str, EfiError)> {
+        let root = self.disabled.iter().find(|(disabled, _)| *disabled == name).map_or(name, |(_, failed)| *failed);
+        self.failed.iter().find(|(failed, _)| *failed == root).copied()
+    }
+
+    /// Logs every failed component and every component disabled because of a failure.
+    pub(crate) fn report(&self) {
+        if self.failed.is_empty() {
+            return;
+        }
+        log::warn!("Components failed:");
+        for (name, err) in &self.failed {
+            log::warn!("  {name} returned {err:?}");
+        }
+        for (name, failed) in &self.disabled {
+            log::warn!("  {name} was disabled because {failed} failed");
+        }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_components_are_disabled_by_default() {
+        let mut tracker = FailureTracker::default();
+        assert_eq!(tracker.on_failure("a", EfiError::Aborted, true), FailureAction::Disable);
+        assert_eq!(tracker.failure_of("a"), Some(("a", EfiError::Aborted)));
+        assert_eq!(tracker.failure_of("b"), None);
+    }
+
+    #[test]
+    fn test_retry_policy_retries_until_attempts_are_exhausted() {
+        let mut tracker = FailureTracker::default();
+        tracker.set_policy("a", FailurePolicy::Retry { attempts: 2 });
+
+        assert_eq!(tracker.on_failure("a", EfiError::NotReady, true), FailureAction::Retry);
+        assert_eq!(tracker.on_failure("a", EfiError::NotReady, true), FailureAction::Retry);
+        assert_eq!(tracker.failure_of("a"), None);
+        assert_eq!(tracker.on_failure("a", EfiError::NotReady, true), FailureAction::Disable);
+        assert_eq!(tracker.failure_of("a"), Some(("a", EfiError::NotReady)));
+    }
+
+    #[test]
+    fn test_retry_policy_does_not_retry_components_that_run_once() {
+        let mut tracker = FailureTracker::default();
+        tracker.set_policy("a", FailurePolicy::Retry { attempts: 2 });
+        assert_eq!(tracker.on_failure("a", EfiError::NotReady, false), FailureAction::Disable);
+    }
+
+    #[test]
+    fn test_halt_policy_halts() {
+        let mut tracker = FailureTracker::default();
+        tracker.set_policy("a", FailurePolicy::Halt);
+        assert_eq!(tracker.on_failure("a", EfiError::DeviceError, true), FailureAction::Halt);
+    }
+
+    #[test]
+    fn test_disabled_components_report_the_root_failure() {
+        let mut tracker = FailureTracker::default();
+        tracker.on_failure("a", EfiError::Unsupported, false);
+        tracker.disable("b", "a");
+        assert_eq!(tracker.failure_of("b"), Some(("a", EfiError::Unsupported)));
+    }
+
+    #[test]
+    fn test_only_failed_producers_are_reported_as_producers() {
+        let mut tracker = FailureTracker::default();
+        tracker.on_failure("a", EfiError::Unsupported, false);
+        tracker.on_failure("b", EfiError::DeviceError, false);
+        tracker.failed_producer("b");
+        assert_eq!(tracker.failed_producers().collect::<Vec<_>>(), [("b", EfiError::DeviceError)]);
+    }
+}
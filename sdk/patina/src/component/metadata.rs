@@ -47,6 +47,20 @@ impl MetaData {
         self.last_failed_param
     }
 
+    /// Returns whether the component consumes the service denoted by `id` through a
+    /// [Service](super::service::Service) parameter.
+    #[inline(always)]
+    pub fn consumes_service(&self, id: usize) -> bool {
+        self.access.has_service_read(id)
+    }
+
+    /// Returns whether the component can run at the same time as the component described by `other`, without either
+    /// of them having conflicting access to a resource.
+    #[inline(always)]
+    pub fn is_compatible(&self, other: &MetaData) -> bool {
+        self.access.is_compatible(&other.access)
+    }
+
     /// Returns mutable access to the param usage metadata for the component.
     #[inline(always)]
     pub(crate) fn access_mut(&mut self) -> &mut Access {
@@ -73,6 +87,8 @@ pub struct Access {
     writes_all_configs: bool,
     /// is `true` if the component accesses the deferred queue.
     has_deferred: bool,
+    /// All accesses to a service.
+    service_reads: FixedBitSet,
 }
 
 impl Access {
@@ -84,6 +100,7 @@ impl Access {
             reads_all_configs: false,
             writes_all_configs: false,
             has_deferred: false,
+            service_reads: FixedBitSet::new(),
         }
     }
 }
@@ -150,6 +167,36 @@ impl Access {
     pub fn deferred(&mut self) {
         self.has_deferred = true;
     }
+
+    /// Registers a read access to the service denoted by `id`.
+    pub fn add_service_read(&mut self, id: usize) {
+        self.service_reads.grow_and_insert(id);
+    }
+
+    /// Returns whether the component consumes the service denoted by `id`.
+    pub fn has_service_read(&self, id: usize) -> bool {
+        self.service_reads.contains(id)
+    }
+
+    /// Returns whether a component with these access requirements can run at the same time as a component with the
+    /// `other` access requirements. Two components conflict if one writes a config resource the other accesses, if
+    /// either has mutable access to all resources, or if both register deferred actions. Service reads never conflict.
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        if self.writes_all_configs || other.writes_all_configs {
+            return false;
+        }
+        if self.has_deferred && other.has_deferred {
+            return false;
+        }
+        if self.reads_all_configs {
+            return !other.has_any_config_write();
+        }
+        if other.reads_all_configs {
+            return !self.has_any_config_write();
+        }
+        self.config_writes.is_disjoint(&other.config_read_and_writes)
+            && other.config_writes.is_disjoint(&self.config_read_and_writes)
+    }
 }
 
 impl fmt::Debug for Access {
@@ -162,6 +209,7 @@ impl fmt::Debug for Access {
                 "config_reads",
                 &PrettyFixedBitSet(&self.config_read_and_writes.difference(&self.config_writes).collect()),
             )
+            .field("service_reads", &PrettyFixedBitSet(&self.service_reads))
             .finish()
     }
 }
@@ -191,7 +239,7 @@ mod tests {
 
         assert_eq!(
             std::format!("{access:?}"),
-            "Access { reads_all_configs: true, writes_all_configs: true, config_writes: [0], config_reads: [1] }"
+            "Access { reads_all_configs: true, writes_all_configs: true, config_writes: [0], config_reads: [1], service_reads: [] }"
         );
     }
 
@@ -254,4 +302,53 @@ mod tests {
         assert!(access.has_any_config_read());
         assert!(access.has_any_config_write());
     }
+
+    #[test]
+    fn test_service_read_is_tracked_per_service() {
+        let mut access = Access::new();
+        access.add_service_read(3);
+
+        assert!(access.has_service_read(3));
+        assert!(!access.has_service_read(0));
+        assert!(!access.has_any_config_read());
+    }
+
+    #[test]
+    fn test_config_access_compatibility() {
+        let mut reader = Access::new();
+        reader.add_config_read(0);
+        let mut other_reader = Access::new();
+        other_reader.add_config_read(0);
+        let mut writer = Access::new();
+        writer.add_config_write(0);
+        let mut other_writer = Access::new();
+        other_writer.add_config_write(1);
+
+        assert!(reader.is_compatible(&other_reader));
+        assert!(!reader.is_compatible(&writer));
+        assert!(!writer.is_compatible(&reader));
+        assert!(writer.is_compatible(&other_writer));
+        assert!(!writer.is_compatible(&writer));
+    }
+
+    #[test]
+    fn test_global_and_deferred_access_compatibility() {
+        let mut writer = Access::new();
+        writer.add_config_write(0);
+        let mut reads_all = Access::new();
+        reads_all.reads_all_configs();
+        let mut writes_all = Access::new();
+        writes_all.writes_all_configs();
+        let mut deferred = Access::new();
+        deferred.deferred();
+        let mut service = Access::new();
+        service.add_service_read(0);
+
+        assert!(!reads_all.is_compatible(&writer));
+        assert!(reads_all.is_compatible(&reads_all));
+        assert!(!writes_all.is_compatible(&Access::new()));
+        assert!(!deferred.is_compatible(&deferred));
+        assert!(deferred.is_compatible(&writer));
+        assert!(service.is_compatible(&service));
+    }
 }
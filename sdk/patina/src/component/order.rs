@@ -0,0 +1,421 @@
+//! Component ordering constraints.
+//!
+//! By default, components are dispatched in the order they were registered with the core, with each component
+//! waiting until all of its [Params](super::params::Param) are available. An [Order] declares explicit constraints on
+//! top of this, so that the dispatch order does not depend on the order of registration:
+//!
+//! - [after](Order::after) / [before](Order::before) order a component relative to another component type.
+//! - [after_service](Order::after_service) / [before_service](Order::before_service) order a component relative to
+//!   the component(s) that produce a service.
+//! - [produces](Order::produces) declares that a component produces a service. Every component that consumes the
+//!   service through a [Service](super::service::Service) parameter is then ordered after it.
+//!
+//! The [Dispatcher](super::dispatch::Dispatcher) builds the dispatch graph from these constraints and from the
+//! [MetaData] of each component before dispatching any of them, rejects cycles, and reports the computed order. The
+//! components are sorted so that every component comes after its predecessors, keeping the registration order wherever
+//! the graph does not constrain it. A component is not dispatched until every component it is ordered after has been
+//! dispatched.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use patina::component::order::Order;
+//!
+//! struct Producer;
+//! struct Consumer;
+//! trait MyService {}
+//!
+//! let orders = [
+//!     Order::of::<Producer>().produces::<dyn MyService>(),
+//!     Order::of::<Consumer>().after::<Producer>(),
+//! ];
+//! assert_eq!(orders[1].component(), core::any::type_name::<Consumer>());
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+use core::any::TypeId;
+
+use crate::component::{Component, MetaData, Storage};
+
+/// Something a component can be ordered relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dependency {
+    /// A component, by the type name of the struct or enum it was created from.
+    Component(&'static str),
+    /// The component(s) that produce a service.
+    Service {
+        /// The id of the service type.
+        id: TypeId,
+        /// The name of the service type.
+        name: &'static str,
+    },
+}
+
+impl Dependency {
+    /// Returns the dependency on the component created from `C`.
+    pub fn component<C: 'static>() -> Self {
+        Dependency::Component(core::any::type_name::<C>())
+    }
+
+    /// Returns the dependency on the producers of the service `S`.
+    pub fn service<S: ?Sized + 'static>() -> Self {
+        Dependency::Service { id: TypeId::of::<S>(), name: core::any::type_name::<S>() }
+    }
+}
+
+/// Ordering constraints for a single component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Order {
+    component: &'static str,
+    after: Vec<Dependency>,
+    before: Vec<Dependency>,
+    produces: Vec<Dependency>,
+}
+
+impl Order {
+    /// Creates an empty set of constraints for the component created from `C`.
+    pub fn of<C: 'static>() -> Self {
+        Self { component: core::any::type_name::<C>(), after: Vec::new(), before: Vec::new(), produces: Vec::new() }
+    }
+
+    /// The component must not be dispatched until the component created from `C` has been dispatched.
+    pub fn after<C: 'static>(mut self) -> Self {
+        self.after.push(Dependency::component::<C>());
+        self
+    }
+
+    /// The component created from `C` must not be dispatched until this component has been dispatched.
+    pub fn before<C: 'static>(mut self) -> Self {
+        self.before.push(Dependency::component::<C>());
+        self
+    }
+
+    /// The component must not be dispatched until every producer of the service `S` has been dispatched.
+    pub fn after_service<S: ?Sized + 'static>(mut self) -> Self {
+        self.after.push(Dependency::service::<S>());
+        self
+    }
+
+    /// No producer of the service `S` may be dispatched until this component has been dispatched.
+    pub fn before_service<S: ?Sized + 'static>(mut self) -> Self {
+        self.before.push(Dependency::service::<S>());
+        self
+    }
+
+    /// The component produces the service `S`.
+    pub fn produces<S: ?Sized + 'static>(mut self) -> Self {
+        self.produces.push(Dependency::service::<S>());
+        self
+    }
+
+    /// Returns the type name of the component these constraints apply to, as reported by
+    /// [MetaData::name](super::MetaData::name).
+    pub fn component(&self) -> &'static str {
+        self.component
+    }
+
+    /// Returns what the component must be dispatched after.
+    pub fn after_dependencies(&self) -> &[Dependency] {
+        &self.after
+    }
+
+    /// Returns what the component must be dispatched before.
+    pub fn before_dependencies(&self) -> &[Dependency] {
+        &self.before
+    }
+
+    /// Returns the services the component produces.
+    pub fn produced_services(&self) -> &[Dependency] {
+        &self.produces
+    }
+}
+
+/// The dispatch constraints computed by [order_components].
+#[derive(Debug, Default)]
+pub(crate) struct DispatchOrder {
+    /// For each constrained component, the components that must be dispatched before it.
+    predecessors: BTreeMap<&'static str, BTreeSet<&'static str>>,
+}
+
+impl DispatchOrder {
+    /// Returns the number of components of each name in `components`.
+    pub(crate) fn pending(components: &[Box<dyn Component>]) -> BTreeMap<&'static str, usize> {
+        let mut pending = BTreeMap::new();
+        for component in components {
+            *pending.entry(component.metadata().name()).or_insert(0) += 1;
+        }
+        pending
+    }
+
+    /// Returns every component that is ordered after `name`, directly or transitively.
+    pub(crate) fn dependents(&self, name: &'static str) -> BTreeSet<&'static str> {
+        let mut dependents = BTreeSet::new();
+        let mut queue = alloc::vec![name];
+        while let Some(current) = queue.pop() {
+            for (component, preds) in &self.predecessors {
+                if preds.contains(current) && dependents.insert(*component) {
+                    queue.push(component);
+                }
+            }
+        }
+        dependents
+    }
+
+    /// Returns the first predecessor of `name` that is still `pending` dispatch, if any.
+    pub(crate) fn waiting_on(&self, name: &str, pending: &BTreeMap<&'static str, usize>) -> Option<&'static str> {
+        self.predecessors.get(name)?.iter().find(|pred| pending.get(*pred).is_some_and(|count| *count > 0)).copied()
+    }
+}
+
+/// Sorts `components` into dispatch order according to `orders` and returns the computed constraints.
+///
+/// Returns the names of the components that form a cycle if the constraints cannot be satisfied, in which case
+/// `components` is left unchanged.
+pub(crate) fn order_components(
+    components: &mut Vec<Box<dyn Component>>,
+    orders: &[Order],
+    storage: &mut Storage,
+) -> Result<DispatchOrder, Vec<&'static str>> {
+    let names: Vec<&'static str> = components.iter().map(|component| component.metadata().name()).collect();
+    let mut indices: BTreeMap<&'static str, Vec<usize>> = BTreeMap::new();
+    for (index, name) in names.iter().enumerate() {
+        indices.entry(name).or_default().push(index);
+    }
+    let component_indices = |name: &'static str| -> &[usize] {
+        let found = indices.get(name).map(Vec::as_slice).unwrap_or_default();
+        if found.is_empty() {
+            log::warn!("Component ordering constraint references {name}, which is not registered.");
+        }
+        found
+    };
+
+    let mut producers: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+    for order in orders {
+        for service in order.produced_services() {
+            let Dependency::Service { id, .. } = service else { continue };
+            let id = storage.get_or_register_service(*id);
+            producers.entry(id).or_default().extend(component_indices(order.component()));
+        }
+    }
+    let mut resolve = |dependency: &Dependency| -> Vec<usize> {
+        match dependency {
+            Dependency::Component(name) => component_indices(name).to_vec(),
+            Dependency::Service { id, name } => {
+                let id = storage.get_or_register_service(*id);
+                let found: Vec<usize> = producers.get(&id).into_iter().flatten().copied().collect();
+                if found.is_empty() {
+                    log::warn!("Component ordering constraint references service {name}, which has no producer.");
+                }
+                found
+            }
+        }
+    };
+
+    let mut predecessors: Vec<BTreeSet<usize>> = alloc::vec![BTreeSet::new(); components.len()];
+    for order in orders {
+        let constrained = component_indices(order.component());
+        for dependency in order.after_dependencies() {
+            for target in resolve(dependency) {
+                constrained.iter().for_each(|index| _ = predecessors[*index].insert(target));
+            }
+        }
+        for dependency in order.before_dependencies() {
+            for target in resolve(dependency) {
+                predecessors[target].extend(constrained);
+            }
+        }
+    }
+    for (id, service_producers) in &producers {
+        for (index, component) in components.iter().enumerate() {
+            if component.metadata().consumes_service(*id) && !service_producers.contains(&index) {
+                predecessors[index].extend(service_producers);
+            }
+        }
+    }
+
+    // Kahn's algorithm, always picking the earliest registered component that is ready so that unconstrained
+    // components keep their registration order.
+    let mut successors: Vec<Vec<usize>> = alloc::vec![Vec::new(); components.len()];
+    let mut in_degree: Vec<usize> = predecessors.iter().map(BTreeSet::len).collect();
+    for (index, preds) in predecessors.iter().enumerate() {
+        preds.iter().for_each(|pred| successors[*pred].push(index));
+    }
+    let mut ready: BTreeSet<usize> = (0..components.len()).filter(|index| in_degree[*index] == 0).collect();
+    let mut sorted = Vec::with_capacity(components.len());
+    while let Some(index) = ready.pop_first() {
+        sorted.push(index);
+        for successor in &successors[index] {
+            in_degree[*successor] -= 1;
+            if in_degree[*successor] == 0 {
+                ready.insert(*successor);
+            }
+        }
+    }
+    if sorted.len() != components.len() {
+        return Err((0..components.len()).filter(|index| in_degree[*index] > 0).map(|index| names[index]).collect());
+    }
+
+    let mut slots: Vec<Option<Box<dyn Component>>> = components.drain(..).map(Some).collect();
+    components.extend(sorted.iter().map(|index| slots[*index].take().expect("Each component is sorted once.")));
+
+    let mut order = DispatchOrder::default();
+    for (index, preds) in predecessors.iter().enumerate() {
+        if !preds.is_empty() {
+            order.predecessors.entry(names[index]).or_default().extend(preds.iter().map(|pred| names[*pred]));
+        }
+    }
+    report(components.iter().map(|component| component.metadata()), &order);
+    Ok(order)
+}
+
+fn report<'a>(components: impl Iterator<Item = &'a MetaData>, order: &DispatchOrder) {
+    log::info!("Component dispatch order:");
+    for (index, metadata) in components.enumerate() {
+        match order.predecessors.get(metadata.name()) {
+            Some(preds) => log::info!("  {index:>3}: {} (after {preds:?})", metadata.name()),
+            None => log::info!("  {index:>3}: {}", metadata.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate as patina;
+    use crate::component::{IntoComponent, service::Service};
+    use alloc::vec;
+
+    trait S {}
+    trait TestService {}
+
+    #[derive(IntoComponent)]
+    struct A;
+
+    impl A {
+        fn entry_point(self) -> patina::error::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct B;
+
+    impl B {
+        fn entry_point(self) -> patina::error::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct Consumer;
+
+    impl Consumer {
+        fn entry_point(self, _service: Service<dyn TestService>) -> patina::error::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn initialized(storage: &mut Storage, components: Vec<Box<dyn Component>>) -> Vec<Box<dyn Component>> {
+        components
+            .into_iter()
+            .map(|mut component| {
+                component.initialize(storage);
+                component
+            })
+            .collect()
+    }
+
+    fn names(components: &[Box<dyn Component>]) -> Vec<&'static str> {
+        components.iter().map(|component| component.metadata().name()).collect()
+    }
+
+    #[test]
+    fn test_order_records_constraints() {
+        let order = Order::of::<A>().after::<B>().before_service::<dyn S>().produces::<u32>();
+
+        assert_eq!(order.component(), core::any::type_name::<A>());
+        assert_eq!(order.after_dependencies(), &[Dependency::Component(core::any::type_name::<B>())]);
+        assert_eq!(
+            order.before_dependencies(),
+            &[Dependency::Service { id: TypeId::of::<dyn S>(), name: core::any::type_name::<dyn S>() }]
+        );
+        assert_eq!(order.produced_services(), &[Dependency::service::<u32>()]);
+    }
+
+    #[test]
+    fn test_unconstrained_components_keep_registration_order() {
+        let mut storage = Storage::new();
+        let mut components = initialized(&mut storage, vec![B.into_component(), A.into_component()]);
+
+        let order = order_components(&mut components, &[], &mut storage).unwrap();
+        assert_eq!(names(&components), [core::any::type_name::<B>(), core::any::type_name::<A>()]);
+        assert!(order.predecessors.is_empty());
+    }
+
+    #[test]
+    fn test_after_and_before_constraints_reorder_components() {
+        let mut storage = Storage::new();
+        let mut components =
+            initialized(&mut storage, vec![Consumer.into_component(), B.into_component(), A.into_component()]);
+
+        let orders = [Order::of::<B>().after::<A>(), Order::of::<A>().before::<Consumer>()];
+        order_components(&mut components, &orders, &mut storage).unwrap();
+        assert_eq!(
+            names(&components),
+            [core::any::type_name::<A>(), core::any::type_name::<Consumer>(), core::any::type_name::<B>()]
+        );
+    }
+
+    #[test]
+    fn test_service_consumers_are_ordered_after_declared_producers() {
+        let mut storage = Storage::new();
+        let mut components =
+            initialized(&mut storage, vec![Consumer.into_component(), B.into_component(), A.into_component()]);
+
+        let orders =
+            [Order::of::<A>().produces::<dyn TestService>(), Order::of::<B>().after_service::<dyn TestService>()];
+        let order = order_components(&mut components, &orders, &mut storage).unwrap();
+        assert_eq!(
+            names(&components),
+            [core::any::type_name::<A>(), core::any::type_name::<Consumer>(), core::any::type_name::<B>()]
+        );
+
+        assert_eq!(
+            order.dependents(core::any::type_name::<A>()),
+            BTreeSet::from([core::any::type_name::<Consumer>(), core::any::type_name::<B>()])
+        );
+
+        let mut pending = DispatchOrder::pending(&components);
+        assert_eq!(order.waiting_on(core::any::type_name::<Consumer>(), &pending), Some(core::any::type_name::<A>()));
+        pending.insert(core::any::type_name::<A>(), 0);
+        assert_eq!(order.waiting_on(core::any::type_name::<Consumer>(), &pending), None);
+    }
+
+    #[test]
+    fn test_cycles_are_rejected() {
+        let mut storage = Storage::new();
+        let mut components =
+            initialized(&mut storage, vec![A.into_component(), B.into_component(), Consumer.into_component()]);
+
+        let orders = [Order::of::<A>().after::<B>(), Order::of::<B>().after::<A>()];
+        let cycle = order_components(&mut components, &orders, &mut storage).unwrap_err();
+        assert_eq!(cycle, [core::any::type_name::<A>(), core::any::type_name::<B>()]);
+        assert_eq!(
+            names(&components),
+            [core::any::type_name::<A>(), core::any::type_name::<B>(), core::any::type_name::<Consumer>()]
+        );
+    }
+}
@@ -98,20 +98,23 @@ extern crate alloc;
 
 use core::{
     cell::{Ref, RefCell, RefMut},
+    fmt::{self, Debug},
     marker::PhantomData,
     ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
 };
 
 use alloc::boxed::Box;
 
 use crate::{
-    boot_services::StandardBootServices,
+    boot_services::{BootServices, StandardBootServices},
     component::{
         metadata::MetaData,
-        service::IntoService,
+        service::{IntoService, Service},
         storage::{Deferred, Storage, UnsafeStorageCell},
     },
     runtime_services::StandardRuntimeServices,
+    uefi_protocol::ProtocolInterface,
 };
 
 use super::storage::ConfigRaw;
@@ -196,6 +199,8 @@ pub trait ParamFunction<Marker>: Send + Sync + 'static {
     type In: ComponentInput;
     /// The return type of the function.
     type Out;
+    /// Whether the function consumes its input, and thus can only be run once.
+    const RUNS_ONCE: bool = false;
 
     /// Runs the function with the given input and parameter values.
     fn run(&mut self, input: &mut Option<Self::In>, param_value: ParamItem<Self::Param>) -> Self::Out;
@@ -242,6 +247,7 @@ macro_rules! impl_param_function {
             type Param = ($($param,)*);
             type In = In;
             type Out = Out;
+            const RUNS_ONCE: bool = true;
             fn run(&mut self, input: &mut Option<In>, param_value: ParamItem<($($param,)*)>) -> Out {
                 fn call_inner<In, Out, $($param,)*>(
                     mut f: impl FnMut(In, $($param),*) -> Out,
@@ -577,6 +583,14 @@ impl Commands<'_> {
         });
     }
 
+    /// Registers an observer of the service `S` sometime after the component has been executed. See
+    /// [Storage::on_service_replaced].
+    pub fn on_service_replaced<S: ?Sized + 'static>(&mut self, observer: impl Fn(Service<S>) + 'static) {
+        self.queue.add_command(move |storage| {
+            storage.on_service_replaced::<S>(observer);
+        });
+    }
+
     /// Creates an instance of Commands that will never apply any commands to the storage.
     ///
     /// This function is intended for testing purposes only. Dropping the returned value will cause a memory leak as
@@ -669,6 +683,108 @@ unsafe impl Param for StandardRuntimeServices {
     fn init_state(_storage: &mut Storage, _meta: &mut MetaData) -> Self::State {}
 }
 
+/// A UEFI protocol interface located in the protocol database prior to [Component](super::Component) execution.
+///
+/// A component with this parameter will not be executed until an instance of `T` has been installed on any handle,
+/// which allows components to consume protocols produced by UEFI drivers without polling for them. The interface is
+/// the first instance returned by `LocateProtocol()`.
+///
+/// The reference is only valid for the duration of the component's execution, as the producer may uninstall the
+/// protocol at any point afterwards. Components that need the protocol past their own execution should locate it again
+/// through [StandardBootServices].
+pub struct Protocol<'p, T: ProtocolInterface + 'static> {
+    interface: NonNull<T>,
+    _marker: PhantomData<&'p T>,
+}
+
+impl<T: ProtocolInterface + 'static> Protocol<'_, T> {
+    /// Creates an instance of Protocol by leaking `interface`.
+    ///
+    /// This function is intended for testing purposes only. Dropping the returned value will cause a memory leak as
+    /// the underlying (leaked) interface cannot be deallocated.
+    ///
+    /// ## Example
+    /// ``` rust
+    /// use patina::{component::params::Protocol, uefi_protocol::ProtocolInterface};
+    /// use r_efi::efi;
+    ///
+    /// struct MyProtocol {
+    ///     value: u32,
+    /// }
+    ///
+    /// unsafe impl ProtocolInterface for MyProtocol {
+    ///     const PROTOCOL_GUID: efi::Guid =
+    ///         efi::Guid::from_fields(0x3c1ba0b5, 0x94a2, 0x4b4d, 0x8b, 0x11, &[0x2f, 0x70, 0xd2, 0x6c, 0x19, 0x05]);
+    /// }
+    ///
+    /// fn my_component_to_test(protocol: Protocol<MyProtocol>) {
+    ///     assert_eq!(protocol.value, 42);
+    /// }
+    ///
+    /// #[test]
+    /// fn test_my_component() {
+    ///     my_component_to_test(Protocol::mock(MyProtocol { value: 42 }));
+    /// }
+    /// ```
+    #[allow(clippy::test_attr_in_doctest)]
+    pub fn mock(interface: T) -> Self {
+        Protocol { interface: NonNull::from(Box::leak(Box::new(interface))), _marker: PhantomData }
+    }
+
+    /// Returns a raw pointer to the interface, for calling protocol functions that take a `*mut` to the protocol.
+    pub fn as_ptr(&self) -> *mut T {
+        self.interface.as_ptr()
+    }
+}
+
+impl<T: ProtocolInterface + 'static> Deref for Protocol<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: The interface was located in the protocol database (or leaked) and is valid for 'p.
+        unsafe { self.interface.as_ref() }
+    }
+}
+
+impl<T: ProtocolInterface + 'static> Debug for Protocol<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Protocol")
+            .field("type", &core::any::type_name::<T>())
+            .field("interface", &self.interface)
+            .finish()
+    }
+}
+
+unsafe impl<T: ProtocolInterface + 'static> Param for Protocol<'_, T> {
+    type State = ();
+    type Item<'storage, 'state> = Protocol<'storage, T>;
+
+    unsafe fn get_param<'storage, 'state>(
+        _state: &'state Self::State,
+        storage: UnsafeStorageCell<'storage>,
+    ) -> Self::Item<'storage, 'state> {
+        // SAFETY: The interface is only handed out for the lifetime of the component's execution.
+        let interface =
+            unsafe { storage.storage().boot_services().locate_protocol::<T>(None) }.unwrap_or_else(|status| {
+                panic!(
+                    "Could not locate Protocol {} even though it was just validated: {status:?}",
+                    core::any::type_name::<T>()
+                )
+            });
+        Protocol { interface: NonNull::from(interface), _marker: PhantomData }
+    }
+
+    // `Protocol` is only available once an instance of the protocol has been installed.
+    fn validate(_state: &Self::State, storage: UnsafeStorageCell) -> bool {
+        let boot_services = unsafe { storage.storage() }.boot_services();
+        // SAFETY: The returned interface is not dereferenced.
+        boot_services.is_init()
+            && unsafe { boot_services.locate_protocol_unchecked(&T::PROTOCOL_GUID, ptr::null_mut()) }.is_ok()
+    }
+
+    fn init_state(_storage: &mut Storage, _meta: &mut MetaData) -> Self::State {}
+}
+
 macro_rules! impl_component_param_tuple {
     ($($param: ident), *) => {
         #[allow(non_snake_case)]
@@ -718,7 +834,7 @@ impl_component_param_tuple!(T1, T2, T3, T4, T5);
 #[cfg(test)]
 #[coverage(off)]
 mod tests {
-    use core::sync::atomic::AtomicBool;
+    use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering};
 
     use crate::{
         component::{IntoComponent, storage::Storage},
@@ -1070,6 +1186,88 @@ mod tests {
         let _ = unsafe { <StandardRuntimeServices as Param>::get_param(&(), cell_storage) };
     }
 
+    struct TestProtocol {
+        value: u32,
+    }
+
+    unsafe impl ProtocolInterface for TestProtocol {
+        const PROTOCOL_GUID: r_efi::efi::Guid = r_efi::efi::Guid::from_fields(
+            0x0f3ad9c4,
+            0x61c2,
+            0x4e0a,
+            0xa8,
+            0x5e,
+            &[0x13, 0x77, 0x9b, 0x20, 0xc1, 0x4d],
+        );
+    }
+
+    static INSTALLED_PROTOCOL: AtomicPtr<TestProtocol> = AtomicPtr::new(ptr::null_mut());
+
+    /// Returns boot services whose `LocateProtocol()` returns [INSTALLED_PROTOCOL], or `NOT_FOUND` if it is null.
+    fn protocol_boot_services() -> StandardBootServices {
+        extern "efiapi" fn locate_protocol(
+            protocol: *mut r_efi::efi::Guid,
+            _registration: *mut core::ffi::c_void,
+            out: *mut *mut core::ffi::c_void,
+        ) -> r_efi::efi::Status {
+            let interface = INSTALLED_PROTOCOL.load(Ordering::SeqCst);
+            if unsafe { *protocol } != TestProtocol::PROTOCOL_GUID || interface.is_null() {
+                return r_efi::efi::Status::NOT_FOUND;
+            }
+            unsafe { out.write(interface as *mut core::ffi::c_void) };
+            r_efi::efi::Status::SUCCESS
+        }
+
+        #[allow(invalid_value)]
+        let mut efi_bs = Box::new(core::mem::MaybeUninit::<r_efi::efi::BootServices>::zeroed());
+        unsafe { (*efi_bs.as_mut_ptr()).locate_protocol = locate_protocol };
+        StandardBootServices::new(unsafe { &*Box::leak(efi_bs).as_ptr() })
+    }
+
+    #[test]
+    fn test_protocol_fails_to_validate_when_boot_services_are_null() {
+        let mut storage = Storage::default();
+        let mut mock_metadata = MetaData::new::<i32>();
+
+        <Protocol<TestProtocol> as Param>::init_state(&mut storage, &mut mock_metadata);
+        assert!(<Protocol<TestProtocol> as Param>::try_validate(&(), (&storage).into()).is_err());
+    }
+
+    #[test]
+    fn test_protocol_component_waits_for_protocol_install() {
+        static VALUE: AtomicU32 = AtomicU32::new(0);
+
+        #[derive(IntoComponent)]
+        struct TestComponent;
+        impl TestComponent {
+            fn entry_point(self, protocol: Protocol<TestProtocol>) -> Result<()> {
+                assert_eq!(protocol.as_ptr(), INSTALLED_PROTOCOL.load(Ordering::SeqCst));
+                VALUE.store(protocol.value, Ordering::SeqCst);
+                Ok(())
+            }
+        }
+
+        let mut storage = Storage::new();
+        storage.set_boot_services(protocol_boot_services());
+
+        let mut component = TestComponent.into_component();
+        component.initialize(&mut storage);
+
+        assert_eq!(component.run(&mut storage), Ok(false));
+        assert_eq!(component.metadata().failed_param(), Some(core::any::type_name::<Protocol<TestProtocol>>()));
+
+        INSTALLED_PROTOCOL.store(Box::leak(Box::new(TestProtocol { value: 42 })), Ordering::SeqCst);
+        assert_eq!(component.run(&mut storage), Ok(true));
+        assert_eq!(VALUE.load(Ordering::SeqCst), 42);
+    }
+
+    #[test]
+    fn test_protocol_mock_derefs_to_interface() {
+        let protocol = Protocol::mock(TestProtocol { value: 7 });
+        assert_eq!(protocol.value, 7);
+        assert_eq!(unsafe { (*protocol.as_ptr()).value }, 7);
+    }
+
     #[test]
     fn test_option_returns_none_when_underlying_param_is_unavailable() {
         let mut storage = Storage::default();
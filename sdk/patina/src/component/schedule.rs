@@ -0,0 +1,295 @@
+//! Component scheduling.
+//!
+//! Each component registers the resources it accesses in its [MetaData] when it is initialized. [batches] uses this
+//! to split a set of components into batches whose components can all run at the same time without conflicting
+//! access to a resource, and an [Executor] then runs each batch:
+//!
+//! - [Sequential] runs the components of a batch one after the other, and is what firmware uses.
+//! - `ThreadPool` (`std` feature only) runs the components of a batch on separate threads, so that host-based tests
+//!   exercise the access requirements declared by components under real concurrency.
+//!
+//! Deferred [Commands](super::params::Commands) are applied to the storage by the caller before each batch. [Sequential]
+//! also applies them before each component, as [Component::run] does, so a component sees the commands of the
+//! components that ran before it in the same batch. `ThreadPool` cannot, so those commands only take effect in the next
+//! batch.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    component::{Component, MetaData, UnsafeStorageCell},
+    error::Result,
+};
+
+/// Splits `components` into batches of components that can run at the same time, returning the indices of the
+/// components in each batch.
+///
+/// Each batch is a contiguous run of the given components, and a component that conflicts with a component of the
+/// current batch starts a new one. Running the batches in order with [Sequential] therefore runs the components in the
+/// order they were given in.
+pub fn batches<'a>(components: impl IntoIterator<Item = &'a MetaData>) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    let mut scheduled: Vec<&MetaData> = Vec::new();
+    for (index, component) in components.into_iter().enumerate() {
+        match batches.last_mut() {
+            Some(batch) if batch.iter().all(|other| scheduled[*other].is_compatible(component)) => batch.push(index),
+            _ => batches.push(vec![index]),
+        }
+        scheduled.push(component);
+    }
+    batches
+}
+
+/// Runs a batch of components.
+pub trait Executor {
+    /// Runs every component of `batch` with [Component::run_unsafe], returning the result of each component in the
+    /// same order as `batch`.
+    ///
+    /// ## Safety
+    ///
+    /// - The components of `batch` must be pairwise [compatible](MetaData::is_compatible).
+    /// - Nothing else may access `storage` until this function returns.
+    unsafe fn execute(&self, storage: UnsafeStorageCell, batch: &mut [&mut dyn Component]) -> Vec<Result<bool>>;
+}
+
+/// An [Executor] that runs the components of a batch one after the other, on the current thread, applying deferred
+/// commands before each component.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sequential;
+
+impl Executor for Sequential {
+    unsafe fn execute(&self, storage: UnsafeStorageCell, batch: &mut [&mut dyn Component]) -> Vec<Result<bool>> {
+        batch
+            .iter_mut()
+            .map(|component| {
+                // SAFETY: The caller guarantees exclusive access to the storage, and no component is running.
+                unsafe { storage.storage_mut() }.apply_deferred();
+                // SAFETY: The caller guarantees that the components of the batch do not conflict.
+                unsafe { component.run_unsafe(storage) }
+            })
+            .collect()
+    }
+}
+
+/// An [Executor] that runs the components of a batch on up to `threads` scoped threads.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadPool {
+    threads: usize,
+}
+
+#[cfg(feature = "std")]
+impl ThreadPool {
+    /// Creates an executor that runs a batch on up to `threads` threads.
+    ///
+    /// ## Safety
+    ///
+    /// Components are moved to other threads, and components of the same batch share the storage across threads, which
+    /// the type system cannot check through `dyn Component` and the type-erased storage. Every component dispatched with
+    /// this executor must be [Send], and every config, service and HOB its params access must be [Sync] (and [Send] for
+    /// [ConfigMut](super::params::ConfigMut)).
+    pub unsafe fn new(threads: usize) -> Self {
+        Self { threads: threads.max(1) }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Executor for ThreadPool {
+    unsafe fn execute(&self, storage: UnsafeStorageCell, batch: &mut [&mut dyn Component]) -> Vec<Result<bool>> {
+        /// A chunk of the batch that is run on its own thread.
+        struct Chunk<'a, 'b>(&'a mut [&'b mut dyn Component]);
+
+        // SAFETY: The creator of the executor guarantees that the components are Send and that the storage they access
+        // is Sync (see ThreadPool::new), and the caller guarantees that the components of the batch do not conflict.
+        unsafe impl Send for Chunk<'_, '_> {}
+
+        impl Chunk<'_, '_> {
+            fn run(self, storage: UnsafeStorageCell) -> Vec<Result<bool>> {
+                // SAFETY: The caller guarantees that the components of the batch do not conflict.
+                self.0.iter_mut().map(|component| unsafe { component.run_unsafe(storage) }).collect()
+            }
+        }
+
+        let chunk_size = batch.len().div_ceil(self.threads).max(1);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = batch
+                .chunks_mut(chunk_size)
+                .map(|chunk| {
+                    let chunk = Chunk(chunk);
+                    scope.spawn(move || chunk.run(storage))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate as patina;
+    use crate::component::{
+        IntoComponent, Storage,
+        params::{Commands, Config, ConfigMut},
+        service::{IntoService, Service},
+    };
+    use alloc::boxed::Box;
+
+    fn metadata(configure: impl FnOnce(&mut MetaData)) -> MetaData {
+        let mut metadata = MetaData::new::<()>();
+        configure(&mut metadata);
+        metadata
+    }
+
+    #[test]
+    fn test_compatible_components_share_a_batch() {
+        let components = [
+            metadata(|m| m.access_mut().add_config_read(0)),
+            metadata(|m| m.access_mut().add_config_read(0)),
+            metadata(|m| m.access_mut().add_config_write(1)),
+        ];
+        assert_eq!(batches(&components), [vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn test_conflicting_components_keep_their_order() {
+        let components = [
+            metadata(|m| m.access_mut().add_config_write(0)),
+            metadata(|m| m.access_mut().add_config_read(0)),
+            metadata(|m| m.access_mut().add_config_read(1)),
+            metadata(|m| m.access_mut().add_config_write(0)),
+            metadata(|m| m.access_mut().writes_all_configs()),
+            metadata(|m| m.access_mut().add_config_read(2)),
+        ];
+        assert_eq!(batches(&components), [vec![0], vec![1, 2], vec![3], vec![4], vec![5]]);
+    }
+
+    #[test]
+    fn test_batches_keep_the_given_order() {
+        let components =
+            [metadata(|m| m.access_mut().deferred()), metadata(|m| m.access_mut().deferred()), metadata(|_| {})];
+        assert_eq!(batches(&components), [vec![0], vec![1, 2]]);
+    }
+
+    #[derive(IntoComponent)]
+    struct Reader;
+
+    impl Reader {
+        fn entry_point(self, config: Config<u32>) -> patina::error::Result<()> {
+            assert_eq!(*config, 42);
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct Writer;
+
+    impl Writer {
+        fn entry_point(self, mut config: ConfigMut<u64>) -> patina::error::Result<()> {
+            *config += 1;
+            Ok(())
+        }
+    }
+
+    fn run_batch(executor: &dyn Executor) {
+        let mut storage = Storage::new();
+        storage.add_config(42u32);
+        storage.lock_configs();
+
+        let mut components: Vec<Box<dyn Component>> =
+            vec![Reader.into_component(), Writer.into_component(), Reader.into_component()];
+        components.iter_mut().for_each(|component| component.initialize(&mut storage));
+        assert_eq!(batches(components.iter().map(|component| component.metadata())), [vec![0, 1, 2]]);
+
+        let mut batch: Vec<&mut dyn Component> =
+            components.iter_mut().map(|component| component.as_mut() as &mut dyn Component).collect();
+        // SAFETY: The components of the batch are compatible, and nothing else accesses the storage.
+        let results = unsafe { executor.execute(UnsafeStorageCell::from(&mut storage), &mut batch) };
+        assert_eq!(results, [Ok(true), Ok(true), Ok(true)]);
+    }
+
+    trait Greeting {
+        fn greet(&self) -> &'static str;
+    }
+
+    #[derive(IntoService)]
+    #[service(dyn Greeting)]
+    struct Hello;
+
+    impl Greeting for Hello {
+        fn greet(&self) -> &'static str {
+            "hello"
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct Producer;
+
+    impl Producer {
+        fn entry_point(self, mut commands: Commands) -> patina::error::Result<()> {
+            commands.add_service(Hello);
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct Consumer;
+
+    impl Consumer {
+        fn entry_point(self, greeting: Service<dyn Greeting>) -> patina::error::Result<()> {
+            assert_eq!(greeting.greet(), "hello");
+            Ok(())
+        }
+    }
+
+    // Runs a batch where the second component consumes a service added by the deferred commands of the first.
+    fn run_deferred_batch(executor: &dyn Executor) -> Vec<Result<bool>> {
+        let mut storage = Storage::new();
+        let mut components: Vec<Box<dyn Component>> = vec![Producer.into_component(), Consumer.into_component()];
+        components.iter_mut().for_each(|component| component.initialize(&mut storage));
+        assert_eq!(batches(components.iter().map(|component| component.metadata())), [vec![0, 1]]);
+
+        let mut batch: Vec<&mut dyn Component> =
+            components.iter_mut().map(|component| component.as_mut() as &mut dyn Component).collect();
+        // SAFETY: The components of the batch are compatible, and nothing else accesses the storage.
+        unsafe { executor.execute(UnsafeStorageCell::from(&mut storage), &mut batch) }
+    }
+
+    #[test]
+    fn test_sequential_executor_runs_every_component() {
+        run_batch(&Sequential);
+    }
+
+    #[test]
+    fn test_sequential_executor_applies_deferred_commands_before_each_component() {
+        assert_eq!(run_deferred_batch(&Sequential), [Ok(true), Ok(true)]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_thread_pool_executor_runs_every_component() {
+        // SAFETY: The test components and the u32 and u64 configs they access are Send and Sync.
+        run_batch(&unsafe { ThreadPool::new(2) });
+        // SAFETY: As above.
+        run_batch(&unsafe { ThreadPool::new(0) });
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_thread_pool_executor_applies_deferred_commands_after_the_batch() {
+        // SAFETY: The test components and the service they access are Send and Sync.
+        let results = run_deferred_batch(&unsafe { ThreadPool::new(2) });
+        assert_eq!(results, [Ok(true), Ok(false)]);
+    }
+}
@@ -23,8 +23,8 @@
 //! While not suggested, it is possible to publish a service as an EDKII compatible protocol for backwards
 //! compatability with existing EDKII code, allowing for a rust service to be consumed by an EDKII driver. As mentioned
 //! multiple times, this is **only** for backwards compatability and should be avoided if possible. Any rust to rust
-//! component interactions should be done through the [Service] [Param] type. Please review the [IntoService] trait on
-//! how to register a service as an EDKII protocol.
+//! component interactions should be done through the [Service] [Param] type. Please review the [protocol] module on
+//! how to register a service as an EDKII protocol, or consume an EDKII protocol as a service.
 //!
 //! ## Example
 //!
@@ -126,7 +126,13 @@ use crate::component::{
     storage::{Storage, UnsafeStorageCell},
 };
 
+pub mod config_table;
+pub mod crash_record;
+pub mod debug_image;
+pub mod dma;
+pub mod lifecycle;
 pub mod memory;
+pub mod protocol;
 
 pub use patina_macro::IntoService;
 
@@ -168,6 +174,26 @@ pub trait IntoService {
         let id = storage.register_service::<S>();
         storage.insert_service(id, service);
     }
+
+    /// Helper function to register the [lifecycle](lifecycle::ServiceLifecycle) hooks of the service `S`. Must be
+    /// called after the service itself is registered with [register_service](IntoService::register_service).
+    fn register_lifecycle<S: ?Sized + 'static>(
+        storage: &mut Storage,
+        lifecycle: &'static dyn lifecycle::ServiceLifecycle,
+    ) {
+        let id = storage.register_service::<S>();
+        storage.insert_service_lifecycle(id, lifecycle);
+    }
+
+    /// Helper function to install the service `S` as a UEFI [protocol](protocol::ServiceProtocol) with the given
+    /// `guid`. If boot services are not yet available, the protocol is installed as soon as they are.
+    fn register_protocol<S: ?Sized + protocol::ServiceProtocol>(
+        storage: &mut Storage,
+        guid: &'static r_efi::efi::Guid,
+        service: &'static S,
+    ) {
+        storage.publish_protocol(guid, service);
+    }
 }
 
 /// A service with a static lifetime that can be used as a parameter to a [Component](super::Component).
@@ -335,8 +361,10 @@ unsafe impl<T: ?Sized + 'static> Param for Service<T> {
         unsafe { storage.storage() }.get_raw_service(*state).is_some()
     }
 
-    fn init_state(storage: &mut Storage, _meta: &mut MetaData) -> Self::State {
-        storage.register_service::<T>()
+    fn init_state(storage: &mut Storage, meta: &mut MetaData) -> Self::State {
+        let id = storage.register_service::<T>();
+        meta.access_mut().add_service_read(id);
+        id
     }
 }
 
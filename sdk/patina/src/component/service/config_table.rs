@@ -0,0 +1,260 @@
+//! Configuration Table Related Service Definitions.
+//!
+//! This module contains the [ConfigTableManager] service, a safe interface over the UEFI configuration table that the
+//! core produces. On top of `InstallConfigurationTable()`, the service tracks which component installed each table,
+//! validates tables before they are published, keeps a per-table version that is bumped on every change and delivers
+//! change notifications to components without them needing to manage event groups themselves.
+//!
+//! Tables that are laid out as a Rust type should implement [ConfigurationTable], which allows them to be installed
+//! with [install_table](trait.ConfigTableManager.html#method.install_table) without any unsafe code:
+//!
+//! ```rust
+//! use patina::component::service::{Service, config_table::*};
+//! use r_efi::efi;
+//!
+//! #[repr(C)]
+//! struct MyTable {
+//!     signature: u32,
+//!     revision: u32,
+//!     count: u64,
+//! }
+//!
+//! // SAFETY: `MyTable` is repr(C) and is the layout published under `GUID`.
+//! unsafe impl ConfigurationTable for MyTable {
+//!     const GUID: efi::Guid =
+//!         efi::Guid::from_fields(0x5c4c47ac, 0x8e6b, 0x4a3b, 0x91, 0x2d, &[0x5f, 0x22, 0x6b, 0x1e, 0x0c, 0x43]);
+//! }
+//!
+//! fn publish(tables: Service<dyn ConfigTableManager>) -> Result<(), ConfigTableError> {
+//!     let table = Box::leak(Box::new(MyTable { signature: 0x4d59_5442, revision: 1, count: 0 }));
+//!     tables.install_table("MyComponent", table)?;
+//!
+//!     tables.register_notify(
+//!         <MyTable as ConfigurationTable>::GUID,
+//!         Box::new(|info| log::info!("MyTable is now {:?}", info.map(|info| info.version))),
+//!     )
+//! }
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::ffi::c_void;
+
+use r_efi::efi;
+
+use crate::error::EfiError;
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+/// The checksum a table must carry to be installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableChecksum {
+    /// The table is not checksummed.
+    None,
+    /// The bytes of the table sum to zero (modulo 256), as used by ACPI and SMBIOS structures.
+    Sum8,
+    /// The table contains a little endian CRC32 at `offset`, computed over the whole table with the CRC32 field set
+    /// to zero, as used by `EFI_TABLE_HEADER`.
+    Crc32 {
+        /// The offset of the CRC32 field from the start of the table.
+        offset: usize,
+    },
+}
+
+/// A table to install with [ConfigTableManager::install].
+#[derive(Debug, Clone, Copy)]
+pub struct TableInstall {
+    /// The GUID the table is published under.
+    pub guid: efi::Guid,
+    /// The table itself.
+    pub table: *mut c_void,
+    /// The size of the table in bytes.
+    pub size: usize,
+    /// The checksum the table is validated against before it is installed.
+    pub checksum: TableChecksum,
+}
+
+/// Information about an installed configuration table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigTableInfo {
+    /// The GUID the table is published under.
+    pub guid: efi::Guid,
+    /// The table itself.
+    pub table: *mut c_void,
+    /// The size of the table in bytes, if it was installed through the [ConfigTableManager].
+    pub size: Option<usize>,
+    /// The owner that installed the table, if it was installed through the [ConfigTableManager]. Tables installed
+    /// with `InstallConfigurationTable()` have no owner.
+    pub owner: Option<String>,
+    /// The number of times a table has been installed, replaced or removed under this GUID.
+    pub version: u32,
+}
+
+/// A function called whenever the table under a GUID is installed, replaced or removed.
+///
+/// The function is called at `TPL_CALLBACK` with the current state of the table, or `None` if it was removed.
+pub type ConfigTableNotify = Box<dyn Fn(Option<ConfigTableInfo>) + Send + Sync>;
+
+/// The `ConfigTableError` enum represents the different types of errors that can occur when using the
+/// [ConfigTableManager] service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigTableError {
+    /// The table pointer is null, the size is zero, or the checksum field is outside of the table.
+    InvalidTable,
+    /// The table does not match its checksum.
+    ChecksumMismatch,
+    /// The table under this GUID was installed by a different owner.
+    NotOwner,
+    /// There is no table under this GUID.
+    NotFound,
+    /// There were not enough resources to complete the operation.
+    OutOfResources,
+    /// The configuration table manager hit an internal error.
+    InternalError,
+}
+
+impl From<ConfigTableError> for EfiError {
+    fn from(value: ConfigTableError) -> Self {
+        match value {
+            ConfigTableError::InvalidTable | ConfigTableError::ChecksumMismatch => EfiError::InvalidParameter,
+            ConfigTableError::NotOwner => EfiError::AccessDenied,
+            ConfigTableError::NotFound => EfiError::NotFound,
+            ConfigTableError::OutOfResources => EfiError::OutOfResources,
+            ConfigTableError::InternalError => EfiError::DeviceError,
+        }
+    }
+}
+
+/// The `ConfigTableManager` trait provides an interface for publishing, inspecting and watching configuration tables.
+/// This trait is implemented by the core.
+///
+/// Only the owner that installed a table through this service may replace or remove it. Tables installed with
+/// `InstallConfigurationTable()` have no owner and may be replaced by anyone.
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait ConfigTableManager {
+    /// Installs (or replaces) a configuration table on behalf of `owner`.
+    ///
+    /// The table is validated against its checksum before it is installed.
+    ///
+    /// # Safety
+    ///
+    /// `table.table` must be valid for reads of `table.size` bytes, and must remain valid until the table is removed
+    /// or replaced.
+    unsafe fn install(&self, owner: &str, table: TableInstall) -> Result<(), ConfigTableError>;
+
+    /// Removes the configuration table under `guid` on behalf of `owner`.
+    fn uninstall(&self, owner: &str, guid: efi::Guid) -> Result<(), ConfigTableError>;
+
+    /// Returns all installed configuration tables.
+    fn tables(&self) -> Vec<ConfigTableInfo>;
+
+    /// Returns the configuration table under `guid`, if one is installed.
+    fn table(&self, guid: efi::Guid) -> Option<ConfigTableInfo>;
+
+    /// Registers `notify` to be called whenever the table under `guid` is installed, replaced or removed, whether
+    /// through this service or through `InstallConfigurationTable()`.
+    fn register_notify(&self, guid: efi::Guid, notify: ConfigTableNotify) -> Result<(), ConfigTableError>;
+}
+
+/// A configuration table laid out as a Rust type.
+///
+/// # Safety
+///
+/// The implementor must be `repr(C)` (or otherwise have a stable layout), and its layout must be the one published
+/// under [GUID](ConfigurationTable::GUID), as consumers of the configuration table read it by layout.
+pub unsafe trait ConfigurationTable: 'static {
+    /// The GUID the table is published under.
+    const GUID: efi::Guid;
+
+    /// The checksum the table must carry to be installed.
+    const CHECKSUM: TableChecksum = TableChecksum::None;
+
+    /// Returns the size of the table in bytes. Tables with trailing variable length data should override this.
+    fn table_size(&self) -> usize
+    where
+        Self: Sized,
+    {
+        core::mem::size_of::<Self>()
+    }
+}
+
+impl dyn ConfigTableManager {
+    /// Installs (or replaces) a typed configuration table on behalf of `owner`.
+    pub fn install_table<T: ConfigurationTable>(
+        &self,
+        owner: &str,
+        table: &'static mut T,
+    ) -> Result<(), ConfigTableError> {
+        let install = TableInstall {
+            guid: T::GUID,
+            size: table.table_size(),
+            table: table as *mut T as *mut c_void,
+            checksum: T::CHECKSUM,
+        };
+        // SAFETY: the table is borrowed for 'static and is valid for reads of its own size.
+        unsafe { self.install(owner, install) }
+    }
+
+    /// Removes a typed configuration table on behalf of `owner`.
+    pub fn uninstall_table<T: ConfigurationTable>(&self, owner: &str) -> Result<(), ConfigTableError> {
+        self.uninstall(owner, T::GUID)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use mockall::predicate::*;
+
+    #[repr(C)]
+    struct TestTable {
+        value: u64,
+    }
+
+    // SAFETY: TestTable is repr(C) and is only used by these tests.
+    unsafe impl ConfigurationTable for TestTable {
+        const GUID: efi::Guid =
+            efi::Guid::from_fields(0x8b1f5b9e, 0x2a55, 0x4a0c, 0x9e, 0x2b, &[0x61, 0x01, 0x7d, 0x34, 0x5c, 0x90]);
+        const CHECKSUM: TableChecksum = TableChecksum::Sum8;
+    }
+
+    #[test]
+    fn test_install_table_describes_the_typed_table() {
+        let mut mock = MockConfigTableManager::new();
+        mock.expect_install()
+            .with(
+                eq("owner"),
+                function(|install: &TableInstall| {
+                    install.guid == TestTable::GUID
+                        && install.size == size_of::<TestTable>()
+                        && install.checksum == TableChecksum::Sum8
+                        && unsafe { (install.table as *const TestTable).read().value } == 42
+                }),
+            )
+            .returning(|_, _| Ok(()));
+        mock.expect_uninstall()
+            .with(eq("owner"), eq(TestTable::GUID))
+            .returning(|_, _| Err(ConfigTableError::NotFound));
+
+        let manager: &dyn ConfigTableManager = &mock;
+        let table = Box::leak(Box::new(TestTable { value: 42 }));
+        assert_eq!(manager.install_table("owner", table), Ok(()));
+        assert_eq!(manager.uninstall_table::<TestTable>("owner"), Err(ConfigTableError::NotFound));
+    }
+
+    #[test]
+    fn test_error_to_efi_error_conversion() {
+        assert_eq!(EfiError::from(ConfigTableError::ChecksumMismatch), EfiError::InvalidParameter);
+        assert_eq!(EfiError::from(ConfigTableError::NotOwner), EfiError::AccessDenied);
+        assert_eq!(EfiError::from(ConfigTableError::NotFound), EfiError::NotFound);
+    }
+}
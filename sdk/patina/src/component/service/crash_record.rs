@@ -0,0 +1,590 @@
+//! Crash Record Related Service Definitions.
+//!
+//! This module contains the [CrashRecord] type captured by the core when the system is about to be reset due to a
+//! failure, and the traits for services that let the core persist it across the reset ([CrashRecordStore]) and enrich
+//! it with recent log output ([LogTail]).
+//!
+//! A record persisted by the previous boot is published by the core as a configuration table with the
+//! [CRASH_RECORD_TABLE_GUID] GUID. The table is a little endian `u32` length followed by the bytes of the record, as
+//! produced by [CrashRecord::to_bytes].
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+use r_efi::efi;
+
+use crate::error::Result;
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+/// The signature at the start of every serialized [CrashRecord] ("PCRR").
+pub const CRASH_RECORD_SIGNATURE: u32 = u32::from_le_bytes(*b"PCRR");
+
+/// The version of the serialized [CrashRecord] layout.
+pub const CRASH_RECORD_VERSION: u16 = 1;
+
+/// The GUID of the configuration table the core publishes a crash record from the previous boot under.
+pub const CRASH_RECORD_TABLE_GUID: efi::Guid =
+    efi::Guid::from_fields(0x1ad47309, 0x77bf, 0x4961, 0xb7, 0x3c, &[0xca, 0xde, 0x0e, 0x4a, 0xc9, 0x32]);
+
+const REASON_WATCHDOG_TIMEOUT: u8 = 0;
+const REASON_EXCEPTION: u8 = 1;
+
+/// The reason a [CrashRecord] was captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashReason {
+    /// The watchdog timer expired. `period` is the watchdog period in 100ns units.
+    WatchdogTimeout {
+        /// The watchdog period, in 100ns units, that elapsed.
+        period: u64,
+    },
+    /// An exception was taken that had no registered handler.
+    Exception {
+        /// The architecture specific exception type.
+        exception_type: u64,
+    },
+}
+
+/// A register value captured in a [CrashRecord].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashRegister {
+    /// The architectural name of the register.
+    pub name: String,
+    /// The value of the register.
+    pub value: u64,
+}
+
+/// A loaded module captured in a [CrashRecord], used to resolve addresses that the stack trace could not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashModule {
+    /// The name of the module, if known.
+    pub name: Option<String>,
+    /// The base address the module was loaded at.
+    pub base: u64,
+    /// The size of the loaded module in bytes.
+    pub size: u64,
+}
+
+/// A single frame of the stack trace captured in a [CrashRecord].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashFrame {
+    /// The program counter of the frame.
+    pub pc: u64,
+    /// The name of the module containing `pc`, if it could be determined.
+    pub module: Option<String>,
+    /// The offset of `pc` from the base of `module`.
+    pub offset: u64,
+}
+
+/// The diagnostic state captured by the core before it resets the system due to a failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashRecord {
+    /// Why the record was captured.
+    pub reason: CrashReason,
+    /// The TPL the system was running at.
+    pub tpl: usize,
+    /// The name of the image that was running, if any.
+    pub running_image: Option<String>,
+    /// The registers of the code that was running, if they were saved.
+    pub registers: Vec<CrashRegister>,
+    /// The stack trace of the code that was running, innermost frame first.
+    pub frames: Vec<CrashFrame>,
+    /// The modules that were loaded.
+    pub modules: Vec<CrashModule>,
+    /// The most recent log output.
+    pub log_tail: String,
+}
+
+impl CrashRecord {
+    /// Serializes the record into the byte layout persisted by a [CrashRecordStore].
+    ///
+    /// The layout is little endian and starts with [CRASH_RECORD_SIGNATURE] and [CRASH_RECORD_VERSION].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&CRASH_RECORD_SIGNATURE.to_le_bytes());
+        bytes.extend_from_slice(&CRASH_RECORD_VERSION.to_le_bytes());
+        match self.reason {
+            CrashReason::WatchdogTimeout { period } => {
+                bytes.push(REASON_WATCHDOG_TIMEOUT);
+                bytes.extend_from_slice(&period.to_le_bytes());
+            }
+            CrashReason::Exception { exception_type } => {
+                bytes.push(REASON_EXCEPTION);
+                bytes.extend_from_slice(&exception_type.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&(self.tpl as u64).to_le_bytes());
+        write_optional_str(&mut bytes, self.running_image.as_deref());
+        bytes.extend_from_slice(&(self.registers.len() as u32).to_le_bytes());
+        for register in &self.registers {
+            write_str(&mut bytes, &register.name);
+            bytes.extend_from_slice(&register.value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            bytes.extend_from_slice(&frame.pc.to_le_bytes());
+            bytes.extend_from_slice(&frame.offset.to_le_bytes());
+            write_optional_str(&mut bytes, frame.module.as_deref());
+        }
+        bytes.extend_from_slice(&(self.modules.len() as u32).to_le_bytes());
+        for module in &self.modules {
+            bytes.extend_from_slice(&module.base.to_le_bytes());
+            bytes.extend_from_slice(&module.size.to_le_bytes());
+            write_optional_str(&mut bytes, module.name.as_deref());
+        }
+        write_str(&mut bytes, &self.log_tail);
+        bytes
+    }
+
+    /// Deserializes a record produced by [to_bytes](Self::to_bytes). Returns `None` if `bytes` is not a valid record.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader { bytes };
+        if reader.u32()? != CRASH_RECORD_SIGNATURE || reader.u16()? != CRASH_RECORD_VERSION {
+            return None;
+        }
+        let reason = match reader.u8()? {
+            REASON_WATCHDOG_TIMEOUT => CrashReason::WatchdogTimeout { period: reader.u64()? },
+            REASON_EXCEPTION => CrashReason::Exception { exception_type: reader.u64()? },
+            _ => return None,
+        };
+        let tpl = reader.u64()? as usize;
+        let running_image = reader.optional_str()?;
+        let register_count = reader.u32()?;
+        let mut registers = Vec::new();
+        for _ in 0..register_count {
+            let name = reader.str()?;
+            registers.push(CrashRegister { name, value: reader.u64()? });
+        }
+        let frame_count = reader.u32()?;
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            let pc = reader.u64()?;
+            let offset = reader.u64()?;
+            frames.push(CrashFrame { pc, module: reader.optional_str()?, offset });
+        }
+        let module_count = reader.u32()?;
+        let mut modules = Vec::new();
+        for _ in 0..module_count {
+            let base = reader.u64()?;
+            let size = reader.u64()?;
+            modules.push(CrashModule { name: reader.optional_str()?, base, size });
+        }
+        let log_tail = reader.str()?;
+        Some(Self { reason, tpl, running_image, registers, frames, modules, log_tail })
+    }
+}
+
+/// The sections of a serialized [CrashRecord] that follow the header, in the order they are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Section {
+    Registers,
+    Frames,
+    Modules,
+    LogTail,
+}
+
+impl Section {
+    // The bytes needed to open every section after this one: a u32 count or length each.
+    fn trailer_size(self) -> usize {
+        (Section::LogTail as usize - self as usize) * size_of::<u32>()
+    }
+}
+
+/// Serializes a [CrashRecord] into a caller provided buffer, in the layout produced by [CrashRecord::to_bytes],
+/// without allocating.
+///
+/// This is intended for failure paths (e.g. an exception handler) where the allocator may not be usable. Registers,
+/// frames and modules are pushed in that order. Once an item does not fit in the buffer, it and the rest of its section
+/// are dropped, so the result is always a valid record that [CrashRecord::from_bytes] accepts.
+#[derive(Debug)]
+pub struct CrashRecordWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+    section: Section,
+    count_offset: usize,
+    count: u32,
+    full: bool,
+}
+
+impl<'a> CrashRecordWriter<'a> {
+    /// Starts a record in `buffer` with the given header fields. Returns `None` if `buffer` is too small to hold them.
+    pub fn new(buffer: &'a mut [u8], reason: CrashReason, tpl: usize, running_image: Option<&str>) -> Option<Self> {
+        let mut writer = Self { buffer, len: 0, section: Section::Registers, count_offset: 0, count: 0, full: false };
+        let (reason, value) = match reason {
+            CrashReason::WatchdogTimeout { period } => (REASON_WATCHDOG_TIMEOUT, period),
+            CrashReason::Exception { exception_type } => (REASON_EXCEPTION, exception_type),
+        };
+        let header = [
+            &CRASH_RECORD_SIGNATURE.to_le_bytes()[..],
+            &CRASH_RECORD_VERSION.to_le_bytes(),
+            &[reason],
+            &value.to_le_bytes(),
+            &(tpl as u64).to_le_bytes(),
+        ];
+        let header_size = header.iter().map(|field| field.len()).sum::<usize>()
+            + optional_str_size(running_image)
+            + size_of::<u32>()
+            + Section::Registers.trailer_size();
+        if header_size > writer.buffer.len() {
+            return None;
+        }
+        header.iter().for_each(|field| writer.put(field));
+        writer.put_optional_str(running_image);
+        writer.open_section();
+        Some(writer)
+    }
+
+    /// Adds a register. Returns `false` if it was dropped, because the section is full or frames have already been
+    /// added.
+    pub fn push_register(&mut self, name: &str, value: u64) -> bool {
+        self.push(Section::Registers, size_of::<u32>() + name.len() + size_of::<u64>(), |writer| {
+            writer.put_str(name);
+            writer.put(&value.to_le_bytes());
+        })
+    }
+
+    /// Adds a stack frame. Returns `false` if it was dropped, because the section is full or modules have already been
+    /// added.
+    pub fn push_frame(&mut self, pc: u64, module: Option<&str>, offset: u64) -> bool {
+        self.push(Section::Frames, 2 * size_of::<u64>() + optional_str_size(module), |writer| {
+            writer.put(&pc.to_le_bytes());
+            writer.put(&offset.to_le_bytes());
+            writer.put_optional_str(module);
+        })
+    }
+
+    /// Adds a loaded module. Returns `false` if it was dropped because the section is full.
+    pub fn push_module(&mut self, name: Option<&str>, base: u64, size: u64) -> bool {
+        self.push(Section::Modules, 2 * size_of::<u64>() + optional_str_size(name), |writer| {
+            writer.put(&base.to_le_bytes());
+            writer.put(&size.to_le_bytes());
+            writer.put_optional_str(name);
+        })
+    }
+
+    /// Completes the record and returns its bytes. `write_log_tail` is passed the rest of the buffer to fill with the
+    /// most recent log output, and returns the number of bytes written. Bytes that are not valid UTF-8 are replaced
+    /// with `?`.
+    pub fn finish(mut self, write_log_tail: impl FnOnce(&mut [u8]) -> usize) -> &'a [u8] {
+        self.enter(Section::LogTail);
+        let log_tail = &mut self.buffer[self.len..];
+        let written = write_log_tail(log_tail).min(log_tail.len());
+        replace_invalid_utf8(&mut log_tail[..written]);
+        self.count = written as u32;
+        self.len += written;
+        self.close_section();
+        &self.buffer[..self.len]
+    }
+
+    fn push(&mut self, section: Section, size: usize, write: impl FnOnce(&mut Self)) -> bool {
+        if section < self.section {
+            return false;
+        }
+        self.enter(section);
+        if self.full || self.len + size + section.trailer_size() > self.buffer.len() {
+            self.full = true;
+            return false;
+        }
+        write(self);
+        self.count += 1;
+        true
+    }
+
+    // Closes the open section, and opens the ones up to `section`. Room for them was reserved when the record was
+    // started.
+    fn enter(&mut self, section: Section) {
+        while self.section < section {
+            self.close_section();
+            self.section = match self.section {
+                Section::Registers => Section::Frames,
+                Section::Frames => Section::Modules,
+                Section::Modules | Section::LogTail => Section::LogTail,
+            };
+            self.open_section();
+        }
+    }
+
+    fn open_section(&mut self) {
+        self.count_offset = self.len;
+        self.count = 0;
+        self.full = false;
+        self.put(&0u32.to_le_bytes());
+    }
+
+    fn close_section(&mut self) {
+        self.buffer[self.count_offset..self.count_offset + size_of::<u32>()].copy_from_slice(&self.count.to_le_bytes());
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        self.buffer[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    fn put_str(&mut self, value: &str) {
+        self.put(&(value.len() as u32).to_le_bytes());
+        self.put(value.as_bytes());
+    }
+
+    fn put_optional_str(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.put(&[1]);
+                self.put_str(value);
+            }
+            None => self.put(&[0]),
+        }
+    }
+}
+
+fn optional_str_size(value: Option<&str>) -> usize {
+    1 + value.map_or(0, |value| size_of::<u32>() + value.len())
+}
+
+// Replaces every byte that is not part of a valid UTF-8 sequence with `?`, in place.
+fn replace_invalid_utf8(mut bytes: &mut [u8]) {
+    while let Err(err) = core::str::from_utf8(bytes) {
+        let start = err.valid_up_to();
+        let end = start + err.error_len().unwrap_or(bytes.len() - start);
+        bytes[start..end].fill(b'?');
+        bytes = &mut core::mem::take(&mut bytes)[end..];
+    }
+}
+
+fn write_str(bytes: &mut Vec<u8>, value: &str) {
+    bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+fn write_optional_str(bytes: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            bytes.push(1);
+            write_str(bytes, value);
+        }
+        None => bytes.push(0),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (value, rest) = self.bytes.split_first_chunk::<N>()?;
+        self.bytes = rest;
+        Some(*value)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (value, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        String::from_utf8(value.to_vec()).ok()
+    }
+
+    fn optional_str(&mut self) -> Option<Option<String>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => self.str().map(Some),
+            _ => None,
+        }
+    }
+}
+
+/// The `CrashRecordStore` trait persists a serialized [CrashRecord] so that it survives the reset that follows.
+///
+/// The storage backend (e.g. a UEFI variable or a reserved memory region) is platform specific, so this trait is
+/// intended to be implemented by a platform component and consumed by the core. The core calls
+/// [store](CrashRecordStore::store) from a failure path, immediately before resetting the system, at `TPL_NOTIFY` or
+/// above, or from an exception handler. Implementations must not allocate or block, and should keep the work done to a
+/// minimum.
+///
+/// On the next boot the core [retrieves](CrashRecordStore::retrieve) the record, publishes it and then
+/// [clears](CrashRecordStore::clear) it, so each record is only reported once.
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait CrashRecordStore {
+    /// Persists `record`, the output of [CrashRecord::to_bytes], replacing any previously stored record.
+    fn store(&self, record: &[u8]) -> Result<()>;
+
+    /// Returns the record persisted by a previous boot, if any.
+    fn retrieve(&self) -> Option<Vec<u8>>;
+
+    /// Removes the persisted record.
+    fn clear(&self) -> Result<()>;
+}
+
+/// The `LogTail` trait provides the most recent output of a log that is kept in memory.
+///
+/// This trait is intended to be implemented by the logger (e.g. the advanced logger) and consumed by the core when
+/// capturing a [CrashRecord]. The core may call [tail](LogTail::tail) from an exception handler, so implementations
+/// must not allocate or block.
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait LogTail {
+    /// Copies the most recent log output into `buffer`, up to its length, and returns the number of bytes copied.
+    fn tail(&self, buffer: &mut [u8]) -> usize;
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn record() -> CrashRecord {
+        CrashRecord {
+            reason: CrashReason::WatchdogTimeout { period: 3_000_000_000 },
+            tpl: 8,
+            running_image: Some("HungDriver.efi".to_string()),
+            registers: alloc::vec![
+                CrashRegister { name: "RIP".to_string(), value: 0x7FF0_1234 },
+                CrashRegister { name: "RSP".to_string(), value: 0x7FFF_0000 },
+            ],
+            frames: alloc::vec![
+                CrashFrame { pc: 0x7FF0_1234, module: Some("HungDriver".to_string()), offset: 0x1234 },
+                CrashFrame { pc: 0xDEAD_BEEF, module: None, offset: 0 },
+            ],
+            modules: alloc::vec![
+                CrashModule { name: Some("HungDriver.efi".to_string()), base: 0x7FF0_0000, size: 0x8000 },
+                CrashModule { name: None, base: 0x8000_0000, size: 0x1000 },
+            ],
+            log_tail: "INFO - Starting driver\nINFO - Waiting for device\n".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_crash_record_round_trips() {
+        let record = record();
+        assert_eq!(CrashRecord::from_bytes(&record.to_bytes()), Some(record));
+
+        let empty = CrashRecord {
+            reason: CrashReason::Exception { exception_type: 0xE },
+            tpl: 4,
+            running_image: None,
+            registers: Vec::new(),
+            frames: Vec::new(),
+            modules: Vec::new(),
+            log_tail: String::new(),
+        };
+        assert_eq!(CrashRecord::from_bytes(&empty.to_bytes()), Some(empty));
+    }
+
+    fn write(record: &CrashRecord, buffer: &mut [u8]) -> Option<Vec<u8>> {
+        let mut writer = CrashRecordWriter::new(buffer, record.reason, record.tpl, record.running_image.as_deref())?;
+        for register in &record.registers {
+            writer.push_register(&register.name, register.value);
+        }
+        for frame in &record.frames {
+            writer.push_frame(frame.pc, frame.module.as_deref(), frame.offset);
+        }
+        for module in &record.modules {
+            writer.push_module(module.name.as_deref(), module.base, module.size);
+        }
+        let bytes = writer.finish(|buffer| {
+            let len = record.log_tail.len().min(buffer.len());
+            buffer[..len].copy_from_slice(&record.log_tail.as_bytes()[..len]);
+            len
+        });
+        Some(bytes.to_vec())
+    }
+
+    #[test]
+    fn test_crash_record_writer_matches_to_bytes() {
+        let record = record();
+        let mut buffer = [0u8; 1024];
+        assert_eq!(write(&record, &mut buffer), Some(record.to_bytes()));
+    }
+
+    #[test]
+    fn test_crash_record_writer_drops_what_does_not_fit() {
+        let record = record();
+        let size = record.to_bytes().len();
+
+        // Every buffer that holds the header produces a valid record, with the leading items of each section that fit.
+        let mut buffer = [0u8; 1024];
+        let mut valid = 0;
+        for len in 0..size {
+            let Some(bytes) = write(&record, &mut buffer[..len]) else {
+                continue;
+            };
+            valid += 1;
+            let truncated = CrashRecord::from_bytes(&bytes).expect("the writer should produce a valid record");
+            assert_eq!(truncated.reason, record.reason);
+            assert!(record.registers.starts_with(&truncated.registers));
+            assert!(record.frames.starts_with(&truncated.frames));
+            assert!(record.modules.starts_with(&truncated.modules));
+            assert!(record.log_tail.starts_with(&truncated.log_tail));
+        }
+        assert!(valid > 0);
+
+        // Items pushed out of order are dropped.
+        let mut writer = CrashRecordWriter::new(&mut buffer, record.reason, record.tpl, None).unwrap();
+        assert!(writer.push_frame(0x1000, None, 0));
+        assert!(!writer.push_register("RIP", 0x1000));
+        let truncated = CrashRecord::from_bytes(writer.finish(|_| 0)).unwrap();
+        assert!(truncated.registers.is_empty());
+        assert_eq!(truncated.frames.len(), 1);
+    }
+
+    #[test]
+    fn test_crash_record_writer_replaces_invalid_utf8_in_the_log_tail() {
+        let mut buffer = [0u8; 256];
+        let writer =
+            CrashRecordWriter::new(&mut buffer, CrashReason::Exception { exception_type: 0 }, 4, None).unwrap();
+        // A log tail cut in the middle of a multi-byte character, followed by an invalid byte.
+        let bytes = writer.finish(|buffer| {
+            let tail = [0xA9, b'o', b'k', 0xFF, b'\n'];
+            buffer[..tail.len()].copy_from_slice(&tail);
+            tail.len()
+        });
+        assert_eq!(CrashRecord::from_bytes(bytes).unwrap().log_tail, "?ok?\n");
+    }
+
+    #[test]
+    fn test_crash_record_rejects_invalid_bytes() {
+        let bytes = record().to_bytes();
+
+        for len in 0..bytes.len() {
+            assert_eq!(CrashRecord::from_bytes(&bytes[..len]), None, "truncated to {len} bytes");
+        }
+
+        let mut bad_signature = bytes.clone();
+        bad_signature[0] ^= 0xFF;
+        assert_eq!(CrashRecord::from_bytes(&bad_signature), None);
+
+        let mut bad_version = bytes.clone();
+        bad_version[4] ^= 0xFF;
+        assert_eq!(CrashRecord::from_bytes(&bad_version), None);
+
+        let mut bad_reason = bytes;
+        bad_reason[6] = 0xFF;
+        assert_eq!(CrashRecord::from_bytes(&bad_reason), None);
+    }
+}
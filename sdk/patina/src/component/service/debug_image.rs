@@ -0,0 +1,35 @@
+//! Debug Image Related Service Definitions.
+//!
+//! This module contains the [DebugImageRegistry] service, which lets components describe code regions that were not
+//! loaded by the core as PE images (e.g. MM images or option ROMs) so that external debuggers can resolve them.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use crate::error::Result;
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+/// The `DebugImageRegistry` trait registers code regions with the `EFI_DEBUG_IMAGE_INFO_TABLE` and the debugger.
+///
+/// This trait is implemented by the core. Images loaded with `LoadImage()` and the core itself are registered
+/// automatically, as are the sub-ranges of the core's image holding Patina components; this service is only needed
+/// for code the core did not load.
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait DebugImageRegistry {
+    /// Registers the code region of `size` bytes at `base` under `name`.
+    ///
+    /// Returns [EfiError::InvalidParameter](crate::error::EfiError::InvalidParameter) if the region is empty or wraps
+    /// the address space, and [EfiError::AlreadyStarted](crate::error::EfiError::AlreadyStarted) if it overlaps a
+    /// region that is already registered.
+    fn register_region(&self, name: &str, base: u64, size: u64) -> Result<()>;
+
+    /// Removes the code region registered at `base`.
+    ///
+    /// Returns [EfiError::NotFound](crate::error::EfiError::NotFound) if no region is registered at `base`.
+    fn unregister_region(&self, base: u64) -> Result<()>;
+}
@@ -0,0 +1,44 @@
+//! DMA Related Service Definitions.
+//!
+//! This module contains traits and types for services that let the core observe DMA activity it does not perform
+//! itself. See [DmaMappingTracker].
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use r_efi::efi;
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+/// A DMA mapping that has been mapped for a device and not yet unmapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaMapping {
+    /// The host (CPU) physical address of the mapped buffer.
+    pub host_address: efi::PhysicalAddress,
+    /// The address the device uses to access the buffer.
+    pub device_address: efi::PhysicalAddress,
+    /// The size of the mapping in bytes.
+    pub number_of_bytes: usize,
+}
+
+/// The `DmaMappingTracker` trait reports the DMA mappings that are currently outstanding.
+///
+/// DMA mappings are created and destroyed by bus and IOMMU drivers, so the core has no visibility into them on its
+/// own. This trait is intended to be implemented by the platform component that owns DMA mapping (e.g. the PCI host
+/// bridge or IOMMU driver) and consumed by the core, which reports any mappings still outstanding at
+/// ExitBootServices, as devices left bus-mastering into firmware memory corrupt the OS.
+///
+/// The core queries the tracker after the memory map has been finalized, so implementations must not allocate memory.
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait DmaMappingTracker {
+    /// Returns the number of outstanding DMA mappings.
+    fn outstanding_mapping_count(&self) -> usize;
+
+    /// Returns the outstanding DMA mapping at `index`, or `None` if `index` is not less than
+    /// [`outstanding_mapping_count`](DmaMappingTracker::outstanding_mapping_count).
+    fn outstanding_mapping(&self, index: usize) -> Option<DmaMapping>;
+}
@@ -0,0 +1,328 @@
+//! Service lifecycle hooks.
+//!
+//! A service registered with [Storage::add_service] lives for the rest of boot, but its lifecycle has three points of
+//! interest to producers and consumers:
+//!
+//! - **Versioning**: A service implementing [ServiceLifecycle] reports the [Version] of the interface(s) it
+//!   implements. A consumer that relies on a newer revision of an interface can require a minimum version with the
+//!   [VersionedService] param, and will not be dispatched until a compatible implementation is produced.
+//! - **Replacement**: Adding a service that is already registered replaces it. Consumers that hold on to the service
+//!   beyond their entry point, such as by storing it in another service or a static, can register an observer with
+//!   [Storage::on_service_replaced] or [Commands::on_service_replaced](crate::component::params::Commands::on_service_replaced)
+//!   to be given the new implementation.
+//! - **Teardown**: [ServiceLifecycle::on_exit_boot_services] is called when the OS loader calls ExitBootServices, in
+//!   the reverse order of registration, so that boot-time-only services can release the resources they hold.
+//!
+//! A service opts into [ServiceLifecycle] with the `#[lifecycle]` attribute of the
+//! [IntoService](patina_macro::IntoService) derive macro.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use patina::component::service::{IntoService, lifecycle::{ServiceLifecycle, Version, VersionedService}};
+//!
+//! trait MyService {}
+//!
+//! #[derive(IntoService)]
+//! #[service(dyn MyService)]
+//! #[lifecycle]
+//! struct MyServiceImpl;
+//!
+//! impl MyService for MyServiceImpl {}
+//!
+//! impl ServiceLifecycle for MyServiceImpl {
+//!     fn version(&self) -> Option<Version> {
+//!         Some(Version::new(1, 2, 0))
+//!     }
+//!
+//!     fn on_exit_boot_services(&self) {
+//!         // Release boot-time-only resources.
+//!     }
+//! }
+//!
+//! // Not dispatched until a 1.x implementation of `MyService`, with x >= 2, is produced.
+//! fn my_component(service: VersionedService<dyn MyService, 1, 2>) -> patina::error::Result<()> {
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::{any::Any, fmt, ops::Deref, ptr};
+
+use crate::component::{
+    metadata::MetaData,
+    params::Param,
+    service::Service,
+    storage::{Storage, UnsafeStorageCell},
+};
+
+/// The semantic version of a service interface.
+///
+/// An implementation of version `major.minor.patch` is compatible with a consumer that requires `major.x` if `minor`
+/// is at least `x`. Implementations with a different major version are never compatible.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    /// Incremented for incompatible changes to the interface.
+    pub major: u16,
+    /// Incremented for backwards compatible additions to the interface.
+    pub minor: u16,
+    /// Incremented for backwards compatible fixes to the implementation.
+    pub patch: u16,
+}
+
+impl Version {
+    /// Creates a new version.
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self { major, minor, patch }
+    }
+
+    /// Returns whether this version satisfies a requirement of at least `major.minor`.
+    pub const fn satisfies(&self, major: u16, minor: u16) -> bool {
+        self.major == major && self.minor >= minor
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Lifecycle hooks of a service.
+///
+/// Registered along with the service by the [IntoService](patina_macro::IntoService) derive macro when the
+/// `#[lifecycle]` attribute is present.
+pub trait ServiceLifecycle {
+    /// Returns the version of the interface(s) implemented by the service, or `None` if it is not versioned.
+    ///
+    /// Unversioned services never satisfy a [VersionedService] requirement.
+    fn version(&self) -> Option<Version> {
+        None
+    }
+
+    /// Called once when the OS loader calls ExitBootServices.
+    ///
+    /// Implementations must not allocate or free memory, as doing so changes the memory map. Other services may
+    /// already have been torn down, so implementations should only release resources they own.
+    fn on_exit_boot_services(&self) {}
+}
+
+/// An observer of a service, called with the raw service each time it is replaced.
+type Observer = Box<dyn Fn(&'static dyn Any)>;
+
+/// The lifecycle state of the services in [Storage].
+#[derive(Default)]
+pub(crate) struct Lifecycles {
+    /// The version of each versioned service, by service id.
+    versions: BTreeMap<usize, Version>,
+    /// The services to tear down at ExitBootServices, in registration order.
+    teardowns: Vec<&'static dyn ServiceLifecycle>,
+    /// The replacement observers of each service, by service id.
+    observers: BTreeMap<usize, Vec<Observer>>,
+}
+
+impl Lifecycles {
+    /// Creates an empty set of lifecycles.
+    pub(crate) const fn new() -> Self {
+        Self { versions: BTreeMap::new(), teardowns: Vec::new(), observers: BTreeMap::new() }
+    }
+
+    /// Registers the lifecycle of the service denoted by `id`. A datum registered as multiple services is only torn
+    /// down once.
+    pub(crate) fn register(&mut self, id: usize, lifecycle: &'static dyn ServiceLifecycle) {
+        if let Some(version) = lifecycle.version() {
+            self.versions.insert(id, version);
+        }
+        if !self.teardowns.iter().any(|registered| ptr::addr_eq(*registered, lifecycle)) {
+            self.teardowns.push(lifecycle);
+        }
+    }
+
+    /// Returns the version of the service denoted by `id`, if it is versioned.
+    pub(crate) fn version(&self, id: usize) -> Option<Version> {
+        self.versions.get(&id).copied()
+    }
+
+    /// Registers an observer to be called each time the service denoted by `id` is replaced.
+    pub(crate) fn observe(&mut self, id: usize, observer: Observer) {
+        self.observers.entry(id).or_default().push(observer);
+    }
+
+    /// Clears the version of the replaced service denoted by `id`, and notifies its observers of the `replacement`.
+    pub(crate) fn replaced(&mut self, id: usize, replacement: &'static dyn Any) {
+        self.versions.remove(&id);
+        for observer in self.observers.get(&id).into_iter().flatten() {
+            observer(replacement);
+        }
+    }
+
+    /// Tears down every registered service, in the reverse order of registration.
+    pub(crate) fn exit_boot_services(&mut self) {
+        while let Some(lifecycle) = self.teardowns.pop() {
+            lifecycle.on_exit_boot_services();
+        }
+    }
+}
+
+impl fmt::Debug for Lifecycles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lifecycles")
+            .field("versions", &self.versions)
+            .field("teardowns", &self.teardowns.len())
+            .field("observers", &self.observers.values().map(Vec::len).sum::<usize>())
+            .finish()
+    }
+}
+
+/// A [Service] whose implementation must satisfy a minimum interface [Version] of `MAJOR.MINOR`.
+///
+/// A component with this param is not dispatched until a compatible implementation of the service is produced.
+pub struct VersionedService<T: ?Sized + 'static, const MAJOR: u16, const MINOR: u16 = 0>(Service<T>);
+
+impl<T: ?Sized + 'static, const MAJOR: u16, const MINOR: u16> VersionedService<T, MAJOR, MINOR> {
+    /// Returns the underlying [Service].
+    pub fn into_inner(self) -> Service<T> {
+        self.0
+    }
+}
+
+impl<T: ?Sized + 'static, const MAJOR: u16, const MINOR: u16> Deref for VersionedService<T, MAJOR, MINOR> {
+    type Target = Service<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+unsafe impl<T: ?Sized + 'static, const MAJOR: u16, const MINOR: u16> Param for VersionedService<T, MAJOR, MINOR> {
+    type State = usize;
+    type Item<'storage, 'state> = VersionedService<T, MAJOR, MINOR>;
+
+    unsafe fn get_param<'storage, 'state>(
+        state: &'state Self::State,
+        storage: UnsafeStorageCell<'storage>,
+    ) -> Self::Item<'storage, 'state> {
+        VersionedService(unsafe { Service::get_param(state, storage) })
+    }
+
+    fn validate(state: &Self::State, storage: UnsafeStorageCell) -> bool {
+        <Service<T> as Param>::validate(state, storage)
+            && unsafe { storage.storage() }
+                .get_service_version(*state)
+                .is_some_and(|version| version.satisfies(MAJOR, MINOR))
+    }
+
+    fn init_state(storage: &mut Storage, meta: &mut MetaData) -> Self::State {
+        <Service<T> as Param>::init_state(storage, meta)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate as patina;
+    use crate::component::service::IntoService;
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    trait Counter {
+        fn count(&self) -> u32;
+    }
+
+    #[derive(IntoService)]
+    #[service(dyn Counter)]
+    #[lifecycle]
+    struct CounterImpl {
+        version: Option<Version>,
+        torn_down: &'static AtomicU32,
+    }
+
+    impl Counter for CounterImpl {
+        fn count(&self) -> u32 {
+            self.torn_down.load(Ordering::SeqCst)
+        }
+    }
+
+    impl ServiceLifecycle for CounterImpl {
+        fn version(&self) -> Option<Version> {
+            self.version
+        }
+
+        fn on_exit_boot_services(&self) {
+            self.torn_down.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn counter(version: Option<Version>, torn_down: &'static AtomicU32) -> CounterImpl {
+        CounterImpl { version, torn_down }
+    }
+
+    #[test]
+    fn test_version_compatibility() {
+        let version = Version::new(1, 2, 3);
+        assert!(version.satisfies(1, 0));
+        assert!(version.satisfies(1, 2));
+        assert!(!version.satisfies(1, 3));
+        assert!(!version.satisfies(2, 0));
+        assert!(!version.satisfies(0, 2));
+        assert_eq!(alloc::format!("{version}"), "1.2.3");
+    }
+
+    #[test]
+    fn test_versioned_service_requires_a_compatible_version() {
+        static TORN_DOWN: AtomicU32 = AtomicU32::new(0);
+        let mut storage = Storage::new();
+        let mut meta = MetaData::new::<()>();
+        let state = VersionedService::<dyn Counter, 1, 2>::init_state(&mut storage, &mut meta);
+        let validate =
+            |storage: &mut Storage| VersionedService::<dyn Counter, 1, 2>::validate(&state, (&*storage).into());
+
+        assert!(!validate(&mut storage));
+        storage.add_service(counter(None, &TORN_DOWN));
+        assert!(!validate(&mut storage));
+        storage.add_service(counter(Some(Version::new(1, 1, 0)), &TORN_DOWN));
+        assert!(!validate(&mut storage));
+        storage.add_service(counter(Some(Version::new(1, 3, 0)), &TORN_DOWN));
+        assert!(validate(&mut storage));
+        assert_eq!(storage.service_version::<dyn Counter>(), Some(Version::new(1, 3, 0)));
+
+        let service = unsafe { VersionedService::<dyn Counter, 1, 2>::get_param(&state, (&storage).into()) };
+        assert_eq!(service.count(), 0);
+    }
+
+    #[test]
+    fn test_observers_are_notified_of_replacement() {
+        static TORN_DOWN: AtomicU32 = AtomicU32::new(0);
+        static REPLACEMENTS: AtomicU32 = AtomicU32::new(0);
+        let mut storage = Storage::new();
+        storage.on_service_replaced::<dyn Counter>(|service| {
+            assert_eq!(service.count(), 0);
+            REPLACEMENTS.fetch_add(1, Ordering::SeqCst);
+        });
+
+        storage.add_service(counter(Some(Version::new(1, 0, 0)), &TORN_DOWN));
+        assert_eq!(REPLACEMENTS.load(Ordering::SeqCst), 0);
+        storage.add_service(counter(None, &TORN_DOWN));
+        assert_eq!(REPLACEMENTS.load(Ordering::SeqCst), 1);
+        assert_eq!(storage.service_version::<dyn Counter>(), None);
+    }
+
+    #[test]
+    fn test_services_are_torn_down_once_at_exit_boot_services() {
+        static TORN_DOWN: AtomicU32 = AtomicU32::new(0);
+        let mut storage = Storage::new();
+        storage.add_service(counter(None, &TORN_DOWN));
+        storage.add_service(counter(None, &TORN_DOWN));
+
+        storage.exit_boot_services();
+        assert_eq!(TORN_DOWN.load(Ordering::SeqCst), 2);
+        storage.exit_boot_services();
+        assert_eq!(TORN_DOWN.load(Ordering::SeqCst), 2);
+    }
+}
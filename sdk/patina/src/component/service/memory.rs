@@ -284,6 +284,37 @@ pub trait MemoryManager {
     fn get_page_attributes(&self, address: usize, page_count: usize) -> Result<(AccessType, CachingType), MemoryError>;
 }
 
+/// The `MemoryAcceptance` trait provides the platform specific mechanism used
+/// to accept memory that was handed off as unaccepted, as is the case for
+/// confidential computing guests (e.g. Intel TDX or AMD SEV-SNP).
+///
+/// This trait is intended to be implemented by the platform and consumed by the
+/// core. When the service is registered, the core will accept unaccepted memory
+/// on demand when allocations cannot otherwise be satisfied, and will produce
+/// the `EDKII_MEMORY_ACCEPT_PROTOCOL`.
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait MemoryAcceptance {
+    /// Accepts a range of memory.
+    ///
+    /// On success, the range must be usable as system memory. The core only
+    /// calls this with page aligned ranges that are tracked as unaccepted.
+    /// The core's memory map is locked while this runs, so implementations
+    /// must not allocate or free memory, or otherwise change the memory map.
+    ///
+    /// # Parameters
+    ///
+    /// - `start`: The page aligned physical address of the range to accept.
+    /// - `size`: The size of the range in bytes. This is a multiple of
+    ///   [`UEFI_PAGE_SIZE`].
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if the range was accepted.
+    /// - `Err(EfiError)` if the range could not be accepted.
+    ///
+    fn accept_memory(&self, start: efi::PhysicalAddress, size: u64) -> Result<(), EfiError>;
+}
+
 /// The `AllocationOptions` structure allows for the caller to  specify
 /// additional constraints on the allocation. This can be used to specify the type
 /// of memory to allocate, alignment requirements, and allocation strategy. Users
@@ -0,0 +1,220 @@
+//! Publishing services as UEFI protocols.
+//!
+//! Services are only visible to Rust components. To allow drivers to be migrated to Rust one side at a time, a service
+//! interface annotated with [service_protocol] can also cross the protocol database in either direction:
+//!
+//! - **Rust to C**: A service registered with `#[service(dyn MyService, protocol = "GUID")]` is also installed as a
+//!   protocol, whose functions forward to the service. If boot services are not yet available when the service is
+//!   registered, the protocol is installed as soon as they are.
+//! - **C to Rust**: [Storage::add_protocol_service](crate::component::Storage::add_protocol_service) locates a
+//!   protocol installed by a C driver, and registers it as the `Service<dyn MyService>`, whose methods call the
+//!   protocol's functions. It is `unsafe`, as the caller must vouch for the layout and lifetime of the protocol.
+//!
+//! Replacing a service that was published as a protocol reinstalls the protocol interface with the replacement.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use patina::component::{
+//!     Storage,
+//!     service::{IntoService, Service, protocol::service_protocol},
+//! };
+//!
+//! #[service_protocol]
+//! trait Greeter {
+//!     fn greet(&self, count: u32) -> u64;
+//! }
+//!
+//! #[derive(IntoService)]
+//! #[service(dyn Greeter, protocol = "6f0d9a52-3c41-4b8e-9f27-1d5e8c03a4b6")]
+//! struct GreeterImpl;
+//!
+//! impl Greeter for GreeterImpl {
+//!     fn greet(&self, count: u32) -> u64 {
+//!         count as u64 * 2
+//!     }
+//! }
+//!
+//! // A C driver sees a `GreeterProtocol`: a table of `extern "efiapi"` functions taking the protocol as `This`.
+//! fn c_caller(protocol: &mut GreeterProtocol) -> u64 {
+//!     (protocol.greet)(protocol, 21)
+//! }
+//! ```
+//!
+//! Argument and return types must be FFI-safe, as they are passed to and from C:
+//!
+//! ```rust,compile_fail
+//! use patina::component::service::protocol::service_protocol;
+//!
+//! #[service_protocol]
+//! trait Greeter {
+//!     fn greet(&self, name: &str) -> u64;
+//! }
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::boxed::Box;
+
+pub use patina_macro::service_protocol;
+
+/// A service interface that can be published as, and consumed from, a UEFI protocol.
+///
+/// Implemented for `dyn Trait` by the [service_protocol] attribute macro; it should not be implemented manually.
+///
+/// ## Safety
+///
+/// - [Protocol](ServiceProtocol::Protocol) must be a `#[repr(C)]` layout of the protocol.
+/// - The protocol returned by [publish](ServiceProtocol::publish) must be valid for the rest of boot.
+pub unsafe trait ServiceProtocol: 'static {
+    /// The UEFI protocol layout of the service interface.
+    type Protocol: 'static;
+
+    /// Creates a protocol instance whose functions forward to `service`. The instance is leaked.
+    fn publish(service: &'static Self) -> *mut Self::Protocol;
+
+    /// Wraps a protocol instance as an implementation of the service interface, whose methods call the protocol's
+    /// functions.
+    ///
+    /// ## Safety
+    ///
+    /// `protocol` must point to a valid instance of the protocol, that remains installed for the rest of boot.
+    unsafe fn wrap(protocol: *mut Self::Protocol) -> Box<Self>;
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate as patina;
+    use crate::{
+        boot_services::StandardBootServices,
+        component::{Storage, service::IntoService},
+    };
+    use core::{
+        ffi::c_void,
+        ptr,
+        sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+    };
+    use r_efi::efi;
+
+    #[service_protocol]
+    trait Greeter {
+        fn greet(&self, count: u32) -> u64;
+        fn reset(&self);
+    }
+
+    #[derive(IntoService)]
+    #[service(dyn Greeter, protocol = "6f0d9a52-3c41-4b8e-9f27-1d5e8c03a4b6")]
+    struct GreeterImpl {
+        factor: u64,
+    }
+
+    impl Greeter for GreeterImpl {
+        fn greet(&self, count: u32) -> u64 {
+            count as u64 * self.factor
+        }
+
+        fn reset(&self) {}
+    }
+
+    const GREETER_GUID: efi::Guid =
+        efi::Guid::from_fields(0x6f0d9a52, 0x3c41, 0x4b8e, 0x9f, 0x27, &[0x1d, 0x5e, 0x8c, 0x03, 0xa4, 0xb6]);
+
+    static INSTALLED: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+    static INSTALLS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Returns boot services that hold a single protocol in [INSTALLED].
+    fn boot_services() -> StandardBootServices {
+        extern "efiapi" fn install_protocol_interface(
+            _handle: *mut efi::Handle,
+            protocol: *mut efi::Guid,
+            _interface_type: efi::InterfaceType,
+            interface: *mut c_void,
+        ) -> efi::Status {
+            assert_eq!(unsafe { *protocol }, GREETER_GUID);
+            INSTALLED.store(interface, Ordering::SeqCst);
+            INSTALLS.fetch_add(1, Ordering::SeqCst);
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn reinstall_protocol_interface(
+            _handle: efi::Handle,
+            protocol: *mut efi::Guid,
+            old_interface: *mut c_void,
+            new_interface: *mut c_void,
+        ) -> efi::Status {
+            assert_eq!(unsafe { *protocol }, GREETER_GUID);
+            match INSTALLED.compare_exchange(old_interface, new_interface, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => efi::Status::SUCCESS,
+                Err(_) => efi::Status::NOT_FOUND,
+            }
+        }
+
+        extern "efiapi" fn locate_protocol(
+            protocol: *mut efi::Guid,
+            _registration: *mut c_void,
+            out: *mut *mut c_void,
+        ) -> efi::Status {
+            let interface = INSTALLED.load(Ordering::SeqCst);
+            if unsafe { *protocol } != GREETER_GUID || interface.is_null() {
+                return efi::Status::NOT_FOUND;
+            }
+            unsafe { out.write(interface) };
+            efi::Status::SUCCESS
+        }
+
+        #[allow(invalid_value)]
+        let mut efi_bs = Box::new(core::mem::MaybeUninit::<efi::BootServices>::zeroed());
+        unsafe {
+            (*efi_bs.as_mut_ptr()).install_protocol_interface = install_protocol_interface;
+            (*efi_bs.as_mut_ptr()).reinstall_protocol_interface = reinstall_protocol_interface;
+            (*efi_bs.as_mut_ptr()).locate_protocol = locate_protocol;
+        }
+        StandardBootServices::new(unsafe { &*Box::leak(efi_bs).as_ptr() })
+    }
+
+    #[test]
+    fn test_published_protocol_forwards_to_service_and_back() {
+        let service: &'static dyn Greeter = Box::leak(Box::new(GreeterImpl { factor: 3 }));
+        let protocol = <dyn Greeter as ServiceProtocol>::publish(service);
+        assert_eq!(unsafe { ((*protocol).greet)(protocol, 2) }, 6);
+
+        let wrapped = unsafe { <dyn Greeter as ServiceProtocol>::wrap(protocol) };
+        assert_eq!(wrapped.greet(5), 15);
+        wrapped.reset();
+    }
+
+    #[test]
+    fn test_service_is_installed_once_boot_services_are_available_and_can_be_located() {
+        let mut storage = Storage::new();
+        storage.add_service(GreeterImpl { factor: 1 });
+        storage.add_service(GreeterImpl { factor: 2 });
+        assert!(INSTALLED.load(Ordering::SeqCst).is_null());
+
+        // Only the replacement is installed.
+        storage.set_boot_services(boot_services());
+        let installed = INSTALLED.load(Ordering::SeqCst) as *mut GreeterProtocol;
+        assert!(!installed.is_null());
+        assert_eq!(INSTALLS.load(Ordering::SeqCst), 1);
+        assert_eq!(unsafe { ((*installed).greet)(installed, 4) }, 8);
+
+        // Replacing the installed service reinstalls the protocol interface, rather than installing a second one.
+        storage.add_service(GreeterImpl { factor: 5 });
+        let reinstalled = INSTALLED.load(Ordering::SeqCst) as *mut GreeterProtocol;
+        assert_ne!(reinstalled, installed);
+        assert_eq!(INSTALLS.load(Ordering::SeqCst), 1);
+        assert_eq!(unsafe { ((*reinstalled).greet)(reinstalled, 4) }, 20);
+        storage.add_service(GreeterImpl { factor: 2 });
+
+        let mut consumer = Storage::new();
+        consumer.set_boot_services(boot_services());
+        // SAFETY: The protocol was published from `dyn Greeter`, and is never uninstalled.
+        unsafe { consumer.add_protocol_service::<dyn Greeter>(&GREETER_GUID) }.unwrap();
+        assert_eq!(consumer.get_service::<dyn Greeter>().unwrap().greet(7), 14);
+    }
+}
@@ -13,20 +13,30 @@ use crate::{
     runtime_services::StandardRuntimeServices,
 };
 
-use crate::{OwnedGuid, boot_services::StandardBootServices};
+use crate::{
+    OwnedGuid,
+    boot_services::{BootServices, StandardBootServices},
+    error::EfiError,
+};
 use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
 use core::{
     any::{Any, TypeId},
     cell::{Ref, RefCell, RefMut, UnsafeCell},
+    ffi::c_void,
     fmt::Debug,
     marker::PhantomData,
     ops::{Deref, DerefMut},
     ptr,
 };
+use r_efi::efi;
 
 use super::{
     hob::{FromHob, Hob},
-    service::{IntoService, Service},
+    service::{
+        IntoService, Service,
+        lifecycle::{Lifecycles, ServiceLifecycle, Version},
+        protocol::ServiceProtocol,
+    },
 };
 
 type HobParsers = BTreeMap<OwnedGuid, BTreeMap<TypeId, fn(&[u8], &mut Storage)>>;
@@ -204,6 +214,11 @@ pub struct Storage {
     services: SparseVec<&'static dyn Any>,
     /// A map to convert a Service type to a concrete service index.
     service_indices: BTreeMap<TypeId, usize>,
+    /// The versions, teardown hooks and replacement observers of the services.
+    service_lifecycles: Lifecycles,
+    /// Services published as protocols, with the handle they are installed on, or `None` if boot services were not yet
+    /// available to install them.
+    protocols: Vec<(&'static efi::Guid, *mut c_void, Option<efi::Handle>)>,
     /// HOB parsers for converting guided HOBs into `Hob<T>` datums.
     hob_parsers: HobParsers,
     /// A container for all [Hob](super::hob::Hob) datums.
@@ -231,6 +246,8 @@ impl Storage {
             config_indices: BTreeMap::new(),
             services: SparseVec::new(),
             service_indices: BTreeMap::new(),
+            service_lifecycles: Lifecycles::new(),
+            protocols: Vec::new(),
             hob_parsers: BTreeMap::new(),
             hobs: SparseVec::new(),
             hob_indices: BTreeMap::new(),
@@ -239,7 +256,8 @@ impl Storage {
         }
     }
 
-    /// Applies all deferred actions to the storage. Used in a multi-threaded context
+    /// Applies all deferred actions to the storage. Used in a multi-threaded context, where components are run with
+    /// [Component::run_unsafe](super::Component::run_unsafe) and their deferred actions are applied between batches.
     pub(crate) fn apply_deferred(&mut self) {
         if let Some(mut deferred) = self.deferred.take() {
             deferred.apply(self);
@@ -257,9 +275,15 @@ impl Storage {
         self.deferred.as_mut().unwrap()
     }
 
-    /// Stores a pointer to the UEFI Boot Services Table.
+    /// Stores a pointer to the UEFI Boot Services Table, and installs the services that were published as protocols
+    /// before it was available.
     pub fn set_boot_services(&mut self, bs: StandardBootServices) {
         self.boot_services = bs;
+        for index in 0..self.protocols.len() {
+            if let (guid, interface, None) = self.protocols[index] {
+                self.protocols[index].2 = self.install_protocol(guid, interface);
+            }
+        }
     }
 
     /// Stores a pointer to the UEFI Runtime Services Table.
@@ -351,9 +375,98 @@ impl Storage {
         *self.service_indices.entry(id).or_insert(idx)
     }
 
-    /// Inserts a service into the storage.
+    /// Inserts a service into the storage, notifying the observers of the service if it replaces another one.
     pub(crate) fn insert_service(&mut self, id: usize, service: &'static dyn Any) {
+        let replaced = self.services.contains(id);
         self.services.insert(id, service);
+        if replaced {
+            self.service_lifecycles.replaced(id, service);
+        }
+    }
+
+    /// Registers the lifecycle hooks of the service denoted by `id`.
+    pub(crate) fn insert_service_lifecycle(&mut self, id: usize, lifecycle: &'static dyn ServiceLifecycle) {
+        self.service_lifecycles.register(id, lifecycle);
+    }
+
+    /// Returns the version of the service denoted by `id`, if it is versioned.
+    pub(crate) fn get_service_version(&self, id: usize) -> Option<Version> {
+        self.service_lifecycles.version(id)
+    }
+
+    /// Returns the version of the service `S`, if it is produced and versioned.
+    pub fn service_version<S: ?Sized + 'static>(&self) -> Option<Version> {
+        self.get_service_version(*self.service_indices.get(&TypeId::of::<S>())?)
+    }
+
+    /// Registers an `observer` to be called with the new implementation each time the service `S` is replaced.
+    pub fn on_service_replaced<S: ?Sized + 'static>(&mut self, observer: impl Fn(Service<S>) + 'static) {
+        let id = self.register_service::<S>();
+        self.service_lifecycles.observe(id, Box::new(move |service| observer(Service::from(service))));
+    }
+
+    /// Installs the service `service` as a protocol with the given `guid`, as soon as boot services are available. If a
+    /// service was already published with `guid`, its protocol interface is reinstalled with the new one.
+    pub(crate) fn publish_protocol<S: ?Sized + ServiceProtocol>(
+        &mut self,
+        guid: &'static efi::Guid,
+        service: &'static S,
+    ) {
+        let interface = S::publish(service) as *mut c_void;
+        let Some(index) = self.protocols.iter().position(|(published, _, _)| *published == guid) else {
+            let handle = if self.boot_services.is_init() { self.install_protocol(guid, interface) } else { None };
+            self.protocols.push((guid, interface, handle));
+            return;
+        };
+
+        let (_, old_interface, handle) = self.protocols[index];
+        self.protocols[index].1 = interface;
+        if let Some(handle) = handle {
+            // SAFETY: Both interfaces were created by `ServiceProtocol::publish`, and are valid for the rest of boot.
+            let result = unsafe {
+                self.boot_services.reinstall_protocol_interface_unchecked(handle, guid, old_interface, interface)
+            };
+            if let Err(status) = result {
+                log::error!("Failed to reinstall service protocol {guid:?}: {status:?}");
+            }
+        }
+    }
+
+    fn install_protocol(&self, guid: &'static efi::Guid, interface: *mut c_void) -> Option<efi::Handle> {
+        // SAFETY: The interface was created by `ServiceProtocol::publish`, and is valid for the rest of boot.
+        match unsafe { self.boot_services.install_protocol_interface_unchecked(None, guid, interface) } {
+            Ok(handle) => Some(handle),
+            Err(status) => {
+                log::error!("Failed to install service protocol {guid:?}: {status:?}");
+                None
+            }
+        }
+    }
+
+    /// Locates the protocol with the given `guid`, installed by a C driver, and registers it as the service `S`.
+    ///
+    /// ## Safety
+    ///
+    /// - The interface of the protocol with `guid` must have the [S::Protocol](ServiceProtocol::Protocol) layout.
+    /// - The protocol must remain installed, and its interface valid, for the rest of boot, as the service holds on to
+    ///   it after this returns.
+    pub unsafe fn add_protocol_service<S: ?Sized + ServiceProtocol>(
+        &mut self,
+        guid: &'static efi::Guid,
+    ) -> Result<(), EfiError> {
+        // SAFETY: The caller guarantees that the protocol with `guid` has the `S::Protocol` layout.
+        let interface = unsafe { self.boot_services.locate_protocol_unchecked(guid, ptr::null_mut()) }?;
+        // SAFETY: The caller guarantees that the protocol remains installed for the rest of boot.
+        let service: &'static S = Box::leak(unsafe { S::wrap(interface as *mut S::Protocol) });
+        let id = self.register_service::<S>();
+        self.insert_service(id, Box::leak(Box::new(service)));
+        Ok(())
+    }
+
+    /// Calls the [on_exit_boot_services](ServiceLifecycle::on_exit_boot_services) hook of every service, in the
+    /// reverse order of registration. Each hook is only called once, no matter how many times this is called.
+    pub fn exit_boot_services(&mut self) {
+        self.service_lifecycles.exit_boot_services();
     }
 
     /// Adds a new service to the storage.
@@ -99,6 +99,16 @@ where
         &self.metadata
     }
 
+    /// Returns whether the entry point consumes the Component.
+    fn runs_once(&self) -> bool {
+        Func::RUNS_ONCE
+    }
+
+    /// Returns the address of this Component's `run_unsafe`, which is monomorphized for (and so unique to) it.
+    fn code_address(&self) -> Option<usize> {
+        Some(Self::run_unsafe as unsafe fn(&mut Self, UnsafeStorageCell) -> Result<bool> as usize)
+    }
+
     /// One-time initialization of the Component. Should set [Access](super::metadata::Access) requirements.
     fn initialize(&mut self, _storage: &mut Storage) {
         self.param_state = Some(Func::Param::init_state(_storage, &mut self.metadata));
@@ -175,6 +185,15 @@ mod tests {
         let _ = test_enum.into_component();
     }
 
+    #[test]
+    fn test_components_have_distinct_code_addresses() {
+        let success = TestStructSuccess { x: 5 }.into_component();
+        let fail = TestStructFail { x: 5 }.into_component();
+
+        assert!(success.code_address().is_some());
+        assert_ne!(success.code_address(), fail.code_address());
+    }
+
     #[test]
     fn test_component_run_handling_works_as_expected() {
         let mut storage = crate::component::storage::Storage::new();
@@ -286,4 +305,19 @@ mod tests {
 
         let _ = ByMut { _x: 5 }.into_component();
     }
+
+    #[test]
+    fn test_only_entry_points_that_consume_self_run_once() {
+        #[derive(crate::component::IntoComponent)]
+        struct ByMut;
+
+        impl ByMut {
+            fn entry_point(&mut self) -> crate::error::Result<()> {
+                Ok(())
+            }
+        }
+
+        assert!(TestStructSuccess { x: 5 }.into_component().runs_once());
+        assert!(!ByMut.into_component().runs_once());
+    }
 }
@@ -0,0 +1,106 @@
+//! Component trigger definitions.
+//!
+//! By default, a [Component](super::Component) is executed once by the core's dispatch loop, as soon as all of its
+//! parameters are available. A [Trigger] instead ties the execution of a component to an event: the core creates the
+//! event on the component's behalf and runs the component every time the event is signaled, with the same
+//! [Param](super::params::Param) injection and [Storage](super::Storage) access rules as a dispatched component.
+//!
+//! Triggered components are always executed at [Trigger::TPL] (`TPL_CALLBACK`). The core raises or restores the TPL
+//! back to `TPL_CALLBACK` if a component returns at any other TPL.
+//!
+//! A component whose entry point consumes `self` can only be executed once. Such a component is disarmed after it has
+//! executed, so it should only be used with triggers that are expected to fire once (e.g. [Trigger::END_OF_DXE]).
+//! Components whose entry point takes `&self` or `&mut self` are executed every time the trigger fires.
+//!
+//! ## Example
+//!
+//! The components below are registered with the core via `Core::with_triggered_component`, e.g.
+//! `.with_triggered_component(Trigger::END_OF_DXE, LockDown)` and
+//! `.with_triggered_component(Trigger::periodic_ms(1000), Heartbeat)`.
+//!
+//! ```rust
+//! use patina::component::{IntoComponent, params::Config};
+//!
+//! #[derive(IntoComponent)]
+//! struct LockDown;
+//!
+//! impl LockDown {
+//!     fn entry_point(self, _config: Config<u32>) -> patina::error::Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[derive(IntoComponent)]
+//! struct Heartbeat;
+//!
+//! impl Heartbeat {
+//!     fn entry_point(&mut self) -> patina::error::Result<()> {
+//!         log::info!("Still alive!");
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use r_efi::efi;
+
+use crate::guids::EVENT_GROUP_END_OF_DXE;
+
+/// The event that triggers the execution of a [Component](super::Component).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// The component is executed every time the event group is signaled.
+    EventGroup(efi::Guid),
+    /// The component is executed every time an instance of the protocol is installed or reinstalled. The component is
+    /// not executed for instances that were installed before the core arms the trigger.
+    ProtocolNotify(efi::Guid),
+    /// The component is executed periodically, with the period in 100ns units. The first execution happens one
+    /// period after the core arms the trigger.
+    Periodic(u64),
+}
+
+impl Trigger {
+    /// The TPL at which all triggered components are executed.
+    pub const TPL: efi::Tpl = efi::TPL_CALLBACK;
+
+    /// Triggers on `EFI_EVENT_GROUP_READY_TO_BOOT`, which may be signaled more than once.
+    pub const READY_TO_BOOT: Trigger = Trigger::EventGroup(efi::EVENT_GROUP_READY_TO_BOOT);
+
+    /// Triggers on `EFI_END_OF_DXE_EVENT_GROUP_GUID`.
+    pub const END_OF_DXE: Trigger = Trigger::EventGroup(EVENT_GROUP_END_OF_DXE);
+
+    /// Triggers on `EFI_EVENT_GROUP_EXIT_BOOT_SERVICES`.
+    ///
+    /// Components triggered by ExitBootServices must not allocate or free memory, as doing so changes the memory map.
+    pub const EXIT_BOOT_SERVICES: Trigger = Trigger::EventGroup(efi::EVENT_GROUP_EXIT_BOOT_SERVICES);
+
+    /// Triggers periodically, every `period` milliseconds.
+    pub const fn periodic_ms(period: u64) -> Trigger {
+        Trigger::Periodic(period * 10_000)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_periodic_ms_is_converted_to_100ns_units() {
+        assert_eq!(Trigger::periodic_ms(0), Trigger::Periodic(0));
+        assert_eq!(Trigger::periodic_ms(1), Trigger::Periodic(10_000));
+        assert_eq!(Trigger::periodic_ms(250), Trigger::Periodic(2_500_000));
+    }
+
+    #[test]
+    fn test_well_known_triggers_use_the_event_group_guids() {
+        assert_eq!(Trigger::READY_TO_BOOT, Trigger::EventGroup(efi::EVENT_GROUP_READY_TO_BOOT));
+        assert_eq!(Trigger::EXIT_BOOT_SERVICES, Trigger::EventGroup(efi::EVENT_GROUP_EXIT_BOOT_SERVICES));
+        assert_ne!(Trigger::END_OF_DXE, Trigger::READY_TO_BOOT);
+    }
+}
@@ -53,6 +53,12 @@
 //! uefi_driver_binding.install().unwrap();
 //!
 //! ```
+//!
+//! Bus drivers that create child handles can use [`bus::BusDriverBinding`] instead of implementing [`DriverBinding`]
+//! directly.
+
+#[cfg(feature = "unstable-device-path")]
+pub mod bus;
 
 #[cfg(any(test, feature = "mockall"))]
 use mockall::automock;
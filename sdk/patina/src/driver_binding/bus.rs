@@ -0,0 +1,791 @@
+//! A typed bus driver model built on top of [`DriverBinding`].
+//!
+//! Bus drivers written directly against [`DriverBinding`] have to open the consumed protocol `BY_DRIVER`, build a
+//! device path for each child, install the produced protocols on new child handles, open the parent protocol
+//! `BY_CHILD_CONTROLLER` on behalf of each child and undo all of it, in the right order, when the controller is
+//! stopped or when starting fails half way. [`BusDriverBinding`] does this bookkeeping and lets a [`BusDriver`]
+//! only describe what it consumes, what it produces and which children exist.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use alloc::{vec, vec::Vec};
+//! # extern crate alloc;
+//!
+//! use r_efi::efi;
+//!
+//! use patina::boot_services::StandardBootServices;
+//! use patina::driver_binding::{
+//!     UefiDriverBinding,
+//!     bus::{BusDriver, Child},
+//! };
+//! use patina::uefi_protocol::{ProtocolInterface, device_path::{DevicePath, nodes::Pci}};
+//!
+//! #[repr(C)]
+//! struct RootBridgeIo {/* ... */}
+//!
+//! unsafe impl ProtocolInterface for RootBridgeIo {
+//!     const PROTOCOL_GUID: efi::Guid =
+//!         efi::Guid::from_fields(0x2f707ebb, 0x4a1a, 0x11d4, 0x9a, 0x38, &[0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d]);
+//! }
+//!
+//! #[repr(C)]
+//! struct PciIo {/* ... */}
+//!
+//! unsafe impl ProtocolInterface for PciIo {
+//!     const PROTOCOL_GUID: efi::Guid =
+//!         efi::Guid::from_fields(0x4cf5b200, 0x68b8, 0x4ca5, 0x9e, 0xec, &[0xb2, 0x3e, 0x3f, 0x50, 0x02, 0x9a]);
+//! }
+//!
+//! struct PciBus;
+//!
+//! impl BusDriver for PciBus {
+//!     type Consumes = RootBridgeIo;
+//!     type Produces = PciIo;
+//!
+//!     fn enumerate(
+//!         &mut self,
+//!         _controller: efi::Handle,
+//!         _root_bridge: &mut RootBridgeIo,
+//!         _remaining_device_path: Option<&DevicePath>,
+//!     ) -> Result<Vec<Child<PciIo>>, efi::Status> {
+//!         // Scan the bus and describe one child per function found.
+//!         Ok(vec![Child::new(Pci { function: 0, device: 0 }, PciIo {})])
+//!     }
+//! }
+//!
+//! let handle = 0 as usize as efi::Handle;
+//! static BOOT_SERVICES: StandardBootServices = StandardBootServices::new_uninit();
+//!
+//! let mut uefi_driver_binding = UefiDriverBinding::new_bus_driver(PciBus, handle, &BOOT_SERVICES);
+//! uefi_driver_binding.install().unwrap();
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::{ffi::c_void, iter, ptr::NonNull, slice};
+
+use r_efi::{
+    efi,
+    protocols::device_path::{PROTOCOL_GUID as DEVICE_PATH_GUID, Protocol as EfiDevicePathProtocol},
+};
+
+use crate::{
+    boot_services::BootServices,
+    uefi_protocol::{
+        ProtocolInterface,
+        device_path::{DevicePath, DevicePathBuf, device_path_node::DevicePathNode},
+    },
+};
+
+use super::{DriverBinding, UefiDriverBinding};
+
+/// A bus driver that consumes one protocol from its controller and produces one protocol per child.
+pub trait BusDriver: 'static {
+    /// The protocol consumed from the controller. It is opened `BY_DRIVER` while the driver manages the controller.
+    type Consumes: ProtocolInterface + 'static;
+    /// The protocol installed on every child handle created by the driver.
+    type Produces: ProtocolInterface + 'static;
+
+    /// Returns whether the driver supports the controller, given the consumed protocol.
+    ///
+    /// Only called once the consumed protocol has been successfully opened `BY_DRIVER`, so the default
+    /// implementation accepts every controller producing [`Self::Consumes`].
+    fn supported(&self, controller: efi::Handle, parent: &Self::Consumes) -> bool {
+        let _ = (controller, parent);
+        true
+    }
+
+    /// Enumerates the children of the controller.
+    ///
+    /// When a remaining device path is provided, the driver should only return the child it describes. Returned children
+    /// whose device path matches a child that already exists for the controller are not created again.
+    fn enumerate(
+        &mut self,
+        controller: efi::Handle,
+        parent: &mut Self::Consumes,
+        remaining_device_path: Option<&DevicePath>,
+    ) -> Result<Vec<Child<Self::Produces>>, efi::Status>;
+
+    /// Called after a child has been removed, giving back ownership of its protocol interface.
+    fn child_stopped(&mut self, controller: efi::Handle, child: efi::Handle, interface: Box<Self::Produces>) {
+        let _ = (controller, child, interface);
+    }
+
+    /// Called once every child has been removed and before the consumed protocol is closed.
+    fn stopped(&mut self, controller: efi::Handle, parent: &mut Self::Consumes) {
+        let _ = (controller, parent);
+    }
+}
+
+/// A child described by a [`BusDriver`].
+pub struct Child<P> {
+    node: DevicePathBuf,
+    interface: Box<P>,
+}
+
+impl<P> Child<P> {
+    /// Create a child whose device path is the controller device path followed by `node`.
+    pub fn new<N: DevicePathNode>(node: N, interface: P) -> Self {
+        Self { node: DevicePathBuf::from_device_path_node_iter(iter::once(node)), interface: Box::new(interface) }
+    }
+
+    /// Create a child whose device path is the controller device path followed by `device_path`.
+    pub fn with_device_path(device_path: DevicePathBuf, interface: P) -> Self {
+        Self { node: device_path, interface: Box::new(interface) }
+    }
+}
+
+/// A child handle created and owned by a [`BusDriverBinding`].
+struct ChildRecord<P> {
+    handle: efi::Handle,
+    device_path: Box<DevicePath>,
+    interface: NonNull<P>,
+}
+
+/// Bookkeeping for a controller started by a [`BusDriverBinding`].
+struct ControllerState<C, P> {
+    parent: NonNull<C>,
+    children: Vec<ChildRecord<P>>,
+}
+
+/// Adapts a [`BusDriver`] into a [`DriverBinding`].
+///
+/// The consumed protocol is opened `BY_DRIVER` for as long as the controller is started, each child gets a device
+/// path and the produced protocol installed on a new handle, and the parent protocol is opened
+/// `BY_CHILD_CONTROLLER` for each child. Everything is torn down in `Stop`, or as soon as `Start` fails.
+pub struct BusDriverBinding<D: BusDriver> {
+    driver: D,
+    driver_binding_handle: efi::Handle,
+    controllers: BTreeMap<usize, ControllerState<D::Consumes, D::Produces>>,
+}
+
+impl<D: BusDriver> BusDriverBinding<D> {
+    /// Create a new bus driver binding. `driver_binding_handle` is used as the agent when opening protocols.
+    pub fn new(driver: D, driver_binding_handle: efi::Handle) -> Self {
+        Self { driver, driver_binding_handle, controllers: BTreeMap::new() }
+    }
+
+    /// Returns the wrapped bus driver.
+    pub fn driver(&self) -> &D {
+        &self.driver
+    }
+
+    /// Returns the handles of the children currently managed for `controller`.
+    pub fn children(&self, controller: efi::Handle) -> Vec<efi::Handle> {
+        self.controllers
+            .get(&(controller as usize))
+            .map(|state| state.children.iter().map(|child| child.handle).collect())
+            .unwrap_or_default()
+    }
+
+    #[allow(clippy::mut_from_ref)] // The interface is owned by the protocol database, not borrowed from self.
+    fn open_parent<T: BootServices>(
+        &self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+    ) -> Result<&'static mut D::Consumes, efi::Status> {
+        // SAFETY: ProtocolInterface guarantees the interface matches the protocol guid.
+        unsafe {
+            let interface = boot_services.open_protocol_unchecked(
+                controller,
+                &D::Consumes::PROTOCOL_GUID,
+                self.driver_binding_handle,
+                controller,
+                efi::OPEN_PROTOCOL_BY_DRIVER,
+            )?;
+            (interface as *mut D::Consumes).as_mut().ok_or(efi::Status::UNSUPPORTED)
+        }
+    }
+
+    fn close_parent<T: BootServices>(&self, boot_services: &'static T, controller: efi::Handle) {
+        if let Err(status) = boot_services.close_protocol(
+            controller,
+            &D::Consumes::PROTOCOL_GUID,
+            self.driver_binding_handle,
+            controller,
+        ) {
+            log::error!("Bus driver failed to close the consumed protocol on {controller:?}: {status:?}");
+        }
+    }
+
+    fn create_child<T: BootServices>(
+        &self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        device_path: Box<DevicePath>,
+        interface: Box<D::Produces>,
+    ) -> Result<ChildRecord<D::Produces>, efi::Status> {
+        let device_path_ptr = device_path.as_ref() as *const DevicePath as *const u8 as *mut c_void;
+
+        // SAFETY: The device path is a valid, EndEntire terminated device path that lives as long as the child.
+        let handle =
+            unsafe { boot_services.install_protocol_interface_unchecked(None, &DEVICE_PATH_GUID, device_path_ptr) }?;
+
+        let interface = NonNull::from(Box::leak(interface));
+        // SAFETY: ProtocolInterface guarantees the interface matches the protocol guid.
+        if let Err(status) = unsafe {
+            boot_services.install_protocol_interface_unchecked(
+                Some(handle),
+                &D::Produces::PROTOCOL_GUID,
+                interface.as_ptr() as *mut c_void,
+            )
+        } {
+            // SAFETY: The device path has just been installed on this handle with this pointer.
+            _ = unsafe {
+                boot_services.uninstall_protocol_interface_unchecked(handle, &DEVICE_PATH_GUID, device_path_ptr)
+            };
+            // SAFETY: The interface was leaked above and never published.
+            drop(unsafe { Box::from_raw(interface.as_ptr()) });
+            return Err(status);
+        }
+
+        let record = ChildRecord { handle, device_path, interface };
+        if let Err(status) = self.open_by_child(boot_services, controller, handle) {
+            if let Err((_, record)) = self.destroy_child(boot_services, controller, record) {
+                log::error!("Bus driver failed to remove partially created child {:?}.", record.handle);
+            }
+            return Err(status);
+        }
+        Ok(record)
+    }
+
+    fn open_by_child<T: BootServices>(
+        &self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        child: efi::Handle,
+    ) -> Result<(), efi::Status> {
+        // SAFETY: The interface returned is not used, this only records the parent/child relationship.
+        unsafe {
+            boot_services.open_protocol_unchecked(
+                controller,
+                &D::Consumes::PROTOCOL_GUID,
+                self.driver_binding_handle,
+                child,
+                efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER,
+            )
+        }
+        .map(|_| ())
+    }
+
+    /// Removes a child, giving back its interface. On failure the child is left as it was and returned.
+    #[allow(clippy::type_complexity)]
+    fn destroy_child<T: BootServices>(
+        &self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        record: ChildRecord<D::Produces>,
+    ) -> Result<Box<D::Produces>, (efi::Status, ChildRecord<D::Produces>)> {
+        let device_path_ptr = record.device_path.as_ref() as *const DevicePath as *const u8 as *mut c_void;
+        let interface_ptr = record.interface.as_ptr() as *mut c_void;
+
+        // The child may not have been opened yet when creation fails, nothing to undo in that case.
+        _ = boot_services.close_protocol(
+            controller,
+            &D::Consumes::PROTOCOL_GUID,
+            self.driver_binding_handle,
+            record.handle,
+        );
+
+        // SAFETY: The interface was installed on this handle with this pointer.
+        if let Err(status) = unsafe {
+            boot_services.uninstall_protocol_interface_unchecked(
+                record.handle,
+                &D::Produces::PROTOCOL_GUID,
+                interface_ptr,
+            )
+        } {
+            _ = self.open_by_child(boot_services, controller, record.handle);
+            return Err((status, record));
+        }
+
+        // SAFETY: The device path was installed on this handle with this pointer.
+        if let Err(status) = unsafe {
+            boot_services.uninstall_protocol_interface_unchecked(record.handle, &DEVICE_PATH_GUID, device_path_ptr)
+        } {
+            // SAFETY: Restores the interface that was installed on this handle just before.
+            _ = unsafe {
+                boot_services.install_protocol_interface_unchecked(
+                    Some(record.handle),
+                    &D::Produces::PROTOCOL_GUID,
+                    interface_ptr,
+                )
+            };
+            _ = self.open_by_child(boot_services, controller, record.handle);
+            return Err((status, record));
+        }
+
+        // SAFETY: The interface was leaked when the child was created and is no longer published.
+        Ok(unsafe { Box::from_raw(record.interface.as_ptr()) })
+    }
+
+    /// Removes every child in `children`, keeping the ones that could not be removed.
+    fn destroy_children<T: BootServices>(
+        &mut self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        children: Vec<ChildRecord<D::Produces>>,
+    ) -> Vec<ChildRecord<D::Produces>> {
+        let mut remaining = Vec::new();
+        for record in children {
+            let handle = record.handle;
+            match self.destroy_child(boot_services, controller, record) {
+                Ok(interface) => self.driver.child_stopped(controller, handle, interface),
+                Err((status, record)) => {
+                    log::error!("Bus driver failed to remove child {handle:?} of {controller:?}: {status:?}");
+                    remaining.push(record);
+                }
+            }
+        }
+        remaining
+    }
+}
+
+impl<D: BusDriver> DriverBinding for BusDriverBinding<D> {
+    fn driver_binding_supported<T: BootServices + 'static>(
+        &self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        _remaining_device_path: Option<NonNull<EfiDevicePathProtocol>>,
+    ) -> Result<bool, efi::Status> {
+        // The consumed protocol is already opened BY_DRIVER for a started controller. Report it as supported, so that
+        // Start is called again to create the child in the remaining device path.
+        if self.controllers.contains_key(&(controller as usize)) {
+            return Ok(true);
+        }
+        let parent = self.open_parent(boot_services, controller)?;
+        let supported = self.driver.supported(controller, parent);
+        self.close_parent(boot_services, controller);
+        Ok(supported)
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)] //this is triggered by the fact that efi::Handle aliases to c_void, but they are opaque to the caller.
+    fn driver_binding_start<T: BootServices + 'static>(
+        &mut self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        remaining_device_path: Option<NonNull<EfiDevicePathProtocol>>,
+    ) -> Result<(), efi::Status> {
+        // A controller that is already started may be started again to create the child in the remaining device path.
+        let started = self.controllers.contains_key(&(controller as usize));
+        let parent = match self.open_parent(boot_services, controller) {
+            Ok(parent) => parent,
+            Err(efi::Status::ALREADY_STARTED) if started => {
+                let state = self.controllers.get_mut(&(controller as usize)).ok_or(efi::Status::DEVICE_ERROR)?;
+                // SAFETY: The parent interface stays valid while the protocol is opened BY_DRIVER.
+                unsafe { state.parent.as_mut() }
+            }
+            Err(status) => return Err(status),
+        };
+
+        let result = (|| {
+            // SAFETY: The device path pointer is not used beyond checking that it is present.
+            let parent_device_path = unsafe {
+                boot_services.open_protocol_unchecked(
+                    controller,
+                    &DEVICE_PATH_GUID,
+                    self.driver_binding_handle,
+                    controller,
+                    efi::OPEN_PROTOCOL_GET_PROTOCOL,
+                )
+            }?;
+            // SAFETY: The device path protocol interface is a device path that lives as long as the controller.
+            let parent_device_path = unsafe { DevicePath::try_from_ptr(parent_device_path as *const u8) }
+                .map_err(|_| efi::Status::INVALID_PARAMETER)?;
+            let remaining_device_path = match remaining_device_path {
+                // SAFETY: The caller provides a valid device path.
+                Some(ptr) => Some(
+                    unsafe { DevicePath::try_from_ptr(ptr.as_ptr() as *const u8) }
+                        .map_err(|_| efi::Status::INVALID_PARAMETER)?,
+                ),
+                None => None,
+            };
+
+            let children = self.driver.enumerate(controller, parent, remaining_device_path)?;
+
+            let existing = self.controllers.get(&(controller as usize)).map(|state| state.children.as_slice());
+            let mut records: Vec<ChildRecord<D::Produces>> = Vec::with_capacity(children.len());
+            for child in children {
+                let mut device_path = DevicePathBuf::from(parent_device_path);
+                device_path.append_device_path(&child.node);
+                let device_path = device_path.into_box_device_path();
+
+                // The driver may enumerate children that already exist when a started controller is started again,
+                // those are left as they are.
+                if existing
+                    .unwrap_or_default()
+                    .iter()
+                    .chain(&records)
+                    .any(|record| record.device_path.as_ref() == device_path.as_ref())
+                {
+                    log::debug!("Bus driver skipped existing child {device_path} of {controller:?}.");
+                    continue;
+                }
+
+                match self.create_child(boot_services, controller, device_path, child.interface) {
+                    Ok(record) => records.push(record),
+                    Err(status) => {
+                        for record in self.destroy_children(boot_services, controller, records) {
+                            log::error!("Bus driver leaked child {:?} of {controller:?}.", record.handle);
+                        }
+                        return Err(status);
+                    }
+                }
+            }
+            Ok(records)
+        })();
+
+        match result {
+            Ok(children) => {
+                let state = self
+                    .controllers
+                    .entry(controller as usize)
+                    .or_insert_with(|| ControllerState { parent: NonNull::from(&mut *parent), children: Vec::new() });
+                state.children.extend(children);
+                Ok(())
+            }
+            Err(status) => {
+                if !started {
+                    self.close_parent(boot_services, controller);
+                }
+                Err(status)
+            }
+        }
+    }
+
+    fn driver_binding_stop<T: BootServices + 'static>(
+        &mut self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        number_of_children: usize,
+        child_handle_buffer: Option<NonNull<efi::Handle>>,
+    ) -> Result<(), efi::Status> {
+        let Some(mut state) = self.controllers.remove(&(controller as usize)) else {
+            return Err(efi::Status::DEVICE_ERROR);
+        };
+
+        let to_stop = match child_handle_buffer {
+            Some(buffer) if number_of_children > 0 => {
+                // SAFETY: The caller provides a buffer of number_of_children handles.
+                let handles = unsafe { slice::from_raw_parts(buffer.as_ptr(), number_of_children) };
+                let (to_stop, keep) = state.children.into_iter().partition(|child| handles.contains(&child.handle));
+                state.children = keep;
+                to_stop
+            }
+            _ => core::mem::take(&mut state.children),
+        };
+
+        let remaining = self.destroy_children(boot_services, controller, to_stop);
+        let failed = !remaining.is_empty();
+        state.children.extend(remaining);
+
+        if number_of_children > 0 || failed {
+            self.controllers.insert(controller as usize, state);
+            return if failed { Err(efi::Status::DEVICE_ERROR) } else { Ok(()) };
+        }
+
+        // SAFETY: The parent interface stays valid while the protocol is opened BY_DRIVER.
+        self.driver.stopped(controller, unsafe { state.parent.as_mut() });
+        self.close_parent(boot_services, controller);
+        Ok(())
+    }
+}
+
+impl<D: BusDriver, U: BootServices + 'static> UefiDriverBinding<BusDriverBinding<D>, U> {
+    /// Create a new driver binding for a [`BusDriver`] with image handle and driver binding handle set to the same value.
+    pub fn new_bus_driver(driver: D, handle: efi::Handle, boot_services: &'static U) -> Self {
+        Self::new(BusDriverBinding::new(driver, handle), handle, boot_services)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use alloc::{rc::Rc, vec};
+    use core::cell::RefCell;
+
+    use crate::{
+        boot_services::MockBootServices,
+        uefi_protocol::device_path::nodes::{Acpi, Pci},
+    };
+
+    use super::*;
+
+    const AGENT: efi::Handle = 0x1000 as efi::Handle;
+    const CONTROLLER: efi::Handle = 0x2000 as efi::Handle;
+
+    #[repr(C)]
+    struct ParentIo {
+        children: u8,
+    }
+
+    unsafe impl ProtocolInterface for ParentIo {
+        const PROTOCOL_GUID: efi::Guid =
+            efi::Guid::from_fields(0x8c1f4d2a, 0x0b3e, 0x4f6a, 0x9d, 0x21, &[0x5e, 0x77, 0x10, 0xa4, 0x3c, 0x01]);
+    }
+
+    #[repr(C)]
+    #[derive(Debug, PartialEq)]
+    struct ChildIo {
+        function: u8,
+    }
+
+    unsafe impl ProtocolInterface for ChildIo {
+        const PROTOCOL_GUID: efi::Guid =
+            efi::Guid::from_fields(0x8c1f4d2a, 0x0b3e, 0x4f6a, 0x9d, 0x21, &[0x5e, 0x77, 0x10, 0xa4, 0x3c, 0x02]);
+    }
+
+    #[derive(Default)]
+    struct TestBus {
+        stopped_children: Rc<RefCell<Vec<u8>>>,
+        stopped: Rc<RefCell<bool>>,
+    }
+
+    impl BusDriver for TestBus {
+        type Consumes = ParentIo;
+        type Produces = ChildIo;
+
+        fn supported(&self, _controller: efi::Handle, parent: &ParentIo) -> bool {
+            parent.children > 0
+        }
+
+        fn enumerate(
+            &mut self,
+            _controller: efi::Handle,
+            parent: &mut ParentIo,
+            _remaining_device_path: Option<&DevicePath>,
+        ) -> Result<Vec<Child<ChildIo>>, efi::Status> {
+            Ok((0..parent.children)
+                .map(|function| Child::new(Pci { function, device: 0 }, ChildIo { function }))
+                .collect())
+        }
+
+        fn child_stopped(&mut self, _controller: efi::Handle, _child: efi::Handle, interface: Box<ChildIo>) {
+            self.stopped_children.borrow_mut().push(interface.function);
+        }
+
+        fn stopped(&mut self, _controller: efi::Handle, _parent: &mut ParentIo) {
+            *self.stopped.borrow_mut() = true;
+        }
+    }
+
+    /// Protocol database operations observed by the mock boot services.
+    #[derive(Default)]
+    struct Db {
+        next_handle: usize,
+        installed: Vec<(usize, efi::Guid, usize)>,
+        opens: Vec<(efi::Guid, usize, u32)>,
+        fail_install_on: Option<usize>,
+        fail_open: bool,
+    }
+
+    fn boot_services(parent: &'static mut ParentIo, db: Rc<RefCell<Db>>) -> &'static MockBootServices {
+        let mut mock = MockBootServices::new();
+        let parent_device_path = Box::leak(
+            DevicePathBuf::from_device_path_node_iter(iter::once(Acpi::new_pci_root(0))).into_box_device_path(),
+        );
+        let parent_ptr = parent as *mut ParentIo as usize;
+        let device_path_ptr = parent_device_path as *const DevicePath as *const u8 as usize;
+
+        let d = db.clone();
+        mock.expect_open_protocol_unchecked().returning_st(move |handle, protocol, agent, controller, attribute| {
+            assert_eq!(AGENT, agent);
+            assert_eq!(CONTROLLER, handle);
+            let mut db = d.borrow_mut();
+            if *protocol == DEVICE_PATH_GUID {
+                return Ok(device_path_ptr as *mut c_void);
+            }
+            if db.fail_open {
+                return Err(efi::Status::ACCESS_DENIED);
+            }
+            if attribute == efi::OPEN_PROTOCOL_BY_DRIVER
+                && db.opens.contains(&(*protocol, controller as usize, attribute))
+            {
+                return Err(efi::Status::ALREADY_STARTED);
+            }
+            db.opens.push((*protocol, controller as usize, attribute));
+            Ok(parent_ptr as *mut c_void)
+        });
+        let d = db.clone();
+        mock.expect_close_protocol().returning_st(move |handle, protocol, agent, controller| {
+            assert_eq!(AGENT, agent);
+            assert_eq!(CONTROLLER, handle);
+            let mut db = d.borrow_mut();
+            match db.opens.iter().position(|(g, c, _)| g == protocol && *c == controller as usize) {
+                Some(index) => {
+                    db.opens.remove(index);
+                    Ok(())
+                }
+                None => Err(efi::Status::NOT_FOUND),
+            }
+        });
+        let d = db.clone();
+        mock.expect_install_protocol_interface_unchecked().returning_st(move |handle, protocol, interface| {
+            let mut db = d.borrow_mut();
+            let handle = match handle {
+                Some(handle) => handle as usize,
+                None => {
+                    db.next_handle += 1;
+                    0x3000 + db.next_handle
+                }
+            };
+            if db.fail_install_on == Some(db.installed.len()) {
+                return Err(efi::Status::OUT_OF_RESOURCES);
+            }
+            db.installed.push((handle, *protocol, interface as usize));
+            Ok(handle as efi::Handle)
+        });
+        let d = db.clone();
+        mock.expect_uninstall_protocol_interface_unchecked().returning_st(move |handle, protocol, interface| {
+            let mut db = d.borrow_mut();
+            let entry = (handle as usize, *protocol, interface as usize);
+            let index = db.installed.iter().position(|e| *e == entry).ok_or(efi::Status::NOT_FOUND)?;
+            db.installed.remove(index);
+            Ok(())
+        });
+        Box::leak(Box::new(mock))
+    }
+
+    fn installed_device_path(db: &Db, handle: usize) -> DevicePathBuf {
+        let (_, _, ptr) =
+            db.installed.iter().find(|(h, g, _)| *h == handle && *g == DEVICE_PATH_GUID).copied().unwrap();
+        DevicePathBuf::from(unsafe { DevicePath::try_from_ptr(ptr as *const u8) }.unwrap())
+    }
+
+    #[test]
+    fn supported_should_open_and_close_the_consumed_protocol() {
+        let db = Rc::new(RefCell::new(Db::default()));
+        let bs = boot_services(Box::leak(Box::new(ParentIo { children: 1 })), db.clone());
+        let bus = BusDriverBinding::new(TestBus::default(), AGENT);
+
+        assert_eq!(Ok(true), bus.driver_binding_supported(bs, CONTROLLER, None));
+        assert!(db.borrow().opens.is_empty());
+
+        let bs = boot_services(Box::leak(Box::new(ParentIo { children: 0 })), db.clone());
+        assert_eq!(Ok(false), bus.driver_binding_supported(bs, CONTROLLER, None));
+
+        db.borrow_mut().fail_open = true;
+        assert_eq!(Err(efi::Status::ACCESS_DENIED), bus.driver_binding_supported(bs, CONTROLLER, None));
+    }
+
+    #[test]
+    fn supported_should_accept_a_started_controller() {
+        let db = Rc::new(RefCell::new(Db::default()));
+        let bs = boot_services(Box::leak(Box::new(ParentIo { children: 1 })), db.clone());
+        let mut bus = BusDriverBinding::new(TestBus::default(), AGENT);
+        bus.driver_binding_start(bs, CONTROLLER, None).unwrap();
+
+        // The consumed protocol can no longer be opened, as the bus driver holds it.
+        db.borrow_mut().fail_open = true;
+        assert_eq!(Ok(true), bus.driver_binding_supported(bs, CONTROLLER, None));
+    }
+
+    #[test]
+    fn start_should_create_children_with_device_paths() {
+        let db = Rc::new(RefCell::new(Db::default()));
+        let bs = boot_services(Box::leak(Box::new(ParentIo { children: 2 })), db.clone());
+        let mut bus = BusDriverBinding::new(TestBus::default(), AGENT);
+
+        bus.driver_binding_start(bs, CONTROLLER, None).unwrap();
+
+        let children = bus.children(CONTROLLER);
+        assert_eq!(2, children.len());
+        let db = db.borrow();
+        assert_eq!(4, db.installed.len());
+        assert!(db.opens.contains(&(ParentIo::PROTOCOL_GUID, CONTROLLER as usize, efi::OPEN_PROTOCOL_BY_DRIVER)));
+        for (function, child) in children.iter().enumerate() {
+            let mut expected = DevicePathBuf::from_device_path_node_iter(iter::once(Acpi::new_pci_root(0)));
+            expected.append_device_path(&DevicePathBuf::from_device_path_node_iter(iter::once(Pci {
+                function: function as u8,
+                device: 0,
+            })));
+            assert_eq!(expected, installed_device_path(&db, *child as usize));
+
+            let (_, _, interface) =
+                db.installed.iter().find(|(h, g, _)| *h == *child as usize && *g == ChildIo::PROTOCOL_GUID).unwrap();
+            assert_eq!(&ChildIo { function: function as u8 }, unsafe { &*(*interface as *const ChildIo) });
+            assert!(db.opens.contains(&(
+                ParentIo::PROTOCOL_GUID,
+                *child as usize,
+                efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER
+            )));
+        }
+    }
+
+    #[test]
+    fn start_on_a_started_controller_should_only_create_new_children() {
+        let db = Rc::new(RefCell::new(Db::default()));
+        let parent = Box::leak(Box::new(ParentIo { children: 2 }));
+        let parent_ptr = parent as *mut ParentIo;
+        let bs = boot_services(parent, db.clone());
+        let mut bus = BusDriverBinding::new(TestBus::default(), AGENT);
+
+        bus.driver_binding_start(bs, CONTROLLER, None).unwrap();
+        let children = bus.children(CONTROLLER);
+
+        // Starting again enumerates the same children, which already exist.
+        bus.driver_binding_start(bs, CONTROLLER, None).unwrap();
+        assert_eq!(children, bus.children(CONTROLLER));
+        assert_eq!(4, db.borrow().installed.len());
+        assert_eq!(3, db.borrow().opens.len());
+
+        // A child that appeared since is created alongside the existing ones.
+        // SAFETY: The parent interface is leaked and only accessed from this test.
+        unsafe { (*parent_ptr).children = 3 };
+        bus.driver_binding_start(bs, CONTROLLER, None).unwrap();
+        let new_children = bus.children(CONTROLLER);
+        assert_eq!(3, new_children.len());
+        assert_eq!(children[..], new_children[..2]);
+        assert_eq!(6, db.borrow().installed.len());
+        assert_eq!(4, db.borrow().opens.len());
+    }
+
+    #[test]
+    fn start_failure_should_tear_down_created_children() {
+        let db = Rc::new(RefCell::new(Db { fail_install_on: Some(3), ..Default::default() }));
+        let bs = boot_services(Box::leak(Box::new(ParentIo { children: 2 })), db.clone());
+        let driver = TestBus::default();
+        let stopped_children = driver.stopped_children.clone();
+        let mut bus = BusDriverBinding::new(driver, AGENT);
+
+        assert_eq!(Err(efi::Status::OUT_OF_RESOURCES), bus.driver_binding_start(bs, CONTROLLER, None));
+
+        assert!(bus.children(CONTROLLER).is_empty());
+        assert!(db.borrow().installed.is_empty());
+        assert!(db.borrow().opens.is_empty());
+        assert_eq!(vec![0], *stopped_children.borrow());
+    }
+
+    #[test]
+    fn stop_should_remove_requested_children_then_close_the_controller() {
+        let db = Rc::new(RefCell::new(Db::default()));
+        let bs = boot_services(Box::leak(Box::new(ParentIo { children: 3 })), db.clone());
+        let driver = TestBus::default();
+        let stopped_children = driver.stopped_children.clone();
+        let stopped = driver.stopped.clone();
+        let mut bus = BusDriverBinding::new(driver, AGENT);
+        bus.driver_binding_start(bs, CONTROLLER, None).unwrap();
+
+        let mut to_stop = vec![bus.children(CONTROLLER)[1]];
+        bus.driver_binding_stop(bs, CONTROLLER, 1, NonNull::new(to_stop.as_mut_ptr())).unwrap();
+        assert_eq!(vec![1], *stopped_children.borrow());
+        assert_eq!(2, bus.children(CONTROLLER).len());
+        assert!(!*stopped.borrow());
+
+        // Stopping the controller removes the remaining children before closing the consumed protocol.
+        bus.driver_binding_stop(bs, CONTROLLER, 0, None).unwrap();
+        assert_eq!(vec![1, 0, 2], *stopped_children.borrow());
+        assert!(*stopped.borrow());
+        assert!(db.borrow().installed.is_empty());
+        assert!(db.borrow().opens.is_empty());
+
+        assert_eq!(Err(efi::Status::DEVICE_ERROR), bus.driver_binding_stop(bs, CONTROLLER, 0, None));
+    }
+}
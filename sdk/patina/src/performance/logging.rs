@@ -30,6 +30,21 @@ fn log_perf_measurement(
     address: usize,
     identifier: u16,
     create_performance_measurement: CreateMeasurement,
+) {
+    log_perf_measurement_at(caller_identifier, guid, string, 0, address, identifier, create_performance_measurement)
+}
+
+/// Create performance record with the timestamp of `ticker`, or the current time if `ticker` is 0.
+///
+/// `caller_identifier` is either a Handle or a pointer to a caller ID GUID.
+fn log_perf_measurement_at(
+    caller_identifier: *const c_void,
+    guid: Option<&efi::Guid>,
+    string: Option<&str>,
+    ticker: u64,
+    address: usize,
+    identifier: u16,
+    create_performance_measurement: CreateMeasurement,
 ) {
     let s = string
         .map(CString::new)
@@ -43,7 +58,7 @@ fn log_perf_measurement(
             caller_identifier,
             guid,
             s,
-            0,
+            ticker,
             address,
             identifier as u32,
             PerfAttribute::PerfEntry,
@@ -262,6 +277,73 @@ pub fn perf_driver_binding_stop_end(
     )
 }
 
+/// Records a run of a Patina component in the core, between the `start` and `end` timer ticks.
+///
+/// `attempt` is the number of times the component has been run, including this run.
+pub fn perf_component_run(
+    component_name: &str,
+    caller_id: &efi::Guid,
+    start: u64,
+    end: u64,
+    attempt: u64,
+    create_performance_measurement: CreateMeasurement,
+) {
+    if get_perf_measurement_mask() & Measurement::ComponentRun as u32 == 0 {
+        return;
+    }
+    log_perf_measurement_at(
+        caller_id as *const efi::Guid as *mut c_void,
+        None,
+        Some(component_name),
+        start,
+        0,
+        KnownPerfId::ComponentRunStart.as_u16(),
+        create_performance_measurement,
+    );
+    log_perf_measurement_at(
+        caller_id as *const efi::Guid as *mut c_void,
+        None,
+        Some(component_name),
+        end,
+        attempt as usize,
+        KnownPerfId::ComponentRunEnd.as_u16(),
+        create_performance_measurement,
+    );
+}
+
+/// Records the change in pool allocations and allocated pages during a run of a Patina component in the core, that
+/// ended at the `end` timer tick. Negative changes are recorded as their two's complement.
+pub fn perf_component_allocations(
+    component_name: &str,
+    caller_id: &efi::Guid,
+    end: u64,
+    pool_allocations: isize,
+    pages: isize,
+    create_performance_measurement: CreateMeasurement,
+) {
+    if get_perf_measurement_mask() & Measurement::ComponentRun as u32 == 0 {
+        return;
+    }
+    log_perf_measurement_at(
+        caller_id as *const efi::Guid as *mut c_void,
+        None,
+        Some(component_name),
+        end,
+        pool_allocations as usize,
+        KnownPerfId::ComponentPoolDelta.as_u16(),
+        create_performance_measurement,
+    );
+    log_perf_measurement_at(
+        caller_id as *const efi::Guid as *mut c_void,
+        None,
+        Some(component_name),
+        end,
+        pages as usize,
+        KnownPerfId::ComponentPageDelta.as_u16(),
+        create_performance_measurement,
+    );
+}
+
 /// Measure the time from power-on to this function execution.
 pub fn perf_event(event_string: &str, caller_id: &efi::Guid, create_performance_measurement: CreateMeasurement) {
     log_perf_measurement(
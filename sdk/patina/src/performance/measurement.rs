@@ -368,7 +368,15 @@ where
             fbpt.lock().add_record(record)?;
         }
 
-        KnownPerfId::PerfFunctionStart
+        KnownPerfId::ComponentRunEnd | KnownPerfId::ComponentPoolDelta | KnownPerfId::ComponentPageDelta => {
+            // SAFETY: Component measurements are logged by the core with its caller id GUID.
+            let module_guid = unsafe { *(caller_identifier as *const efi::Guid) };
+            let string = string.unwrap_or("unknown name");
+            let record = GuidQwordStringEventRecord::new(perf_id, 0, timestamp, module_guid, address as u64, string);
+            fbpt.lock().add_record(record)?;
+        }
+        KnownPerfId::ComponentRunStart
+        | KnownPerfId::PerfFunctionStart
         | KnownPerfId::PerfFunctionEnd
         | KnownPerfId::PerfInModuleStart
         | KnownPerfId::PerfInModuleEnd
@@ -400,6 +408,8 @@ pub enum Measurement {
     DriverBindingStart = 1 << 3,
     /// Diver binding stop function call.
     DriverBindingStop = 1 << 4,
+    /// Patina component runs.
+    ComponentRun = 1 << 5,
 }
 
 impl Measurement {
@@ -411,6 +421,7 @@ impl Measurement {
             Measurement::DriverBindingSupport => Measurement::DriverBindingSupport as u32,
             Measurement::DriverBindingStart => Measurement::DriverBindingStart as u32,
             Measurement::DriverBindingStop => Measurement::DriverBindingStop as u32,
+            Measurement::ComponentRun => Measurement::ComponentRun as u32,
         }
     }
 }
@@ -688,7 +699,7 @@ mod tests {
             efi::Status::SUCCESS
         }
 
-        const EXPECTED_NUMBER_OF_RECORD: usize = 21;
+        const EXPECTED_NUMBER_OF_RECORD: usize = 25;
 
         perf_image_start_begin(module_handle, test_create_performance_measurement);
         perf_image_start_end(module_handle, test_create_performance_measurement);
@@ -721,5 +732,8 @@ mod tests {
 
         perf_cross_module_begin("measurement_str", &caller_id, test_create_performance_measurement);
         perf_cross_module_end("measurement_str", &caller_id, test_create_performance_measurement);
+
+        perf_component_run("component", &caller_id, 10, 20, 3, test_create_performance_measurement);
+        perf_component_allocations("component", &caller_id, 20, 4, -2, test_create_performance_measurement);
     }
 }
@@ -125,6 +125,14 @@ pub enum KnownPerfId {
     PerfCrossModuleStart = 0x50,
     /// The performance ID for the end of behavior spanning multiple modules.
     PerfCrossModuleEnd = 0x51,
+    /// Core Measurement: The performance ID for when the core starts running a Patina component.
+    ComponentRunStart = 0x60,
+    /// Core Measurement: The performance ID for when a Patina component finishes running.
+    ComponentRunEnd = 0x61,
+    /// Core Measurement: The performance ID for the change in pool allocations during a Patina component run.
+    ComponentPoolDelta = 0x62,
+    /// Core Measurement: The performance ID for the change in allocated pages during a Patina component run.
+    ComponentPageDelta = 0x63,
 }
 
 impl KnownPerfId {
@@ -152,6 +160,10 @@ impl KnownPerfId {
             Self::PerfInModuleEnd => Self::PerfInModuleEnd as u16,
             Self::PerfCrossModuleStart => Self::PerfCrossModuleStart as u16,
             Self::PerfCrossModuleEnd => Self::PerfCrossModuleEnd as u16,
+            Self::ComponentRunStart => Self::ComponentRunStart as u16,
+            Self::ComponentRunEnd => Self::ComponentRunEnd as u16,
+            Self::ComponentPoolDelta => Self::ComponentPoolDelta as u16,
+            Self::ComponentPageDelta => Self::ComponentPageDelta as u16,
         }
     }
 
@@ -241,6 +253,10 @@ impl TryFrom<u16> for KnownPerfId {
             v if v == Self::PerfInModuleEnd as u16 => Self::PerfInModuleEnd,
             v if v == Self::PerfCrossModuleStart as u16 => Self::PerfCrossModuleStart,
             v if v == Self::PerfCrossModuleEnd as u16 => Self::PerfCrossModuleEnd,
+            v if v == Self::ComponentRunStart as u16 => Self::ComponentRunStart,
+            v if v == Self::ComponentRunEnd as u16 => Self::ComponentRunEnd,
+            v if v == Self::ComponentPoolDelta as u16 => Self::ComponentPoolDelta,
+            v if v == Self::ComponentPageDelta as u16 => Self::ComponentPageDelta,
             _ => return Err(()),
         };
         Ok(this)
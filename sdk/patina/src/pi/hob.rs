@@ -377,17 +377,14 @@ pub const EFI_RESOURCE_MEMORY_RESERVED: u32 = 0x00000005;
 /// Reserved I/O address space.
 pub const EFI_RESOURCE_IO_RESERVED: u32 = 0x00000006;
 
-//
-// BZ3937_EFI_RESOURCE_MEMORY_UNACCEPTED is defined for unaccepted memory.
-// But this definition has not been officially in the PI spec. Base
-// on the code-first we define BZ3937_EFI_RESOURCE_MEMORY_UNACCEPTED at
-// MdeModulePkg/Include/Pi/PrePiHob.h and update EFI_RESOURCE_MAX_MEMORY_TYPE
-// to 8. After BZ3937_EFI_RESOURCE_MEMORY_UNACCEPTED is officially published
-// in PI spec, we will re-visit here.
-//
-// #define BZ3937_EFI_RESOURCE_MEMORY_UNACCEPTED      0x00000007
+/// Memory that has not yet been accepted by the guest (e.g. in a confidential VM) and must be accepted before use.
+///
+/// This definition has not been officially published in the PI spec. Per the code-first process (BZ3937) it is
+/// defined in MdeModulePkg/Include/Pi/PrePiHob.h as `BZ3937_EFI_RESOURCE_MEMORY_UNACCEPTED`, with
+/// `EFI_RESOURCE_MAX_MEMORY_TYPE` updated to 8.
+pub const EFI_RESOURCE_MEMORY_UNACCEPTED: u32 = 0x00000007;
 /// Maximum memory type value.
-pub const EFI_RESOURCE_MAX_MEMORY_TYPE: u32 = 0x00000007;
+pub const EFI_RESOURCE_MAX_MEMORY_TYPE: u32 = 0x00000008;
 
 //
 // These types can be ORed together as needed.
@@ -425,6 +422,9 @@ pub const EFI_RESOURCE_ATTRIBUTE_PERSISTENT: u32 = 0x00800000;
 //
 /// Physical memory relative reliability attribute: This memory provides higher reliability relative to other memory in the system.
 pub const EFI_RESOURCE_ATTRIBUTE_MORE_RELIABLE: u32 = 0x02000000;
+/// Physical memory specific-purpose attribute: This memory is earmarked for specific purposes such as for specific
+/// device drivers or applications (e.g. HBM or CXL attached memory) and should not be used for general allocation.
+pub const EFI_RESOURCE_ATTRIBUTE_SPECIAL_PURPOSE: u32 = 0x08000000;
 
 //
 // The rest of the attributes are used to describe capabilities
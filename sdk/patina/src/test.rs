@@ -83,6 +83,8 @@ pub use linkme;
 // WARNING: this is not a part of the crate's public API and is subject to change at any time.
 #[doc(hidden)]
 pub mod __private_api;
+#[cfg(any(test, feature = "std"))]
+pub mod harness;
 
 /// The result type for a test case, an alias for `Result<(), &'static str>`.
 pub type Result = core::result::Result<(), &'static str>;
@@ -0,0 +1,406 @@
+//! A host-based harness for dispatching a set of components.
+//!
+//! [ComponentHarness] runs components through the same [Dispatcher] as the DXE core, without booting it: guided HOBs
+//! are parsed by the parsers the components registered, components are sorted according to their
+//! [Order] constraints and dispatched in [batches](crate::component::schedule::batches) on an [Executor] until none of
+//! them can make progress, configuration is locked, and the remaining components are dispatched again. Components that
+//! fail are handled according to their [FailurePolicy]. The storage, the order the components were dispatched in, and
+//! any component failures can then be inspected.
+//!
+//! The harness is only available in host-based builds, with the `std` feature.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use patina::{
+//!     component::{IntoComponent, params::{Config, ConfigMut}},
+//!     test::harness::ComponentHarness,
+//! };
+//!
+//! #[derive(IntoComponent)]
+//! struct Consumer;
+//!
+//! impl Consumer {
+//!     fn entry_point(self, config: Config<u32>) -> patina::error::Result<()> {
+//!         assert_eq!(*config, 42);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[derive(IntoComponent)]
+//! struct Producer;
+//!
+//! impl Producer {
+//!     fn entry_point(self, mut config: ConfigMut<u32>) -> patina::error::Result<()> {
+//!         *config += 2;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut harness = ComponentHarness::new().with_config(40u32).with_component(Consumer).with_component(Producer);
+//! harness.dispatch().unwrap();
+//!
+//! assert_eq!(harness.dispatched(), [core::any::type_name::<Producer>(), core::any::type_name::<Consumer>()]);
+//! assert!(harness.failures().is_empty());
+//! assert_eq!(*harness.storage().get_config::<u32>().unwrap(), 42);
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use crate::{
+    OwnedGuid,
+    boot_services::StandardBootServices,
+    component::{
+        IntoComponent, Storage, dispatch::Dispatcher, failure::FailurePolicy, order::Order, schedule::Executor,
+        service::IntoService,
+    },
+    error::{EfiError, Result},
+    pi::hob::{Hob, HobList},
+};
+
+/// Dispatches a set of components against a [Storage] populated with mock services, configuration and HOBs.
+pub struct ComponentHarness {
+    storage: Storage,
+    dispatcher: Dispatcher,
+    hobs: Vec<(OwnedGuid, Vec<u8>)>,
+}
+
+impl Default for ComponentHarness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ComponentHarness {
+    /// Creates an empty harness that dispatches components with the
+    /// [Sequential](crate::component::schedule::Sequential) executor.
+    pub fn new() -> Self {
+        Self { storage: Storage::new(), dispatcher: Dispatcher::new(), hobs: Vec::new() }
+    }
+
+    /// Registers a component to be dispatched. As with the core, components are dispatched in the order they are
+    /// registered unless ordering constraints say otherwise, each waiting until its parameters are available.
+    pub fn with_component<I>(mut self, component: impl IntoComponent<I>) -> Self {
+        self.dispatcher.add_component(component.into_component(), &mut self.storage);
+        self
+    }
+
+    /// Registers ordering constraints for a component, as a platform would with the core. See the
+    /// [order](crate::component::order) module.
+    pub fn with_component_order(mut self, order: Order) -> Self {
+        self.dispatcher.add_order(order);
+        self
+    }
+
+    /// Sets what the harness does when the component created from `C` fails, as a platform would with the core.
+    /// Components without a policy use [FailurePolicy::DisableDependents].
+    pub fn with_component_failure_policy<C: 'static>(mut self, policy: FailurePolicy) -> Self {
+        self.dispatcher.set_failure_policy(core::any::type_name::<C>(), policy);
+        self
+    }
+
+    /// Adds a service to the storage, as a platform would with the core.
+    pub fn with_service(mut self, service: impl IntoService + 'static) -> Self {
+        self.storage.add_service(service);
+        self
+    }
+
+    /// Adds a configuration value to the storage. As with the core, configuration is locked by default and unlocked
+    /// only if a component requests it mutably.
+    pub fn with_config<C: Default + 'static>(mut self, config: C) -> Self {
+        self.storage.add_config(config);
+        self
+    }
+
+    /// Adds a guided HOB, which is parsed by every parser registered for `guid` when the components are dispatched.
+    pub fn with_guid_hob(mut self, guid: impl Into<OwnedGuid>, data: &[u8]) -> Self {
+        self.hobs.push((guid.into(), data.to_vec()));
+        self
+    }
+
+    /// Adds every guided HOB of `hob_list`, see [with_guid_hob](Self::with_guid_hob).
+    pub fn with_hob_list(mut self, hob_list: &HobList) -> Self {
+        for hob in hob_list.iter() {
+            if let Hob::GuidHob(guid, data) = hob {
+                self.hobs.push((OwnedGuid::from(guid.name), data.to_vec()));
+            }
+        }
+        self
+    }
+
+    /// Sets the boot services made available to components.
+    pub fn with_boot_services(mut self, boot_services: StandardBootServices) -> Self {
+        self.storage.set_boot_services(boot_services);
+        self
+    }
+
+    /// Sets the executor that runs each batch of components.
+    pub fn with_executor(mut self, executor: impl Executor + 'static) -> Self {
+        self.dispatcher.set_executor(executor);
+        self
+    }
+
+    /// Parses the HOBs, sorts the components into dispatch order and dispatches them to completion, locking
+    /// configuration once the components stop making progress, as the core does.
+    ///
+    /// Returns an error if the ordering constraints contain a cycle, or if a component whose [FailurePolicy] is
+    /// [Halt](FailurePolicy::Halt) fails. This may be called again after registering more components, services or HOBs.
+    pub fn dispatch(&mut self) -> Result<()> {
+        for (guid, data) in core::mem::take(&mut self.hobs) {
+            let parsers = self.storage.get_hob_parsers(&guid);
+            if parsers.is_empty() {
+                log::warn!("No parser registered for HOB: {guid}");
+            }
+            for parser in parsers {
+                parser(&data, &mut self.storage);
+            }
+        }
+
+        self.dispatcher.order(&mut self.storage)?;
+        while self.dispatcher.dispatch(&mut self.storage)? {}
+        self.storage.lock_configs();
+        while self.dispatcher.dispatch(&mut self.storage)? {}
+        Ok(())
+    }
+
+    /// Returns the storage the components were dispatched against.
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// Returns the storage the components were dispatched against, mutably.
+    pub fn storage_mut(&mut self) -> &mut Storage {
+        &mut self.storage
+    }
+
+    /// Returns the names of the components that ran successfully, in the order they were dispatched.
+    pub fn dispatched(&self) -> &[&'static str] {
+        self.dispatcher.dispatched()
+    }
+
+    /// Returns whether the component created from `C` ran successfully.
+    pub fn is_dispatched<C: 'static>(&self) -> bool {
+        self.dispatched().contains(&core::any::type_name::<C>())
+    }
+
+    /// Returns the name and error of each component that failed without being retried, in the order they failed.
+    pub fn failures(&self) -> &[(&'static str, EfiError)] {
+        self.dispatcher.failures()
+    }
+
+    /// Returns the names of the components that have not run, along with the parameter that was not available.
+    /// Components disabled because a component they are ordered after failed are not included.
+    pub fn not_dispatched(&self) -> Vec<(&'static str, Option<&'static str>)> {
+        self.dispatcher
+            .components()
+            .iter()
+            .map(|component| (component.metadata().name(), component.metadata().failed_param()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate as patina;
+    use crate::component::{
+        hob::{FromHob, Hob},
+        params::{Config, ConfigMut},
+        service::{IntoService, Service},
+    };
+
+    const GREETING_GUID: OwnedGuid = OwnedGuid::from_fields(0x6a1c2f4e, 0x51b3, 0x4d7a, 0x9e, 0x08, [1, 2, 3, 4, 5, 6]);
+    const LATE_GUID: OwnedGuid = OwnedGuid::from_fields(0x6a1c2f4e, 0x51b3, 0x4d7a, 0x9e, 0x08, [1, 2, 3, 4, 5, 7]);
+
+    trait Greeter {
+        fn greet(&self) -> u32;
+    }
+
+    #[derive(IntoService)]
+    #[service(dyn Greeter)]
+    struct Constant(u32);
+
+    impl Greeter for Constant {
+        fn greet(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct Publisher;
+
+    impl Publisher {
+        fn entry_point(self, hob: Hob<Greeting>, storage: &mut Storage) -> crate::error::Result<()> {
+            storage.add_service(Constant(hob.0));
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct Greeted;
+
+    impl Greeted {
+        fn entry_point(self, greeter: Service<dyn Greeter>, mut config: ConfigMut<u32>) -> crate::error::Result<()> {
+            *config = greeter.greet();
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct Reader;
+
+    impl Reader {
+        fn entry_point(self, config: Config<u32>) -> crate::error::Result<()> {
+            match *config {
+                7 => Ok(()),
+                _ => Err(EfiError::InvalidParameter),
+            }
+        }
+    }
+
+    #[derive(IntoComponent)]
+    struct Waiting;
+
+    impl Waiting {
+        fn entry_point(self, _hob: Hob<Late>) -> crate::error::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(IntoComponent, Default)]
+    struct Flaky {
+        attempts: u32,
+    }
+
+    impl Flaky {
+        fn entry_point(&mut self) -> crate::error::Result<()> {
+            self.attempts += 1;
+            match self.attempts {
+                1 => Err(EfiError::NotReady),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    struct Greeting(u32);
+
+    impl FromHob for Greeting {
+        const HOB_GUID: OwnedGuid = GREETING_GUID;
+
+        fn parse(bytes: &[u8]) -> Self {
+            Greeting(u32::from_le_bytes(bytes.try_into().unwrap()))
+        }
+    }
+
+    struct Late;
+
+    impl FromHob for Late {
+        const HOB_GUID: OwnedGuid = LATE_GUID;
+
+        fn parse(_bytes: &[u8]) -> Self {
+            Late
+        }
+    }
+
+    #[test]
+    fn test_components_are_dispatched_once_their_params_are_available() {
+        let mut harness = ComponentHarness::new()
+            .with_component(Reader)
+            .with_component(Greeted)
+            .with_component(Publisher)
+            .with_guid_hob(GREETING_GUID, &7u32.to_le_bytes());
+        harness.dispatch().unwrap();
+
+        assert_eq!(
+            harness.dispatched(),
+            [core::any::type_name::<Publisher>(), core::any::type_name::<Greeted>(), core::any::type_name::<Reader>()]
+        );
+        assert!(harness.failures().is_empty());
+        assert!(harness.not_dispatched().is_empty());
+        assert_eq!(harness.storage().get_service::<dyn Greeter>().unwrap().greet(), 7);
+        assert!(harness.storage().get_config::<u32>().is_some());
+    }
+
+    #[test]
+    fn test_failures_and_missing_params_are_reported() {
+        let mut harness = ComponentHarness::new().with_config(3u32).with_component(Reader).with_component(Waiting);
+        harness.dispatch().unwrap();
+
+        assert!(harness.dispatched().is_empty());
+        assert_eq!(harness.failures(), [(core::any::type_name::<Reader>(), EfiError::InvalidParameter)]);
+        assert_eq!(harness.not_dispatched().len(), 1);
+        assert_eq!(harness.not_dispatched()[0].0, core::any::type_name::<Waiting>());
+        assert!(!harness.is_dispatched::<Waiting>());
+
+        let mut harness = harness.with_guid_hob(LATE_GUID, &[]);
+        harness.dispatch().unwrap();
+        assert!(harness.is_dispatched::<Waiting>());
+        assert!(harness.not_dispatched().is_empty());
+    }
+
+    #[test]
+    fn test_services_can_be_mocked() {
+        let mut harness =
+            ComponentHarness::new().with_service(Constant(7)).with_component(Greeted).with_component(Reader);
+        harness.dispatch().unwrap();
+
+        assert!(harness.is_dispatched::<Greeted>());
+        assert!(harness.is_dispatched::<Reader>());
+        assert_eq!(*harness.storage().get_config::<u32>().unwrap(), 7);
+    }
+
+    #[test]
+    fn test_ordering_constraints_are_applied() {
+        let mut harness = ComponentHarness::new()
+            .with_config(7u32)
+            .with_component(Reader)
+            .with_component(Flaky::default())
+            .with_component_order(Order::of::<Reader>().after::<Flaky>())
+            .with_component_failure_policy::<Flaky>(FailurePolicy::Retry { attempts: 1 });
+        harness.dispatch().unwrap();
+
+        assert_eq!(harness.dispatched(), [core::any::type_name::<Flaky>(), core::any::type_name::<Reader>()]);
+        assert!(harness.failures().is_empty());
+
+        let mut harness = ComponentHarness::new()
+            .with_component(Reader)
+            .with_component(Flaky::default())
+            .with_component_order(Order::of::<Reader>().after::<Flaky>())
+            .with_component_order(Order::of::<Flaky>().after::<Reader>());
+        assert_eq!(harness.dispatch(), Err(EfiError::InvalidParameter));
+        assert!(harness.dispatched().is_empty());
+    }
+
+    #[test]
+    fn test_failure_policies_are_applied() {
+        let mut harness = ComponentHarness::new()
+            .with_config(7u32)
+            .with_component(Flaky::default())
+            .with_component(Reader)
+            .with_component_order(Order::of::<Reader>().after::<Flaky>());
+        harness.dispatch().unwrap();
+
+        assert!(harness.dispatched().is_empty());
+        assert_eq!(harness.failures(), [(core::any::type_name::<Flaky>(), EfiError::NotReady)]);
+        assert!(harness.not_dispatched().is_empty());
+
+        let mut harness = ComponentHarness::new()
+            .with_config(7u32)
+            .with_component(Flaky::default())
+            .with_component(Reader)
+            .with_component_order(Order::of::<Reader>().after::<Flaky>())
+            .with_component_failure_policy::<Flaky>(FailurePolicy::Halt);
+        assert_eq!(harness.dispatch(), Err(EfiError::NotReady));
+        assert!(!harness.is_dispatched::<Reader>());
+        assert_eq!(harness.not_dispatched()[0].0, core::any::type_name::<Reader>());
+    }
+}
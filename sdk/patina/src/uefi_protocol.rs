@@ -11,6 +11,7 @@
 pub mod device_path;
 
 pub mod decompress;
+pub mod memory_accept;
 pub mod performance_measurement;
 pub mod status_code;
 
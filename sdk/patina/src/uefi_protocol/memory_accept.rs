@@ -0,0 +1,40 @@
+//! Definition of [`EdkiiMemoryAcceptProtocol`].
+//!
+//! This protocol is used to accept memory that was handed off as unaccepted (`EfiUnacceptedMemoryType`), as is done by
+//! confidential computing guests that accept memory lazily.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use r_efi::efi;
+
+use crate::uefi_protocol::ProtocolInterface;
+
+/// GUID for the EDKII Memory Accept Protocol.
+pub const EDKII_MEMORY_ACCEPT_PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x38c74800, 0x5590, 0x4db4, 0xa0, 0xf3, &[0x67, 0x5d, 0x9b, 0x8e, 0x80, 0x26]);
+
+/// Accepts the memory range described by `start_address` and `size`.
+///
+/// Both `start_address` and `size` must be page aligned. Returns `EFI_SUCCESS` if the range was accepted, or an error
+/// status if the range is invalid or could not be accepted.
+pub type AcceptMemory = extern "efiapi" fn(
+    this: *mut EdkiiMemoryAcceptProtocol,
+    start_address: efi::PhysicalAddress,
+    size: usize,
+) -> efi::Status;
+
+/// EDKII defined Memory Accept Protocol structure.
+#[repr(C)]
+pub struct EdkiiMemoryAcceptProtocol {
+    /// Function to accept a range of unaccepted memory.
+    pub accept_memory: AcceptMemory,
+}
+
+unsafe impl ProtocolInterface for EdkiiMemoryAcceptProtocol {
+    const PROTOCOL_GUID: efi::Guid = EDKII_MEMORY_ACCEPT_PROTOCOL_GUID;
+}
@@ -12,6 +12,7 @@
 mod component_macro;
 mod hob_macro;
 mod service_macro;
+mod service_protocol_macro;
 mod test_macro;
 
 /// Derive Macro for implementing the `IntoComponent` trait for a type.
@@ -86,11 +87,11 @@ pub fn component(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
 /// ## Macro Attribute
 ///
 /// - `service`: The service trait(s) that the type implements.
-/// - `protocol`: Publishes the entire struct as a protocol with the given GUID.
-///
-/// ## Member Attributes
-///
-/// - `protocol`: Publishes the field as a protocol with the given GUID.
+/// - `lifecycle`: Registers the type's `ServiceLifecycle` implementation, providing the version of the service(s) and
+///   a teardown hook called at ExitBootServices.
+/// - `protocol`: An entry of the `service` attribute, `#[service(dyn MyService, protocol = "GUID")]`, that also
+///   installs the service as a UEFI protocol with the given GUID. The service interface must be annotated with
+///   [service_protocol](macro@service_protocol), and only one service may be specified.
 ///
 /// ## Pure Rust Example
 ///
@@ -117,11 +118,50 @@ pub fn component(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
 ///   }
 /// }
 /// ```
-#[proc_macro_derive(IntoService, attributes(service))]
+#[proc_macro_derive(IntoService, attributes(service, lifecycle))]
 pub fn service(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     service_macro::service2(item.into()).into()
 }
 
+/// Attribute Macro for publishing a service interface (`Trait`) as a UEFI protocol.
+///
+/// This macro generates a `#[repr(C)]` struct named `<Trait>Protocol`, containing an `extern "efiapi"` function
+/// pointer for each method of the trait. Each function takes a pointer to the protocol instance as its first argument,
+/// followed by the arguments of the method. It also implements `ServiceProtocol` for `dyn Trait`, which allows:
+///
+/// - A Rust implementation of the service to be installed as a protocol, so that it can be consumed by C drivers. See
+///   the `protocol` entry of the [IntoService](derive@IntoService) `service` attribute.
+/// - A protocol installed by a C driver to be consumed as a `Service<dyn Trait>` by Rust components. See
+///   `Storage::add_protocol_service`.
+///
+/// Every method must take `&self`, and cannot be generic. Argument and return types must be FFI-safe: the generated
+/// functions deny the `improper_ctypes_definitions` lint, so types such as `&str`, `Vec` or `Result` fail to compile.
+///
+/// ## Example
+///
+/// ```rust, ignore
+/// use patina::component::service::{IntoService, protocol::service_protocol};
+///
+/// #[service_protocol]
+/// trait MyService {
+///   fn do_something(&self, value: u32) -> u64;
+/// }
+///
+/// #[derive(IntoService)]
+/// #[service(dyn MyService, protocol = "8be4df61-93ca-11d2-aa0d-00e098032b8c")]
+/// struct MyStruct;
+///
+/// impl MyService for MyStruct {
+///   fn do_something(&self, value: u32) -> u64 {
+///     value as u64
+///   }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn service_protocol(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    service_protocol_macro::service_protocol2(attr.into(), item.into()).into()
+}
+
 /// Derive Macro for implementing the `HobConfig` trait for a type.
 ///
 /// This macro uses the [zerocopy::FromBytes](https://docs.rs/zerocopy/latest/zerocopy/trait.FromBytes.html)
@@ -8,12 +8,16 @@
 //!
 use proc_macro2::TokenStream;
 use quote::{ToTokens, format_ident, quote};
-use syn::{Attribute, Generics, ItemEnum, ItemStruct, Meta, parse::Parse, spanned::Spanned};
+use syn::{
+    Attribute, Expr, ExprLit, Generics, ItemEnum, ItemStruct, Lit, Meta, MetaNameValue, parse::Parse, spanned::Spanned,
+};
 
 /// A struct responsible for parsing any additional #[...] attributes associated with the main derive macro.
 #[derive(Clone)]
 struct AttrConfig {
     pub services: Vec<TokenStream>,
+    pub lifecycle: bool,
+    pub protocol: Option<TokenStream>,
 }
 
 /// A struct containing the parsed struct and its attribute configs.
@@ -25,10 +29,13 @@ struct Service {
 impl Service {
     /// Parses all attributes of the struct
     fn parse_attr(attrs: &mut Vec<Attribute>) -> syn::Result<AttrConfig> {
-        let mut config = AttrConfig { services: vec![] };
+        let mut config = AttrConfig { services: vec![], lifecycle: false, protocol: None };
         for attr in attrs {
             if attr.path().is_ident("service") {
-                config.services = Self::parse_service_attr(attr)?;
+                (config.services, config.protocol) = Self::parse_service_attr(attr)?;
+            } else if attr.path().is_ident("lifecycle") {
+                attr.meta.require_path_only()?;
+                config.lifecycle = true;
             }
         }
 
@@ -53,18 +60,38 @@ impl Service {
         streams
     }
 
-    /// Parses the `#[service(...)]` attribute.
-    fn parse_service_attr(attr: &Attribute) -> syn::Result<Vec<TokenStream>> {
+    /// Parses the `#[service(...)]` attribute, returning the services and the protocol GUID, if any.
+    fn parse_service_attr(attr: &Attribute) -> syn::Result<(Vec<TokenStream>, Option<TokenStream>)> {
         let Meta::List(meta_list) = &attr.meta else {
             return Err(syn::Error::new(attr.span(), "Expected #[service(...)]"));
         };
 
         let mut services = Vec::new();
+        let mut protocol = None;
         for s in Self::split_by_comma(meta_list.tokens.clone()) {
-            services.push(quote!(#s));
+            match syn::parse2::<MetaNameValue>(s.clone()) {
+                Ok(nv) if nv.path.is_ident("protocol") => protocol = Some(Self::parse_protocol_guid(&nv)?),
+                _ => services.push(quote!(#s)),
+            }
         }
 
-        Ok(services)
+        Ok((services, protocol))
+    }
+
+    /// Parses the `protocol = "GUID"` entry of the `#[service(...)]` attribute into a `&'static efi::Guid`.
+    fn parse_protocol_guid(nv: &MetaNameValue) -> syn::Result<TokenStream> {
+        let Expr::Lit(ExprLit { lit: Lit::Str(guid), .. }) = &nv.value else {
+            return Err(syn::Error::new(nv.value.span(), "Expected protocol = \"GUID\""));
+        };
+        if uuid::Uuid::parse_str(&guid.value()).is_err() {
+            return Err(syn::Error::new(guid.span(), "Invalid GUID format"));
+        }
+        Ok(quote! {
+            {
+                static GUID: patina::BinaryGuid = patina::BinaryGuid::from_string(#guid);
+                GUID.as_efi_guid()
+            }
+        })
     }
 
     /// Returns the name [Ident](syn::Ident) of the struct
@@ -92,7 +119,17 @@ impl Service {
                 let ref_service: &'static #service = leaked;
                 let any: &'static dyn core::any::Any = #alloc_name::boxed::Box::leak(#alloc_name::boxed::Box::new(ref_service));
                 Self::register_service::<#service>(storage, any);
-            })
+            });
+            if self.config.lifecycle {
+                tokens.extend(quote! {
+                    Self::register_lifecycle::<#service>(storage, leaked);
+                });
+            }
+            if let Some(guid) = &self.config.protocol {
+                tokens.extend(quote! {
+                    Self::register_protocol::<#service>(storage, #guid, leaked);
+                });
+            }
         }
         tokens
     }
@@ -161,6 +198,14 @@ pub(crate) fn service2(item: proc_macro2::TokenStream) -> proc_macro2::TokenStre
         .to_compile_error();
     }
 
+    if config.protocol.is_some() && config.services.len() != 1 {
+        return syn::Error::new(
+            service.ident().span(),
+            "Exactly one Service must be specified when publishing it as a protocol.",
+        )
+        .to_compile_error();
+    }
+
     // Tokens for expanding the IntoService trait implementation.
     let name = service.ident();
     let lhs = service.lhs_generics();
@@ -294,6 +339,93 @@ mod tests {
         assert_eq!(expected.to_string(), service2(input).to_string());
     }
 
+    #[test]
+    fn test_struct_with_lifecycle() {
+        let input = quote! {
+            #[service(dyn MyService)]
+            #[lifecycle]
+            struct MyStruct;
+        };
+
+        let expected = quote! {
+            extern crate alloc as __alloc_service_MyStruct;
+            impl patina::component::service::IntoService for MyStruct {
+                fn register(self, storage: &mut patina::component::Storage) {
+                    let leaked: &'static Self = __alloc_service_MyStruct::boxed::Box::leak(__alloc_service_MyStruct::boxed::Box::new(self));
+                    let ref_service: &'static dyn MyService = leaked;
+                    let any: &'static dyn core::any::Any = __alloc_service_MyStruct::boxed::Box::leak(__alloc_service_MyStruct::boxed::Box::new(ref_service));
+                    Self::register_service::<dyn MyService>(storage, any);
+                    Self::register_lifecycle::<dyn MyService>(storage, leaked);
+                }
+            }
+
+            impl patina::component::service::IntoService for &'static MyStruct {
+                fn register(self, storage: &mut patina::component::Storage) {
+                    let leaked: Self = self;
+                    let ref_service: &'static dyn MyService = leaked;
+                    let any: &'static dyn core::any::Any = __alloc_service_MyStruct::boxed::Box::leak(__alloc_service_MyStruct::boxed::Box::new(ref_service));
+                    Self::register_service::<dyn MyService>(storage, any);
+                    Self::register_lifecycle::<dyn MyService>(storage, leaked);
+                }
+            }
+        };
+
+        assert_eq!(expected.to_string(), service2(input).to_string());
+    }
+
+    #[test]
+    fn test_struct_with_protocol() {
+        let input = quote! {
+            #[service(dyn MyService, protocol = "8be4df61-93ca-11d2-aa0d-00e098032b8c")]
+            struct MyStruct;
+        };
+
+        let expected = quote! {
+            extern crate alloc as __alloc_service_MyStruct;
+            impl patina::component::service::IntoService for MyStruct {
+                fn register(self, storage: &mut patina::component::Storage) {
+                    let leaked: &'static Self = __alloc_service_MyStruct::boxed::Box::leak(__alloc_service_MyStruct::boxed::Box::new(self));
+                    let ref_service: &'static dyn MyService = leaked;
+                    let any: &'static dyn core::any::Any = __alloc_service_MyStruct::boxed::Box::leak(__alloc_service_MyStruct::boxed::Box::new(ref_service));
+                    Self::register_service::<dyn MyService>(storage, any);
+                    Self::register_protocol::<dyn MyService>(storage, {
+                        static GUID: patina::BinaryGuid = patina::BinaryGuid::from_string("8be4df61-93ca-11d2-aa0d-00e098032b8c");
+                        GUID.as_efi_guid()
+                    }, leaked);
+                }
+            }
+
+            impl patina::component::service::IntoService for &'static MyStruct {
+                fn register(self, storage: &mut patina::component::Storage) {
+                    let leaked: Self = self;
+                    let ref_service: &'static dyn MyService = leaked;
+                    let any: &'static dyn core::any::Any = __alloc_service_MyStruct::boxed::Box::leak(__alloc_service_MyStruct::boxed::Box::new(ref_service));
+                    Self::register_service::<dyn MyService>(storage, any);
+                    Self::register_protocol::<dyn MyService>(storage, {
+                        static GUID: patina::BinaryGuid = patina::BinaryGuid::from_string("8be4df61-93ca-11d2-aa0d-00e098032b8c");
+                        GUID.as_efi_guid()
+                    }, leaked);
+                }
+            }
+        };
+
+        assert_eq!(expected.to_string(), service2(input).to_string());
+    }
+
+    #[test]
+    fn test_protocol_with_multiple_services_gives_error() {
+        let input = quote! {
+            #[service(dyn MyService, dyn MyService2, protocol = "8be4df61-93ca-11d2-aa0d-00e098032b8c")]
+            struct MyStruct;
+        };
+
+        let expected = quote! {
+            :: core :: compile_error ! { "Exactly one Service must be specified when publishing it as a protocol." }
+        };
+
+        assert_eq!(expected.to_string(), service2(input).to_string());
+    }
+
     #[test]
     fn test_enum_gives_good_error() {
         let input = quote! {
@@ -0,0 +1,268 @@
+//! A module containing Macro(s) implementation details for publishing a service interface as a UEFI protocol.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{FnArg, ItemTrait, ReturnType, TraitItem, TraitItemFn, spanned::Spanned};
+
+/// A method of the service interface, as it is laid out in the protocol.
+struct Method {
+    ident: syn::Ident,
+    args: Vec<syn::Ident>,
+    arg_types: Vec<syn::Type>,
+    output: TokenStream,
+}
+
+impl Method {
+    /// Parses a trait method, which must take `&self` and have no generics.
+    fn parse(method: &TraitItemFn) -> syn::Result<Self> {
+        let sig = &method.sig;
+        if !sig.generics.params.is_empty() || sig.generics.where_clause.is_some() {
+            return Err(syn::Error::new(sig.generics.span(), "Protocol methods cannot be generic."));
+        }
+        if sig.asyncness.is_some() || sig.unsafety.is_some() || sig.variadic.is_some() {
+            return Err(syn::Error::new(sig.span(), "Protocol methods must be safe, synchronous and non-variadic."));
+        }
+
+        let mut inputs = sig.inputs.iter();
+        match inputs.next() {
+            Some(FnArg::Receiver(receiver)) if receiver.reference.is_some() && receiver.mutability.is_none() => {}
+            _ => return Err(syn::Error::new(sig.span(), "Protocol methods must take `&self`.")),
+        }
+
+        let mut args = Vec::new();
+        let mut arg_types = Vec::new();
+        for (index, input) in inputs.enumerate() {
+            let FnArg::Typed(arg) = input else {
+                return Err(syn::Error::new(input.span(), "Unexpected receiver."));
+            };
+            args.push(format_ident!("arg{}", index));
+            arg_types.push((*arg.ty).clone());
+        }
+
+        let output = match &sig.output {
+            ReturnType::Default => quote!(()),
+            ReturnType::Type(_, ty) => quote!(#ty),
+        };
+
+        Ok(Self { ident: sig.ident.clone(), args, arg_types, output })
+    }
+}
+
+/// The testable version of the `service_protocol` macro that uses proc_macro2::Tokenstreams.
+pub(crate) fn service_protocol2(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(attr.span(), "#[service_protocol] does not take any arguments.").to_compile_error();
+    }
+
+    let item = match syn::parse2::<ItemTrait>(item) {
+        Ok(item) => item,
+        Err(e) => return e.to_compile_error(),
+    };
+
+    if !item.generics.params.is_empty() || item.generics.where_clause.is_some() {
+        return syn::Error::new(item.generics.span(), "Protocol service interfaces cannot be generic.")
+            .to_compile_error();
+    }
+
+    let mut methods = Vec::new();
+    for trait_item in &item.items {
+        let TraitItem::Fn(method) = trait_item else {
+            return syn::Error::new(trait_item.span(), "Protocol service interfaces can only contain methods.")
+                .to_compile_error();
+        };
+        match Method::parse(method) {
+            Ok(method) => methods.push(method),
+            Err(e) => return e.to_compile_error(),
+        }
+    }
+
+    let vis = &item.vis;
+    let name = &item.ident;
+    let protocol = format_ident!("{}Protocol", name);
+    let alloc_name = format_ident!("__alloc_service_protocol_{}", name);
+    let doc = format!(" The UEFI protocol layout of the [{name}] service interface.");
+
+    let fields = methods.iter().map(|Method { ident, arg_types, output, .. }| {
+        quote! {
+            pub #ident: extern "efiapi" fn(this: *mut #protocol, #(#arg_types),*) -> #output
+        }
+    });
+    let thunks = methods.iter().map(|Method { ident, args, arg_types, output }| {
+        // The protocol is consumed from C, so a type with no C equivalent (e.g. `&str`, `Vec` or `Result`) is an error.
+        quote! {
+            #[deny(improper_ctypes_definitions)]
+            extern "efiapi" fn #ident(this: *mut #protocol, #(#args: #arg_types),*) -> #output {
+                // SAFETY: This function is only installed in protocols created by `publish`, which are always the
+                // first field of a `Published` instance.
+                let published = unsafe { &*(this as *const Published) };
+                published.service.#ident(#(#args),*)
+            }
+        }
+    });
+    let client_methods = methods.iter().map(|Method { ident, args, arg_types, output }| {
+        quote! {
+            fn #ident(&self, #(#args: #arg_types),*) -> #output {
+                // SAFETY: The caller of `wrap` guarantees that the protocol is valid for the rest of boot.
+                unsafe { ((*self.0).#ident)(self.0, #(#args),*) }
+            }
+        }
+    });
+    let initializers = methods.iter().map(|Method { ident, .. }| quote!(#ident));
+
+    quote! {
+        #item
+
+        #[doc = #doc]
+        #[repr(C)]
+        #vis struct #protocol {
+            #(#fields,)*
+        }
+
+        const _: () = {
+            extern crate alloc as #alloc_name;
+
+            #[repr(C)]
+            struct Published {
+                protocol: #protocol,
+                service: &'static dyn #name,
+            }
+
+            #(#thunks)*
+
+            struct Client(*mut #protocol);
+
+            // SAFETY: UEFI protocols can be used from any context that can use boot services.
+            unsafe impl Send for Client {}
+            unsafe impl Sync for Client {}
+
+            impl #name for Client {
+                #(#client_methods)*
+            }
+
+            unsafe impl patina::component::service::protocol::ServiceProtocol for dyn #name {
+                type Protocol = #protocol;
+
+                fn publish(service: &'static Self) -> *mut Self::Protocol {
+                    let published = #alloc_name::boxed::Box::leak(#alloc_name::boxed::Box::new(Published {
+                        protocol: #protocol { #(#initializers),* },
+                        service,
+                    }));
+                    &mut published.protocol
+                }
+
+                unsafe fn wrap(protocol: *mut Self::Protocol) -> #alloc_name::boxed::Box<Self> {
+                    #alloc_name::boxed::Box::new(Client(protocol))
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use quote::quote;
+
+    #[test]
+    fn test_trait_generates_protocol() {
+        let input = quote! {
+            pub trait Greeter {
+                fn greet(&self, count: u32) -> u64;
+            }
+        };
+
+        let expected = quote! {
+            pub trait Greeter {
+                fn greet(&self, count: u32) -> u64;
+            }
+
+            #[doc = " The UEFI protocol layout of the [Greeter] service interface."]
+            #[repr(C)]
+            pub struct GreeterProtocol {
+                pub greet: extern "efiapi" fn(this: *mut GreeterProtocol, u32) -> u64,
+            }
+
+            const _: () = {
+                extern crate alloc as __alloc_service_protocol_Greeter;
+
+                #[repr(C)]
+                struct Published {
+                    protocol: GreeterProtocol,
+                    service: &'static dyn Greeter,
+                }
+
+                #[deny(improper_ctypes_definitions)]
+                extern "efiapi" fn greet(this: *mut GreeterProtocol, arg0: u32) -> u64 {
+                    let published = unsafe { &*(this as *const Published) };
+                    published.service.greet(arg0)
+                }
+
+                struct Client(*mut GreeterProtocol);
+
+                unsafe impl Send for Client {}
+                unsafe impl Sync for Client {}
+
+                impl Greeter for Client {
+                    fn greet(&self, arg0: u32) -> u64 {
+                        unsafe { ((*self.0).greet)(self.0, arg0) }
+                    }
+                }
+
+                unsafe impl patina::component::service::protocol::ServiceProtocol for dyn Greeter {
+                    type Protocol = GreeterProtocol;
+
+                    fn publish(service: &'static Self) -> *mut Self::Protocol {
+                        let published = __alloc_service_protocol_Greeter::boxed::Box::leak(__alloc_service_protocol_Greeter::boxed::Box::new(Published {
+                            protocol: GreeterProtocol { greet },
+                            service,
+                        }));
+                        &mut published.protocol
+                    }
+
+                    unsafe fn wrap(protocol: *mut Self::Protocol) -> __alloc_service_protocol_Greeter::boxed::Box<Self> {
+                        __alloc_service_protocol_Greeter::boxed::Box::new(Client(protocol))
+                    }
+                }
+            };
+        };
+
+        assert_eq!(expected.to_string(), service_protocol2(TokenStream::new(), input).to_string());
+    }
+
+    #[test]
+    fn test_mutable_receiver_gives_error() {
+        let input = quote! {
+            trait Greeter {
+                fn greet(&mut self);
+            }
+        };
+
+        let expected = quote! {
+            :: core :: compile_error ! { "Protocol methods must take `&self`." }
+        };
+
+        assert_eq!(expected.to_string(), service_protocol2(TokenStream::new(), input).to_string());
+    }
+
+    #[test]
+    fn test_generic_method_gives_error() {
+        let input = quote! {
+            trait Greeter {
+                fn greet<T>(&self, value: T);
+            }
+        };
+
+        let expected = quote! {
+            :: core :: compile_error ! { "Protocol methods cannot be generic." }
+        };
+
+        assert_eq!(expected.to_string(), service_protocol2(TokenStream::new(), input).to_string());
+    }
+}